@@ -0,0 +1,63 @@
+use chip8rs::chip8::Chip8;
+use chip8rs::instruction::Instruction;
+use chip8rs::platform::MachineConfig;
+use chip8rs::sound::NullBackend;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+/* One opcode for each instruction of the original interpreter */
+const OPCODES: [u16; 34] = [
+    0x00E0, 0x00EE, 0x1234, 0x2345, 0x3456, 0x4567, 0x5670, 0x6789, 0x789A, 0x8120, 0x8121, 0x8122,
+    0x8123, 0x8124, 0x8125, 0x8126, 0x8127, 0x812E, 0x9120, 0xA123, 0xB123, 0xC1FF, 0xD125, 0xE19E,
+    0xE1A1, 0xF107, 0xF10A, 0xF115, 0xF118, 0xF11E, 0xF129, 0xF133, 0xF155, 0xF165,
+];
+
+/* Loops of instructions, each one ending with a jump back to the start */
+const ALU_MIX: [u8; 16] = [
+    0x60, 0x05, 0x71, 0x03, 0x82, 0x14, 0x83, 0x25, 0x84, 0x36, 0x85, 0x43, 0x86, 0x51, 0x12, 0x00,
+];
+const DRAW_MIX: [u8; 12] = [0xF2, 0x29, 0xD0, 0x15, 0x70, 0x05, 0x71, 0x03, 0x72, 0x01, 0x12, 0x00];
+const MEMORY_MIX: [u8; 12] = [0xA3, 0x00, 0xF3, 0x55, 0xF3, 0x65, 0xF0, 0x33, 0x70, 0x07, 0x12, 0x00];
+
+/* Instructions executed in each iteration of the execution benchmarks */
+const STEPS: u64 = 1000;
+
+fn machine(program: &[u8]) -> Chip8 {
+    /* Without a channel the draw commands are discarded, like when the renderer has been closed */
+    let mut chip = Chip8::builder().config(MachineConfig::default()).sound_backend(NullBackend).build();
+    chip.load_program(program).unwrap();
+    chip
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    group.throughput(Throughput::Elements(OPCODES.len() as u64));
+    group.bench_function("all instructions", |b| {
+        b.iter(|| {
+            for &opcode in OPCODES.iter() {
+                black_box(Instruction::from(black_box(opcode)));
+            }
+        })
+    });
+    group.finish();
+}
+
+fn execute(c: &mut Criterion) {
+    let mut group = c.benchmark_group("execute");
+    group.throughput(Throughput::Elements(STEPS));
+
+    for (name, program) in [("alu", &ALU_MIX[..]), ("draw", &DRAW_MIX[..]), ("memory", &MEMORY_MIX[..])] {
+        let mut chip = machine(program);
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                for _ in 0..STEPS {
+                    chip.step().unwrap();
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, decode, execute);
+criterion_main!(benches);
@@ -0,0 +1,328 @@
+use crate::instruction::Instruction;
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fmt::Write;
+
+/* Instruction sets a program can be written for, from the smallest */
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Extension {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl fmt::Display for Extension {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Extension::Chip8 => "CHIP-8",
+            Extension::SuperChip => "SUPER-CHIP",
+            Extension::XoChip => "XO-CHIP",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/* Construct found at the address of an instruction */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Finding {
+    /* 0NNN, running COSMAC VIP machine code, which can't be emulated */
+    MachineCode { address: u16, target: u16 },
+    UnknownOpcode { address: u16, opcode: u16 },
+    /* Jump or call to an address outside of the ROM */
+    OutOfRange { address: u16, target: u16 },
+    OddTarget { address: u16, target: u16 },
+    /* BNNN, whose target is only known at runtime, so the code after it is not analyzed */
+    ComputedJump { address: u16 },
+    /* A store to the memory overwrites an instruction */
+    SelfModifying { address: u16, target: u16 },
+    Extension { address: u16, opcode: u16, extension: Extension },
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Finding::MachineCode { address, target } => {
+                write!(f, "{:03X}: call to machine code at {:03X}, not supported", address, target)
+            }
+            Finding::UnknownOpcode { address, opcode } => write!(f, "{:03X}: unknown opcode {:04X}", address, opcode),
+            Finding::OutOfRange { address, target } => write!(f, "{:03X}: jump outside of the ROM, to {:03X}", address, target),
+            Finding::OddTarget { address, target } => write!(f, "{:03X}: jump to the odd address {:03X}", address, target),
+            Finding::ComputedJump { address } => write!(f, "{:03X}: computed jump, not followed", address),
+            Finding::SelfModifying { address, target } => {
+                write!(f, "{:03X}: self-modifying code, overwrites the instruction at {:03X}", address, target)
+            }
+            Finding::Extension { address, opcode, extension } => {
+                write!(f, "{:03X}: {:04X} is a {} instruction", address, opcode, extension)
+            }
+        }
+    }
+}
+
+/* Result of following the code of a ROM from its first instruction */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Analysis {
+    /* Addresses of the instructions which can be reached */
+    pub code: BTreeSet<u16>,
+    pub findings: Vec<Finding>,
+    /* Smallest instruction set with all the instructions found */
+    pub extension: Extension,
+    /* Quirks changing the behaviour of some of the instructions found, see platform::Quirks */
+    pub quirks: Vec<&'static str>,
+}
+
+/* XO-CHIP instructions, not decoded by this emulator */
+fn xo_chip_opcode(opcode: u16) -> bool {
+    opcode & 0xFFF0 == 0x00D0
+        || opcode & 0xF00F == 0x5002
+        || opcode & 0xF00F == 0x5003
+        || opcode == 0xF000
+        || opcode & 0xF0FF == 0xF001
+        || opcode == 0xF002
+        || opcode & 0xF0FF == 0xF03A
+}
+
+fn super_chip_instruction(instr: &Instruction) -> bool {
+    matches!(
+        instr,
+        Instruction::ScrollDown(_)
+            | Instruction::ScrollRight
+            | Instruction::ScrollLeft
+            | Instruction::Exit
+            | Instruction::LowResolution
+            | Instruction::HighResolution
+            | Instruction::Display(_, _, 0)
+            | Instruction::SetIndexToBigFont(_)
+            | Instruction::SaveFlags(_)
+            | Instruction::LoadFlags(_)
+    )
+}
+
+/* Follow the instructions of a ROM loaded at start, through the jumps, calls and skips, so that the
+ * sprites and the data are not mistaken for code. I is followed along the way, as set by the last ANNN,
+ * to find the stores overwriting the code */
+pub fn analyze(rom: &[u8], start: u16) -> Analysis {
+    let end = start as usize + rom.len();
+    let opcode_at = |address: u16| -> Option<u16> {
+        let offset = (address as usize).checked_sub(start as usize)?;
+        Some(u16::from_be_bytes([*rom.get(offset)?, *rom.get(offset + 1)?]))
+    };
+
+    let mut code = BTreeSet::new();
+    let mut findings = Vec::new();
+    let mut extension = Extension::Chip8;
+    let mut quirks = BTreeSet::new();
+    /* Addresses written by the stores, with the address of the store */
+    let mut writes: Vec<(u16, u16, u16)> = Vec::new();
+
+    let mut pending: Vec<(u16, Option<u16>)> = vec![(start, None)];
+    while let Some((address, mut index)) = pending.pop() {
+        if !code.insert(address) {
+            continue;
+        }
+        let opcode = match opcode_at(address) {
+            Some(opcode) => opcode,
+            None => continue,
+        };
+        let next = address.wrapping_add(2);
+        /* XO-CHIP skips the whole F000 NNNN, which takes 4 bytes */
+        let skip = if opcode_at(next) == Some(0xF000) { next + 4 } else { next + 2 };
+
+        if xo_chip_opcode(opcode) {
+            findings.push(Finding::Extension { address, opcode, extension: Extension::XoChip });
+            extension = extension.max(Extension::XoChip);
+
+            if opcode == 0xF000 {
+                index = opcode_at(next);
+                pending.push((next + 2, index));
+            } else {
+                pending.push((next, index));
+            }
+            continue;
+        }
+
+        let instr = match Instruction::decode(opcode) {
+            Some(instr) => instr,
+            /* The machine code returns to the next instruction */
+            None if opcode & 0xF000 == 0 => {
+                findings.push(Finding::MachineCode { address, target: opcode });
+                pending.push((next, index));
+                continue;
+            }
+            /* Probably data, reached by a wrong guess */
+            None => {
+                findings.push(Finding::UnknownOpcode { address, opcode });
+                continue;
+            }
+        };
+
+        if super_chip_instruction(&instr) {
+            findings.push(Finding::Extension { address, opcode, extension: Extension::SuperChip });
+            extension = extension.max(Extension::SuperChip);
+        }
+
+        match instr {
+            Instruction::Jump(target) | Instruction::Call(target) => {
+                if target % 2 != 0 {
+                    findings.push(Finding::OddTarget { address, target });
+                }
+                if (target as usize) < start as usize || target as usize >= end {
+                    findings.push(Finding::OutOfRange { address, target });
+                } else {
+                    pending.push((target, index));
+                }
+                if let Instruction::Call(_) = instr {
+                    pending.push((next, index));
+                }
+            }
+            Instruction::Return | Instruction::Exit => (),
+            Instruction::JumpWithRegister(_) => {
+                findings.push(Finding::ComputedJump { address });
+                quirks.insert("jump-uses-vx");
+            }
+            Instruction::SkipIfEqual(..)
+            | Instruction::SkipIfDifferent(..)
+            | Instruction::SkipIfContentEqual(..)
+            | Instruction::SkipIfContentDifferent(..)
+            | Instruction::SkipIfPressed(_)
+            | Instruction::SkipIfNotPressed(_) => {
+                pending.push((next, index));
+                pending.push((skip, index));
+            }
+            _ => {
+                match instr {
+                    Instruction::SetIndex(value) => index = Some(value),
+                    Instruction::AddToIndex(_)
+                    | Instruction::SetIndexToFont(_)
+                    | Instruction::SetIndexToBigFont(_) => index = None,
+                    Instruction::BinaryConversion(_) => {
+                        if let Some(i) = index {
+                            writes.push((address, i, i + 2));
+                        }
+                    }
+                    Instruction::Store(x) | Instruction::Load(x) => {
+                        if let (Some(i), Instruction::Store(_)) = (index, instr) {
+                            writes.push((address, i, i + x as u16));
+                        }
+                        quirks.insert("load-store-increment");
+                        /* Where I ends depends on the quirk */
+                        index = None;
+                    }
+                    Instruction::LogicalRightShift(x, y) | Instruction::LogicalLeftShift(x, y) if x != y => {
+                        quirks.insert("shift-copies-vy");
+                    }
+                    Instruction::LogicalOr(..) | Instruction::LogicalAnd(..) | Instruction::LogicalXor(..) => {
+                        quirks.insert("vf-reset");
+                    }
+                    _ => (),
+                }
+                pending.push((next, index));
+            }
+        }
+    }
+
+    for (address, first, last) in writes {
+        if let Some(&target) = code.range(first.saturating_sub(1)..=last).next() {
+            findings.push(Finding::SelfModifying { address, target });
+        }
+    }
+
+    /* Addresses outside of the ROM were only recorded to stop following them */
+    code.retain(|&address| opcode_at(address).is_some());
+    findings.sort_by_key(|finding| match *finding {
+        Finding::MachineCode { address, .. }
+        | Finding::UnknownOpcode { address, .. }
+        | Finding::OutOfRange { address, .. }
+        | Finding::OddTarget { address, .. }
+        | Finding::ComputedJump { address }
+        | Finding::SelfModifying { address, .. }
+        | Finding::Extension { address, .. } => address,
+    });
+
+    Analysis {
+        code,
+        findings,
+        extension,
+        quirks: quirks.into_iter().collect(),
+    }
+}
+
+impl Analysis {
+    /* Compatibility summary followed by the findings, one per line */
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+
+        let _ = writeln!(report, "Instruction set: {}", self.extension);
+        match self.extension {
+            Extension::Chip8 => (),
+            Extension::SuperChip => {
+                let _ = writeln!(report, "Run with --platform schip");
+            }
+            Extension::XoChip => {
+                let _ = writeln!(report, "XO-CHIP is not supported by this emulator");
+            }
+        }
+        if !self.quirks.is_empty() {
+            let _ = writeln!(report, "Behaviour depends on the quirks: {}", self.quirks.join(", "));
+        }
+        let _ = writeln!(report, "Reachable instructions: {}", self.code.len());
+
+        for finding in &self.findings {
+            let _ = writeln!(report, "{}", finding);
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flow_and_findings() {
+        let rom = [
+            0xA2, 0x10, /* 200: LD I, 0x210 */
+            0x00, 0xFF, /* 202: HIGH */
+            0x3F, 0x00, /* 204: SE VF, 0x00 */
+            0x13, 0x01, /* 206: JP 0x301 */
+            0x22, 0x0E, /* 208: CALL 0x20E */
+            0x12, 0x08, /* 20A: JP 0x208 */
+            0xF1, 0x55, /* 20C: LD [I], V1, never reached */
+            0xF1, 0x55, /* 20E: LD [I], V1 */
+            0x81, 0x06, /* 210: SHR V1, V0 */
+            0x00, 0xEE, /* 212: RET */
+        ];
+        let analysis = analyze(&rom, 0x200);
+
+        assert_eq!(analysis.code.len(), 9);
+        assert!(!analysis.code.contains(&0x20C));
+        assert_eq!(analysis.extension, Extension::SuperChip);
+        assert_eq!(analysis.quirks, vec!["load-store-increment", "shift-copies-vy"]);
+        assert_eq!(
+            analysis.findings,
+            vec![
+                Finding::Extension { address: 0x202, opcode: 0x00FF, extension: Extension::SuperChip },
+                Finding::OddTarget { address: 0x206, target: 0x301 },
+                Finding::OutOfRange { address: 0x206, target: 0x301 },
+                Finding::SelfModifying { address: 0x20E, target: 0x210 },
+            ]
+        );
+    }
+
+    #[test]
+    fn unsupported_instructions() {
+        /* SYS 0x300; LD I, long 0x400; unknown */
+        let analysis = analyze(&[0x03, 0x00, 0xF0, 0x00, 0x04, 0x00, 0xE0, 0x00], 0x200);
+
+        assert_eq!(analysis.extension, Extension::XoChip);
+        assert_eq!(
+            analysis.findings,
+            vec![
+                Finding::MachineCode { address: 0x200, target: 0x300 },
+                Finding::Extension { address: 0x202, opcode: 0xF000, extension: Extension::XoChip },
+                Finding::UnknownOpcode { address: 0x206, opcode: 0xE000 },
+            ]
+        );
+    }
+}
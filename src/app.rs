@@ -6,72 +6,661 @@ extern crate piston;
 use opengl_graphics::{GlGraphics, OpenGL};
 use piston::event_loop::*;
 use piston::input::*;
-use piston::window::WindowSettings;
+use piston::window::{Window, WindowSettings};
 
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
 use std::panic;
+use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Instant;
 
-use crate::chip8::Chip8;
+use crate::chip8::{Chip8, Command, Metrics, DEFAULT_SPEED};
+use crate::console;
+use crate::coverage::{self, Coverage};
+use crate::crash;
+use crate::debugger::{Debugger, MemoryViewer, StepMode, Watchpoint};
 use crate::display::*;
+use crate::dump;
+use crate::effects;
+use crate::effects::{CycleSettings, Effect, FrameInfo, PulseSettings};
+use crate::fonts::Fonts;
+use crate::gamedb;
+use crate::gamedb::GameSettings;
+use crate::heatmap;
 use crate::hsl::*;
+use crate::keyboard_layout::KeyboardLayout;
 use crate::keypad::*;
+use crate::logger::{JsonLogger, LogFormat, LogLevel, NullLogger, LOG_FILE};
+use crate::macros::{MacroPlayer, MacroStep};
+use crate::netplay;
+use crate::netplay::NetplayMode;
+use crate::platform::MachineConfig;
+use crate::profiler::Profiler;
+use crate::render_style::RenderStyle;
+use crate::rng::SeededRngSource;
+use crate::rom::{self, RomFormat};
+use crate::savestate;
+use crate::scripting::Script;
+use crate::server;
+use crate::sound::{NullBackend, RodioBackend};
+use crate::symbols::Symbols;
+use crate::text;
+use crate::trace::Tracer;
+use crate::turbo::{Turbo, TURBO_RATE};
+use crate::virtual_keypad::VirtualKeypad;
+use crate::wav::AudioRecorder;
 
-const WINDOW_WIDTH: usize = 640;
-const WINDOW_HEIGHT: usize = 320;
+/* Height of each LED; the width also depends on the pixel aspect */
+const LED_HEIGHT: f64 = 10.0;
 
-const DISPLAY_WIDTH: usize = 64;
-const DISPLAY_HEIGHT: usize = 32;
+/* Largest window scale selected with Ctrl + Shift + number, in screen pixels per CHIP-8 pixel */
+const MAX_WINDOW_SCALE: usize = 8;
 
-const LED_WIDTH: f64 = 10.0;
+/* Height of the on-screen keypad, drawn below the display */
+const VIRTUAL_KEYPAD_HEIGHT: f64 = 160.0;
+
+/* Size of the keypad overlay, drawn in the top right corner of the display */
+const KEYPAD_OVERLAY_SIZE: f64 = 100.0;
+const KEYPAD_OVERLAY_MARGIN: f64 = 10.0;
+
+/* Size of the contents once rotated clockwise by the given degrees */
+fn rotated_size(content: [f64; 2], rotation: u32) -> [f64; 2] {
+    match rotation {
+        90 | 270 => [content[1], content[0]],
+        _ => content,
+    }
+}
+
+/* Rotation, scale and offset mapping the window contents, laid out with LED_HEIGHT tall LEDs, to the
+ * actual window size; the aspect ratio is preserved by centering the contents */
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Layout {
+    content: [f64; 2],
+    /* Clockwise, in degrees: 0, 90, 180 or 270 */
+    rotation: u32,
+    scale: f64,
+    offset: [f64; 2],
+}
+
+impl Layout {
+    fn fit(content: [f64; 2], rotation: u32, window: [f64; 2]) -> Layout {
+        let [width, height] = rotated_size(content, rotation);
+        let scale = f64::min(window[0] / width, window[1] / height);
+
+        Layout {
+            content,
+            rotation,
+            scale,
+            offset: [(window[0] - width * scale) / 2.0, (window[1] - height * scale) / 2.0],
+        }
+    }
+
+    /* Transform drawing the unscaled contents in the window */
+    fn transform(self, base: graphics::math::Matrix2d) -> graphics::math::Matrix2d {
+        use graphics::Transformed;
+
+        let [width, height] = self.content;
+        let transform = base.trans(self.offset[0], self.offset[1]).scale(self.scale, self.scale);
+        match self.rotation {
+            90 => transform.trans(height, 0.0).rot_deg(90.0),
+            180 => transform.trans(width, height).rot_deg(180.0),
+            270 => transform.trans(0.0, width).rot_deg(270.0),
+            _ => transform,
+        }
+    }
+
+    /* Map a position in the window to the unscaled contents */
+    fn to_content(self, position: [f64; 2]) -> [f64; 2] {
+        let [width, height] = self.content;
+        let x = (position[0] - self.offset[0]) / self.scale;
+        let y = (position[1] - self.offset[1]) / self.scale;
+
+        match self.rotation {
+            90 => [y, height - x],
+            180 => [width - x, height - y],
+            270 => [width - y, x],
+            _ => [x, y],
+        }
+    }
+}
+
+/* CRT effect: scanlines darken the lower part of each LED row, the vignette darkens the borders and
+ * the rounded corners mimic the curvature of the tube */
+const CRT_SCANLINE_HEIGHT: f64 = 0.3;
+const CRT_SCANLINE_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.3];
+const CRT_VIGNETTE_STEPS: usize = 8;
+const CRT_VIGNETTE_WIDTH: f64 = 40.0;
+const CRT_VIGNETTE_ALPHA: f32 = 0.06;
+const CRT_CORNER_RADIUS: f64 = 30.0;
+
+/* Status overlay drawn in the top left corner of the display */
+const HUD_PIXEL: f64 = 2.0;
+const HUD_MARGIN: f64 = 6.0;
+const HUD_LINE_HEIGHT: f64 = ((text::GLYPH_HEIGHT + 2) as f64) * HUD_PIXEL;
+const HUD_BACKGROUND: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+const HUD_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+/* Bar over the current instruction of the disassembly view */
+const CODE_HIGHLIGHT: [f32; 4] = [1.0, 1.0, 1.0, 0.3];
+
+/* Border flashing around a display while its sound plays, with --visual-beep */
+const BEEP_BORDER_WIDTH: f64 = 4.0;
+const BEEP_BORDER_COLOR: [f32; 4] = [1.0, 0.8, 0.0, 1.0];
+
+/* How long messages, like the ones confirming a save, are shown */
+const MESSAGE_SECONDS: f32 = 2.0;
+
+struct KeypadStyle {
+    key: [f32; 4],
+    pressed: [f32; 4],
+    label: [f32; 4],
+    margin: f64,
+}
+
+const VIRTUAL_KEYPAD_STYLE: KeypadStyle = KeypadStyle {
+    key: [0.8, 0.8, 0.8, 1.0],
+    pressed: [0.5, 0.5, 0.5, 1.0],
+    label: [0.0, 0.0, 0.0, 1.0],
+    margin: 4.0,
+};
+
+const KEYPAD_OVERLAY_STYLE: KeypadStyle = KeypadStyle {
+    key: [0.2, 0.2, 0.2, 0.5],
+    pressed: [1.0, 0.6, 0.0, 0.8],
+    label: [1.0, 1.0, 1.0, 0.9],
+    margin: 2.0,
+};
+
+/* Colors of the pixels with a value above 1; 0 is the background and 1 is drawn by the effect */
+const EXTRA_COLORS: [RGBPixel; 2] = [
+    RGBPixel {
+        r: 0.6,
+        g: 0.6,
+        b: 0.6,
+    },
+    RGBPixel {
+        r: 0.3,
+        g: 0.3,
+        b: 0.3,
+    },
+];
 
 const KEYPAD_SIZE: usize = 0x10;
 
-pub struct App {
-    display: Arc<Mutex<LedsDisplay>>,
+/* A full redraw must reach both the front and the back buffer */
+const FULL_REDRAW_FRAMES: u8 = 2;
+
+/* Speed multiplier while Tab is held, and divider in slow motion */
+const FAST_FORWARD_FACTOR: u32 = 5;
+const SLOW_MOTION_FACTOR: u32 = 4;
+
+/* TODO : add ASCII art for keypad */
+const DEFAULT_KEYMAP: [(Key, usize); KEYPAD_SIZE] = [
+    (Key::D1, 0x01),
+    (Key::D2, 0x02),
+    (Key::D3, 0x03),
+    (Key::D4, 0x0C),
+    (Key::Q, 0x04),
+    (Key::W, 0x05),
+    (Key::E, 0x06),
+    (Key::R, 0x0D),
+    (Key::A, 0x07),
+    (Key::S, 0x08),
+    (Key::D, 0x09),
+    (Key::F, 0x0E),
+    (Key::Z, 0x0A),
+    (Key::X, 0x00),
+    (Key::C, 0x0B),
+    (Key::V, 0x0F),
+];
+
+/* Keys of the machine on the right in split screen, the same layout moved to the right half of the
+ * keyboard */
+const SECOND_KEYMAP: [(Key, usize); KEYPAD_SIZE] = [
+    (Key::D7, 0x01),
+    (Key::D8, 0x02),
+    (Key::D9, 0x03),
+    (Key::D0, 0x0C),
+    (Key::U, 0x04),
+    (Key::I, 0x05),
+    (Key::O, 0x06),
+    (Key::P, 0x0D),
+    (Key::J, 0x07),
+    (Key::K, 0x08),
+    (Key::L, 0x09),
+    (Key::Semicolon, 0x0E),
+    (Key::M, 0x0A),
+    (Key::Comma, 0x00),
+    (Key::Period, 0x0B),
+    (Key::Slash, 0x0F),
+];
+
+/* Space between the displays in split screen */
+const SPLIT_SCREEN_GAP: f64 = 20.0;
+
+#[derive(Debug, Clone, Default)]
+pub struct AppOptions {
+    /* Name of the color effect, one of effects::NAMES; the palette color is used if not set */
+    pub effect: Option<String>,
+    /* Shown in the window title, e.g. the title of the ROM found in the metadata */
+    pub title: Option<String>,
+    /* Speed and colors of the hue cycling effects */
+    pub cycle: CycleSettings,
+    pub pulse: PulseSettings,
+    /* Draw a clickable hex keypad below the display */
+    pub virtual_keypad: bool,
+    /* Draw scanlines, vignette and rounded corners over the display */
+    pub crt: bool,
+    /* Flash a border around the display while the sound plays, for when it can't be heard */
+    pub visual_beep: bool,
+    /* Width of each pixel relative to its height, e.g. 2.0 for double width pixels; the game settings
+     * are used if not set */
+    pub pixel_aspect: Option<f64>,
+    /* Clockwise rotation of the window contents in degrees, for rotated monitors: 0, 90, 180 or 270 */
+    pub rotation: u32,
+    /* Layout of the host keyboard, so that the keypad keeps its physical position */
+    pub keyboard_layout: KeyboardLayout,
+    /* Keypad keys repeating on their own while held, see turbo::Turbo */
+    pub turbo_keys: Vec<usize>,
+    /* Presses per second of the turbo keys; turbo::TURBO_RATE is used if not set */
+    pub turbo_rate: Option<f64>,
+    /* Send each sprite to the renderer as soon as it's drawn, instead of whole frames */
+    pub immediate_draw: bool,
+    /* Shape of the pixels and grid between them */
+    pub render_style: RenderStyle,
+    /* Number of frames a turned off LED takes to fade out; 0 turns it off immediately */
+    pub ghosting_frames: u32,
+    /* Show each frame ORed with the previous one, to smooth the flickering */
+    pub frame_blending: bool,
+    /* Restore the state saved when the emulator was last closed with the same ROM */
+    pub resume: bool,
+    /* Locations stopping the execution when accessed */
+    pub watchpoints: Vec<Watchpoint>,
+    /* Instructions recorded so that the debugger can step back; 0 disables the recording */
+    pub history: usize,
+    /* Seed of the random numbers of CXNN, to get the same ones on each run; random if not set */
+    pub seed: Option<u64>,
+    /* Read debugger commands from the standard input */
+    pub console: bool,
+    /* Address of the WebSocket server publishing the machine state */
+    pub serve: Option<SocketAddr>,
+    /* Share the keypad with another instance over the network */
+    pub netplay: Option<NetplayMode>,
+    /* Rhai script handling the machine events */
+    pub script: Option<PathBuf>,
+    /* Process exit code used when the program stops with 00FD */
+    pub exit_code: i32,
+    /* File where the framebuffer is written when the emulator is closed, see dump::write */
+    pub dump_display: Option<PathBuf>,
+    /* File where the executed instructions are traced, see trace::Tracer */
+    pub trace: Option<PathBuf>,
+    /* WAV file where the sound of the first machine is recorded, see wav::AudioRecorder */
+    pub record_audio: Option<PathBuf>,
+    /* File where the executed parts of the ROM are written when the emulator is closed, see
+     * coverage::write */
+    pub coverage: Option<PathBuf>,
+    /* File where the memory accesses are drawn when the emulator is closed, see heatmap::Heatmap */
+    pub heatmap: Option<PathBuf>,
+    /* Labels of the program, shown by the debugger console and the trace */
+    pub symbols: Symbols,
+    /* Encoding of the ROM, used to decode it again when it changes */
+    pub rom_format: RomFormat,
+    pub log_level: LogLevel,
+    pub log_format: LogFormat,
+}
+
+fn spawn_machine_thread<F: FnOnce() + Send + 'static>(f: F) -> thread::JoinHandle<()> {
+    thread::Builder::new()
+        .name(crash::THREAD_NAME.to_string())
+        .spawn(f)
+        .unwrap()
+}
+
+/* Run the ROM, exiting on errors; when the machine panics, e.g. because of a bad ROM, its state is
+ * written to a crash dump first */
+fn run_machine(chip: &mut Chip8, rom: &[u8]) {
+    match panic::catch_unwind(panic::AssertUnwindSafe(|| chip.run(rom))) {
+        Ok(Ok(())) => (),
+        Ok(Err(error)) => {
+            eprintln!("CHIP-8 error: {}", error);
+            /* Dropping the logger and the sound backend flushes the lines and the audio still buffered,
+             * which exit would lose */
+            chip.set_logger(Box::new(NullLogger));
+            chip.set_sound_backend(Box::new(NullBackend));
+            process::exit(1);
+        }
+        Err(payload) => {
+            let report = crash::report(&crash::panic_message(&*payload), chip.current_pc(), &chip.snapshot());
+            match crash::write(&report) {
+                Ok(path) => eprintln!("The state of the machine was written to {}", path.display()),
+                Err(error) => eprintln!("Cannot write the crash dump: {}", error),
+            }
+            chip.set_logger(Box::new(NullLogger));
+            chip.set_sound_backend(Box::new(NullBackend));
+            process::exit(1);
+        }
+    }
+}
+
+/* A CHIP-8 machine shown in the window, with its own display, keypad and thread */
+struct Machine {
+    config: MachineConfig,
+    /* The renderer owns the framebuffer, updated with the commands received from the CHIP-8 thread */
+    display: LedsDisplay,
+    draw_commands: Receiver<DrawCommand>,
+    draw_sender: Sender<DrawCommand>,
     keypad: Arc<Mutex<KeyboardKeypad>>,
+    keymap: HashMap<Key, usize>,
+    /* Host keys playing a sequence of key presses, see macros::MacroPlayer */
+    macros: HashMap<Key, Vec<MacroStep>>,
+    macro_player: MacroPlayer,
+    turbo: Turbo,
+    /* The CHIP-8 thread sends whole frames instead of each sprite, see Chip8::set_double_buffered */
+    double_buffered: bool,
+    /* Horizontal position of the display in the window contents */
+    left: f64,
+    previous_dirty: Vec<(usize, usize)>,
+    /* Set by the CHIP-8 thread when a sound starts */
+    beep: Arc<AtomicBool>,
+    /* Set by the CHIP-8 thread while the sound plays */
+    sound_playing: Arc<AtomicBool>,
+    /* Set by the CHIP-8 thread when the program stops with 00FD */
+    exited: Arc<AtomicBool>,
+    /* Requests to the CHIP-8 thread, available while running */
+    commands: Option<Sender<Command>>,
+    chip_thread: Option<thread::JoinHandle<()>>,
+    rom_hash: String,
+    /* Program run by the machine; the one of the first machine is passed to App::run */
+    rom: Vec<u8>,
+}
+
+impl Machine {
+    fn new(config: MachineConfig, settings: &GameSettings, options: &AppOptions, left: f64) -> Machine {
+        let mut display = LedsDisplay::new(config.display_width, config.display_height, false);
+        display.set_fade_frames(options.ghosting_frames);
+        display.set_frame_blending(options.frame_blending);
+        display.set_pixel_aspect(options.pixel_aspect.or(settings.pixel_aspect).unwrap_or(1.0));
+
+        let (draw_sender, draw_commands) = mpsc::channel();
+
+        Machine {
+            config,
+            display,
+            draw_commands,
+            draw_sender,
+            keypad: Arc::new(Mutex::new(KeyboardKeypad::new(KEYPAD_SIZE))),
+            keymap: build_keymap(settings, options.keyboard_layout),
+            macros: build_macros(settings),
+            macro_player: MacroPlayer::new(),
+            turbo: Turbo::new(&options.turbo_keys, options.turbo_rate.unwrap_or(TURBO_RATE)),
+            double_buffered: !options.immediate_draw,
+            left,
+            previous_dirty: Vec::new(),
+            beep: Arc::new(AtomicBool::new(false)),
+            sound_playing: Arc::new(AtomicBool::new(false)),
+            exited: Arc::new(AtomicBool::new(false)),
+            commands: None,
+            chip_thread: None,
+            rom_hash: String::new(),
+            rom: Vec::new(),
+        }
+    }
+
+    /* Apply the pending draw commands and return the LEDs to draw */
+    fn update_display(&mut self, full_redraw: bool) -> Vec<(usize, usize)> {
+        while let Ok(command) = self.draw_commands.try_recv() {
+            command.apply(&mut self.display);
+        }
+
+        self.display.fade();
+        let dirty: Vec<(usize, usize)> = self.display.take_dirty().iter().map(|&(x, y, _)| (x, y)).collect();
+
+        let positions = if full_redraw {
+            let mut positions = Vec::with_capacity(self.display.width() * self.display.height());
+            for y in 0..self.display.height() {
+                for x in 0..self.display.width() {
+                    positions.push((x, y));
+                }
+            }
+            positions
+        } else {
+            /* The back buffer contains the frame before the previous one, so the LEDs changed in
+             * the previous frame must be drawn again */
+            let mut positions = dirty.clone();
+            positions.extend_from_slice(&self.previous_dirty);
+            positions
+        };
+
+        self.previous_dirty = dirty;
+        positions
+    }
+
+    /* Start a plain CHIP-8 thread, without the debugging tools attached to the first machine */
+    fn spawn(&mut self, speed: u32, speed_control: &Arc<AtomicU32>) {
+        let config = self.config;
+        let draw_sender = self.draw_sender.clone();
+        let keypad = self.keypad.clone();
+        let speed_control = speed_control.clone();
+        let beep = self.beep.clone();
+        let sound_playing = self.sound_playing.clone();
+        let exited = self.exited.clone();
+        let rom = self.rom.clone();
+        let double_buffered = self.double_buffered;
+
+        let (commands, command_receiver) = mpsc::channel();
+        self.commands = Some(commands);
+        self.rom_hash = gamedb::rom_hash(&rom);
+
+        self.chip_thread = Some(spawn_machine_thread(move || {
+            /* The log file belongs to the first machine */
+            let mut chip = Chip8::builder()
+                .config(config)
+                .speed(speed)
+                .keypad(&keypad)
+                .draw_commands(draw_sender)
+                .logger(NullLogger)
+                .build();
+            chip.set_speed_control(&speed_control);
+            chip.set_double_buffered(double_buffered);
+            chip.set_commands(command_receiver);
+            chip.on_sound(move |value| {
+                if value > 0 {
+                    beep.store(true, Ordering::Relaxed);
+                }
+            });
+            chip.set_sound_flag(&sound_playing);
+            run_machine(&mut chip, &rom);
+            if chip.has_exited() {
+                exited.store(true, Ordering::Relaxed);
+            }
+        }));
+    }
+}
+
+pub struct App {
+    /* Machines shown side by side; the first one is the one controlled by the debugger, the savestates
+     * and the other tools */
+    machines: Vec<Machine>,
     window: glutin_window::GlutinWindow,
     gl: GlGraphics,
-    color: RGBPixel,
+    /* Foreground color of the palette, used as the base color by the effects */
+    foreground: RGBPixel,
     background: [f32; 4],
-    nyan_mode: bool,
+    effect: Box<dyn Effect>,
+    cycle: CycleSettings,
+    crt: bool,
+    /* Speed of the game, and the actual speed shared with the CHIP-8 thread */
+    speed: u32,
+    speed_control: Arc<AtomicU32>,
+    fast_forward: bool,
+    slow_motion: bool,
+    log_level: LogLevel,
+    full_redraw_frames: u8,
+    debugger: Arc<Mutex<Debugger>>,
+    memory_viewer: MemoryViewer,
+    /* Set when the memory viewer must be printed again, as soon as a snapshot is available */
+    memory_viewer_dirty: bool,
+    /* Pause count of the debugger when the memory viewer was last printed */
+    pauses_shown: u64,
+    console: bool,
+    serve: Option<SocketAddr>,
+    netplay: Option<NetplayMode>,
+    script: Option<PathBuf>,
+    exit_code: i32,
+    dump_display: Option<PathBuf>,
+    trace: Option<PathBuf>,
+    /* Used to create the other machines of the split screen */
+    options: AppOptions,
+    profiler: Option<Arc<Mutex<Profiler>>>,
+    coverage: Option<Arc<Mutex<Coverage>>>,
+    virtual_keypad: Option<VirtualKeypad>,
+    virtual_key_pressed: Option<usize>,
+    cursor: [f64; 2],
+    fonts: Fonts,
+    keypad_overlay: VirtualKeypad,
+    keypad_overlay_visible: bool,
+    hud_visible: bool,
+    /* Disassembly view, drawn in the bottom right corner of the display */
+    code_visible: bool,
+    /* Set while a beep border is drawn, so that it's erased when the sound stops */
+    beep_shown: bool,
+    metrics: Metrics,
+    metrics_receiver: Receiver<Metrics>,
+    metrics_sender: Sender<Metrics>,
+    /* Frames rendered since fps_start, used to compute the frames per second shown by the HUD */
+    frames: u32,
+    fps_start: Instant,
+    fps: u32,
+    /* Message shown on the display, with the time it appeared */
+    message: Option<(String, Instant)>,
+    resume: bool,
+    ctrl_pressed: bool,
+    alt_pressed: bool,
+    shift_pressed: bool,
+    /* Size of the window contents before scaling */
+    content_size: [f64; 2],
+    layout: Layout,
 }
 
 impl App {
-    pub fn new(nyan_mode: bool) -> App {
+    pub fn new(config: MachineConfig, settings: &GameSettings, options: &AppOptions) -> App {
         let opengl = OpenGL::V3_2;
 
+        let machine = Machine::new(config, settings, options, 0.0);
+        let [window_width, mut window_height] = machine.display.size(LED_HEIGHT);
+
+        let virtual_keypad = if options.virtual_keypad {
+            let keypad = VirtualKeypad::new(0.0, window_height, window_width, VIRTUAL_KEYPAD_HEIGHT);
+            window_height += VIRTUAL_KEYPAD_HEIGHT;
+            Some(keypad)
+        } else {
+            None
+        };
+
         let mut starting_color = RGBPixel {
             r: 0.0,
             g: 0.0,
             b: 0.0,
         };
+        let mut background = [1.0, 1.0, 1.0, 1.0];
+        let (metrics_sender, metrics_receiver) = mpsc::channel();
+
+        if let Some(palette) = settings.palette {
+            let [r, g, b] = palette.foreground;
+            starting_color = RGBPixel { r, g, b };
 
-        if nyan_mode {
-            starting_color = RGBPixel {
-                r: 1.0,
-                g: 0.0,
-                b: 0.0,
-            };
+            let [r, g, b] = palette.background;
+            background = [r, g, b, 1.0];
         }
 
+        let mut debugger = Debugger::new();
+        for &watchpoint in &options.watchpoints {
+            debugger.add_watchpoint(watchpoint);
+        }
+        if options.heatmap.is_some() {
+            debugger.enable_heatmap(config.memory_size);
+        }
+        debugger.set_symbols(options.symbols.clone());
+
+        let effect_name = options.effect.as_deref().unwrap_or("static");
+        let effect = effects::from_name(effect_name, starting_color, options.cycle, options.pulse).unwrap();
+
+        let window_title = match &options.title {
+            Some(title) => format!("CHIP-8 RS - {}", title),
+            None => String::from("CHIP-8 RS"),
+        };
+
+        let window_size = rotated_size([window_width, window_height], options.rotation);
+
         App {
-            display: Arc::new(Mutex::new(LedsDisplay::new(
-                DISPLAY_WIDTH,
-                DISPLAY_HEIGHT,
-                false,
-            ))),
-            keypad: Arc::new(Mutex::new(KeyboardKeypad::new(KEYPAD_SIZE))),
-            window: WindowSettings::new("CHIP-8 RS", [WINDOW_WIDTH as u32, WINDOW_HEIGHT as u32])
+            machines: vec![machine],
+            window: WindowSettings::new(window_title, [window_size[0] as u32, window_size[1] as u32])
                 .opengl(opengl)
                 .exit_on_esc(true)
                 .build()
                 .unwrap(),
             gl: GlGraphics::new(opengl),
-            color: starting_color,
-            background: [1.0, 1.0, 1.0, 1.0],
-            nyan_mode: nyan_mode,
+            foreground: starting_color,
+            background,
+            effect,
+            cycle: options.cycle,
+            crt: options.crt,
+            speed: settings.speed.unwrap_or(DEFAULT_SPEED),
+            speed_control: Arc::new(AtomicU32::new(settings.speed.unwrap_or(DEFAULT_SPEED))),
+            fast_forward: false,
+            slow_motion: false,
+            log_level: options.log_level,
+            full_redraw_frames: FULL_REDRAW_FRAMES,
+            debugger: Arc::new(Mutex::new(debugger)),
+            memory_viewer: MemoryViewer::new(),
+            memory_viewer_dirty: false,
+            pauses_shown: 0,
+            console: options.console,
+            serve: options.serve,
+            netplay: options.netplay.clone(),
+            script: options.script.clone(),
+            exit_code: options.exit_code,
+            dump_display: options.dump_display.clone(),
+            trace: options.trace.clone(),
+            options: options.clone(),
+            profiler: None,
+            coverage: options
+                .coverage
+                .as_ref()
+                .map(|_| Arc::new(Mutex::new(Coverage::new(config.memory_size)))),
+            virtual_keypad,
+            virtual_key_pressed: None,
+            cursor: [0.0, 0.0],
+            fonts: Fonts::new(),
+            keypad_overlay: VirtualKeypad::new(
+                window_width - KEYPAD_OVERLAY_SIZE - KEYPAD_OVERLAY_MARGIN,
+                KEYPAD_OVERLAY_MARGIN,
+                KEYPAD_OVERLAY_SIZE,
+                KEYPAD_OVERLAY_SIZE,
+            ),
+            keypad_overlay_visible: false,
+            hud_visible: false,
+            code_visible: false,
+            beep_shown: false,
+            metrics: Metrics::default(),
+            metrics_receiver,
+            metrics_sender,
+            frames: 0,
+            fps_start: Instant::now(),
+            fps: 0,
+            message: None,
+            resume: options.resume,
+            ctrl_pressed: false,
+            alt_pressed: false,
+            shift_pressed: false,
+            content_size: [window_width, window_height],
+            layout: Layout::fit([window_width, window_height], options.rotation, window_size),
         }
     }
 
@@ -79,113 +668,1038 @@ impl App {
         use graphics::*;
         let background = self.background;
 
-        if self.nyan_mode {
-            let mut hsl = rgb_to_hsl(&self.color);
+        let frame = FrameInfo {
+            beep: self.machines.iter().any(|machine| machine.beep.swap(false, Ordering::Relaxed)),
+        };
+        if self.effect.update(&frame) {
+            /* The color of every lit LED changed */
+            self.full_redraw_frames = FULL_REDRAW_FRAMES;
+        }
 
-            if hsl.h >= 360 {
-                hsl.h = 1;
-            } else {
-                hsl.h += 1;
-            }
+        let layout = Layout::fit(self.content_size, self.options.rotation, [args.width as f64, args.height as f64]);
+        if layout != self.layout {
+            self.layout = layout;
+            self.full_redraw_frames = FULL_REDRAW_FRAMES;
+        }
 
-            self.color = hsl_to_rgb(&hsl);
+        let beeps: Vec<bool> = self
+            .machines
+            .iter()
+            .map(|machine| self.options.visual_beep && machine.sound_playing.load(Ordering::Relaxed))
+            .collect();
+        let beep_shown = beeps.contains(&true);
+        if beep_shown || self.beep_shown {
+            self.full_redraw_frames = FULL_REDRAW_FRAMES;
         }
+        self.beep_shown = beep_shown;
 
-        let color = self.color;
-        let display = self.display.clone();
+        /* The overlays are blended over the LEDs, which must be drawn again below them */
+        if self.keypad_overlay_visible
+            || self.hud_visible
+            || self.code_visible
+            || self.message.is_some()
+            || self.crt
+            || self.options.render_style.needs_full_redraw()
+        {
+            self.full_redraw_frames = FULL_REDRAW_FRAMES;
+        }
+
+        let crt = self.crt;
+        let style = self.options.render_style;
+        let full_redraw = self.full_redraw_frames > 0;
+
+        let positions: Vec<Vec<(usize, usize)>> = self
+            .machines
+            .iter_mut()
+            .map(|machine| machine.update_display(full_redraw))
+            .collect();
+
+        let mut leds: Vec<([f64; 4], f32, RGBPixel)> = Vec::new();
+        for (machine, positions) in self.machines.iter().zip(positions) {
+            leds.extend(positions.into_iter().map(|(x, y)| {
+                let [left, top, width, height] = machine.display.led_rect(x, y, LED_HEIGHT);
+                (
+                    [machine.left + left, top, width, height],
+                    machine.display.intensity(x, y),
+                    self.pixel_color(&machine.display, x, y),
+                )
+            }));
+        }
+        let screens: Vec<(f64, [f64; 2])> = self
+            .machines
+            .iter()
+            .map(|machine| (machine.left, machine.display.size(LED_HEIGHT)))
+            .collect();
+
+        if full_redraw {
+            self.full_redraw_frames -= 1;
+        }
 
         self.gl.draw(args.viewport(), |c, gl| {
-            /* Clear the screen. */
-            clear(background, gl);
+            if full_redraw {
+                /* Clear the screen. */
+                clear(background, gl);
+            }
+
+            let transform = layout.transform(c.transform);
 
-            for y in 0..DISPLAY_HEIGHT {
-                for x in 0..DISPLAY_WIDTH {
-                    if display.lock().unwrap().is_on(x, y) {
-                        let square = rectangle::square(
-                            (x as f64) * LED_WIDTH,
-                            (y as f64) * LED_WIDTH,
-                            LED_WIDTH,
-                        );
+            for &(rect, intensity, color) in &leds {
+                style.draw_glow([color.r, color.g, color.b, 1.0], intensity, rect, transform, gl);
+            }
 
-                        /* TODO : empty transformation; is there a way to skip this? */
-                        let transform = c.transform.trans(0.0, 0.0);
-                        rectangle([color.r, color.g, color.b, 1.0], square, transform, gl);
-                    }
+            for (rect, intensity, color) in leds {
+                /* After a clear only the lit LEDs need to be drawn */
+                if full_redraw && intensity == 0.0 && !style.draws_background() {
+                    continue;
                 }
+
+                /* Fading LEDs are blended with the background */
+                let led_color = [
+                    background[0] + (color.r - background[0]) * intensity,
+                    background[1] + (color.g - background[1]) * intensity,
+                    background[2] + (color.b - background[2]) * intensity,
+                    1.0,
+                ];
+
+                style.draw_pixel(led_color, background, rect, transform, gl);
             }
+
+            if crt {
+                for &(left, size) in &screens {
+                    draw_crt_effect(size, transform.trans(left, 0.0), gl);
+                }
+            }
+
+            for (&(left, size), _) in screens.iter().zip(&beeps).filter(|(_, &beep)| beep) {
+                draw_beep_border(size, transform.trans(left, 0.0), gl);
+            }
+        });
+
+        self.render_keypads(args);
+        self.render_hud(args);
+        self.render_code(args);
+        self.render_message(args);
+    }
+
+    /* Map the value of a pixel to its color; turned off pixels which are still fading out keep the
+     * foreground color */
+    fn pixel_color(&self, display: &LedsDisplay, x: usize, y: usize) -> RGBPixel {
+        match display.pixel(x, y) {
+            0 | 1 => self.effect.color(x, y),
+            value => EXTRA_COLORS[(value as usize - 2) % EXTRA_COLORS.len()],
+        }
+    }
+
+    fn render_keypads(&mut self, args: &RenderArgs) {
+        if self.virtual_keypad.is_none() && !self.keypad_overlay_visible {
+            return;
+        }
+
+        let keypad = self.machines[0].keypad.lock().unwrap();
+        let fonts = &self.fonts;
+        let virtual_keypad = &self.virtual_keypad;
+        let keypad_overlay = &self.keypad_overlay;
+        let keypad_overlay_visible = self.keypad_overlay_visible;
+        let layout = self.layout;
+
+        /* The keypads are small, so they're redrawn on every frame */
+        self.gl.draw(args.viewport(), |c, gl| {
+            let transform = layout.transform(c.transform);
+
+            if let Some(virtual_keypad) = virtual_keypad {
+                draw_keypad(virtual_keypad, &keypad, fonts, &VIRTUAL_KEYPAD_STYLE, transform, gl);
+            }
+
+            if keypad_overlay_visible {
+                draw_keypad(keypad_overlay, &keypad, fonts, &KEYPAD_OVERLAY_STYLE, transform, gl);
+            }
+        });
+    }
+
+    /* Switch to the next color effect */
+    fn cycle_effect(&mut self) {
+        let current = effects::NAMES.iter().position(|&name| name == self.effect.name()).unwrap_or(0);
+        let next = effects::NAMES[(current + 1) % effects::NAMES.len()];
+
+        self.effect = effects::from_name(next, self.foreground, self.cycle, self.options.pulse).unwrap();
+        self.full_redraw_frames = FULL_REDRAW_FRAMES;
+    }
+
+    fn update_speed(&mut self) {
+        let mut speed = self.speed;
+        if self.fast_forward {
+            speed *= FAST_FORWARD_FACTOR;
+        }
+        if self.slow_motion {
+            speed /= SLOW_MOTION_FACTOR;
+        }
+
+        self.speed_control.store(speed.max(1), Ordering::Relaxed);
+    }
+
+    /* Tab fast-forwards while held, F3 toggles slow motion */
+    fn handle_speed_key(&mut self, key: Key, pressed: bool) -> bool {
+        match key {
+            Key::Tab => self.fast_forward = pressed,
+            Key::F3 if pressed => {
+                self.slow_motion = !self.slow_motion;
+                let state = if self.slow_motion { "on" } else { "off" };
+                self.show_message(format!("Slow motion {}", state));
+            }
+            _ => return false,
+        }
+
+        self.update_speed();
+        true
+    }
+
+    fn render_hud(&mut self, args: &RenderArgs) {
+        self.frames += 1;
+        let elapsed = self.fps_start.elapsed().as_secs_f32();
+        if elapsed >= 1.0 {
+            self.fps = ((self.frames as f32) / elapsed).round() as u32;
+            self.frames = 0;
+            self.fps_start = Instant::now();
+        }
+
+        while let Ok(metrics) = self.metrics_receiver.try_recv() {
+            self.metrics = metrics;
+        }
+
+        if !self.hud_visible {
+            return;
+        }
+
+        let lines = [
+            format!("FPS {}", self.fps),
+            format!("IPS {}", self.metrics.instructions_per_second),
+            format!("DT {} ST {}", self.metrics.delay_timer, self.metrics.sound_timer),
+        ];
+        let layout = self.layout;
+
+        self.gl.draw(args.viewport(), |c, gl| {
+            let transform = layout.transform(c.transform);
+
+            draw_text_box(&lines, [0.0, 0.0], transform, gl);
+        });
+    }
+
+    /* Instructions around the PC of the first machine, following it while running and stepping */
+    fn render_code(&mut self, args: &RenderArgs) {
+        use graphics::*;
+
+        if !self.code_visible {
+            return;
+        }
+
+        let code = match self.debugger.lock().unwrap().code() {
+            Some(code) => code,
+            None => return,
+        };
+
+        let lines = code.lines();
+        let [width, height] = text_box_size(&lines);
+        let [display_width, display_height] = self.machines[0].display.size(LED_HEIGHT);
+        let [left, top] = [display_width - width, display_height - height];
+        let highlight_top = top + HUD_MARGIN + (code.current_line() as f64) * HUD_LINE_HEIGHT - HUD_PIXEL;
+        let layout = self.layout;
+
+        self.gl.draw(args.viewport(), |c, gl| {
+            let transform = layout.transform(c.transform);
+
+            draw_text_box(&lines, [left, top], transform, gl);
+            rectangle(CODE_HIGHLIGHT, [left, highlight_top, width, HUD_LINE_HEIGHT], transform, gl);
+        });
+    }
+
+    /* Show a short message in the bottom left corner of the display for a while */
+    fn show_message(&mut self, message: String) {
+        println!("{}", message);
+        self.message = Some((message, Instant::now()));
+    }
+
+    fn render_message(&mut self, args: &RenderArgs) {
+        let message = match &self.message {
+            Some((message, shown)) if shown.elapsed().as_secs_f32() < MESSAGE_SECONDS => message.clone(),
+            Some(_) => {
+                /* Erase the expired message */
+                self.message = None;
+                self.full_redraw_frames = FULL_REDRAW_FRAMES;
+                return;
+            }
+            None => return,
+        };
+
+        let lines = [message];
+        let top = (self.machines[0].display.height() as f64) * LED_HEIGHT - text_box_size(&lines)[1];
+        let layout = self.layout;
+
+        self.gl.draw(args.viewport(), |c, gl| {
+            let transform = layout.transform(c.transform);
+
+            draw_text_box(&lines, [0.0, top], transform, gl);
         });
     }
 
-    pub fn run(&mut self, rom_path: String) {
-        /* Set a hook on panic so that panics on the CHIP-8 thread cause the program to exit */
+    /* Ctrl + Shift + number resizes the window so that each pixel is that many screen pixels tall */
+    fn handle_scale_key(&mut self, key: Key) -> bool {
+        if !self.ctrl_pressed || !self.shift_pressed {
+            return false;
+        }
+
+        match digit(key) {
+            Some(scale) if (1..=MAX_WINDOW_SCALE).contains(&scale) => {
+                self.resize_window(scale as f64);
+                self.show_message(format!("Scale {}x", scale));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /* Fit the window to the contents, with LEDs of the given height in screen pixels; the wider pixels
+     * of --pixel-aspect keep their ratio */
+    fn resize_window(&mut self, led_height: f64) {
+        let [width, height] = rotated_size(self.content_size, self.options.rotation);
+        let scale = led_height / LED_HEIGHT;
+        self.window.window.set_inner_size((width * scale).round() as u32, (height * scale).round() as u32);
+        self.full_redraw_frames = FULL_REDRAW_FRAMES;
+    }
+
+    /* Ctrl + number saves the state to a slot, Alt + number loads it */
+    fn handle_savestate_key(&mut self, key: Key) -> bool {
+        if !self.ctrl_pressed && !self.alt_pressed {
+            return false;
+        }
+
+        let slot = match digit(key) {
+            Some(slot) => slot,
+            None => return false,
+        };
+
+        if self.ctrl_pressed {
+            self.save_state(slot);
+        } else {
+            self.load_state(slot);
+        }
+
+        true
+    }
+
+    /* Ask the CHIP-8 thread for its state and write it to the given file */
+    fn write_state(&self, path: &Path) -> Result<(), String> {
+        let commands = self.machines[0].commands.as_ref().ok_or("the emulator is not running")?;
+
+        let (sender, receiver) = mpsc::channel();
+        commands.send(Command::Save(sender)).map_err(|error| error.to_string())?;
+
+        let snapshot = receiver.recv().map_err(|error| error.to_string())?;
+        savestate::save(path, &snapshot, &self.machines[0].rom_hash).map_err(|error| error.to_string())
+    }
+
+    fn save_state(&mut self, slot: usize) {
+        match self.write_state(&savestate::slot_path(&self.machines[0].rom_hash, slot)) {
+            Ok(()) => self.show_message(format!("Saved slot {}", slot)),
+            Err(error) => self.show_message(format!("Save failed: {}", error)),
+        }
+    }
+
+    fn load_state(&mut self, slot: usize) {
+        let commands = match &self.machines[0].commands {
+            Some(commands) => commands,
+            None => return,
+        };
+
+        let rom_hash = &self.machines[0].rom_hash;
+        match savestate::load(&savestate::slot_path(rom_hash, slot), Some(rom_hash)) {
+            Ok(snapshot) => {
+                let _ = commands.send(Command::Restore(snapshot));
+                self.show_message(format!("Loaded slot {}", slot));
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                self.show_message(format!("Slot {} is empty", slot))
+            }
+            Err(error) => self.show_message(format!("Load failed: {}", error)),
+        }
+    }
+
+    /* Save the state for --resume and wait for the CHIP-8 threads to stop */
+    fn shutdown(&mut self) {
+        /* A program which exited by itself has nothing to resume */
+        if !self.machines[0].exited.load(Ordering::Relaxed) {
+            if let Err(error) = self.write_state(&savestate::autosave_path(&self.machines[0].rom_hash)) {
+                eprintln!("Could not save the state: {}", error);
+            }
+        }
+
+        for machine in &mut self.machines {
+            if let Some(commands) = machine.commands.take() {
+                let _ = commands.send(Command::Quit);
+            }
+
+            if let Some(chip_thread) = machine.chip_thread.take() {
+                let _ = chip_thread.join();
+            }
+        }
+
+        if let Some(path) = &self.dump_display {
+            let machine = &mut self.machines[0];
+
+            /* The CHIP-8 thread has stopped, so the framebuffer is complete once the pending commands
+             * are applied */
+            while let Ok(command) = machine.draw_commands.try_recv() {
+                command.apply(&mut machine.display);
+            }
+
+            if let Err(error) = dump::write(path, &machine.display.snapshot()) {
+                eprintln!("Could not write the display to {}: {}", path.display(), error);
+            }
+        }
+    }
+
+    fn toggle_frame_blending(&mut self) {
+        let enabled = !self.machines[0].display.frame_blending();
+        for machine in &mut self.machines {
+            machine.display.set_frame_blending(enabled);
+        }
+
+        self.show_message(format!("Frame blending {}", if enabled { "on" } else { "off" }));
+    }
+
+    fn toggle_hud(&mut self) {
+        self.hud_visible = !self.hud_visible;
+
+        /* Erase the HUD when it gets hidden */
+        self.full_redraw_frames = FULL_REDRAW_FRAMES;
+    }
+
+    fn toggle_code(&mut self) {
+        self.code_visible = !self.code_visible;
+        self.debugger.lock().unwrap().set_code_view(self.code_visible);
+
+        /* Erase the view when it gets hidden */
+        self.full_redraw_frames = FULL_REDRAW_FRAMES;
+    }
+
+    fn toggle_keypad_overlay(&mut self) {
+        self.keypad_overlay_visible = !self.keypad_overlay_visible;
+
+        /* Erase the overlay when it gets hidden */
+        self.full_redraw_frames = FULL_REDRAW_FRAMES;
+    }
+
+    fn handle_mouse_press(&mut self) {
+        if let Some(virtual_keypad) = &self.virtual_keypad {
+            let [x, y] = self.layout.to_content(self.cursor);
+            if let Some(code) = virtual_keypad.key_at(x, y) {
+                self.machines[0].keypad.lock().unwrap().set_is_pressed(code, true);
+                self.virtual_key_pressed = Some(code);
+            }
+        }
+    }
+
+    fn handle_mouse_release(&mut self) {
+        if let Some(code) = self.virtual_key_pressed.take() {
+            self.machines[0].keypad.lock().unwrap().set_is_pressed(code, false);
+        }
+    }
+
+    /* Profile the executed instructions; the report is printed when the application exits or when F6 is pressed */
+    pub fn enable_profiler(&mut self) {
+        self.profiler = Some(Arc::new(Mutex::new(Profiler::new())));
+    }
+
+    /* Written once the CHIP-8 thread has stopped, for the ROM the program was started with */
+    fn write_coverage(&self, rom: &[u8]) {
+        if let (Some(path), Some(coverage)) = (&self.options.coverage, &self.coverage) {
+            let start = self.machines[0].config.rom_start;
+            if let Err(error) = coverage::write(path, &coverage.lock().unwrap(), rom, start) {
+                eprintln!("Could not write the coverage to {}: {}", path.display(), error);
+            }
+        }
+    }
+
+    fn write_heatmap(&self) {
+        if let (Some(path), Some(heatmap)) = (&self.options.heatmap, self.debugger.lock().unwrap().heatmap()) {
+            if let Err(error) = heatmap::write(path, heatmap) {
+                eprintln!("Could not write the heatmap to {}: {}", path.display(), error);
+            }
+        }
+    }
+
+    fn print_profile(&self) {
+        if let Some(profiler) = &self.profiler {
+            print!("{}", profiler.lock().unwrap().report());
+        }
+    }
+
+    /* Print the display on the standard output, e.g. to paste it in a bug report */
+    fn print_display(&self) {
+        for machine in &self.machines {
+            print!("{}", dump::to_text(&machine.display.snapshot()));
+        }
+    }
+
+    fn toggle_pause(&mut self) {
+        let mut debugger = self.debugger.lock().unwrap();
+        let paused = !debugger.is_paused();
+        debugger.set_paused(paused);
+
+        self.memory_viewer_dirty = paused;
+    }
+
+    fn step(&mut self, mode: StepMode) {
+        self.debugger.lock().unwrap().step(mode, 1);
+    }
+
+    fn update_memory_viewer(&mut self) {
+        let debugger = self.debugger.lock().unwrap();
+
+        /* The CHIP-8 thread also pauses on its own, when a step completes or a breakpoint or watchpoint
+         * is hit */
+        if debugger.is_paused() && debugger.pauses() != self.pauses_shown {
+            self.memory_viewer_dirty = true;
+        }
+
+        if !self.memory_viewer_dirty {
+            return;
+        }
+
+        if let Some(snapshot) = debugger.snapshot() {
+            /* The console shares the terminal, so it's not cleared to show the memory */
+            if self.console {
+                match debugger.symbols().locate(snapshot.pc) {
+                    Some(location) => println!("Paused at {:03X} ({})", snapshot.pc, location),
+                    None => println!("Paused at {:03X}", snapshot.pc),
+                }
+            } else {
+                self.memory_viewer.show_address(snapshot.pc as usize);
+                self.memory_viewer.print(snapshot);
+            }
+            if let Some(hit) = debugger.watch_hit() {
+                println!("Stopped by watchpoint: {}", hit);
+            }
+            self.memory_viewer_dirty = false;
+            self.pauses_shown = debugger.pauses();
+        }
+    }
+
+    fn scroll_memory_viewer(&mut self, pages: isize) {
+        let debugger = self.debugger.lock().unwrap();
+        if let Some(snapshot) = debugger.snapshot() {
+            self.memory_viewer.scroll(pages, snapshot.memory.len());
+            self.memory_viewer.print(snapshot);
+        }
+    }
+
+    fn update_modifiers(&mut self, key: Key, pressed: bool) {
+        match key {
+            Key::LCtrl | Key::RCtrl => self.ctrl_pressed = pressed,
+            Key::LAlt | Key::RAlt => self.alt_pressed = pressed,
+            Key::LShift | Key::RShift => self.shift_pressed = pressed,
+            _ => (),
+        }
+    }
+
+    fn handle_debugger_key(&mut self, key: Key) -> bool {
+        let paused = self.debugger.lock().unwrap().is_paused();
+
+        match key {
+            Key::F1 => self.toggle_code(),
+            Key::F2 => self.print_display(),
+            Key::F5 => self.toggle_pause(),
+            Key::F6 => self.print_profile(),
+            Key::F7 => self.toggle_keypad_overlay(),
+            Key::F8 => self.cycle_effect(),
+            Key::F9 => self.toggle_hud(),
+            Key::B if self.ctrl_pressed => self.toggle_frame_blending(),
+            Key::PageUp if paused => self.scroll_memory_viewer(-1),
+            Key::PageDown if paused => self.scroll_memory_viewer(1),
+            Key::F4 if paused => {
+                self.debugger.lock().unwrap().step_frame();
+            }
+            Key::F10 if paused => self.step(StepMode::Over),
+            Key::F11 if paused => self.step(StepMode::Into),
+            Key::F12 if paused => self.step(StepMode::Out),
+            Key::Backspace if paused => {
+                self.debugger.lock().unwrap().step_back(1);
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    /* Add a machine on the right of the others, running its own ROM; in split screen the second machine
+     * is played with the keys on the right half of the keyboard */
+    pub fn add_machine(&mut self, config: MachineConfig, settings: &GameSettings, rom: Vec<u8>) {
+        let last = self.machines.last().unwrap();
+        let left = last.left + last.display.size(LED_HEIGHT)[0] + SPLIT_SCREEN_GAP;
+
+        let mut machine = Machine::new(config, settings, &self.options, left);
+        machine.keymap = translate_keymap(SECOND_KEYMAP.iter().cloned(), self.options.keyboard_layout);
+        machine.rom = rom;
+
+        let [width, height] = machine.display.size(LED_HEIGHT);
+        self.content_size = [left + width, self.content_size[1].max(height)];
+        self.resize_window(LED_HEIGHT);
+
+        self.machines.push(machine);
+    }
+
+    /* Forward a key to the machines it's mapped to */
+    fn set_key_pressed(&mut self, key: Key, pressed: bool) {
+        for machine in &mut self.machines {
+            if let Some(&code) = machine.keymap.get(&key) {
+                if machine.turbo.is_turbo(code) {
+                    machine.turbo.set_held(code, pressed, Instant::now());
+                } else {
+                    machine.keypad.lock().unwrap().set_is_pressed(code, pressed);
+                }
+            }
+            if let (true, Some(steps)) = (pressed, machine.macros.get(&key)) {
+                machine.macro_player.start(steps, Instant::now());
+            }
+        }
+    }
+
+    /* Press and release the keys of the macros being played and the turbo keys held */
+    fn play_macros(&mut self) {
+        let now = Instant::now();
+
+        for machine in &mut self.machines {
+            let mut changes: Vec<(usize, bool)> =
+                machine.macro_player.update(now).into_iter().map(|(code, pressed)| (code as usize, pressed)).collect();
+            changes.extend(machine.turbo.update(now));
+            if !changes.is_empty() {
+                let mut keypad = machine.keypad.lock().unwrap();
+                for (code, pressed) in changes {
+                    keypad.set_is_pressed(code, pressed);
+                }
+            }
+        }
+    }
+
+    pub fn run(&mut self, rom: Vec<u8>, watch_path: Option<String>) {
+        /* Set a hook on panic so that panics on any thread cause the program to exit; the CHIP-8 threads
+         * exit on their own after writing a crash dump, see run_machine */
         let orig_hook = panic::take_hook();
         panic::set_hook(Box::new(move |panic_info| {
             orig_hook(panic_info);
-            process::exit(1);
+            if thread::current().name() != Some(crash::THREAD_NAME) {
+                process::exit(1);
+            }
         }));
 
         let mut events = Events::new(EventSettings::new());
 
-        let display = self.display.clone();
-        let keypad = self.keypad.clone();
+        let primary = &self.machines[0];
+        let config = primary.config;
+        let draw_sender = primary.draw_sender.clone();
+        let keypad = primary.keypad.clone();
+        let beep = primary.beep.clone();
+        let sound_playing = primary.sound_playing.clone();
+        let exited = primary.exited.clone();
+        let double_buffered = primary.double_buffered;
+        let speed = self.speed;
+        let speed_control = self.speed_control.clone();
+        let log_level = self.log_level;
+        let log_format = self.options.log_format;
+        let history = self.options.history;
+        let seed = self.options.seed;
+        let debugger = self.debugger.clone();
+        let profiler = self.profiler.clone();
+        let coverage = self.coverage.clone();
+        let metrics = self.metrics_sender.clone();
+
+        if self.console {
+            console::spawn(self.debugger.clone());
+        }
+
+        let (commands, command_receiver) = mpsc::channel();
+        let rom_hash = gamedb::rom_hash(&rom);
+
+        if self.resume {
+            /* The restore is handled as soon as the CHIP-8 thread starts */
+            match savestate::load(&savestate::autosave_path(&rom_hash), Some(&rom_hash)) {
+                Ok(snapshot) => {
+                    let _ = commands.send(Command::Restore(snapshot));
+                }
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                    eprintln!("No saved state to resume for this ROM")
+                }
+                Err(error) => eprintln!("Cannot resume the saved state: {}", error),
+            }
+        }
+        if let Some(address) = self.serve {
+            if let Err(error) = server::serve(address, &self.debugger, &commands, &keypad) {
+                eprintln!("Cannot start the WebSocket server on {}: {}", address, error);
+            }
+        }
+        if let Some(mode) = &self.netplay {
+            if let Err(error) = netplay::start(mode, &keypad) {
+                eprintln!("Cannot start netplay: {}", error);
+            }
+        }
+        let script = self.script.as_ref().and_then(|path| {
+            match Script::load(path, &self.debugger, &keypad) {
+                Ok(script) => Some(script),
+                Err(error) => {
+                    eprintln!("Cannot load the script {}: {}", path.display(), error);
+                    None
+                }
+            }
+        });
+
+        /* A missing file just means the program never saved its flags */
+        let rpl_flags_path = savestate::rpl_flags_path(&rom_hash);
+        let rpl_flags = savestate::load_rpl_flags(&rpl_flags_path).unwrap_or_default();
+
+        let tracer = self.trace.as_ref().and_then(|path| match Tracer::create(path) {
+            Ok(mut tracer) => {
+                tracer.set_symbols(self.options.symbols.clone());
+                Some(tracer)
+            }
+            Err(error) => {
+                eprintln!("Cannot write the trace to {}: {}", path.display(), error);
+                None
+            }
+        });
 
-        thread::spawn(move || {
-            let mut chip = Chip8::new(&display, &keypad);
-            chip.run(&rom_path);
+        let audio_recorder = self.options.record_audio.as_ref().and_then(|path| {
+            match AudioRecorder::create(path, Box::new(RodioBackend::new())) {
+                Ok(recorder) => Some(recorder),
+                Err(error) => {
+                    eprintln!("Cannot record the audio to {}: {}", path.display(), error);
+                    None
+                }
+            }
         });
 
+        let rom_format = self.options.rom_format;
+        let reloads = watch_path.map(|path| {
+            let (sender, receiver) = mpsc::channel();
+            rom::watch_rom(path, rom_format, sender);
+            receiver
+        });
+
+        let program = rom.clone();
+        let primary = &mut self.machines[0];
+        primary.commands = Some(commands);
+        primary.rom_hash = rom_hash;
+        primary.chip_thread = Some(spawn_machine_thread(move || {
+            let mut chip = Chip8::new(config, draw_sender, &keypad, speed, log_level);
+            if log_format == LogFormat::Json {
+                chip.set_logger(Box::new(JsonLogger::new(LOG_FILE.to_string(), log_level)));
+            }
+            chip.set_speed_control(&speed_control);
+            chip.set_double_buffered(double_buffered);
+            if let Some(receiver) = reloads {
+                chip.set_rom_reloads(receiver);
+            }
+            chip.set_debugger(&debugger);
+            if let Some(seed) = seed {
+                chip.set_rng(Box::new(SeededRngSource::new(seed)));
+            }
+            if history > 0 {
+                chip.enable_history(history);
+            }
+            chip.set_metrics(metrics);
+            chip.set_commands(command_receiver);
+            chip.set_rpl_flags(rpl_flags);
+            chip.on_rpl_flags(move |flags| {
+                if let Err(error) = savestate::save_rpl_flags(&rpl_flags_path, flags) {
+                    eprintln!("Cannot save the RPL flags: {}", error);
+                }
+            });
+            chip.on_sound(move |value| {
+                if value > 0 {
+                    beep.store(true, Ordering::Relaxed);
+                }
+            });
+            chip.set_sound_flag(&sound_playing);
+            if let Some(profiler) = profiler {
+                chip.set_profiler(&profiler);
+            }
+            if let Some(tracer) = tracer {
+                chip.set_tracer(tracer);
+            }
+            if let Some(recorder) = audio_recorder {
+                chip.set_sound_backend(Box::new(recorder));
+            }
+            if let Some(coverage) = coverage {
+                chip.set_coverage(&coverage);
+            }
+            if let Some(mut script) = script {
+                chip.on_hook(move |event, snapshot| script.handle(event, snapshot));
+            }
+            run_machine(&mut chip, &rom);
+            if chip.has_exited() {
+                exited.store(true, Ordering::Relaxed);
+            }
+        }));
+
+        for machine in self.machines.iter_mut().skip(1) {
+            machine.spawn(speed, &self.speed_control);
+        }
+
         while let Some(e) = events.next(&mut self.window) {
+            if self.machines[0].exited.load(Ordering::Relaxed) {
+                self.window.set_should_close(true);
+            }
+
             if let Some(r) = e.render_args() {
+                self.play_macros();
                 self.render(&r);
+                self.update_memory_viewer();
+            }
+
+            if let Some(cursor) = e.mouse_cursor_args() {
+                self.cursor = cursor;
+            }
+
+            if let Some(Button::Mouse(MouseButton::Left)) = e.press_args() {
+                self.handle_mouse_press();
+            }
+
+            if let Some(Button::Mouse(MouseButton::Left)) = e.release_args() {
+                self.handle_mouse_release();
             }
 
             if let Some(Button::Keyboard(key)) = e.press_args() {
-                match key {
-                    /* TODO : add ASCII art for keypad */
-                    /* TODO : move this logic in the keypad struct */
-                    Key::D1 => self.keypad.lock().unwrap().set_is_pressed(0x01, true),
-                    Key::D2 => self.keypad.lock().unwrap().set_is_pressed(0x02, true),
-                    Key::D3 => self.keypad.lock().unwrap().set_is_pressed(0x03, true),
-                    Key::D4 => self.keypad.lock().unwrap().set_is_pressed(0x0C, true),
-                    Key::Q => self.keypad.lock().unwrap().set_is_pressed(0x04, true),
-                    Key::W => self.keypad.lock().unwrap().set_is_pressed(0x05, true),
-                    Key::E => self.keypad.lock().unwrap().set_is_pressed(0x06, true),
-                    Key::R => self.keypad.lock().unwrap().set_is_pressed(0x0D, true),
-                    Key::A => self.keypad.lock().unwrap().set_is_pressed(0x07, true),
-                    Key::S => self.keypad.lock().unwrap().set_is_pressed(0x08, true),
-                    Key::D => self.keypad.lock().unwrap().set_is_pressed(0x09, true),
-                    Key::F => self.keypad.lock().unwrap().set_is_pressed(0x0E, true),
-                    Key::Z => self.keypad.lock().unwrap().set_is_pressed(0x0A, true),
-                    Key::X => self.keypad.lock().unwrap().set_is_pressed(0x00, true),
-                    Key::C => self.keypad.lock().unwrap().set_is_pressed(0x0B, true),
-                    Key::V => self.keypad.lock().unwrap().set_is_pressed(0x0F, true),
-                    _ => {}
+                self.update_modifiers(key, true);
+
+                if self.handle_debugger_key(key)
+                    || self.handle_scale_key(key)
+                    || self.handle_savestate_key(key)
+                    || self.handle_speed_key(key, true)
+                {
+                    continue;
                 }
+
+                self.set_key_pressed(key, true);
             }
 
             if let Some(Button::Keyboard(key)) = e.release_args() {
-                match key {
-                    /* TODO : add ASCII art for keypad */
-                    /* TODO : move this logic in the keypad struct */
-                    Key::D1 => self.keypad.lock().unwrap().set_is_pressed(0x01, false),
-                    Key::D2 => self.keypad.lock().unwrap().set_is_pressed(0x02, false),
-                    Key::D3 => self.keypad.lock().unwrap().set_is_pressed(0x03, false),
-                    Key::D4 => self.keypad.lock().unwrap().set_is_pressed(0x0C, false),
-                    Key::Q => self.keypad.lock().unwrap().set_is_pressed(0x04, false),
-                    Key::W => self.keypad.lock().unwrap().set_is_pressed(0x05, false),
-                    Key::E => self.keypad.lock().unwrap().set_is_pressed(0x06, false),
-                    Key::R => self.keypad.lock().unwrap().set_is_pressed(0x0D, false),
-                    Key::A => self.keypad.lock().unwrap().set_is_pressed(0x07, false),
-                    Key::S => self.keypad.lock().unwrap().set_is_pressed(0x08, false),
-                    Key::D => self.keypad.lock().unwrap().set_is_pressed(0x09, false),
-                    Key::F => self.keypad.lock().unwrap().set_is_pressed(0x0E, false),
-                    Key::Z => self.keypad.lock().unwrap().set_is_pressed(0x0A, false),
-                    Key::X => self.keypad.lock().unwrap().set_is_pressed(0x00, false),
-                    Key::C => self.keypad.lock().unwrap().set_is_pressed(0x0B, false),
-                    Key::V => self.keypad.lock().unwrap().set_is_pressed(0x0F, false),
-                    _ => {}
+                self.update_modifiers(key, false);
+                self.handle_speed_key(key, false);
+
+                self.set_key_pressed(key, false);
+            }
+        }
+
+        self.shutdown();
+        self.print_profile();
+        self.write_coverage(&program);
+        self.write_heatmap();
+
+        if self.machines[0].exited.load(Ordering::Relaxed) {
+            process::exit(self.exit_code);
+        }
+    }
+}
+
+/* Size of a box containing the given lines of text */
+fn text_box_size(lines: &[String]) -> [f64; 2] {
+    let columns = lines.iter().map(|line| line.len()).max().unwrap_or(0);
+
+    [
+        ((columns * (crate::text::GLYPH_WIDTH + 1)) as f64) * HUD_PIXEL + 2.0 * HUD_MARGIN,
+        (lines.len() as f64) * HUD_LINE_HEIGHT + 2.0 * HUD_MARGIN,
+    ]
+}
+
+/* Draw lines of text on a translucent box with its top left corner at the given position */
+fn draw_text_box<G: graphics::Graphics>(
+    lines: &[String],
+    position: [f64; 2],
+    transform: graphics::math::Matrix2d,
+    gl: &mut G,
+) {
+    use graphics::*;
+
+    let [width, height] = text_box_size(lines);
+    let [left, top] = position;
+
+    rectangle(HUD_BACKGROUND, [left, top, width, height], transform, gl);
+
+    for (row, line) in lines.iter().enumerate() {
+        let line_top = top + HUD_MARGIN + (row as f64) * HUD_LINE_HEIGHT;
+        for (x, y) in crate::text::pixels(line) {
+            let pixel = rectangle::square(
+                left + HUD_MARGIN + (x as f64) * HUD_PIXEL,
+                line_top + (y as f64) * HUD_PIXEL,
+                HUD_PIXEL,
+            );
+            rectangle(HUD_COLOR, pixel, transform, gl);
+        }
+    }
+}
+
+fn draw_beep_border<G: graphics::Graphics>(
+    size: [f64; 2],
+    transform: graphics::math::Matrix2d,
+    gl: &mut G,
+) {
+    use graphics::*;
+
+    let [width, height] = size;
+
+    rectangle(BEEP_BORDER_COLOR, [0.0, 0.0, width, BEEP_BORDER_WIDTH], transform, gl);
+    rectangle(BEEP_BORDER_COLOR, [0.0, height - BEEP_BORDER_WIDTH, width, BEEP_BORDER_WIDTH], transform, gl);
+    rectangle(BEEP_BORDER_COLOR, [0.0, 0.0, BEEP_BORDER_WIDTH, height], transform, gl);
+    rectangle(BEEP_BORDER_COLOR, [width - BEEP_BORDER_WIDTH, 0.0, BEEP_BORDER_WIDTH, height], transform, gl);
+}
+
+fn draw_crt_effect<G: graphics::Graphics>(
+    size: [f64; 2],
+    transform: graphics::math::Matrix2d,
+    gl: &mut G,
+) {
+    use graphics::*;
+
+    let [width, height] = size;
+
+    let scanline_height = LED_HEIGHT * CRT_SCANLINE_HEIGHT;
+    let mut y = LED_HEIGHT - scanline_height;
+    while y < height {
+        rectangle(CRT_SCANLINE_COLOR, [0.0, y, width, scanline_height], transform, gl);
+        y += LED_HEIGHT;
+    }
+
+    /* Frames overlapping towards the borders, so that the outer ones are the darkest; each one
+     * extends from its inset up to CRT_VIGNETTE_WIDTH from the border */
+    let color = [0.0, 0.0, 0.0, CRT_VIGNETTE_ALPHA];
+    let step = CRT_VIGNETTE_WIDTH / (CRT_VIGNETTE_STEPS as f64);
+    let inner_height = height - 2.0 * CRT_VIGNETTE_WIDTH;
+    for i in 0..CRT_VIGNETTE_STEPS {
+        let inset = (i as f64) * step;
+        let band = CRT_VIGNETTE_WIDTH - inset;
+
+        rectangle(color, [inset, inset, width - 2.0 * inset, band], transform, gl);
+        rectangle(color, [inset, height - CRT_VIGNETTE_WIDTH, width - 2.0 * inset, band], transform, gl);
+        rectangle(color, [inset, CRT_VIGNETTE_WIDTH, band, inner_height], transform, gl);
+        rectangle(color, [width - CRT_VIGNETTE_WIDTH, CRT_VIGNETTE_WIDTH, band, inner_height], transform, gl);
+    }
+
+    /* Mask the corners outside a quarter of circle, one row at a time */
+    let black = [0.0, 0.0, 0.0, 1.0];
+    for row in 0..(CRT_CORNER_RADIUS as usize) {
+        let dy = CRT_CORNER_RADIUS - (row as f64) - 0.5;
+        let mask = CRT_CORNER_RADIUS - (CRT_CORNER_RADIUS * CRT_CORNER_RADIUS - dy * dy).sqrt();
+        let top = row as f64;
+        let bottom = height - (row as f64) - 1.0;
+
+        rectangle(black, [0.0, top, mask, 1.0], transform, gl);
+        rectangle(black, [width - mask, top, mask, 1.0], transform, gl);
+        rectangle(black, [0.0, bottom, mask, 1.0], transform, gl);
+        rectangle(black, [width - mask, bottom, mask, 1.0], transform, gl);
+    }
+}
+
+/* Draw the keys of a keypad, highlighting the pressed ones and labeling them with the glyphs of the
+ * built-in font */
+fn draw_keypad<G: graphics::Graphics>(
+    layout: &VirtualKeypad,
+    keypad: &KeyboardKeypad,
+    fonts: &Fonts,
+    style: &KeypadStyle,
+    transform: graphics::math::Matrix2d,
+    gl: &mut G,
+) {
+    use graphics::*;
+
+    for (code, [x, y, width, height]) in layout.keys() {
+        let color = if keypad.get_is_pressed(code) {
+            style.pressed
+        } else {
+            style.key
+        };
+        let key = [
+            x + style.margin,
+            y + style.margin,
+            width - 2.0 * style.margin,
+            height - 2.0 * style.margin,
+        ];
+        rectangle(color, key, transform, gl);
+
+        /* Glyphs are 4x5 pixels, stored in the high nibbles */
+        let pixel_size = (height / 10.0).floor().max(1.0);
+        let label_x = x + (width - 4.0 * pixel_size) / 2.0;
+        let label_y = y + (height - 5.0 * pixel_size) / 2.0;
+        for (row, bits) in fonts.fonts[code].iter().enumerate() {
+            for column in 0..4 {
+                if bits & (0x80 >> column) != 0 {
+                    let pixel = rectangle::square(
+                        label_x + (column as f64) * pixel_size,
+                        label_y + (row as f64) * pixel_size,
+                        pixel_size,
+                    );
+                    rectangle(style.label, pixel, transform, gl);
                 }
             }
         }
     }
 }
+
+fn digit(key: Key) -> Option<usize> {
+    const DIGITS: [Key; savestate::SLOTS] = [
+        Key::D0,
+        Key::D1,
+        Key::D2,
+        Key::D3,
+        Key::D4,
+        Key::D5,
+        Key::D6,
+        Key::D7,
+        Key::D8,
+        Key::D9,
+    ];
+
+    DIGITS.iter().position(|&digit| digit == key)
+}
+
+/* Move the keys of a keymap, given by their position on a QWERTY keyboard, to the same positions on
+ * the host layout; the keys the window can't report on that layout are dropped */
+fn translate_keymap<I: Iterator<Item = (Key, usize)>>(keys: I, layout: KeyboardLayout) -> HashMap<Key, usize> {
+    keys.filter_map(|(key, code)| layout.translate(key).map(|key| (key, code))).collect()
+}
+
+/* Macros of the game, bound to keys named like in its keymap; the steps with an invalid CHIP-8 key are
+ * dropped */
+fn build_macros(settings: &GameSettings) -> HashMap<Key, Vec<MacroStep>> {
+    let mut macros = HashMap::new();
+
+    for (name, steps) in settings.macros.iter().flatten() {
+        let mut chars = name.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            if c.is_ascii_alphanumeric() {
+                let steps = steps.iter().filter(|step| (step.key as usize) < KEYPAD_SIZE).cloned().collect();
+                macros.insert(Key::from(c.to_ascii_lowercase() as u32), steps);
+            }
+        }
+    }
+
+    macros
+}
+
+fn build_keymap(settings: &GameSettings, layout: KeyboardLayout) -> HashMap<Key, usize> {
+    let mut keymap = DEFAULT_KEYMAP.to_vec();
+
+    if let Some(game_keymap) = &settings.keymap {
+        /* The game keymap replaces the default one; keys are named after single letters or digits,
+         * whose piston codes match their lowercase ASCII values */
+        keymap.clear();
+
+        for (name, code) in game_keymap {
+            let mut chars = name.chars();
+            if let (Some(c), None) = (chars.next(), chars.next()) {
+                if c.is_ascii_alphanumeric() && (*code as usize) < KEYPAD_SIZE {
+                    keymap.push((Key::from(c.to_ascii_lowercase() as u32), *code as usize));
+                }
+            }
+        }
+    }
+
+    translate_keymap(keymap.into_iter(), layout)
+}
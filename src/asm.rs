@@ -0,0 +1,262 @@
+use crate::instruction::Instruction;
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+
+/* Error in the source, with the number of the line starting from 1 */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl error::Error for AsmError {}
+
+/* Line of the source without the comment, split in its parts */
+struct Statement<'a> {
+    line: usize,
+    mnemonic: String,
+    operands: Vec<&'a str>,
+}
+
+impl<'a> Statement<'a> {
+    /* Number of bytes assembled from the statement */
+    fn size(&self) -> usize {
+        if self.mnemonic == "DB" {
+            self.operands.len()
+        } else {
+            2
+        }
+    }
+}
+
+/* Parse "V0" to "VF" */
+fn register(operand: &str) -> Option<u8> {
+    let digit = operand.strip_prefix('V').or_else(|| operand.strip_prefix('v'))?;
+    if digit.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(digit, 16).ok()
+}
+
+/* Parse a number, in hexadecimal with the "0x" prefix or in decimal, or the address of a label */
+fn number(operand: &str, labels: &HashMap<String, u16>, max: u16) -> Result<u16, String> {
+    let value = if let Some(hex) = operand.strip_prefix("0x").or_else(|| operand.strip_prefix("0X")) {
+        u16::from_str_radix(hex, 16).ok()
+    } else if operand.starts_with(|c: char| c.is_ascii_digit()) {
+        operand.parse().ok()
+    } else {
+        labels.get(operand).copied()
+    };
+
+    match value {
+        Some(value) if value <= max => Ok(value),
+        Some(value) => Err(format!("Value out of range: {} (at most 0x{:X})", value, max)),
+        None => Err(format!("Invalid number or unknown label: {}", operand)),
+    }
+}
+
+fn parse(statement: &Statement, labels: &HashMap<String, u16>) -> Result<Instruction, String> {
+    let reg = |operand: &str| register(operand).ok_or_else(|| format!("Invalid register: {}", operand));
+    let addr = |operand: &str| number(operand, labels, 0x0FFF);
+    let byte = |operand: &str| number(operand, labels, 0xFF).map(|value| value as u8);
+    let nibble = |operand: &str| number(operand, labels, 0x0F).map(|value| value as u8);
+
+    let operands: Vec<String> = statement.operands.iter().map(|operand| operand.to_uppercase()).collect();
+    let operands: Vec<&str> = operands.iter().map(String::as_str).collect();
+    /* Labels keep their case */
+    let raw = &statement.operands;
+
+    let instr = match (statement.mnemonic.as_str(), operands.as_slice()) {
+        ("CLS", []) => Instruction::ClearScreen,
+        ("RET", []) => Instruction::Return,
+        ("SCD", [_]) => Instruction::ScrollDown(nibble(raw[0])?),
+        ("SCR", []) => Instruction::ScrollRight,
+        ("SCL", []) => Instruction::ScrollLeft,
+        ("EXIT", []) => Instruction::Exit,
+        ("LOW", []) => Instruction::LowResolution,
+        ("HIGH", []) => Instruction::HighResolution,
+        ("JP", ["V0", _]) => Instruction::JumpWithRegister(addr(raw[1])?),
+        ("JP", [_]) => Instruction::Jump(addr(raw[0])?),
+        ("CALL", [_]) => Instruction::Call(addr(raw[0])?),
+        ("SE", [x, y]) if register(y).is_some() => Instruction::SkipIfContentEqual(reg(x)?, reg(y)?),
+        ("SE", [x, _]) => Instruction::SkipIfEqual(reg(x)?, byte(raw[1])?),
+        ("SNE", [x, y]) if register(y).is_some() => Instruction::SkipIfContentDifferent(reg(x)?, reg(y)?),
+        ("SNE", [x, _]) => Instruction::SkipIfDifferent(reg(x)?, byte(raw[1])?),
+        ("LD", ["I", _]) => Instruction::SetIndex(addr(raw[1])?),
+        ("LD", ["DT", x]) => Instruction::SetDelayTimer(reg(x)?),
+        ("LD", ["ST", x]) => Instruction::SetSoundTimer(reg(x)?),
+        ("LD", ["F", x]) => Instruction::SetIndexToFont(reg(x)?),
+        ("LD", ["HF", x]) => Instruction::SetIndexToBigFont(reg(x)?),
+        ("LD", ["B", x]) => Instruction::BinaryConversion(reg(x)?),
+        ("LD", ["[I]", x]) => Instruction::Store(reg(x)?),
+        ("LD", ["R", x]) => Instruction::SaveFlags(reg(x)?),
+        ("LD", [x, "DT"]) => Instruction::CopyDelayTimer(reg(x)?),
+        ("LD", [x, "K"]) => Instruction::WaitForKey(reg(x)?),
+        ("LD", [x, "[I]"]) => Instruction::Load(reg(x)?),
+        ("LD", [x, "R"]) => Instruction::LoadFlags(reg(x)?),
+        ("LD", [x, y]) if register(y).is_some() => Instruction::LogicalCopy(reg(x)?, reg(y)?),
+        ("LD", [x, _]) => Instruction::SetRegister(reg(x)?, byte(raw[1])?),
+        ("ADD", ["I", x]) => Instruction::AddToIndex(reg(x)?),
+        ("ADD", [x, y]) if register(y).is_some() => Instruction::LogicalAdd(reg(x)?, reg(y)?),
+        ("ADD", [x, _]) => Instruction::AddToRegister(reg(x)?, byte(raw[1])?),
+        ("OR", [x, y]) => Instruction::LogicalOr(reg(x)?, reg(y)?),
+        ("AND", [x, y]) => Instruction::LogicalAnd(reg(x)?, reg(y)?),
+        ("XOR", [x, y]) => Instruction::LogicalXor(reg(x)?, reg(y)?),
+        ("SUB", [x, y]) => Instruction::LogicalSubtract(reg(x)?, reg(y)?),
+        ("SUBN", [x, y]) => Instruction::LogicalSubtractInverse(reg(x)?, reg(y)?),
+        /* The shifts can also be written with a single register, shifting it in place */
+        ("SHR", [x, y]) => Instruction::LogicalRightShift(reg(x)?, reg(y)?),
+        ("SHR", [x]) => Instruction::LogicalRightShift(reg(x)?, reg(x)?),
+        ("SHL", [x, y]) => Instruction::LogicalLeftShift(reg(x)?, reg(y)?),
+        ("SHL", [x]) => Instruction::LogicalLeftShift(reg(x)?, reg(x)?),
+        ("RND", [x, _]) => Instruction::Random(reg(x)?, byte(raw[1])?),
+        ("DRW", [x, y, _]) => Instruction::Display(reg(x)?, reg(y)?, nibble(raw[2])?),
+        ("SKP", [x]) => Instruction::SkipIfPressed(reg(x)?),
+        ("SKNP", [x]) => Instruction::SkipIfNotPressed(reg(x)?),
+        _ => {
+            return Err(format!(
+                "Invalid instruction: {} {}",
+                statement.mnemonic,
+                statement.operands.join(", ")
+            ))
+        }
+    };
+
+    Ok(instr)
+}
+
+/* Assemble a program to be loaded at the given address, using the mnemonics printed by the
+ * disassembler. Each line holds an instruction, optionally preceded by a "label:"; "DB" emits the
+ * listed bytes and comments start with ";" */
+pub fn assemble(source: &str, start: u16) -> Result<Vec<u8>, AsmError> {
+    let mut statements = Vec::new();
+    let mut labels = HashMap::new();
+    let mut address = start as usize;
+
+    /* The first pass finds the address of each label, so that they can be used before being defined */
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let mut code = line.split(';').next().unwrap_or("").trim();
+
+        if let Some((label, rest)) = code.split_once(':') {
+            let label = label.trim();
+            if label.is_empty() || !label.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(AsmError {
+                    line: line_number,
+                    message: format!("Invalid label: {}", label),
+                });
+            }
+            if labels.insert(label.to_string(), address as u16).is_some() {
+                return Err(AsmError {
+                    line: line_number,
+                    message: format!("Label defined twice: {}", label),
+                });
+            }
+            code = rest.trim();
+        }
+
+        if code.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, operands) = code.split_once(char::is_whitespace).unwrap_or((code, ""));
+        let statement = Statement {
+            line: line_number,
+            mnemonic: mnemonic.to_uppercase(),
+            operands: operands
+                .split(',')
+                .map(str::trim)
+                .filter(|operand| !operand.is_empty())
+                .collect(),
+        };
+        address += statement.size();
+        statements.push(statement);
+    }
+
+    let mut program = Vec::new();
+    for statement in &statements {
+        let error = |message| AsmError {
+            line: statement.line,
+            message,
+        };
+
+        if statement.mnemonic == "DB" {
+            for operand in &statement.operands {
+                program.push(number(operand, &labels, 0xFF).map_err(error)? as u8);
+            }
+        } else {
+            let instr = parse(statement, &labels).map_err(error)?;
+            program.extend_from_slice(&instr.opcode().to_be_bytes());
+        }
+    }
+
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::{disassemble, disassemble_with_symbols};
+    use crate::symbols::Symbols;
+
+    #[test]
+    fn labels_and_data() {
+        let source = "
+            start:  LD I, sprite   ; a forward reference
+                    DRW V0, V1, 5
+                    JP start
+            sprite: DB 0xF0, 0x90, 144, 0x90, 0xF0
+        ";
+
+        assert_eq!(
+            assemble(source, 0x200),
+            Ok(vec![0xA2, 0x06, 0xD0, 0x15, 0x12, 0x00, 0xF0, 0x90, 0x90, 0x90, 0xF0])
+        );
+    }
+
+    #[test]
+    fn disassembly_round_trip() {
+        let opcodes: Vec<u16> = vec![
+            0x00E0, 0x00EE, 0x00C4, 0x00FB, 0x00FC, 0x00FD, 0x00FE, 0x00FF, 0x1234, 0x2345, 0x3A12, 0x4B34,
+            0x5120, 0x6C56, 0x7D78, 0x8120, 0x8121, 0x8122, 0x8123, 0x8124, 0x8125, 0x8126, 0x8127, 0x812E,
+            0x9120, 0xA456, 0xB567, 0xC3FF, 0xD12F, 0xE19E, 0xE2A1, 0xF307, 0xF40A, 0xF515, 0xF618, 0xF71E,
+            0xF829, 0xF930, 0xFA33, 0xFB55, 0xFC65, 0xF775, 0xF785,
+        ];
+        let rom: Vec<u8> = opcodes.iter().flat_map(|opcode| opcode.to_be_bytes()).collect();
+
+        assert_eq!(assemble(&disassemble(&rom, 0x200), 0x200), Ok(rom));
+    }
+
+    #[test]
+    fn disassembly_with_symbols() {
+        /* main: CALL draw; JP main; draw: LD I, main; RET */
+        let rom = vec![0x22, 0x04, 0x12, 0x00, 0xA2, 0x00, 0x00, 0xEE];
+        let symbols = Symbols::parse("main 0x200\ndraw-sprite 0x204").unwrap();
+
+        let source = disassemble_with_symbols(&rom, 0x200, &symbols);
+        let lines: Vec<&str> = source.lines().map(|line| line.split(';').next().unwrap().trim()).collect();
+        assert_eq!(
+            lines,
+            vec!["main:", "CALL draw_sprite", "JP main", "draw_sprite:", "LD I, main", "RET"]
+        );
+        assert_eq!(assemble(&source, 0x200), Ok(rom));
+    }
+
+    #[test]
+    fn errors() {
+        let error = assemble("CLS\nLD V0, 0x100", 0x200).unwrap_err();
+        assert_eq!(error.line, 2);
+
+        assert!(assemble("JP nowhere", 0x200).is_err());
+        assert!(assemble("DRW V0, VG, 1", 0x200).is_err());
+        assert!(assemble("a: CLS\na: RET", 0x200).is_err());
+    }
+}
@@ -0,0 +1,123 @@
+use crate::chip8::{Chip8, Chip8Error};
+use crate::coverage::Coverage;
+use crate::debugger::Snapshot;
+use crate::platform::MachineConfig;
+use crate::profiler::{LockStats, Profiler};
+use crate::sound::NullBackend;
+use crate::trace::Tracer;
+
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/* Timers are ticked as if the machine ran at the default speed of the frontend, ~700 instructions
+ * per second */
+pub const INSTRUCTIONS_PER_TICK: u64 = 12;
+
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub cycles: u64,
+    pub elapsed: Duration,
+    pub decode: Duration,
+    pub execute: Duration,
+    pub locks: LockStats,
+}
+
+impl BenchReport {
+    /* Millions of instructions executed per second */
+    pub fn mips(&self) -> f64 {
+        (self.cycles as f64) / self.elapsed.as_secs_f64() / 1_000_000.0
+    }
+
+    pub fn summary(&self) -> String {
+        let mut summary = String::new();
+        let percentage = |part: Duration| part.as_secs_f64() * 100.0 / self.elapsed.as_secs_f64();
+
+        let _ = writeln!(summary, "Cycles:   {}", self.cycles);
+        let _ = writeln!(summary, "Elapsed:  {} ms", self.elapsed.as_millis());
+        let _ = writeln!(summary, "MIPS:     {:.3}", self.mips());
+        let _ = writeln!(
+            summary,
+            "Decode:   {} ms ({:.1}%)",
+            self.decode.as_millis(),
+            percentage(self.decode)
+        );
+        let _ = writeln!(
+            summary,
+            "Execute:  {} ms ({:.1}%)",
+            self.execute.as_millis(),
+            percentage(self.execute)
+        );
+        let _ = writeln!(
+            summary,
+            "Locks:    {} acquired, {} contended, {} us waiting",
+            self.locks.acquisitions,
+            self.locks.contentions,
+            self.locks.wait.as_micros()
+        );
+
+        summary
+    }
+}
+
+/* Run the ROM headlessly for the given number of instructions, as fast as possible; the draw
+ * commands are discarded */
+pub fn run(config: MachineConfig, rom: &[u8], cycles: u64) -> Result<BenchReport, Chip8Error> {
+    let profiler = Arc::new(Mutex::new(Profiler::new()));
+
+    let mut chip = Chip8::builder().config(config).sound_backend(NullBackend).build();
+    chip.set_profiler(&profiler);
+    chip.load_program(rom)?;
+
+    let start = Instant::now();
+    for cycle in 0..cycles {
+        chip.step()?;
+
+        if cycle % INSTRUCTIONS_PER_TICK == 0 {
+            chip.tick_timers();
+        }
+    }
+    let elapsed = start.elapsed();
+
+    let profiler = profiler.lock().unwrap();
+    Ok(BenchReport {
+        cycles,
+        elapsed,
+        decode: profiler.decode_time(),
+        execute: profiler.execute_time(),
+        locks: profiler.lock_stats(),
+    })
+}
+
+/* Run the ROM headlessly for the given number of instructions, or until it exits, and return the
+ * final state; the executed instructions are written to the tracer and counted in the coverage, if
+ * any */
+pub fn run_headless(
+    config: MachineConfig,
+    rom: &[u8],
+    cycles: u64,
+    tracer: Option<Tracer>,
+    coverage: Option<&Arc<Mutex<Coverage>>>,
+) -> Result<Snapshot, Chip8Error> {
+    let mut chip = Chip8::builder().config(config).sound_backend(NullBackend).build();
+    if let Some(tracer) = tracer {
+        chip.set_tracer(tracer);
+    }
+    if let Some(coverage) = coverage {
+        chip.set_coverage(coverage);
+    }
+    chip.load_program(rom)?;
+
+    for cycle in 0..cycles {
+        if chip.has_exited() {
+            break;
+        }
+        chip.step()?;
+
+        if cycle % INSTRUCTIONS_PER_TICK == 0 {
+            chip.tick_timers();
+        }
+    }
+
+    Ok(chip.snapshot())
+}
@@ -0,0 +1,92 @@
+/* Memory seen by the CPU; peripherals, like memory-mapped test devices, can implement it to intercept
+ * the accesses to some addresses, forwarding the other ones to a Ram */
+pub trait Bus {
+    /* Reads must not change the state, since the debugger and the snapshots read the whole memory */
+    fn read(&self, address: u16) -> u8;
+    fn write(&mut self, address: u16, value: u8);
+    /* Number of addressable bytes, starting from 0 */
+    fn size(&self) -> usize;
+
+    /* Copy the data starting from the given address, e.g. the fonts and the ROM */
+    fn load(&mut self, start: u16, data: &[u8]) {
+        for (offset, &value) in data.iter().enumerate() {
+            self.write((start as usize + offset) as u16, value);
+        }
+    }
+
+    /* Set every byte to 0, when the machine is reset */
+    fn clear(&mut self) {
+        for address in 0..self.size() {
+            self.write(address as u16, 0);
+        }
+    }
+
+    /* Copy of the whole memory */
+    fn dump(&self) -> Vec<u8> {
+        (0..self.size()).map(|address| self.read(address as u16)).collect()
+    }
+}
+
+impl<T: Bus + ?Sized> Bus for Box<T> {
+    fn read(&self, address: u16) -> u8 {
+        (**self).read(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        (**self).write(address, value);
+    }
+
+    fn size(&self) -> usize {
+        (**self).size()
+    }
+
+    fn load(&mut self, start: u16, data: &[u8]) {
+        (**self).load(start, data);
+    }
+
+    fn clear(&mut self) {
+        (**self).clear();
+    }
+
+    fn dump(&self) -> Vec<u8> {
+        (**self).dump()
+    }
+}
+
+/* Plain memory, used by default */
+pub struct Ram {
+    bytes: Vec<u8>,
+}
+
+impl Ram {
+    pub fn new(size: usize) -> Ram {
+        Ram { bytes: vec![0; size] }
+    }
+}
+
+impl Bus for Ram {
+    fn read(&self, address: u16) -> u8 {
+        self.bytes[address as usize]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.bytes[address as usize] = value;
+    }
+
+    fn size(&self) -> usize {
+        self.bytes.len()
+    }
+
+    fn load(&mut self, start: u16, data: &[u8]) {
+        let start = start as usize;
+        self.bytes[start..(start + data.len())].copy_from_slice(data);
+    }
+
+    fn clear(&mut self) {
+        self.bytes.fill(0);
+    }
+
+    fn dump(&self) -> Vec<u8> {
+        self.bytes.clone()
+    }
+}
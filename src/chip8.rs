@@ -1,110 +1,917 @@
+use crate::bus::{Bus, Ram};
+use crate::coverage::Coverage;
+use crate::debugger::{Access, CodeWindow, Debugger, Edit, Snapshot, Watchpoint};
 use crate::display::*;
 use crate::fonts::Fonts;
-use crate::fonts::FONT_SIZE;
+use crate::fonts::{BIG_FONTS_SIZE, BIG_FONT_SIZE, FONTS_SIZE, FONT_SIZE};
+use crate::history::{Delta, History};
 use crate::keypad::*;
-use crate::logger::FileLogger;
-use crate::logger::Logger;
+use crate::logger::{FileLogger, LOG_FILE};
+use crate::logger::LogLevel;
+use crate::logger::{Logger, NullLogger};
+use crate::platform::{MachineConfig, Quirks};
+use crate::profiler::Profiler;
+use crate::rng::{RngSource, ThreadRngSource};
+use crate::sound::SoundBackend;
 use crate::instruction::Instruction;
 use crate::timer::{Timer, DelayTimer, SoundTimer};
+use crate::trace::Tracer;
 
-use rand::Rng;
-use std::sync::{Arc, Mutex};
-use std::{fs, thread, time};
+use std::error;
+use std::fmt;
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+use std::time::Instant;
+use std::{thread, time};
 
-const MEMORY_SIZE: usize = 4096;
-const STACK_SIZE: usize = 100;
+const STACK_SIZE: usize = 16;
 const REGISTERS_SIZE: usize = 16;
-const FONT_START: u16 = 0x50;
-const ROM_START: u16 = 0x200;
+const KEYPAD_SIZE: usize = 0x10;
 
-/* TODO : add getters from real display struct */
-const DISPLAY_WIDTH: usize = 64;
-const DISPLAY_HEIGHT: usize = 32;
+/* Number of RPL user flags of the HP48, where SUPER-CHIP programs can save up to V7 */
+pub const RPL_FLAGS_SIZE: usize = 8;
 
-const LOG_FILE: &str = "chip8rs.log";
+/* Default number of instructions executed per second; ~700 seems like a speed which fits well enough
+ * for most games */
+pub const DEFAULT_SPEED: u32 = 700;
+
+const TIMER_FREQUENCY: f32 = 60.0;
+
+/* Machine cycles of the COSMAC VIP in a 60Hz frame: its 1.76MHz clock takes 8 pulses per cycle */
+const VIP_CYCLES_PER_FRAME: u32 = 3668;
+
+/* How often the CHIP-8 thread checks if the execution has been resumed */
+const PAUSE_POLL_MS: u64 = 10;
+
+/* How often the metrics are published */
+const METRICS_PERIOD_MS: u64 = 500;
+
+/* Requests from the frontend, handled by the CHIP-8 thread between two instructions */
+pub enum Command {
+    /* Send back a snapshot of the machine state */
+    Save(Sender<Snapshot>),
+    Restore(Snapshot),
+    /* Restart the program currently loaded */
+    Reset,
+    /* Stop running; run returns after the commands received before this one are handled */
+    Quit,
+}
+
+/* Statistics published periodically by the CHIP-8 thread */
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Metrics {
+    pub instructions_per_second: u32,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Chip8Error {
+    /* A subroutine has been called with the stack already full; the PC is the address of the call */
+    StackOverflow { pc: u16 },
+    /* A return has been executed with an empty stack; the PC is the address of the return */
+    StackUnderflow { pc: u16 },
+    /* The program doesn't fit in the memory after the address where it's loaded */
+    RomTooLarge { size: usize, max: usize },
+    /* The opcode at the PC isn't a known instruction, e.g. when the program runs into its data */
+    UnknownInstruction { pc: u16, opcode: u16 },
+    /* The instruction at the PC fetched or accessed a byte past the end of the memory */
+    MemoryOutOfBounds { pc: u16, address: usize },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chip8Error::StackOverflow { pc } => write!(
+                f,
+                "stack overflow at {:03X}: more than {} nested subroutine calls",
+                pc, STACK_SIZE
+            ),
+            Chip8Error::StackUnderflow { pc } => {
+                write!(f, "stack underflow at {:03X}: return outside of a subroutine", pc)
+            }
+            Chip8Error::RomTooLarge { size, max } => {
+                write!(f, "the ROM is {} bytes, but at most {} bytes fit in memory", size, max)
+            }
+            Chip8Error::UnknownInstruction { pc, opcode } => {
+                write!(f, "unknown instruction at {:03X}: {:04X}", pc, opcode)
+            }
+            Chip8Error::MemoryOutOfBounds { pc, address } => {
+                write!(f, "memory access out of bounds at {:03X}: address {:X}", pc, address)
+            }
+        }
+    }
+}
+
+impl error::Error for Chip8Error {}
+
+/* Events passed to the hook, along with the machine state */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HookEvent {
+    /* The timers have been ticked, at 60Hz */
+    Frame,
+    /* The execution stopped on a breakpoint at the given address */
+    Breakpoint(u16),
+    Key { key: u8, pressed: bool },
+}
+
+type InstructionCallback = Box<dyn FnMut(u16, &Instruction) + Send>;
+type DrawCallback = Box<dyn FnMut(&DrawCommand) + Send>;
+type SoundCallback = Box<dyn FnMut(u8) + Send>;
+type RplFlagsCallback = Box<dyn FnMut(&[u8; RPL_FLAGS_SIZE]) + Send>;
+type HookCallback = Box<dyn FnMut(HookEvent, &mut Snapshot) + Send>;
 
-/* TODO : restore debug trait */
 /* TODO : use arrays instead of vecs? */
-/* TODO : set option for more verbose logs */
 pub struct Chip8 {
-    display: Arc<Mutex<LedsDisplay>>,
-    keypad: Arc<Mutex<KeyboardKeypad>>,
-    memory: [u8; MEMORY_SIZE],
+    config: MachineConfig,
+    /* The CHIP-8 thread keeps its own framebuffer to detect collisions; the renderer is kept in sync
+     * through the draw commands */
+    display: LedsDisplay,
+    /* Channel to the renderer, if any */
+    draw_commands: Option<Sender<DrawCommand>>,
+    /* Display receiving the draw commands in the same thread, see Chip8Builder::display */
+    output: Option<Box<dyn Display + Send>>,
+    /* With double buffering the renderer only receives whole frames, see set_double_buffered */
+    double_buffered: bool,
+    /* Set when the display changed since the last frame sent to the renderer, with double buffering */
+    frame_changed: bool,
+    keypad: Arc<Mutex<dyn Keypad + Send>>,
+    memory: Box<dyn Bus + Send>,
+    /* Program currently loaded, kept to reset the machine */
+    rom: Vec<u8>,
     pc: u16,
     i: u16,
     stack: Vec<u16>,
     delay_timer: DelayTimer,
     sound_timer: SoundTimer,
     regs: [u8; REGISTERS_SIZE],
+    /* SUPER-CHIP high resolution mode, only available if the configuration has a hires display */
+    hires: bool,
+    /* Set when the program stops with 00FD; nothing is executed afterwards until a reset */
+    exited: bool,
+    /* VIP machine cycles taken by the instructions of the current frame, with the VIP timing */
+    frame_cycles: u32,
+    /* Kept across resets, like the calculator memory; the frontend persists them */
+    rpl_flags: [u8; RPL_FLAGS_SIZE],
     fonts: Fonts,
-    logger: FileLogger,
+    logger: Box<dyn Logger + Send>,
+    /* Source of the random numbers of CXNN */
+    rng: Box<dyn RngSource + Send>,
+    /* Instructions executed per second; shared so that it can be changed while running */
+    speed: Arc<AtomicU32>,
+    rom_reloads: Option<Receiver<Vec<u8>>>,
+    metrics: Option<Sender<Metrics>>,
+    commands: Option<Receiver<Command>>,
+    debugger: Option<Arc<Mutex<Debugger>>>,
+    profiler: Option<Arc<Mutex<Profiler>>>,
+    coverage: Option<Arc<Mutex<Coverage>>>,
+    tracer: Option<Tracer>,
+    /* Last instructions executed, to step backwards from the debugger */
+    history: Option<History>,
+    /* Memory and register accesses done by the current instruction, recorded only when a debugger is
+     * set to check the watchpoints */
+    accesses: Vec<(Watchpoint, Access)>,
+    instruction_callback: Option<InstructionCallback>,
+    draw_callback: Option<DrawCallback>,
+    sound_callback: Option<SoundCallback>,
+    /* Shared with the frontend, set while the tone plays */
+    sound_playing: Option<Arc<AtomicBool>>,
+    rpl_flags_callback: Option<RplFlagsCallback>,
+    hook: Option<HookCallback>,
+    /* Keys pressed when the hook was last called, to detect the key events */
+    hook_keys: [bool; KEYPAD_SIZE],
+    /* Address of the instruction being executed, or of the last one */
+    current_pc: u16,
+    /* Key events waking up the machine from an idle loop; subscribed the first time it's idle */
+    key_events: Option<Receiver<KeyEvent>>,
+}
+
+/* Only the CPU state is shown; the memory can be inspected with the debugger */
+impl fmt::Debug for Chip8 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Chip8")
+            .field("config", &self.config)
+            .field("pc", &format_args!("{:03X}", self.pc))
+            .field("i", &format_args!("{:03X}", self.i))
+            .field("regs", &format_args!("{:02X?}", self.regs))
+            .field("stack", &format_args!("{:03X?}", self.stack))
+            .field("speed", &self.speed.load(Ordering::Relaxed))
+            .finish_non_exhaustive()
+    }
 }
 
 impl Chip8 {
-    pub fn new(display: &Arc<Mutex<LedsDisplay>>, keypad: &Arc<Mutex<KeyboardKeypad>>) -> Chip8 {
+    pub fn new(
+        config: MachineConfig,
+        draw_commands: Sender<DrawCommand>,
+        keypad: &Arc<Mutex<KeyboardKeypad>>,
+        speed: u32,
+        log_level: LogLevel,
+    ) -> Chip8 {
+        let logger = Box::new(FileLogger::new(LOG_FILE.to_string(), log_level));
+        let keypad: Arc<Mutex<dyn Keypad + Send>> = keypad.clone();
+        let mut chip = Chip8::with_keypad(config, keypad, speed, logger);
+        chip.draw_commands = Some(draw_commands);
+        chip
+    }
+
+    /* Machine with any keypad and any outputs, e.g. to embed it without the window; see
+     * Chip8Builder */
+    pub fn builder() -> Chip8Builder {
+        Chip8Builder::new()
+    }
+
+    fn with_keypad(
+        config: MachineConfig,
+        keypad: Arc<Mutex<dyn Keypad + Send>>,
+        speed: u32,
+        logger: Box<dyn Logger + Send>,
+    ) -> Chip8 {
         Chip8 {
-            display: Arc::clone(display),
-            keypad: Arc::clone(keypad),
-            memory: [0; MEMORY_SIZE],
+            config,
+            display: LedsDisplay::new(config.display_width, config.display_height, false),
+            draw_commands: None,
+            output: None,
+            double_buffered: false,
+            frame_changed: false,
+            keypad,
+            memory: Box::new(Ram::new(config.memory_size)),
+            rom: Vec::new(),
             pc: 0,
             i: 0,
-            stack: vec![0; STACK_SIZE],
+            stack: Vec::with_capacity(STACK_SIZE),
             delay_timer: DelayTimer::new(),
             sound_timer: SoundTimer::new(),
             regs: [0; REGISTERS_SIZE],
-            fonts: Fonts::new(),
-            logger: FileLogger::new(LOG_FILE.to_string()),
+            hires: false,
+            exited: false,
+            frame_cycles: 0,
+            rpl_flags: [0; RPL_FLAGS_SIZE],
+            fonts: Fonts::with_set(config.font),
+            logger,
+            rng: Box::new(ThreadRngSource),
+            speed: Arc::new(AtomicU32::new(speed)),
+            rom_reloads: None,
+            metrics: None,
+            commands: None,
+            debugger: None,
+            profiler: None,
+            coverage: None,
+            tracer: None,
+            history: None,
+            accesses: Vec::new(),
+            instruction_callback: None,
+            draw_callback: None,
+            sound_callback: None,
+            sound_playing: None,
+            rpl_flags_callback: None,
+            hook: None,
+            hook_keys: [false; KEYPAD_SIZE],
+            current_pc: 0,
+            key_events: None,
+        }
+    }
+
+    /* Send the display to the renderer once per frame, at the timer tick, instead of each draw
+     * command; the renderer then never shows a frame the program is still drawing, like a sprite
+     * erased and not drawn again yet. The draw callback still receives every command */
+    pub fn set_double_buffered(&mut self, enabled: bool) {
+        self.present_frame();
+        self.double_buffered = enabled;
+    }
+
+    /* Called with the address and the decoded instruction before executing it */
+    pub fn on_instruction<F: FnMut(u16, &Instruction) + Send + 'static>(&mut self, callback: F) {
+        self.instruction_callback = Some(Box::new(callback));
+    }
+
+    /* Called for each command changing the display, after applying it to the internal framebuffer */
+    pub fn on_draw<F: FnMut(&DrawCommand) + Send + 'static>(&mut self, callback: F) {
+        self.draw_callback = Some(Box::new(callback));
+    }
+
+    /* Called with the new value each time the sound timer is set */
+    pub fn on_sound<F: FnMut(u8) + Send + 'static>(&mut self, callback: F) {
+        self.sound_callback = Some(Box::new(callback));
+    }
+
+    /* Keep the flag set while the tone plays, e.g. to show the beep in the window; it's updated on each
+     * tick of the timers */
+    pub fn set_sound_flag(&mut self, playing: &Arc<AtomicBool>) {
+        self.sound_playing = Some(Arc::clone(playing));
+    }
+
+    /* Called with all the RPL user flags each time a program saves them with FX75 */
+    pub fn on_rpl_flags<F: FnMut(&[u8; RPL_FLAGS_SIZE]) + Send + 'static>(&mut self, callback: F) {
+        self.rpl_flags_callback = Some(Box::new(callback));
+    }
+
+    /* Initial value of the RPL user flags, usually the ones saved by a previous run */
+    pub fn set_rpl_flags(&mut self, flags: [u8; RPL_FLAGS_SIZE]) {
+        self.rpl_flags = flags;
+    }
+
+    /* Called on the events in HookEvent with a snapshot of the machine, which is restored if the hook
+     * changes it */
+    pub fn on_hook<F: FnMut(HookEvent, &mut Snapshot) + Send + 'static>(&mut self, callback: F) {
+        self.hook = Some(Box::new(callback));
+    }
+
+    /* Replace the RAM, e.g. with a bus mapping a device to some addresses; its size should be the
+     * memory size of the configuration */
+    pub fn set_bus(&mut self, bus: Box<dyn Bus + Send>) {
+        self.memory = bus;
+    }
+
+    /* Replace the speed given to new with a value that can be changed while running */
+    pub fn set_speed_control(&mut self, speed: &Arc<AtomicU32>) {
+        self.speed = Arc::clone(speed);
+    }
+
+    /* Replace the default audio output, e.g. with sound::NullBackend when running headless */
+    pub fn set_sound_backend(&mut self, backend: Box<dyn SoundBackend + Send>) {
+        self.sound_timer.set_backend(backend);
+    }
+
+    /* Replace the thread RNG used by CXNN, e.g. with rng::SeededRngSource for runs which can be
+     * reproduced */
+    pub fn set_rng(&mut self, rng: Box<dyn RngSource + Send>) {
+        self.rng = rng;
+    }
+
+    pub fn set_profiler(&mut self, profiler: &Arc<Mutex<Profiler>>) {
+        self.profiler = Some(Arc::clone(profiler));
+    }
+
+    /* Count the executions of each address, see coverage::Coverage */
+    pub fn set_coverage(&mut self, coverage: &Arc<Mutex<Coverage>>) {
+        self.coverage = Some(Arc::clone(coverage));
+    }
+
+    /* Write the state before each instruction, see trace::format_line */
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    /* Record the changes made by the last instructions, up to capacity, so that they can be undone with
+     * step_back */
+    pub fn enable_history(&mut self, capacity: usize) {
+        self.history = Some(History::new(capacity));
+    }
+
+    /* Number of instructions which can be undone */
+    pub fn history_len(&self) -> usize {
+        self.history.as_ref().map_or(0, History::len)
+    }
+
+    /* Replace the default file logger */
+    pub fn set_logger(&mut self, logger: Box<dyn Logger + Send>) {
+        self.logger = logger;
+    }
+
+    pub fn set_debugger(&mut self, debugger: &Arc<Mutex<Debugger>>) {
+        self.debugger = Some(Arc::clone(debugger));
+    }
+
+    /* Address of the instruction being executed, e.g. when the machine panicked, or of the last one */
+    pub fn current_pc(&self) -> u16 {
+        self.current_pc
+    }
+
+    /* Read access to the state without copying it like snapshot; it's changed with apply_edit */
+    pub fn config(&self) -> &MachineConfig {
+        &self.config
+    }
+
+    /* Address of the next instruction */
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn index(&self) -> u16 {
+        self.i
+    }
+
+    pub fn registers(&self) -> &[u8; REGISTERS_SIZE] {
+        &self.regs
+    }
+
+    /* Return addresses, the last one on top */
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer.get_timer_value()
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer.get_timer_value()
+    }
+
+    /* The reads done through the bus are not recorded, so they don't hit watchpoints */
+    pub fn memory(&self) -> &(dyn Bus + Send) {
+        self.memory.as_ref()
+    }
+
+    /* Framebuffer of the machine, with the pixels drawn so far even if not sent to the renderer yet */
+    pub fn display(&self) -> &dyn Display {
+        &self.display
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            memory: self.memory.dump(),
+            pc: self.pc,
+            i: self.i,
+            regs: self.regs,
+            stack: self.stack.clone(),
+            delay_timer: self.delay_timer.get_timer_value(),
+            sound_timer: self.sound_timer.get_timer_value(),
+            display: self.display.snapshot(),
+            hires: self.hires,
+        }
+    }
+
+    /* Check if the debugger paused the execution; once stopped, publish the machine state so that it can be
+     * inspected from the frontend. The changes made from the debugger are applied first */
+    fn is_paused(&mut self) -> bool {
+        if let Some(debugger) = self.debugger.clone() {
+            let mut debugger = debugger.lock().unwrap();
+
+            for edit in debugger.take_edits() {
+                self.apply_edit(&edit);
+            }
+
+            if debugger.is_paused() {
+                if debugger.snapshot().is_none() {
+                    debugger.set_history_len(self.history_len());
+                    debugger.publish(self.snapshot());
+                }
+
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn is_frame_step(&self) -> bool {
+        match &self.debugger {
+            Some(debugger) => debugger.lock().unwrap().take_frame_step(),
+            None => false,
+        }
+    }
+
+    /* Undo the instructions the debugger asked to step back while paused; returns false if none */
+    fn is_back_step(&mut self) -> bool {
+        let steps = match &self.debugger {
+            Some(debugger) => debugger.lock().unwrap().take_back_steps(),
+            None => 0,
+        };
+        if steps == 0 {
+            return false;
+        }
+
+        let undone = (0..steps).take_while(|_| self.step_back()).count();
+        self.logger.debug(format!("Stepped back {} instructions, to {:03X}", undone, self.pc));
+
+        if let Some(debugger) = &self.debugger {
+            debugger.lock().unwrap().stepped_back(self.history_len());
+        }
+        true
+    }
+
+    /* Check if the next instruction has a breakpoint; the execution is paused if so */
+    fn is_breakpoint(&mut self) -> bool {
+        if let Some(debugger) = &self.debugger {
+            if debugger.lock().unwrap().check_breakpoint(self.pc) {
+                self.logger.info(format!("Breakpoint hit at {:03X}", self.pc));
+                self.call_hook(HookEvent::Breakpoint(self.pc));
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn call_hook(&mut self, event: HookEvent) {
+        let mut hook = match self.hook.take() {
+            Some(hook) => hook,
+            None => return,
+        };
+
+        /* Key events are sent before each frame, for the keys changed since the previous one */
+        if event == HookEvent::Frame {
+            let keypad = lock_keypad(&self.keypad, &self.profiler);
+            let keys: Vec<bool> = (0..KEYPAD_SIZE).map(|key| keypad.get_is_pressed(key)).collect();
+            drop(keypad);
+
+            for (key, &pressed) in keys.iter().enumerate() {
+                if pressed != self.hook_keys[key] {
+                    self.hook_keys[key] = pressed;
+                    self.run_hook(&mut hook, HookEvent::Key { key: key as u8, pressed });
+                }
+            }
+        }
+
+        self.run_hook(&mut hook, event);
+        self.hook = Some(hook);
+    }
+
+    fn run_hook(&mut self, hook: &mut HookCallback, event: HookEvent) {
+        let snapshot = self.snapshot();
+        let mut changed = snapshot.clone();
+        hook(event, &mut changed);
+
+        if changed != snapshot {
+            self.restore(&changed);
+        }
+    }
+
+    /* Publish the metrics on the channel while running */
+    pub fn set_metrics(&mut self, sender: Sender<Metrics>) {
+        self.metrics = Some(sender);
+    }
+
+    /* Commands received from the channel are handled while running, even when paused */
+    pub fn set_commands(&mut self, receiver: Receiver<Command>) {
+        self.commands = Some(receiver);
+    }
+
+    /* Each ROM received from the channel resets the machine and replaces the running program */
+    pub fn set_rom_reloads(&mut self, receiver: Receiver<Vec<u8>>) {
+        self.rom_reloads = Some(receiver);
+    }
+
+    fn reset(&mut self) {
+        self.memory.clear();
+        self.pc = self.config.rom_start;
+        self.i = 0;
+        self.stack.clear();
+        self.regs = [0; REGISTERS_SIZE];
+        self.delay_timer.set_timer_value(0);
+        self.sound_timer.set_timer_value(0);
+        self.hires = false;
+        self.exited = false;
+        self.frame_cycles = 0;
+        self.clear_screen();
+        self.load_fonts();
+
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
+    }
+
+    /* Change the state from the debugger; the accesses are not recorded, so they don't hit watchpoints */
+    pub fn apply_edit(&mut self, edit: &Edit) {
+        match edit {
+            Edit::Memory(address, bytes) => self.memory.load(*address, bytes),
+            Edit::Register(reg, value) => self.regs[(reg & 0xF) as usize] = *value,
+            Edit::Pc(pc) => self.pc = *pc,
+            Edit::Index(i) => self.i = *i,
+            Edit::DelayTimer(value) => self.delay_timer.set_timer_value(*value),
+            Edit::SoundTimer(value) => self.sound_timer.set_timer_value(*value),
+        }
+    }
+
+    /* Replace the machine state with a snapshot taken with the same configuration */
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        if snapshot.memory.len() != self.memory.size() {
+            self.logger.warn("Save state taken with a different memory size, ignoring".to_string());
+            return;
+        }
+
+        self.memory.load(0, &snapshot.memory);
+        self.pc = snapshot.pc;
+        self.i = snapshot.i;
+        self.regs = snapshot.regs;
+        self.stack = snapshot.stack.clone();
+        self.delay_timer.set_timer_value(snapshot.delay_timer);
+        self.sound_timer.set_timer_value(snapshot.sound_timer);
+        self.hires = snapshot.hires && self.config.hires;
+        self.send_draw_command(DrawCommand::Frame {
+            pixels: snapshot.display.clone(),
+        });
+
+        /* The recorded instructions led to the previous state */
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
+    }
+
+    /* Undo the last instruction recorded in the history; returns false if there is none. The effects
+     * outside of the machine, like the sound and the saved RPL flags, are not undone */
+    pub fn step_back(&mut self) -> bool {
+        let delta = match self.history.as_mut().and_then(History::pop) {
+            Some(delta) => delta,
+            None => return false,
+        };
+
+        /* In reverse order, so that an address written twice gets its first value back */
+        for &(address, value) in delta.memory.iter().rev() {
+            self.memory.write(address, value);
+        }
+
+        if !delta.pixels.is_empty() {
+            let mut pixels = self.display.snapshot();
+            for &(x, y, value) in &delta.pixels {
+                pixels[y][x] = value;
+            }
+
+            /* Restoring the pixels must not be recorded as a change of the previous instruction */
+            let history = self.history.take();
+            self.send_draw_command(DrawCommand::Frame { pixels });
+            self.history = history;
+        }
+
+        self.pc = delta.pc;
+        self.current_pc = delta.pc;
+        self.i = delta.i;
+        self.regs = delta.regs;
+        self.stack.truncate(delta.stack_len);
+        if let (true, Some(top)) = (self.stack.len() < delta.stack_len, delta.stack_top) {
+            self.stack.push(top);
+        }
+        self.delay_timer.set_timer_value(delta.delay_timer);
+        self.sound_timer.set_timer_value(delta.sound_timer);
+        self.hires = delta.hires;
+        self.exited = delta.exited;
+
+        true
+    }
+
+    /* Handle the pending commands; returns false if the machine must stop */
+    fn handle_commands(&mut self) -> bool {
+        let commands: Vec<Command> = match &self.commands {
+            Some(receiver) => receiver.try_iter().collect(),
+            None => return true,
+        };
+
+        for command in commands {
+            match command {
+                Command::Save(sender) => {
+                    let _ = sender.send(self.snapshot());
+                }
+                Command::Restore(snapshot) => {
+                    self.logger.info("Restoring state".to_string());
+                    self.restore(&snapshot);
+                }
+                Command::Reset => {
+                    self.logger.info("Resetting".to_string());
+                    self.reset();
+                    /* The ROM was already loaded once, so it fits */
+                    let _ = self.load_rom(&self.rom.clone());
+                }
+                Command::Quit => {
+                    self.logger.info("Quitting".to_string());
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
+    fn reload_rom(&mut self) {
+        let mut new_rom = None;
+
+        if let Some(receiver) = &self.rom_reloads {
+            /* Only the most recent version is relevant if the file changed more than once */
+            while let Ok(rom) = receiver.try_recv() {
+                new_rom = Some(rom);
+            }
+        }
+
+        if let Some(rom) = new_rom {
+            /* Keep running the previous version if the new one can't be loaded */
+            if let Err(error) = self.check_rom_size(&rom) {
+                self.logger.error(format!("ROM changed, but not reloaded: {}", error));
+                return;
+            }
+
+            self.logger.info("ROM changed, reloading".to_string());
+            self.reset();
+            let _ = self.load_rom(&rom);
+            self.rom = rom;
         }
     }
 
     fn load_fonts(&mut self) {
-        let mut dest = FONT_START as usize;
+        let mut dest = self.config.font_start;
 
         for font in self.fonts.fonts {
-            self.memory[dest..(dest + font.len())].copy_from_slice(&font);
-            dest += font.len();
+            self.memory.load(dest, &font);
+            dest += font.len() as u16;
         }
-    }
 
-    fn load_rom(&mut self, rom_path: &str) {
-        let file_content = fs::read(rom_path).unwrap();
+        /* The big font follows the small one */
+        for font in self.fonts.big_fonts {
+            self.memory.load(dest, &font);
+            dest += font.len() as u16;
+        }
+    }
 
-        if file_content.len() > (MEMORY_SIZE - ROM_START as usize) {
-            panic!("ROM content is too large");
+    fn check_rom_size(&self, rom: &[u8]) -> Result<(), Chip8Error> {
+        let max = self.memory.size().saturating_sub(self.config.rom_start as usize);
+        if rom.len() > max {
+            return Err(Chip8Error::RomTooLarge { size: rom.len(), max });
         }
 
-        let dest = ROM_START as usize;
-        self.memory[dest..(dest + file_content.len())].copy_from_slice(&file_content);
+        Ok(())
+    }
+
+    fn load_rom(&mut self, rom: &[u8]) -> Result<(), Chip8Error> {
+        self.check_rom_size(rom)?;
+        self.memory.load(self.config.rom_start, rom);
+        Ok(())
     }
 
-    fn fetch(&mut self) -> u16 {
-        let first = self.memory[self.pc as usize] as u16;
-        let second = self.memory[(self.pc + 1) as usize] as u16;
+    fn fetch(&mut self) -> Result<u16, Chip8Error> {
+        let address = self.pc as usize + 1;
+        if address >= self.memory.size() {
+            return Err(Chip8Error::MemoryOutOfBounds { pc: self.pc, address });
+        }
+
+        let first = self.memory.read(self.pc) as u16;
+        let second = self.memory.read(self.pc + 1) as u16;
 
         self.pc += 2;
 
-        (first << 8) | second
+        Ok((first << 8) | second)
+    }
+
+    fn send_draw_command(&mut self, command: DrawCommand) -> usize {
+        let before = self.history.as_ref().map(|_| self.display.snapshot());
+        let collision = command.apply(&mut self.display);
+
+        if let (Some(history), Some(before)) = (&mut self.history, before) {
+            history.record_pixels(&before, &self.display.snapshot());
+        }
+
+        if let Some(callback) = &mut self.draw_callback {
+            callback(&command);
+        }
+
+        if self.double_buffered {
+            self.frame_changed = true;
+        } else {
+            self.output_draw_command(command);
+        }
+
+        collision
+    }
+
+    /* Pass a command on to the output display and to the renderer */
+    fn output_draw_command(&mut self, command: DrawCommand) {
+        if let Some(display) = &mut self.output {
+            command.apply(display.as_mut());
+        }
+        /* The renderer may have already been closed; the machine keeps running until the process
+         * exits */
+        if let Some(draw_commands) = &self.draw_commands {
+            let _ = draw_commands.send(command);
+        }
+    }
+
+    /* Send the whole display to the renderer if it changed, with double buffering */
+    fn present_frame(&mut self) {
+        if self.frame_changed {
+            self.frame_changed = false;
+            self.output_draw_command(DrawCommand::Frame {
+                pixels: self.display.snapshot(),
+            });
+        }
     }
 
     fn clear_screen(&mut self) {
-        self.display.lock().unwrap().clear_screen(false);
+        self.send_draw_command(DrawCommand::Clear);
+    }
+
+    fn set_resolution(&mut self, hires: bool) {
+        if !self.config.hires {
+            self.logger.debug("Resolution change ignored, the display has a single one".to_string());
+            return;
+        }
+
+        /* The pixels drawn in the other resolution would have the wrong size, so the display is
+         * cleared like most SUPER-CHIP interpreters do */
+        self.hires = hires;
+        self.clear_screen();
+    }
+
+    /* Scrolling moves by pixels of the current resolution, like modern SUPER-CHIP interpreters; the
+     * original one moved by LEDs also in low resolution */
+    fn scroll_down(&mut self, n: u8) {
+        let leds = (n as usize) * self.pixel_scale();
+        self.send_draw_command(DrawCommand::ScrollDown(leds));
+    }
+
+    fn scroll_right(&mut self) {
+        let leds = 4 * self.pixel_scale();
+        self.send_draw_command(DrawCommand::ScrollRight(leds));
+    }
+
+    fn scroll_left(&mut self) {
+        let leds = 4 * self.pixel_scale();
+        self.send_draw_command(DrawCommand::ScrollLeft(leds));
+    }
+
+    fn exit(&mut self) {
+        /* The PC stays on the instruction, so that stepping again has no effect */
+        self.pc -= 2;
+        self.exited = true;
+    }
+
+    /* True if the program stopped itself with 00FD */
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    /* Number of LEDs used for each side of a pixel; 2 in low resolution on the hires display */
+    fn pixel_scale(&self) -> usize {
+        if self.config.hires && !self.hires {
+            2
+        } else {
+            1
+        }
     }
 
-    fn return_subroutine(&mut self) {
-        self.pc = self.stack.pop().unwrap();
+    fn return_subroutine(&mut self) -> Result<(), Chip8Error> {
+        /* The PC has already been moved past the instruction */
+        let pc = self.pc - 2;
+        self.pc = self.stack.pop().ok_or(Chip8Error::StackUnderflow { pc })?;
+        Ok(())
     }
 
     fn jump(&mut self, addr: u16) {
         self.pc = addr;
     }
 
-    fn call_subroutine(&mut self, addr: u16) {
+    fn call_subroutine(&mut self, addr: u16) -> Result<(), Chip8Error> {
+        if self.stack.len() >= STACK_SIZE {
+            return Err(Chip8Error::StackOverflow { pc: self.pc - 2 });
+        }
+
         self.stack.push(self.pc);
         self.pc = addr;
+        Ok(())
+    }
+
+    fn record_access(&mut self, watchpoint: Watchpoint, access: Access) {
+        if self.debugger.is_some() {
+            self.accesses.push((watchpoint, access));
+        }
+    }
+
+    /* The instructions access the registers and the memory only through these functions, so that the
+     * watchpoints can be checked */
+    fn read_reg(&mut self, reg: u8) -> u8 {
+        self.record_access(Watchpoint::Register(reg), Access::Read);
+        self.regs[reg as usize]
+    }
+
+    fn write_reg(&mut self, reg: u8, value: u8) {
+        self.record_access(Watchpoint::Register(reg), Access::Write);
+        self.regs[reg as usize] = value;
+    }
+
+    /* Addresses are computed as usize, so that I plus an offset past the memory is an error instead of
+     * wrapping around */
+    fn check_address(&self, address: usize) -> Result<u16, Chip8Error> {
+        if address < self.memory.size() {
+            Ok(address as u16)
+        } else {
+            Err(Chip8Error::MemoryOutOfBounds {
+                pc: self.current_pc,
+                address,
+            })
+        }
+    }
+
+    fn read_memory(&mut self, address: usize) -> Result<u8, Chip8Error> {
+        let address = self.check_address(address)?;
+        self.record_access(Watchpoint::Memory(address), Access::Read);
+        Ok(self.memory.read(address))
+    }
+
+    fn write_memory(&mut self, address: usize, value: u8) -> Result<(), Chip8Error> {
+        let address = self.check_address(address)?;
+        self.record_access(Watchpoint::Memory(address), Access::Write);
+        if let Some(history) = &mut self.history {
+            history.record_memory(address, self.memory.read(address));
+        }
+        self.memory.write(address, value);
+        Ok(())
     }
 
     fn skip_if_eq(&mut self, reg: u8, value: u8) {
-        let reg_value = self.regs[reg as usize];
+        let reg_value = self.read_reg(reg);
 
         if value == reg_value {
             self.pc += 2;
@@ -112,7 +919,7 @@ impl Chip8 {
     }
 
     fn skip_if_diff(&mut self, reg: u8, value: u8) {
-        let reg_value = self.regs[reg as usize];
+        let reg_value = self.read_reg(reg);
 
         if value != reg_value {
             self.pc += 2;
@@ -120,114 +927,99 @@ impl Chip8 {
     }
 
     fn skip_if_content_eq(&mut self, reg_x: u8, reg_y: u8) {
-        if self.regs[reg_x as usize] == self.regs[reg_y as usize] {
+        if self.read_reg(reg_x) == self.read_reg(reg_y) {
             self.pc += 2;
         }
     }
 
     fn set_register(&mut self, reg: u8, value: u8) {
-        self.regs[reg as usize] = value;
+        self.write_reg(reg, value);
     }
 
     fn add_to_reg(&mut self, reg: u8, value: u8) {
-        let mut add_value = self.regs[reg as usize] as u16;
+        let mut add_value = self.read_reg(reg) as u16;
         add_value += value as u16;
-        self.regs[reg as usize] = (add_value & 0x00FF) as u8;
+        self.write_reg(reg, (add_value & 0x00FF) as u8);
     }
 
     fn logical_copy(&mut self, reg_x: u8, reg_y: u8) {
-        self.regs[reg_x as usize] = self.regs[reg_y as usize];
+        let value = self.read_reg(reg_y);
+        self.write_reg(reg_x, value);
+    }
+
+    /* The COSMAC VIP interpreter reset the flag register after the logic operations */
+    fn logic_reset_flag(&mut self) {
+        if self.config.quirks.vf_reset {
+            self.write_reg(0x0F, 0);
+        }
     }
 
     fn logical_or(&mut self, reg_x: u8, reg_y: u8) {
-        self.regs[reg_x as usize] = self.regs[reg_x as usize] | self.regs[reg_y as usize];
+        let value = self.read_reg(reg_x) | self.read_reg(reg_y);
+        self.write_reg(reg_x, value);
+        self.logic_reset_flag();
     }
 
     fn logical_and(&mut self, reg_x: u8, reg_y: u8) {
-        self.regs[reg_x as usize] = self.regs[reg_x as usize] & self.regs[reg_y as usize];
+        let value = self.read_reg(reg_x) & self.read_reg(reg_y);
+        self.write_reg(reg_x, value);
+        self.logic_reset_flag();
     }
 
     fn logical_xor(&mut self, reg_x: u8, reg_y: u8) {
-        self.regs[reg_x as usize] = self.regs[reg_x as usize] ^ self.regs[reg_y as usize];
+        let value = self.read_reg(reg_x) ^ self.read_reg(reg_y);
+        self.write_reg(reg_x, value);
+        self.logic_reset_flag();
     }
 
     fn logical_add(&mut self, reg_x: u8, reg_y: u8) {
-        let add_value =
-                    (self.regs[reg_x as usize] as u16) + self.regs[reg_y as usize] as u16;
-
-        if add_value > 0xFF {
-            /* Overflow occurred, set the flag register */
-            self.regs[0x0F as usize] = 1;
-        } else {
-            self.regs[0x0F as usize] = 0;
-        }
+        let (result, overflow) = self.read_reg(reg_x).overflowing_add(self.read_reg(reg_y));
 
-        self.regs[reg_x as usize] = (add_value & 0x00FF) as u8;
+        /* VF is set after the result, so that it holds the flag even when it's the destination */
+        self.write_reg(reg_x, result);
+        self.write_reg(0x0F, overflow as u8);
     }
 
     fn logical_sub(&mut self, reg_x: u8, reg_y: u8) {
-        if self.regs[reg_x as usize] > self.regs[reg_y as usize] {
-            self.regs[0x0F as usize] = 1;
-            self.regs[reg_x as usize] =
-                self.regs[reg_x as usize] - self.regs[reg_y as usize];
-        } else {
-            self.regs[0x0F as usize] = 0;
-            /* From the specification, this instruction should result in the rolling of the uint */
-            self.regs[reg_x as usize] = ((0x100 - (self.regs[reg_y as usize] as u16)
-                + (self.regs[reg_x as usize] as u16))
-                & 0xFF) as u8;
-        }
-    }
+        let (result, borrow) = self.read_reg(reg_x).overflowing_sub(self.read_reg(reg_y));
 
-    fn logical_right_shift(&mut self, reg_x: u8) {
-        /* TODO: this should be made optional, since some implementation (like CHIP-48 or SUPER-CHIP)
-         * did not apply this instruction */
-        //self.regs[reg_x as usize] = self.regs[reg_y as usize];
+        /* VF is set to 1 when there is no borrow, which includes VX == VY */
+        self.write_reg(reg_x, result);
+        self.write_reg(0x0F, !borrow as u8);
+    }
 
-        /* Set the flag register to 1 if the shifted bit was 1 */
-        if (self.regs[reg_x as usize] & 0x01) == 0x01 {
-            self.regs[0x0F as usize] = 1;
+    /* The value shifted: VX, or VY with the original interpreter; CHIP-48 and SUPER-CHIP ignored VY */
+    fn shift_operand(&mut self, reg_x: u8, reg_y: u8) -> u8 {
+        if self.config.quirks.shift_copies_vy {
+            self.read_reg(reg_y)
         } else {
-            self.regs[0x0F as usize] = 0;
+            self.read_reg(reg_x)
         }
+    }
 
-        self.regs[reg_x as usize] = self.regs[reg_x as usize] >> 1;
+    fn logical_right_shift(&mut self, reg_x: u8, reg_y: u8) {
+        /* Set the flag register to the shifted out bit, after the result */
+        let value = self.shift_operand(reg_x, reg_y);
+        self.write_reg(reg_x, value >> 1);
+        self.write_reg(0x0F, value & 0x01);
     }
 
     fn logical_sub_inv(&mut self, reg_x: u8, reg_y: u8) {
-        if self.regs[reg_y as usize] >= self.regs[reg_x as usize] {
-            self.regs[reg_x as usize] =
-                self.regs[reg_y as usize] - self.regs[reg_x as usize];
-
-            /* Set VF after the operation so that VF can be used in subtractions; this should not break compatibility anyway */
-            self.regs[0x0F as usize] = 1;
-        } else {
-            /* Since the operation would underflow, let's multiply by -1 by swapping the operands */
-            self.regs[reg_x as usize] =
-                self.regs[reg_x as usize] - self.regs[reg_y as usize];
+        let (result, borrow) = self.read_reg(reg_y).overflowing_sub(self.read_reg(reg_x));
 
-            /* Set VF after the operation so that VF can be used in subtractions; this should not break compatibility anyway */
-            self.regs[0x0F as usize] = 0;
-        }
+        self.write_reg(reg_x, result);
+        self.write_reg(0x0F, !borrow as u8);
     }
 
-    fn logical_left_shift(&mut self, reg_x: u8) {
-        /* TODO: this should be made optional, since some implementation (like CHIP-48 or SUPER-CHIP)
-         * did not apply this instruction */
-        //self.regs[reg_x as usize] = self.regs[reg_y as usize];
-
-        /* Set the flag register to 1 if the shifted bit was 1 */
-        if (self.regs[reg_x as usize] & 0x80) == 0x80 {
-            self.regs[0x0F as usize] = 1;
-        } else {
-            self.regs[0x0F as usize] = 0;
-        }
-
-        self.regs[reg_x as usize] = self.regs[reg_x as usize] << 1;
+    fn logical_left_shift(&mut self, reg_x: u8, reg_y: u8) {
+        /* Set the flag register to the shifted out bit, after the result */
+        let value = self.shift_operand(reg_x, reg_y);
+        self.write_reg(reg_x, value << 1);
+        self.write_reg(0x0F, value >> 7);
     }
 
     fn skip_if_content_diff(&mut self, reg_x: u8, reg_y: u8) {
-        if self.regs[reg_x as usize] != self.regs[reg_y as usize] {
+        if self.read_reg(reg_x) != self.read_reg(reg_y) {
             self.pc += 2;
         }
     }
@@ -237,104 +1029,131 @@ impl Chip8 {
     }
 
     fn jump_with_reg(&mut self, value: u16) {
-        /* TODO : this should be made configurable, as some implementations interpret this like a "BXNN" */
-        self.pc = value + (self.regs[0x00 as usize] as u16);
+        /* CHIP-48 and SUPER-CHIP interpret this like a "BXNN", adding VX to XNN */
+        let reg = if self.config.quirks.jump_uses_vx {
+            ((value >> 8) & 0x0F) as u8
+        } else {
+            0x00
+        };
+
+        self.pc = value + (self.read_reg(reg) as u16);
     }
 
     fn random(&mut self, reg: u8, value: u8) {
-        let mut rng = rand::thread_rng();
-        let random: u8 = rng.gen();
-        self.regs[reg as usize] = random & value;
+        let random = self.rng.next_byte();
+        self.write_reg(reg, random & value);
     }
 
-    /* TODO : this should be moved to another entity */
-    fn draw_sprite(&mut self, x: u8, y: u8, n: u8) {
+    fn draw_sprite(&mut self, x: u8, y: u8, n: u8) -> Result<(), Chip8Error> {
+        let scale = self.pixel_scale();
+        let width = self.display.width() / scale;
+        let height = self.display.height() / scale;
+
         /* Get X and Y coordinates from the registers */
-        let x = self.regs[x as usize] % (DISPLAY_WIDTH as u8);
-        let y = self.regs[y as usize] % (DISPLAY_HEIGHT as u8);
-
-        /* Set VF to 0 as default; it will be set to 1 if any pixel is turned off */
-        self.regs[0x0F as usize] = 0;
-
-        for sprite_row in 0..n {
-            let y_pos = (y + sprite_row) as usize;
-            if y_pos < DISPLAY_HEIGHT {
-                let sprite_data = self.memory[(self.i + (sprite_row as u16)) as usize];
-
-                for sprite_bit_i in 0..8 {
-                    let x_pos = (x + sprite_bit_i) as usize;
-                    if x_pos < DISPLAY_WIDTH {
-                        /* The bits must be read from MIB to LIB */
-                        let bit_index = 7 - sprite_bit_i;
-                        let bit_value = (sprite_data & (0x1 << bit_index)) >> bit_index;
-                        let led_status = self.display.lock().unwrap().is_on(x_pos, y_pos);
-
-                        /* If current pixel is on and bit is high, flip the led */
-                        if (bit_value != 0) && led_status {
-                            self.display.lock().unwrap().led_off(x_pos, y_pos);
-
-                            /* Set VF to 1 since a led has been changed */
-                            self.regs[0x0F as usize] = 1;
-                        } else if (bit_value != 0) && !led_status {
-                            self.display.lock().unwrap().led_on(x_pos, y_pos);
-                        }
-                    } else {
-                        self.logger.log(format!("X overflow while drawing sprite"));
-                    }
-                }
-            } else {
-                self.logger.log(format!("Y overflow while drawing sprite"));
-            }
+        let x = (self.read_reg(x) as usize) % width;
+        let y = (self.read_reg(y) as usize) % height;
+
+        /* On SUPER-CHIP DXY0 draws a 16x16 sprite in high resolution, and an 8x16 one in low
+         * resolution; rows are read from the memory as 16 bit words in the first case */
+        let rows: Vec<u16> = if n == 0 && self.hires {
+            (0..16)
+                .map(|row| {
+                    let address = self.i as usize + 2 * row;
+                    Ok((self.read_memory(address)? as u16) << 8 | self.read_memory(address + 1)? as u16)
+                })
+                .collect::<Result<_, _>>()?
+        } else {
+            let n = if n == 0 && self.config.hires { 16 } else { n as usize };
+            (0..n)
+                .map(|row| Ok((self.read_memory(self.i as usize + row)? as u16) << 8))
+                .collect::<Result<_, _>>()?
+        };
+        let sprite_width = if n == 0 && self.hires { 16 } else { 8 };
+
+        if x + sprite_width > width {
+            self.logger.debug("X overflow while drawing sprite".to_string());
+        }
+
+        let clipped_rows = (y + rows.len()).saturating_sub(height);
+        if clipped_rows > 0 {
+            self.logger.debug("Y overflow while drawing sprite".to_string());
         }
+
+        let command = if scale == 1 && sprite_width == 8 {
+            DrawCommand::Sprite {
+                x,
+                y,
+                rows: rows.iter().map(|row| (row >> 8) as u8).collect(),
+            }
+        } else if scale == 1 {
+            DrawCommand::WideSprite { x, y, rows }
+        } else {
+            DrawCommand::WideSprite {
+                x: x * scale,
+                y: y * scale,
+                rows: rows.iter().flat_map(|&row| [double_bits((row >> 8) as u8); 2]).collect(),
+            }
+        };
+        let collisions = self.send_draw_command(command);
+
+        /* VF is set to 1 if any pixel is turned off; in high resolution SUPER-CHIP sets it to the
+         * number of rows with a collision, plus the ones clipped at the bottom */
+        let flag = if self.hires {
+            collisions + clipped_rows
+        } else {
+            (collisions > 0) as usize
+        };
+        self.write_reg(0x0F, flag as u8);
+        Ok(())
     }
 
     fn skip_if_pressed(&mut self, reg: u8) {
-        let key = self.regs[reg as usize];
-        if self.keypad.lock().unwrap().get_is_pressed(key as usize) {
+        let key = self.read_reg(reg);
+        if lock_keypad(&self.keypad, &self.profiler).get_is_pressed(key as usize) {
             self.pc += 2;
         }
     }
 
     fn skip_if_not_pressed(&mut self, reg: u8) {
-        let key = self.regs[reg as usize];
-        if !self.keypad.lock().unwrap().get_is_pressed(key as usize) {
+        let key = self.read_reg(reg);
+        if !lock_keypad(&self.keypad, &self.profiler).get_is_pressed(key as usize) {
             self.pc += 2;
         }
     }
 
     fn copy_delay_timer(&mut self, reg: u8) {
-        self.regs[reg as usize] = self.delay_timer.get_timer_value();
+        let value = self.delay_timer.get_timer_value();
+        self.write_reg(reg, value);
     }
 
     fn wait_for_key(&mut self, reg: u8) {
-        let keypad = self.keypad.lock().unwrap();
-        let mut key = 0;
-
-        while key < 0x10 {
-            if keypad.get_is_pressed(key as usize) {
-                self.regs[reg as usize] = key;
-                break;
-            } else {
-                key += 1;
-            }
-        }
-
-        /* If not key was pressed, decrement the PC so that this instruction is executed again */
-        if key >= 0x10 {
-            self.pc -= 2;
+        let keypad = lock_keypad(&self.keypad, &self.profiler);
+        let pressed = (0..0x10).find(|&key| keypad.get_is_pressed(key as usize));
+        drop(keypad);
+
+        match pressed {
+            Some(key) => self.write_reg(reg, key),
+            /* If not key was pressed, decrement the PC so that this instruction is executed again */
+            None => self.pc -= 2,
         }
     }
 
     fn set_delay_timer(&mut self, reg: u8) {
-        self.delay_timer.set_timer_value(self.regs[reg as usize]);
+        let value = self.read_reg(reg);
+        self.delay_timer.set_timer_value(value);
     }
 
     fn set_sound_timer(&mut self, reg: u8) {
-        self.sound_timer.set_timer_value(self.regs[reg as usize]);
+        let value = self.read_reg(reg);
+        self.sound_timer.set_timer_value(value);
+
+        if let Some(callback) = &mut self.sound_callback {
+            callback(value);
+        }
     }
 
     fn add_to_index(&mut self, reg: u8) {
-        let reg_value = self.regs[reg as usize];
+        let reg_value = self.read_reg(reg);
 
         let mut temp_add = self.i as u32;
         temp_add += reg_value as u32;
@@ -342,58 +1161,110 @@ impl Chip8 {
         /* The original interpreter doesn't seem to need the overflow check and flag register set, but it seems
          * the Amiga interpreter for CHIP-8 did, so let's check it here */
         if temp_add > 0xFFFF {
-            self.regs[0x0F as usize] = 1;
+            self.write_reg(0x0F, 1);
         } else {
-            self.regs[0x0F as usize] = 0;
+            self.write_reg(0x0F, 0);
         }
 
         self.i = (temp_add & 0x0000FFFF) as u16;
     }
 
     fn set_index_to_font(&mut self, reg: u8) {
-        let reg_value = self.regs[reg as usize];
-        self.i = FONT_START + ((FONT_SIZE as u16) * (reg_value as u16));
+        let reg_value = self.read_reg(reg);
+        self.i = self.config.font_start + ((FONT_SIZE as u16) * (reg_value as u16));
     }
 
-    fn binary_conversion(&mut self, reg: u8) {
-        let mut reg_value = self.regs[reg as usize];
+    fn set_index_to_big_font(&mut self, reg: u8) {
+        let reg_value = self.read_reg(reg) as usize;
+        if reg_value >= BIG_FONTS_SIZE {
+            self.logger.debug(format!("No big font digit for {:X}", reg_value));
+        }
+
+        let offset = FONT_SIZE * FONTS_SIZE + BIG_FONT_SIZE * (reg_value % BIG_FONTS_SIZE);
+        self.i = self.config.font_start + (offset as u16);
+    }
+
+    fn binary_conversion(&mut self, reg: u8) -> Result<(), Chip8Error> {
+        let mut reg_value = self.read_reg(reg);
 
         /* For example, if the value was "156" ->
          * memory[i] = 1
          * memory[i + 1] = 5
          * memory[i + 2] = 6
         */
-        self.memory[(self.i + 2) as usize] = reg_value % 10;
+        let i = self.i as usize;
+        self.write_memory(i + 2, reg_value % 10)?;
         reg_value /= 10;
-        self.memory[(self.i + 1) as usize] = reg_value % 10;
+        self.write_memory(i + 1, reg_value % 10)?;
         reg_value /= 10;
-        self.memory[(self.i + 0) as usize] = reg_value % 10;
+        self.write_memory(i, reg_value % 10)
     }
 
-    fn store(&mut self, reg_max: u8) {
-        /* TODO : this should be made configurable as the original CHIP-8 interpreter incremented the I register
-         * while executing the instruction; more moderns ROMs do not expect this */
+    fn store(&mut self, reg_max: u8) -> Result<(), Chip8Error> {
         /* The range uses reg_max + 1 since reg_max must be included */
         for reg_i in 0..(reg_max + 1) {
-            self.memory[(self.i + (reg_i as u16)) as usize] = self.regs[reg_i as usize];
+            let value = self.read_reg(reg_i);
+            self.write_memory(self.i as usize + reg_i as usize, value)?;
         }
+
+        /* The original CHIP-8 interpreter incremented the I register while executing the instruction; more
+         * modern ROMs do not expect this */
+        if self.config.quirks.load_store_increment {
+            self.i = self.i.wrapping_add((reg_max as u16) + 1);
+        }
+        Ok(())
     }
 
-    fn load(&mut self, reg_max: u8) {
-        /* TODO : this should be made configurable as the original CHIP-8 interpreter incremented the I register
-         * while executing the instruction; more moderns ROMs do not expect this */
+    fn load(&mut self, reg_max: u8) -> Result<(), Chip8Error> {
         /* The range uses reg_max + 1 since reg_max must be included */
         for reg_i in 0..(reg_max + 1) {
-            self.regs[reg_i as usize] = self.memory[(self.i + (reg_i as u16)) as usize];
+            let value = self.read_memory(self.i as usize + reg_i as usize)?;
+            self.write_reg(reg_i, value);
+        }
+
+        if self.config.quirks.load_store_increment {
+            self.i = self.i.wrapping_add((reg_max as u16) + 1);
+        }
+        Ok(())
+    }
+
+    /* Only V0 to V7 fit in the flags; SUPER-CHIP ignores the higher registers */
+    fn rpl_flags_count(&mut self, reg_max: u8) -> usize {
+        if reg_max as usize >= RPL_FLAGS_SIZE {
+            self.logger.debug(format!("RPL flags beyond V7 requested up to V{:X}", reg_max));
+        }
+
+        (reg_max as usize + 1).min(RPL_FLAGS_SIZE)
+    }
+
+    fn save_flags(&mut self, reg_max: u8) {
+        for reg_i in 0..self.rpl_flags_count(reg_max) {
+            self.rpl_flags[reg_i] = self.read_reg(reg_i as u8);
+        }
+
+        if let Some(callback) = &mut self.rpl_flags_callback {
+            callback(&self.rpl_flags);
+        }
+    }
+
+    fn load_flags(&mut self, reg_max: u8) {
+        for reg_i in 0..self.rpl_flags_count(reg_max) {
+            self.write_reg(reg_i as u8, self.rpl_flags[reg_i]);
         }
     }
 
-    fn execute(&mut self, instr: Instruction) {
+    fn execute(&mut self, instr: Instruction) -> Result<(), Chip8Error> {
         match instr {
             Instruction::ClearScreen => self.clear_screen(),
-            Instruction::Return => self.return_subroutine(),
+            Instruction::Return => return self.return_subroutine(),
+            Instruction::ScrollDown(n) => self.scroll_down(n),
+            Instruction::ScrollRight => self.scroll_right(),
+            Instruction::ScrollLeft => self.scroll_left(),
+            Instruction::Exit => self.exit(),
+            Instruction::LowResolution => self.set_resolution(false),
+            Instruction::HighResolution => self.set_resolution(true),
             Instruction::Jump(i) => self.jump(i),
-            Instruction::Call(i) => self.call_subroutine(i),
+            Instruction::Call(i) => return self.call_subroutine(i),
             Instruction::SkipIfEqual(r, v) => self.skip_if_eq(r, v),
             Instruction::SkipIfDifferent(r, v) => self.skip_if_diff(r, v),
             Instruction::SkipIfContentEqual(x, y) => self.skip_if_content_eq(x, y),
@@ -405,14 +1276,14 @@ impl Chip8 {
             Instruction::LogicalXor(x, y) => self.logical_xor(x, y),
             Instruction::LogicalAdd(x, y) => self.logical_add(x, y),
             Instruction::LogicalSubtract(x, y) => self.logical_sub(x, y),
-            Instruction::LogicalRightShift(x) => self.logical_right_shift(x),
+            Instruction::LogicalRightShift(x, y) => self.logical_right_shift(x, y),
             Instruction::LogicalSubtractInverse(x, y) => self.logical_sub_inv(x, y),
-            Instruction::LogicalLeftShift(x) => self.logical_left_shift(x),
+            Instruction::LogicalLeftShift(x, y) => self.logical_left_shift(x, y),
             Instruction::SkipIfContentDifferent(x,y) => self.skip_if_content_diff(x, y),
             Instruction::SetIndex(v) => self.set_index(v),
             Instruction::JumpWithRegister(i) => self.jump_with_reg(i),
             Instruction::Random(r, v) => self.random(r, v),
-            Instruction::Display(x, y, n) => self.draw_sprite(x, y, n),
+            Instruction::Display(x, y, n) => return self.draw_sprite(x, y, n),
             Instruction::SkipIfPressed(r) => self.skip_if_pressed(r),
             Instruction::SkipIfNotPressed(r) => self.skip_if_not_pressed(r),
             Instruction::CopyDelayTimer(r) => self.copy_delay_timer(r),
@@ -421,30 +1292,474 @@ impl Chip8 {
             Instruction::SetSoundTimer(r) => self.set_sound_timer(r),
             Instruction::AddToIndex(r) => self.add_to_index(r),
             Instruction::SetIndexToFont(r) => self.set_index_to_font(r),
-            Instruction::BinaryConversion(r) => self.binary_conversion(r),
-            Instruction::Store(v) => self.store(v),
-            Instruction::Load(v) => self.load(v),
+            Instruction::SetIndexToBigFont(r) => self.set_index_to_big_font(r),
+            Instruction::BinaryConversion(r) => return self.binary_conversion(r),
+            Instruction::Store(v) => return self.store(v),
+            Instruction::Load(v) => return self.load(v),
+            Instruction::SaveFlags(v) => self.save_flags(v),
+            Instruction::LoadFlags(v) => self.load_flags(v),
         }
+
+        Ok(())
     }
 
-    pub fn run(&mut self, rom_path: &str) {
+    /* Load the fonts and the ROM in memory; the machine is then ready to be driven with step or run */
+    pub fn load_program(&mut self, rom: &[u8]) -> Result<(), Chip8Error> {
         self.load_fonts();
-        self.load_rom(rom_path);
+        self.load_rom(rom)?;
+        self.rom = rom.to_vec();
+
+        self.pc = self.config.rom_start;
+        Ok(())
+    }
+
+    /* Decrement the delay and sound timers; when driving the machine with step, this must be called at 60Hz */
+    pub fn tick_timers(&mut self) {
+        /* The tone plays until the first tick after the timer reached 0 */
+        let playing = self.sound_timer.is_playing();
+
+        self.delay_timer.tick();
+        self.sound_timer.tick();
+        self.present_frame();
+
+        if let Some(flag) = &self.sound_playing {
+            flag.store(playing, Ordering::Relaxed);
+        }
+    }
+
+    pub fn is_sound_playing(&self) -> bool {
+        self.sound_timer.is_playing()
+    }
+
+    /* True once the instructions executed with the VIP timing have used up the cycles of the frame */
+    fn is_frame_done(&self) -> bool {
+        self.frame_cycles >= VIP_CYCLES_PER_FRAME
+    }
+
+    /* Start a new frame with the VIP timing; the cycles of the last instruction exceeding the previous
+     * one are taken from it */
+    fn next_frame(&mut self) {
+        self.frame_cycles = self.frame_cycles.saturating_sub(VIP_CYCLES_PER_FRAME);
+    }
+
+    /* Execute the instructions run in a 60Hz frame at the current speed, or in the cycles of a frame
+     * with the VIP timing, then tick the timers once */
+    pub fn run_frame(&mut self) -> Result<(), Chip8Error> {
+        if self.config.vip_timing {
+            while !self.is_frame_done() && !self.exited {
+                self.step()?;
+            }
+            self.next_frame();
+        } else {
+            let speed = self.speed.load(Ordering::Relaxed);
+            let instructions = ((speed as f32) / TIMER_FREQUENCY).round().max(1.0) as u32;
+
+            for _ in 0..instructions {
+                if self.exited {
+                    break;
+                }
+                self.step()?;
+            }
+        }
+        self.tick_timers();
+        self.call_hook(HookEvent::Frame);
+
+        if let Some(debugger) = &self.debugger {
+            debugger.lock().unwrap().frame_done();
+        }
+
+        Ok(())
+    }
+
+    /* Fetch, decode and execute a single instruction */
+    pub fn step(&mut self) -> Result<(), Chip8Error> {
+        /* Time is only measured when needed, since it's not available on every platform */
+        let start = self.profiler.as_ref().map(|_| Instant::now());
+
+        let pc = self.pc;
+        self.current_pc = pc;
+        let opcode = match self.fetch() {
+            Ok(opcode) => opcode,
+            Err(error) => return Err(self.fail(error)),
+        };
+        if let Some(coverage) = &self.coverage {
+            coverage.lock().unwrap().record(pc);
+        }
+        self.logger.set_instruction(pc, opcode);
+        /* The PC stays on the unknown instruction, so that it's shown by the debugger */
+        let instr = match Instruction::decode(opcode) {
+            Some(instr) => instr,
+            None => {
+                self.pc = pc;
+                return Err(self.fail(Chip8Error::UnknownInstruction { pc, opcode }));
+            }
+        };
+
+        if let (Some(profiler), Some(start)) = (&self.profiler, start) {
+            profiler.lock().unwrap().record_decode(start.elapsed());
+        }
+
+        if self.logger.enabled(LogLevel::Trace) {
+            self.logger.trace(format!("{:03X}: {:04X} {}", pc, opcode, instr));
+        }
+        if let Some(tracer) = &mut self.tracer {
+            if let Err(error) = tracer.record(pc, opcode, &self.regs, self.i, self.stack.len()) {
+                self.logger.error(format!("Cannot write the trace, tracing stopped: {}", error));
+                self.tracer = None;
+            }
+        }
+        if let Some(callback) = &mut self.instruction_callback {
+            callback(pc, &instr);
+        }
+        if let Some(history) = &mut self.history {
+            history.begin(Delta {
+                pc,
+                i: self.i,
+                regs: self.regs,
+                stack_len: self.stack.len(),
+                stack_top: self.stack.last().copied(),
+                delay_timer: self.delay_timer.get_timer_value(),
+                sound_timer: self.sound_timer.get_timer_value(),
+                hires: self.hires,
+                exited: self.exited,
+                memory: Vec::new(),
+                pixels: Vec::new(),
+            });
+        }
+
+        let name = instr.name();
+        if self.config.vip_timing {
+            self.frame_cycles = self.frame_cycles.saturating_add(instr.vip_cycles());
+        }
+        let start = self.profiler.as_ref().map(|_| Instant::now());
+        let result = self.execute(instr);
+
+        if let (Some(profiler), Some(start)) = (&self.profiler, start) {
+            profiler.lock().unwrap().record(name, start.elapsed());
+        }
+
+        self.update_debugger(pc);
+
+        result.map_err(|error| self.fail(error))
+    }
+
+    fn fail(&mut self, error: Chip8Error) -> Chip8Error {
+        self.logger.error(error.to_string());
+        error
+    }
+
+    /* Pause the execution if the last instruction accessed a watched location, or completed a step */
+    fn update_debugger(&mut self, pc: u16) {
+        if let Some(debugger) = &self.debugger {
+            let mut debugger = debugger.lock().unwrap();
+
+            if !self.accesses.is_empty() {
+                if let Some(hit) = debugger.check_accesses(&self.accesses, pc) {
+                    self.logger.info(format!("Watchpoint hit: {}", hit));
+                }
+            }
+
+            debugger.check_step(self.stack.len());
+        }
+
+        self.accesses.clear();
+    }
 
-        self.delay_timer.start(60.0);
-        self.sound_timer.start(60.0);
+    /* Copy the code around the PC for the disassembly view, when shown */
+    fn publish_code(&self) {
+        if let Some(debugger) = &self.debugger {
+            let mut debugger = debugger.lock().unwrap();
+
+            if debugger.is_code_view() {
+                let (start, end) = CodeWindow::range(self.pc, self.memory.size());
+                debugger.publish_code(CodeWindow {
+                    pc: self.pc,
+                    start: start as u16,
+                    bytes: (start..end).map(|address| self.memory.read(address as u16)).collect(),
+                });
+            }
+        }
+    }
+
+    fn publish_metrics(&mut self, instructions: u32, elapsed: time::Duration) {
+        if let Some(sender) = &self.metrics {
+            let metrics = Metrics {
+                instructions_per_second: ((instructions as f32) / elapsed.as_secs_f32()) as u32,
+                delay_timer: self.delay_timer.get_timer_value(),
+                sound_timer: self.sound_timer.get_timer_value(),
+            };
+
+            /* The receiver is gone when the frontend is closing */
+            if sender.send(metrics).is_err() {
+                self.metrics = None;
+            }
+        }
+    }
 
-        self.pc = ROM_START;
+    /* The next instruction jumps to itself, or waits for a key while none is pressed: nothing can change
+     * until the next timer tick or key event */
+    fn is_idle(&self) -> bool {
+        if self.pc as usize + 1 >= self.memory.size() {
+            return false;
+        }
+        let opcode = (self.memory.read(self.pc) as u16) << 8 | self.memory.read(self.pc + 1) as u16;
+
+        match Instruction::decode(opcode) {
+            Some(Instruction::Jump(address)) => address == self.pc,
+            Some(Instruction::WaitForKey(_)) => {
+                let keypad = lock_keypad(&self.keypad, &self.profiler);
+                !(0..KEYPAD_SIZE).any(|key| keypad.get_is_pressed(key))
+            }
+            _ => false,
+        }
+    }
+
+    /* Sleep until the given time, or until a key changes state, instead of spinning on an idle loop */
+    fn wait_idle(&mut self, until: Instant) {
+        if self.key_events.is_none() {
+            self.key_events = lock_keypad(&self.keypad, &self.profiler).key_events();
+        }
+
+        match &self.key_events {
+            Some(events) => {
+                /* Only the events from now on can wake up the machine */
+                events.try_iter().for_each(drop);
+                let _ = events.recv_timeout(until.saturating_duration_since(Instant::now()));
+            }
+            /* The keys are checked again after the sleep */
+            None => thread::sleep(until.saturating_duration_since(Instant::now())),
+        }
+    }
+
+    /* Run the ROM; this only returns if the execution cannot continue or when asked to quit */
+    pub fn run(&mut self, rom: &[u8]) -> Result<(), Chip8Error> {
+        self.load_program(rom)?;
+
+        let timer_period = time::Duration::from_secs_f32(1.0 / TIMER_FREQUENCY);
+        let mut next_tick = Instant::now() + timer_period;
+
+        let metrics_period = time::Duration::from_millis(METRICS_PERIOD_MS);
+        let mut metrics_start = Instant::now();
+        let mut instructions = 0;
 
         loop {
-            let instr = Instruction::from(self.fetch());
-            self.execute(instr);
+            self.reload_rom();
+            if !self.handle_commands() {
+                return Ok(());
+            }
+
+            if self.is_paused() {
+                /* Timers are stopped too while paused, unless a single frame is run */
+                if self.is_frame_step() {
+                    self.run_frame()?;
+                } else if !self.is_back_step() {
+                    /* The instructions stepped in the debugger are shown right away */
+                    self.present_frame();
+                    thread::sleep(time::Duration::from_millis(PAUSE_POLL_MS));
+                }
+                next_tick = Instant::now() + timer_period;
+                continue;
+            }
+
+            if self.is_breakpoint() {
+                continue;
+            }
 
-            /* TODO : timing can be implemented better; but supposing that the fetch/execution times
-             * are negligible, a 1429us sleep will make the emulator execute ~700 instruction per seconds,
-             * which seems like a speed which fits well enough for most games */
-            let millis = time::Duration::from_micros(1429);
-            thread::sleep(millis);
+            if self.is_idle() {
+                self.wait_idle(next_tick);
+            }
+
+            self.step()?;
+            instructions += 1;
+
+            if self.exited {
+                self.logger.info(format!("Program exited at {:03X}", self.pc));
+                return Ok(());
+            }
+
+            let now = Instant::now();
+            while now >= next_tick {
+                self.tick_timers();
+                self.call_hook(HookEvent::Frame);
+                self.publish_code();
+                next_tick += timer_period;
+            }
+
+            if now - metrics_start >= metrics_period {
+                self.publish_metrics(instructions, now - metrics_start);
+                metrics_start = now;
+                instructions = 0;
+            }
+
+            if self.config.vip_timing {
+                /* Instructions run at full speed until the cycles of the frame are used up, then wait
+                 * for the next frame */
+                if self.is_frame_done() {
+                    self.next_frame();
+                    thread::sleep(next_tick.saturating_duration_since(Instant::now()));
+                }
+            } else {
+                /* TODO : timing can be implemented better; but supposing that the fetch/execution times
+                 * are negligible, sleeping for one period will make the emulator execute ~speed
+                 * instructions per second */
+                let speed = self.speed.load(Ordering::Relaxed).max(1);
+                let millis = time::Duration::from_micros(1_000_000 / (speed as u64));
+                thread::sleep(millis);
+            }
         }
     }
 }
+
+/* Builds a machine from any implementation of the traits of its components, like a GPIO keypad or an
+ * LED matrix; the components not given get a default: the configuration of the default platform, a
+ * keypad only changed with set_is_pressed, no display, the default audio output and no log */
+pub struct Chip8Builder {
+    config: MachineConfig,
+    quirks: Option<Quirks>,
+    speed: u32,
+    keypad: Option<Arc<Mutex<dyn Keypad + Send>>>,
+    display: Option<Box<dyn Display + Send>>,
+    draw_commands: Option<Sender<DrawCommand>>,
+    sound_backend: Option<Box<dyn SoundBackend + Send>>,
+    logger: Option<Box<dyn Logger + Send>>,
+}
+
+impl Chip8Builder {
+    pub fn new() -> Chip8Builder {
+        Chip8Builder {
+            config: MachineConfig::default(),
+            quirks: None,
+            speed: DEFAULT_SPEED,
+            keypad: None,
+            display: None,
+            draw_commands: None,
+            sound_backend: None,
+            logger: None,
+        }
+    }
+
+    pub fn config(mut self, config: MachineConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /* Replace the quirks of the configuration, even if the configuration is set afterwards */
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = Some(quirks);
+        self
+    }
+
+    /* Instructions executed per second by run */
+    pub fn speed(mut self, speed: u32) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /* Keypad shared with the code pressing the keys */
+    pub fn keypad<K: Keypad + Send + 'static>(mut self, keypad: &Arc<Mutex<K>>) -> Self {
+        let keypad: Arc<Mutex<dyn Keypad + Send>> = keypad.clone();
+        self.keypad = Some(keypad);
+        self
+    }
+
+    /* Display updated by the machine thread with each draw command, or with each frame when double
+     * buffered; its size should be the display size of the configuration */
+    pub fn display<D: Display + Send + 'static>(mut self, display: D) -> Self {
+        self.display = Some(Box::new(display));
+        self
+    }
+
+    /* Channel receiving the draw commands, e.g. to draw them on another thread */
+    pub fn draw_commands(mut self, sender: Sender<DrawCommand>) -> Self {
+        self.draw_commands = Some(sender);
+        self
+    }
+
+    pub fn sound_backend<S: SoundBackend + Send + 'static>(mut self, backend: S) -> Self {
+        self.sound_backend = Some(Box::new(backend));
+        self
+    }
+
+    pub fn logger<L: Logger + Send + 'static>(mut self, logger: L) -> Self {
+        self.logger = Some(Box::new(logger));
+        self
+    }
+
+    pub fn build(self) -> Chip8 {
+        let mut config = self.config;
+        if let Some(quirks) = self.quirks {
+            config.quirks = quirks;
+        }
+        let keypad = self.keypad.unwrap_or_else(|| Arc::new(Mutex::new(KeyboardKeypad::new(KEYPAD_SIZE))));
+        let logger = self.logger.unwrap_or_else(|| Box::new(NullLogger));
+
+        let mut chip = Chip8::with_keypad(config, keypad, self.speed, logger);
+        chip.output = self.display;
+        chip.draw_commands = self.draw_commands;
+        if let Some(backend) = self.sound_backend {
+            chip.set_sound_backend(backend);
+        }
+        chip
+    }
+}
+
+impl Default for Chip8Builder {
+    fn default() -> Self {
+        Chip8Builder::new()
+    }
+}
+
+/* The keypad is shared with the frontend; contentions are recorded when profiling. This borrows the
+ * fields instead of the whole machine, so that the registers can be changed while holding the lock */
+fn lock_keypad<'a>(
+    keypad: &'a Mutex<dyn Keypad + Send + 'static>,
+    profiler: &Option<Arc<Mutex<Profiler>>>,
+) -> MutexGuard<'a, dyn Keypad + Send + 'static> {
+    let profiler = match profiler {
+        Some(profiler) => profiler,
+        None => return keypad.lock().unwrap(),
+    };
+
+    match keypad.try_lock() {
+        Ok(keypad) => {
+            profiler.lock().unwrap().record_lock(None);
+            keypad
+        }
+        Err(TryLockError::WouldBlock) => {
+            let start = Instant::now();
+            let keypad = keypad.lock().unwrap();
+            profiler.lock().unwrap().record_lock(Some(start.elapsed()));
+            keypad
+        }
+        Err(TryLockError::Poisoned(error)) => panic!("{}", error),
+    }
+}
+
+/* Stretch a sprite row to twice its width, for the low resolution pixels on the hires display */
+fn double_bits(row: u8) -> u16 {
+    (0..8)
+        .filter(|bit| row & (0x80 >> bit) != 0)
+        .fold(0, |doubled, bit| doubled | (0xC000 >> (2 * bit)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sound::NullBackend;
+
+    #[test]
+    fn frame_cycles() {
+        /* CLS; JP 0x200, starting where adding the cycles of CLS overflowed a u32 */
+        let mut chip = Chip8::builder().sound_backend(NullBackend).build();
+        chip.load_program(&[0x00, 0xE0, 0x12, 0x00]).unwrap();
+        chip.frame_cycles = u32::MAX - 100;
+        for _ in 0..4 {
+            chip.step().unwrap();
+        }
+        assert_eq!(chip.frame_cycles, u32::MAX - 100);
+
+        chip.config.vip_timing = true;
+        chip.step().unwrap();
+        assert_eq!(chip.frame_cycles, u32::MAX);
+    }
+}
@@ -0,0 +1,692 @@
+use crate::debugger::Watchpoint;
+use crate::effects;
+use crate::fonts::FontSet;
+use crate::hsl::RGB8Pixel;
+use crate::keyboard_layout;
+use crate::logger::LogFormat;
+use crate::platform::{MachineConfig, Platform, Quirks};
+use crate::render_style;
+use crate::rom::RomFormat;
+
+use clap::{App, AppSettings, Arg, ArgMatches, Shell, SubCommand};
+use std::ffi::OsString;
+use std::net::IpAddr;
+
+pub const SUBCOMMANDS: [&str; 13] = [
+    "run", "disasm", "analyze", "sprites", "memdump", "asm", "test", "verify", "diffstate", "bench", "info",
+    "import-archive", "completions",
+];
+
+/* Values like a saturation or a lightness, between 0.0 and 1.0 */
+fn validate_unit(value: String) -> Result<(), String> {
+    match value.parse::<f32>() {
+        Ok(value) if (0.0..=1.0).contains(&value) => Ok(()),
+        Ok(_) => Err(String::from("the value must be between 0.0 and 1.0")),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+/* Addresses are hexadecimal, with or without the "0x" prefix */
+pub fn parse_address(value: &str) -> Option<u16> {
+    u16::from_str_radix(value.trim_start_matches("0x"), 16).ok()
+}
+
+fn validate_address(value: String) -> Result<(), String> {
+    parse_address(&value).map(|_| ()).ok_or(format!("invalid address: {}", value))
+}
+
+/* The ROM must start in the memory, whose size is the same on every platform */
+fn validate_rom_start(value: String) -> Result<(), String> {
+    match parse_address(&value) {
+        Some(address) if (address as usize) < MachineConfig::default().memory_size => Ok(()),
+        Some(_) => Err(format!("the address must be below {:X}", MachineConfig::default().memory_size)),
+        None => Err(format!("invalid address: {}", value)),
+    }
+}
+
+fn validate_count(count: String) -> Result<(), String> {
+    count.parse::<u64>().map(|_| ()).map_err(|error| error.to_string())
+}
+
+fn rom_arg() -> Arg<'static, 'static> {
+    Arg::with_name("rom")
+        .short("r")
+        .long("rom")
+        .value_name("FILE")
+        .help("Path to the CHIP-8 ROM file; use \"-\" to read from stdin or an HTTP(S) URL to download it")
+        .required(true)
+        .takes_value(true)
+}
+
+fn format_arg() -> Arg<'static, 'static> {
+    Arg::with_name("format")
+        .long("format")
+        .value_name("FORMAT")
+        .help("Encoding of the ROM: \"hex\" for hex text, as in magazine listings; \"auto\" detects it")
+        .possible_values(&RomFormat::NAMES)
+        .default_value("auto")
+        .takes_value(true)
+}
+
+/* Options defining the emulated machine, shared by the subcommands running a ROM */
+fn machine_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("platform")
+            .long("platform")
+            .value_name("NAME")
+            .help("Machine to emulate, which defines the memory layout and the display size")
+            .possible_values(&Platform::NAMES)
+            .default_value("chip8")
+            .takes_value(true),
+        Arg::with_name("rom-start")
+            .long("rom-start")
+            .value_name("ADDRESS")
+            .help("Override the address where the ROM is loaded, in hexadecimal (e.g. 0x200)")
+            .validator(validate_rom_start)
+            .takes_value(true),
+        Arg::with_name("font")
+            .long("font")
+            .value_name("NAME")
+            .help("Built-in font of an interpreter, changing the look of the digits")
+            .possible_values(&FontSet::NAMES)
+            .takes_value(true),
+        Arg::with_name("quirk")
+            .long("quirk")
+            .value_name("NAME")
+            .help("Enable a behavior of the original interpreters which some older ROMs rely on")
+            .possible_values(&Quirks::NAMES)
+            .multiple(true)
+            .number_of_values(1)
+            .takes_value(true),
+        Arg::with_name("vip-timing")
+            .long("vip-timing")
+            .help("Take the time of each instruction on the COSMAC VIP, ignoring the speed"),
+    ]
+}
+
+/* Databases with per-game settings and descriptions, keyed by the ROM hash */
+fn database_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("game-db")
+            .long("game-db")
+            .value_name("FILE")
+            .help("Path to a JSON database of per-game settings, keyed by the ROM SHA-1")
+            .takes_value(true),
+        Arg::with_name("metadata")
+            .long("metadata")
+            .value_name("FILE")
+            .help("Path to a JSON file describing ROMs, keyed by the ROM SHA-1")
+            .takes_value(true),
+    ]
+}
+
+/* Options of the emulator window and of the tools attached to it */
+fn window_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("watch")
+            .long("watch")
+            .help("Reset and reload the ROM each time the file changes")
+            .takes_value(false),
+        Arg::with_name("verbose")
+            .short("v")
+            .long("verbose")
+            .multiple(true)
+            .help("Increase the log verbosity; use -vv to trace every instruction"),
+        Arg::with_name("log-stderr")
+            .long("log-stderr")
+            .help("Print the log messages on the standard error as well as in the log file")
+            .takes_value(false),
+        Arg::with_name("log-format")
+            .long("log-format")
+            .value_name("FORMAT")
+            .help("Format of the log file: \"json\" writes an object per message, with the instruction being executed")
+            .possible_values(&LogFormat::NAMES)
+            .default_value("text")
+            .takes_value(true),
+        Arg::with_name("profile")
+            .long("profile")
+            .help("Collect per-instruction execution statistics, printed on exit or by pressing F6")
+            .takes_value(false),
+        Arg::with_name("virtual-keypad")
+            .long("virtual-keypad")
+            .help("Show a hex keypad below the display, which can be used with the mouse")
+            .takes_value(false),
+        Arg::with_name("split-screen")
+            .long("split-screen")
+            .value_name("FILE")
+            .help("Run a second ROM, or the same one again, side by side, played with the keys 7-0, U-P, J-; and M-/")
+            .takes_value(true),
+        Arg::with_name("crt")
+            .long("crt")
+            .help("Emulate the look of a CRT screen, with scanlines and rounded corners")
+            .takes_value(false),
+        Arg::with_name("visual-beep")
+            .long("visual-beep")
+            .help("Flash a border around the display while the sound plays, to see the beeps with the sound muted")
+            .takes_value(false),
+        Arg::with_name("pixel-aspect")
+            .long("pixel-aspect")
+            .value_name("RATIO")
+            .help("Width of each pixel relative to its height, e.g. 2 to stretch the display like a TV")
+            .validator(|ratio| match ratio.parse::<f64>() {
+                Ok(ratio) if ratio > 0.0 => Ok(()),
+                Ok(_) => Err(String::from("the ratio must be positive")),
+                Err(error) => Err(error.to_string()),
+            })
+            .takes_value(true),
+        Arg::with_name("keyboard-layout")
+            .long("keyboard-layout")
+            .help("Layout of the keyboard, so that the keypad keys stay in the same place as on a QWERTY keyboard; \"qwerty\" maps the keys by their symbols")
+            .possible_values(&keyboard_layout::NAMES)
+            .default_value("qwerty")
+            .takes_value(true),
+        Arg::with_name("turbo")
+            .long("turbo")
+            .value_name("KEY")
+            .help("Keypad key (0-F) pressed and released on its own while held, e.g. to keep shooting")
+            .multiple(true)
+            .number_of_values(1)
+            .validator(|key| match usize::from_str_radix(&key, 16) {
+                Ok(key) if key < 16 => Ok(()),
+                _ => Err(format!("invalid key: {}", key)),
+            })
+            .takes_value(true),
+        Arg::with_name("turbo-rate")
+            .long("turbo-rate")
+            .value_name("HZ")
+            .help("Presses per second of the turbo keys, 10 by default")
+            .validator(|rate| match rate.parse::<f64>() {
+                Ok(rate) if rate > 0.0 => Ok(()),
+                Ok(_) => Err(String::from("the rate must be positive")),
+                Err(error) => Err(error.to_string()),
+            })
+            .takes_value(true),
+        Arg::with_name("immediate-draw")
+            .long("immediate-draw")
+            .help("Show each sprite as soon as it's drawn instead of whole frames, with the tearing of the original interpreters")
+            .takes_value(false),
+        Arg::with_name("pixel-shape")
+            .long("pixel-shape")
+            .help("Draw the pixels as squares filling the display, or as round LEDs with gaps between them")
+            .possible_values(&render_style::NAMES)
+            .default_value("square")
+            .takes_value(true),
+        Arg::with_name("grid")
+            .long("grid")
+            .help("Draw faint lines between the pixels")
+            .takes_value(false),
+        Arg::with_name("glow")
+            .long("glow")
+            .help("Draw a glow around the lit pixels, like LED panels and CRTs")
+            .takes_value(false),
+        Arg::with_name("rotate")
+            .long("rotate")
+            .value_name("DEGREES")
+            .help("Rotate the display clockwise, to play vertical games on a rotated monitor")
+            .possible_values(&["90", "180", "270"])
+            .takes_value(true),
+        Arg::with_name("ghosting")
+            .long("ghosting")
+            .value_name("FRAMES")
+            .help("Fade out turned off pixels over the given number of frames, to reduce flickering")
+            .validator(|frames| frames.parse::<u32>().map(|_| ()).map_err(|error| error.to_string()))
+            .takes_value(true),
+        Arg::with_name("blend")
+            .long("blend")
+            .help("Show each frame ORed with the previous one, to smooth the flickering of games like BRIX; Ctrl+B toggles it")
+            .takes_value(false),
+        Arg::with_name("effect")
+            .long("effect")
+            .help("Color effect applied to the display")
+            .possible_values(&effects::NAMES)
+            .takes_value(true),
+        Arg::with_name("nyan")
+            .long("nyan")
+            .help("Enter \"Nyan Cat\" mode; same as --effect hue-cycle")
+            .conflicts_with("effect")
+            .takes_value(false),
+        Arg::with_name("cycle-step")
+            .long("cycle-step")
+            .value_name("DEGREES")
+            .help("Hue added on each frame by the hue cycling effects; negative values cycle backwards")
+            .validator(|step| step.parse::<i32>().map(|_| ()).map_err(|error| error.to_string()))
+            .allow_hyphen_values(true)
+            .takes_value(true),
+        Arg::with_name("cycle-saturation")
+            .long("cycle-saturation")
+            .value_name("VALUE")
+            .help("Saturation of the hue-cycle effect, from 0.0 to 1.0")
+            .validator(validate_unit)
+            .takes_value(true),
+        Arg::with_name("cycle-lightness")
+            .long("cycle-lightness")
+            .value_name("VALUE")
+            .help("Lightness of the hue-cycle effect, from 0.0 to 1.0")
+            .validator(validate_unit)
+            .takes_value(true),
+        Arg::with_name("pulse-color")
+            .long("pulse-color")
+            .value_name("RRGGBB")
+            .help("Color the display flashes to when a sound starts with the pulse effect, in hexadecimal")
+            .validator(|color| color.parse::<RGB8Pixel>().map(|_| ()))
+            .takes_value(true),
+        Arg::with_name("pulse-frames")
+            .long("pulse-frames")
+            .value_name("FRAMES")
+            .help("Frames taken by the pulse effect to fade back to the foreground color")
+            .validator(|frames| frames.parse::<u32>().map(|_| ()).map_err(|error| error.to_string()))
+            .takes_value(true),
+        Arg::with_name("resume")
+            .long("resume")
+            .help("Continue from the state saved when the emulator was last closed with the same ROM")
+            .takes_value(false),
+        Arg::with_name("watchpoint")
+            .long("watchpoint")
+            .value_name("LOCATION")
+            .help("Pause when a register (V0-VF) or a memory address (hexadecimal) is read or written")
+            .multiple(true)
+            .number_of_values(1)
+            .validator(|location| location.parse::<Watchpoint>().map(|_| ()))
+            .takes_value(true),
+        Arg::with_name("seed")
+            .long("seed")
+            .value_name("N")
+            .help("Seed of the random numbers of CXNN, so that a game behaves the same on each run")
+            .validator(|seed| seed.parse::<u64>().map(|_| ()).map_err(|error| error.to_string()))
+            .takes_value(true),
+        Arg::with_name("history")
+            .long("history")
+            .value_name("N")
+            .help("Record the last N instructions, so that the debugger can step back with Backspace or \"back\"; 0 disables it")
+            .default_value("100000")
+            .validator(validate_count)
+            .takes_value(true),
+        Arg::with_name("console")
+            .long("console")
+            .help("Read debugger commands from the terminal, like \"break 0x2A0\" or \"regs\"; type \"help\" for the list")
+            .takes_value(false),
+        Arg::with_name("serve")
+            .long("serve")
+            .value_name("PORT")
+            .help("Publish the machine state and accept remote commands over WebSocket on the given port")
+            .validator(|port| port.parse::<u16>().map(|_| ()).map_err(|error| error.to_string()))
+            .takes_value(true),
+        Arg::with_name("serve-address")
+            .long("serve-address")
+            .value_name("ADDRESS")
+            .help("Address the WebSocket server listens on; 0.0.0.0 accepts the clients of every network interface")
+            .default_value("127.0.0.1")
+            .validator(|address| address.parse::<IpAddr>().map(|_| ()).map_err(|error| error.to_string()))
+            .takes_value(true),
+        Arg::with_name("netplay-host")
+            .long("netplay-host")
+            .value_name("PORT")
+            .help("Share the keypad with the instances connecting to the given port, to play with a friend")
+            .validator(|port| port.parse::<u16>().map(|_| ()).map_err(|error| error.to_string()))
+            .conflicts_with("netplay-connect")
+            .takes_value(true),
+        Arg::with_name("netplay-connect")
+            .long("netplay-connect")
+            .value_name("ADDRESS")
+            .help("Share the keypad with the instance hosting at ADDRESS, given as host:port")
+            .takes_value(true),
+        Arg::with_name("exit-code")
+            .long("exit-code")
+            .value_name("CODE")
+            .help("Exit code of the emulator when the program stops with 00FD, e.g. to report test results")
+            .validator(|code| code.parse::<i32>().map(|_| ()).map_err(|error| error.to_string()))
+            .takes_value(true),
+        Arg::with_name("script")
+            .long("script")
+            .value_name("FILE")
+            .help("Rhai script handling the machine events, to automate tests or write bots and trainers")
+            .takes_value(true),
+        Arg::with_name("dump-display")
+            .long("dump-display")
+            .value_name("FILE")
+            .help("Write the final display to FILE on exit: a PBM image if it ends in .pbm, its SHA-1 otherwise")
+            .takes_value(true),
+        Arg::with_name("record-audio")
+            .long("record-audio")
+            .value_name("FILE")
+            .help("Record the tone of the sound timer to FILE as a WAV file, alongside the gameplay")
+            .takes_value(true),
+        Arg::with_name("heatmap")
+            .long("heatmap")
+            .value_name("FILE")
+            .help("Count the reads and writes of each memory address, shown by the \"heat\" console command, and write them to FILE on exit as a PPM image")
+            .takes_value(true),
+    ]
+}
+
+/* Address where the program is loaded, for the tools working on a ROM without running it */
+fn start_arg() -> Arg<'static, 'static> {
+    Arg::with_name("start")
+        .long("start")
+        .value_name("ADDRESS")
+        .help("Address where the program is loaded, in hexadecimal")
+        .default_value("0x200")
+        .validator(validate_address)
+        .takes_value(true)
+}
+
+fn trace_arg() -> Arg<'static, 'static> {
+    Arg::with_name("trace")
+        .long("trace")
+        .value_name("FILE")
+        .help("Write PC, opcode, V0-VF, I and SP before each instruction to FILE, or to the standard output if \"-\", to diff runs with other emulators")
+        .takes_value(true)
+}
+
+fn symbols_arg() -> Arg<'static, 'static> {
+    Arg::with_name("symbols")
+        .long("symbols")
+        .value_name("FILE")
+        .help("Symbol file with a label and its address on each line, like \"main 0x200\", to show the labels instead of the addresses; the debugger accepts them as @LABEL")
+        .takes_value(true)
+}
+
+fn detect_quirks_arg() -> Arg<'static, 'static> {
+    Arg::with_name("detect-quirks")
+        .long("detect-quirks")
+        .help("Run the ROM briefly with each combination of the quirks it depends on, and use the one which gets the farthest")
+        .takes_value(false)
+}
+
+fn coverage_arg() -> Arg<'static, 'static> {
+    Arg::with_name("coverage")
+        .long("coverage")
+        .value_name("FILE")
+        .help("Write which parts of the ROM were executed to FILE on exit: the disassembly with the execution counts if it ends in .asm, a summary otherwise")
+        .takes_value(true)
+}
+
+fn cycles_arg(default: &'static str) -> Arg<'static, 'static> {
+    Arg::with_name("cycles")
+        .long("cycles")
+        .value_name("N")
+        .help("Number of instructions to execute")
+        .default_value(default)
+        .validator(validate_count)
+        .takes_value(true)
+}
+
+pub fn build() -> App<'static, 'static> {
+    App::new("chip8rs")
+        .version("0.0.1")
+        .author("Lorenzo A.")
+        .about("CHIP-8 emulator written in Rust")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("run")
+                .about("Run a ROM in a window; the default when no subcommand is given")
+                .args(&[rom_arg(), format_arg()])
+                .args(&machine_args())
+                .args(&database_args())
+                .args(&window_args())
+                .arg(trace_arg())
+                .arg(symbols_arg())
+                .arg(coverage_arg())
+                .arg(detect_quirks_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("disasm")
+                .about("Print the instructions of a ROM, in a format which can be assembled again")
+                .args(&[rom_arg(), format_arg()])
+                .arg(start_arg())
+                .arg(symbols_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("analyze")
+                .about("Follow the code of a ROM and report the constructs and the instruction set it needs to run")
+                .args(&[rom_arg(), format_arg()])
+                .arg(start_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("sprites")
+                .about("Show the sprites drawn by a ROM, to browse its graphics")
+                .args(&[rom_arg(), format_arg()])
+                .arg(start_arg())
+                .arg(
+                    Arg::with_name("height")
+                        .long("height")
+                        .value_name("N")
+                        .help("Show every run of N bytes as a sprite, instead of the ones drawn by the program")
+                        .validator(|height| match height.parse::<usize>() {
+                            Ok(1..=16) => Ok(()),
+                            Ok(_) => Err(String::from("the value must be between 1 and 16")),
+                            Err(error) => Err(error.to_string()),
+                        })
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .value_name("FILE")
+                        .help("Also write all the sprites to a PBM image")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("memdump")
+                .about("Load a ROM and print the memory, with the areas of the fonts and of the ROM")
+                .args(&[rom_arg(), format_arg()])
+                .args(&machine_args())
+                .arg(
+                    Arg::with_name("at")
+                        .long("at")
+                        .value_name("ADDRESS")
+                        .help("First address to print, in hexadecimal")
+                        .default_value("0x000")
+                        .validator(validate_address)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("len")
+                        .long("len")
+                        .value_name("BYTES")
+                        .help("Number of bytes to print; the whole memory after --at if not given")
+                        .validator(validate_count)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("asm")
+                .about("Assemble a program written with the mnemonics printed by disasm")
+                .arg(
+                    Arg::with_name("source")
+                        .long("source")
+                        .value_name("FILE")
+                        .help("Path to the source file")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .value_name("FILE")
+                        .help("Path of the ROM to write")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(start_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("test")
+                .about("Run a ROM headlessly and check what it draws, e.g. from a shell script")
+                .args(&[rom_arg(), format_arg()])
+                .args(&machine_args())
+                .arg(cycles_arg("1000000").help("Number of instructions to execute, unless the program exits before"))
+                .arg(trace_arg())
+                .arg(symbols_arg())
+                .arg(coverage_arg())
+                .arg(detect_quirks_arg())
+                .arg(
+                    Arg::with_name("dump-display")
+                        .long("dump-display")
+                        .value_name("FILE")
+                        .help("Write the final display to FILE: a PBM image if it ends in .pbm, its SHA-1 otherwise")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("expect")
+                        .long("expect")
+                        .value_name("SHA1")
+                        .help("Fail unless the SHA-1 of the final display is the given one")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("verify")
+                .about("Run a ROM on this core and on a reference interpreter, or on the core with other quirks, and report the first divergence")
+                .args(&[rom_arg(), format_arg()])
+                .args(&machine_args())
+                .arg(cycles_arg("100000"))
+                .arg(
+                    Arg::with_name("every")
+                        .long("every")
+                        .value_name("N")
+                        .help("Compare the two machines every N instructions")
+                        .default_value("1")
+                        .validator(|every| match every.parse::<u64>() {
+                            Ok(0) => Err(String::from("the value must be at least 1")),
+                            Ok(_) => Ok(()),
+                            Err(error) => Err(error.to_string()),
+                        })
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("against-quirk")
+                        .long("against-quirk")
+                        .value_name("NAME")
+                        .help("Compare with the core running with these quirks, instead of the reference interpreter; can be repeated")
+                        .possible_values(&Quirks::NAMES)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("diffstate")
+                .about("Compare two save states and print the registers, memory ranges and display regions which differ")
+                .arg(
+                    Arg::with_name("first")
+                        .value_name("FIRST")
+                        .help("Path to the first save state")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("second")
+                        .value_name("SECOND")
+                        .help("Path to the second save state")
+                        .required(true)
+                        .index(2),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("bench")
+                .about("Run a ROM headlessly as fast as possible and report the emulator performance")
+                .args(&[rom_arg(), format_arg()])
+                .args(&machine_args())
+                .arg(cycles_arg("10000000")),
+        )
+        .subcommand(
+            SubCommand::with_name("info")
+                .about("Print the hash of a ROM and what the databases know about it")
+                .args(&[rom_arg(), format_arg()])
+                .args(&database_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("import-archive")
+                .about("Print the ROM metadata of the programs of the CHIP-8 Archive, to be used with --metadata")
+                .arg(
+                    Arg::with_name("programs")
+                        .value_name("PROGRAMS")
+                        .help("programs.json of the archive")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("roms")
+                        .value_name("ROMS")
+                        .help("Directory of the ROMs of the archive")
+                        .required(true)
+                        .index(2),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("completions")
+                .about("Print the completion script of a shell, listing the subcommands, the options and their values")
+                .arg(
+                    Arg::with_name("shell")
+                        .value_name("SHELL")
+                        .help("Shell loading the script")
+                        .possible_values(&Shell::variants())
+                        .required(true)
+                        .index(1),
+                ),
+        )
+}
+
+/* Invocations without a subcommand, like "chip8rs --rom game.ch8", are handled by "run" */
+pub fn with_default_subcommand<I: IntoIterator<Item = OsString>>(args: I) -> Vec<OsString> {
+    let mut args: Vec<OsString> = args.into_iter().collect();
+
+    let explicit = match args.get(1).and_then(|arg| arg.to_str()) {
+        Some(arg) => SUBCOMMANDS.contains(&arg) || ["help", "-h", "--help", "-V", "--version"].contains(&arg),
+        None => true,
+    };
+    if !explicit {
+        args.insert(1, OsString::from("run"));
+    }
+
+    args
+}
+
+/* Configuration of the emulated machine, from the options of machine_args */
+pub fn machine_config(matches: &ArgMatches) -> MachineConfig {
+    let platform: Platform = matches.value_of("platform").unwrap().parse().unwrap();
+
+    let mut config = platform.config();
+    if let Some(address) = matches.value_of("rom-start") {
+        config.rom_start = parse_address(address).unwrap();
+    }
+    if let Some(font) = matches.value_of("font") {
+        config.font = font.parse().unwrap();
+    }
+    for quirk in matches.values_of("quirk").into_iter().flatten() {
+        config.quirks.enable(quirk).unwrap();
+    }
+    config.vip_timing = matches.is_present("vip-timing");
+
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn completions() {
+        let mut script = Vec::new();
+        build().gen_completions_to("chip8rs", Shell::Bash, &mut script);
+        let script = String::from_utf8(script).unwrap();
+
+        assert!(script.contains("--platform"));
+        assert!(script.contains(&Platform::NAMES.join(" ")));
+        assert!(script.contains(&effects::NAMES.join(" ")));
+    }
+
+    #[test]
+    fn rom_start() {
+        let run = |address| build().get_matches_from_safe(vec!["chip8rs", "run", "-r", "a", "--rom-start", address]);
+
+        let matches = run("0x600").unwrap();
+        assert_eq!(machine_config(matches.subcommand_matches("run").unwrap()).rom_start, 0x600);
+        assert!(run("0x1000").is_err());
+        assert!(run("0xZ00").is_err());
+    }
+}
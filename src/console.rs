@@ -0,0 +1,388 @@
+use crate::debugger::{format_registers, hexdump, Debugger, Edit, StepMode, Watchpoint};
+use crate::symbols::Symbols;
+
+use std::fmt::Write;
+use std::io::{self, BufRead};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+const PROMPT: &str = "(chip8rs) ";
+
+/* Number of bytes shown by "mem" when the length is not given */
+const DEFAULT_MEMORY_LENGTH: usize = 64;
+
+const HELP: &str = "\
+Addresses can also be given as @LABEL, with --symbols
+
+break ADDR        stop before executing the instruction at ADDR
+delete ADDR       remove the breakpoint at ADDR
+watch LOCATION    stop when a register (V0-VF) or a memory address is accessed
+unwatch LOCATION  remove a watchpoint
+list              show the breakpoints and the watchpoints
+pause             stop the execution
+continue          resume the execution
+step [N]          execute N instructions
+next [N]          execute N instructions, running subroutine calls to completion
+finish            run until the current subroutine returns
+back [N]          undo the last N instructions
+frame             run the instructions of a single 60Hz frame and tick the timers
+regs              show the registers, the timers and the stack
+mem ADDR [LEN]    show LEN bytes of memory starting from ADDR
+heat [ADDR [LEN]] show how often each address was read and written, with --heatmap
+set REG VALUE     while paused, set V0-VF, PC, I, DT or ST to VALUE
+write ADDR BYTES  while paused, write the bytes to memory starting from ADDR
+help              show this message
+";
+
+/* Commands accepted by the debugger console */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConsoleCommand {
+    Break(u16),
+    Delete(u16),
+    Watch(Watchpoint),
+    Unwatch(Watchpoint),
+    List,
+    Pause,
+    Continue,
+    Step(StepMode, u32),
+    StepBack(u32),
+    Frame,
+    Registers,
+    Memory { start: u16, len: usize },
+    Heatmap { start: u16, len: Option<usize> },
+    Edit(Edit),
+    Help,
+}
+
+/* Addresses are hexadecimal, with or without the "0x" prefix */
+fn parse_address(arg: Option<&str>) -> Result<u16, String> {
+    let arg = arg.ok_or("Missing address")?;
+    u16::from_str_radix(arg.trim_start_matches("0x"), 16).map_err(|_| format!("Invalid address: {}", arg))
+}
+
+/* Values are hexadecimal too */
+fn parse_byte(arg: Option<&str>) -> Result<u8, String> {
+    let arg = arg.ok_or("Missing value")?;
+    u8::from_str_radix(arg.trim_start_matches("0x"), 16).map_err(|_| format!("Invalid byte: {}", arg))
+}
+
+/* "set" target, a register or PC, I, DT and ST */
+fn parse_set(target: Option<&str>, value: Option<&str>) -> Result<Edit, String> {
+    let target = target.ok_or("Missing register")?;
+
+    let edit = match target.to_ascii_uppercase().as_str() {
+        "PC" => Edit::Pc(parse_address(value)?),
+        "I" => Edit::Index(parse_address(value)?),
+        "DT" => Edit::DelayTimer(parse_byte(value)?),
+        "ST" => Edit::SoundTimer(parse_byte(value)?),
+        _ => match target.parse()? {
+            Watchpoint::Register(reg) => Edit::Register(reg, parse_byte(value)?),
+            Watchpoint::Memory(_) => return Err(format!("Invalid register: {}", target)),
+        },
+    };
+
+    Ok(edit)
+}
+
+fn parse_count(arg: Option<&str>) -> Result<u32, String> {
+    match arg {
+        Some(arg) => arg.parse().map_err(|_| format!("Invalid count: {}", arg)),
+        None => Ok(1),
+    }
+}
+
+impl FromStr for ConsoleCommand {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut words = line.split_whitespace();
+        let name = words.next().ok_or("Empty command")?;
+        let arg = words.next();
+
+        let command = match name {
+            "break" | "b" => ConsoleCommand::Break(parse_address(arg)?),
+            "delete" | "d" => ConsoleCommand::Delete(parse_address(arg)?),
+            "watch" | "w" => ConsoleCommand::Watch(arg.ok_or("Missing location")?.parse()?),
+            "unwatch" => ConsoleCommand::Unwatch(arg.ok_or("Missing location")?.parse()?),
+            "list" | "l" => ConsoleCommand::List,
+            "pause" | "p" => ConsoleCommand::Pause,
+            "continue" | "c" => ConsoleCommand::Continue,
+            "step" | "s" => ConsoleCommand::Step(StepMode::Into, parse_count(arg)?),
+            "next" | "n" => ConsoleCommand::Step(StepMode::Over, parse_count(arg)?),
+            "finish" | "f" => ConsoleCommand::Step(StepMode::Out, 1),
+            "back" => ConsoleCommand::StepBack(parse_count(arg)?),
+            "frame" => ConsoleCommand::Frame,
+            "regs" | "r" => ConsoleCommand::Registers,
+            "mem" | "m" => ConsoleCommand::Memory {
+                start: parse_address(arg)?,
+                len: match words.next() {
+                    Some(len) => len.parse().map_err(|_| format!("Invalid length: {}", len))?,
+                    None => DEFAULT_MEMORY_LENGTH,
+                },
+            },
+            "heat" => ConsoleCommand::Heatmap {
+                start: match arg {
+                    Some(_) => parse_address(arg)?,
+                    None => 0,
+                },
+                len: match words.next() {
+                    Some(len) => Some(len.parse().map_err(|_| format!("Invalid length: {}", len))?),
+                    None => None,
+                },
+            },
+            "set" => ConsoleCommand::Edit(parse_set(arg, words.next())?),
+            "write" => {
+                let address = parse_address(arg)?;
+                let bytes = words.map(|word| parse_byte(Some(word))).collect::<Result<Vec<u8>, String>>()?;
+                if bytes.is_empty() {
+                    return Err(String::from("Missing bytes"));
+                }
+                ConsoleCommand::Edit(Edit::Memory(address, bytes))
+            }
+            "help" | "h" => ConsoleCommand::Help,
+            _ => return Err(format!("Unknown command: {}; try \"help\"", name)),
+        };
+
+        Ok(command)
+    }
+}
+
+/* Parse a command, after replacing the labels with their addresses */
+pub fn parse_command(line: &str, symbols: &Symbols) -> Result<ConsoleCommand, String> {
+    symbols.expand(line)?.parse()
+}
+
+/* Address followed by its label, if it has one */
+fn describe(address: u16, symbols: &Symbols) -> String {
+    match symbols.label(address) {
+        Some(name) => format!("{:03X} ({})", address, name),
+        None => format!("{:03X}", address),
+    }
+}
+
+/* Execute a command, returning the text to show */
+pub fn execute(command: ConsoleCommand, debugger: &mut Debugger) -> String {
+    /* Inspecting the state needs the snapshot published by the CHIP-8 thread when it stops */
+    let not_paused = String::from("The execution is not paused\n");
+
+    match command {
+        ConsoleCommand::Break(address) => {
+            debugger.add_breakpoint(address);
+            format!("Breakpoint at {}\n", describe(address, debugger.symbols()))
+        }
+        ConsoleCommand::Delete(address) => {
+            debugger.remove_breakpoint(address);
+            format!("Removed breakpoint at {}\n", describe(address, debugger.symbols()))
+        }
+        ConsoleCommand::Watch(watchpoint) => {
+            debugger.add_watchpoint(watchpoint);
+            format!("Watching {}\n", watchpoint)
+        }
+        ConsoleCommand::Unwatch(watchpoint) => {
+            debugger.remove_watchpoint(watchpoint);
+            format!("Removed watchpoint on {}\n", watchpoint)
+        }
+        ConsoleCommand::List => {
+            let mut output = String::new();
+            for &address in debugger.breakpoints() {
+                let _ = writeln!(output, "Breakpoint at {}", describe(address, debugger.symbols()));
+            }
+            for watchpoint in debugger.watchpoints() {
+                let _ = writeln!(output, "Watchpoint on {}", watchpoint);
+            }
+            output
+        }
+        ConsoleCommand::Pause => {
+            debugger.set_paused(true);
+            String::new()
+        }
+        ConsoleCommand::Continue => {
+            debugger.set_paused(false);
+            String::new()
+        }
+        ConsoleCommand::Step(mode, count) => {
+            if debugger.step(mode, count) {
+                String::new()
+            } else if debugger.is_paused() && mode == StepMode::Out {
+                String::from("Not in a subroutine\n")
+            } else {
+                not_paused
+            }
+        }
+        ConsoleCommand::StepBack(count) => {
+            if debugger.step_back(count) {
+                String::new()
+            } else if debugger.is_paused() {
+                String::from("No instruction to step back to; the history is empty or disabled\n")
+            } else {
+                not_paused
+            }
+        }
+        ConsoleCommand::Frame => {
+            if debugger.step_frame() {
+                String::new()
+            } else {
+                not_paused
+            }
+        }
+        ConsoleCommand::Registers => match debugger.snapshot() {
+            Some(snapshot) => format_registers(snapshot),
+            None => not_paused,
+        },
+        ConsoleCommand::Memory { start, len } => match debugger.snapshot() {
+            Some(snapshot) => hexdump(snapshot, start as usize, len),
+            None => not_paused,
+        },
+        /* The counts are updated while running, so the heatmap doesn't need the execution to be paused */
+        ConsoleCommand::Heatmap { start, len } => match debugger.heatmap() {
+            Some(heatmap) => heatmap.to_text(start as usize, len.unwrap_or(heatmap.size())),
+            None => String::from("The heatmap is not enabled; start with --heatmap FILE\n"),
+        },
+        ConsoleCommand::Edit(edit) => match debugger.edit(edit) {
+            Ok(()) => String::new(),
+            Err(error) => format!("{}\n", error),
+        },
+        ConsoleCommand::Help => String::from(HELP),
+    }
+}
+
+/* Read the commands from the standard input on a separate thread; the console stops when the input
+ * is closed */
+pub fn spawn(debugger: Arc<Mutex<Debugger>>) {
+    thread::spawn(move || {
+        print!("{}", PROMPT);
+        let _ = io::Write::flush(&mut io::stdout());
+
+        for line in io::stdin().lock().lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+
+            if !line.trim().is_empty() {
+                let mut debugger = debugger.lock().unwrap();
+                match parse_command(&line, debugger.symbols()) {
+                    Ok(command) => print!("{}", execute(command, &mut debugger)),
+                    Err(error) => println!("{}", error),
+                }
+            }
+
+            print!("{}", PROMPT);
+            let _ = io::Write::flush(&mut io::stdout());
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debugger::Snapshot;
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!("break 0x2A0".parse(), Ok(ConsoleCommand::Break(0x2A0)));
+        assert_eq!("b 2a0".parse(), Ok(ConsoleCommand::Break(0x2A0)));
+        assert_eq!("watch VF".parse(), Ok(ConsoleCommand::Watch(Watchpoint::Register(0xF))));
+        assert_eq!("step".parse(), Ok(ConsoleCommand::Step(StepMode::Into, 1)));
+        assert_eq!("step 10".parse(), Ok(ConsoleCommand::Step(StepMode::Into, 10)));
+        assert_eq!("finish".parse(), Ok(ConsoleCommand::Step(StepMode::Out, 1)));
+        assert_eq!("back 3".parse(), Ok(ConsoleCommand::StepBack(3)));
+        assert_eq!(
+            "mem 0x300 32".parse(),
+            Ok(ConsoleCommand::Memory { start: 0x300, len: 32 })
+        );
+        assert_eq!(
+            "mem 300".parse(),
+            Ok(ConsoleCommand::Memory {
+                start: 0x300,
+                len: DEFAULT_MEMORY_LENGTH
+            })
+        );
+        assert_eq!("heat".parse(), Ok(ConsoleCommand::Heatmap { start: 0, len: None }));
+        assert_eq!(
+            "heat f00 256".parse(),
+            Ok(ConsoleCommand::Heatmap {
+                start: 0xF00,
+                len: Some(256)
+            })
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!("".parse::<ConsoleCommand>().is_err());
+        assert!("jump 0x200".parse::<ConsoleCommand>().is_err());
+        assert!("break".parse::<ConsoleCommand>().is_err());
+        assert!("break xyz".parse::<ConsoleCommand>().is_err());
+        assert!("watch V10".parse::<ConsoleCommand>().is_err());
+        assert!("step many".parse::<ConsoleCommand>().is_err());
+        assert!("set V0".parse::<ConsoleCommand>().is_err());
+        assert!("set 0x200 1".parse::<ConsoleCommand>().is_err());
+        assert!("write 0x300".parse::<ConsoleCommand>().is_err());
+        assert!("write 0x300 100".parse::<ConsoleCommand>().is_err());
+    }
+
+    #[test]
+    fn breakpoints() {
+        let mut debugger = Debugger::new();
+
+        execute(ConsoleCommand::Break(0x2A0), &mut debugger);
+        execute(ConsoleCommand::Break(0x300), &mut debugger);
+        execute(ConsoleCommand::Delete(0x2A0), &mut debugger);
+        assert_eq!(debugger.breakpoints(), [0x300]);
+
+        assert_eq!(execute(ConsoleCommand::Registers, &mut debugger), "The execution is not paused\n");
+    }
+
+    #[test]
+    fn labels() {
+        let mut debugger = Debugger::new();
+        debugger.set_symbols(Symbols::parse("main_loop 0x2A0").unwrap());
+
+        let command = parse_command("break @main_loop", debugger.symbols()).unwrap();
+        assert_eq!(command, ConsoleCommand::Break(0x2A0));
+        assert_eq!(execute(command, &mut debugger), "Breakpoint at 2A0 (main_loop)\n");
+        assert_eq!(execute(ConsoleCommand::List, &mut debugger), "Breakpoint at 2A0 (main_loop)\n");
+
+        assert!(parse_command("break @other", debugger.symbols()).is_err());
+    }
+
+    #[test]
+    fn editing() {
+        let mut debugger = Debugger::new();
+        let set = |debugger: &mut Debugger, line: &str| execute(line.parse().unwrap(), debugger);
+
+        assert_eq!(set(&mut debugger, "set VA 3c"), "The execution is not paused\n");
+
+        debugger.set_paused(true);
+        debugger.publish(Snapshot {
+            memory: vec![0; 0x1000],
+            pc: 0x200,
+            i: 0,
+            regs: [0; 16],
+            stack: Vec::new(),
+            delay_timer: 0,
+            sound_timer: 0,
+            display: Vec::new(),
+            hires: false,
+        });
+
+        assert_eq!(set(&mut debugger, "set VA 3c"), "");
+        assert_eq!(set(&mut debugger, "set pc 0x2A0"), "");
+        assert_eq!(set(&mut debugger, "write 0x300 12 34"), "");
+        assert_eq!(set(&mut debugger, "write 0xFFF 12 34"), "Write outside of the memory at 1000\n");
+
+        let snapshot = debugger.snapshot().unwrap();
+        assert_eq!((snapshot.regs[0xA], snapshot.pc), (0x3C, 0x2A0));
+        assert_eq!(snapshot.memory[0x300..0x302], [0x12, 0x34]);
+        assert_eq!(
+            debugger.take_edits(),
+            vec![
+                Edit::Register(0xA, 0x3C),
+                Edit::Pc(0x2A0),
+                Edit::Memory(0x300, vec![0x12, 0x34])
+            ]
+        );
+    }
+}
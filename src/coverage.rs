@@ -0,0 +1,129 @@
+use crate::disasm;
+
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/* Number of times the instruction at each memory address was fetched, to find the code of a ROM which
+ * is never executed */
+pub struct Coverage {
+    counts: Vec<u64>,
+}
+
+impl Coverage {
+    pub fn new(memory_size: usize) -> Coverage {
+        Coverage {
+            counts: vec![0; memory_size],
+        }
+    }
+
+    pub fn record(&mut self, address: u16) {
+        if let Some(count) = self.counts.get_mut(address as usize) {
+            *count += 1;
+        }
+    }
+
+    pub fn count(&self, address: usize) -> u64 {
+        self.counts.get(address).copied().unwrap_or(0)
+    }
+
+    /* Executions of the word at the given address; an instruction at an odd address is counted with
+     * the word it starts in */
+    fn word_count(&self, address: usize) -> u64 {
+        self.count(address) + self.count(address + 1)
+    }
+
+    /* Number of words of a ROM of the given length which were executed */
+    pub fn executed_words(&self, rom_len: usize, start: u16) -> usize {
+        let start = start as usize;
+        (start..start + rom_len).step_by(2).filter(|&address| self.word_count(address) > 0).count()
+    }
+
+    /* Share of the ROM which was executed, and the ranges which never were: unreachable code, or the
+     * sprites and the data of the program */
+    pub fn report(&self, rom: &[u8], start: u16) -> String {
+        let executed = self.executed_words(rom.len(), start);
+        let start = start as usize;
+        let words: Vec<usize> = (start..start + rom.len()).step_by(2).collect();
+
+        let mut report = String::new();
+        let _ = writeln!(
+            report,
+            "Executed {} of {} ROM words ({:.1}%)",
+            executed,
+            words.len(),
+            100.0 * executed as f64 / words.len().max(1) as f64
+        );
+
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for &address in words.iter().filter(|&&address| self.word_count(address) == 0) {
+            let end = (address + 2).min(start + rom.len());
+            match ranges.last_mut() {
+                Some(range) if range.1 == address => range.1 = end,
+                _ => ranges.push((address, end)),
+            }
+        }
+
+        if !ranges.is_empty() {
+            let _ = writeln!(report, "Never executed:");
+        }
+        for (first, end) in ranges {
+            let _ = writeln!(report, "  {:03X}-{:03X} ({} bytes)", first, end - 1, end - first);
+        }
+
+        report
+    }
+
+    /* Disassembly of the ROM, see disasm::disassemble, with the execution count of each word at the
+     * end of its line, or "-" if it was never executed */
+    pub fn annotate(&self, rom: &[u8], start: u16) -> String {
+        let mut output = String::new();
+
+        for (offset, line) in disasm::disassemble(rom, start).lines().enumerate() {
+            let count = self.word_count(start as usize + 2 * offset);
+            let mark = if count > 0 { count.to_string() } else { String::from("-") };
+            let _ = writeln!(output, "{:<31} {}", line, mark);
+        }
+
+        output
+    }
+}
+
+/* Write the disassembly with the execution counts if the file has the .asm extension, otherwise the
+ * summary */
+pub fn write(path: &Path, coverage: &Coverage, rom: &[u8], start: u16) -> io::Result<()> {
+    let content = if path.extension().map_or(false, |extension| extension == "asm") {
+        coverage.annotate(rom, start)
+    } else {
+        coverage.report(rom, start)
+    };
+
+    fs::write(path, content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* A loop on the first two instructions, never reaching the sprite after them */
+    const ROM: [u8; 7] = [0x60, 0x01, 0x12, 0x00, 0xF0, 0x90, 0xF0];
+
+    #[test]
+    fn executed_words() {
+        let mut coverage = Coverage::new(4096);
+        for _ in 0..3 {
+            coverage.record(0x200);
+            coverage.record(0x202);
+        }
+
+        assert_eq!(
+            coverage.report(&ROM, 0x200),
+            "Executed 2 of 4 ROM words (50.0%)\nNever executed:\n  204-206 (3 bytes)\n"
+        );
+
+        let annotated = coverage.annotate(&ROM, 0x200);
+        let marks: Vec<&str> = annotated.lines().map(|line| line.rsplit(' ').next().unwrap()).collect();
+        assert_eq!(marks, vec!["3", "3", "-", "-"]);
+    }
+}
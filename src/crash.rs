@@ -0,0 +1,84 @@
+use crate::debugger::{annotated_hexdump, format_registers, Snapshot};
+use crate::instruction::Instruction;
+
+use std::any::Any;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/* Name of the threads running a machine; the panic hook of the frontend lets them write a crash dump
+ * before exiting */
+pub const THREAD_NAME: &str = "chip8";
+
+/* Message given to panic!, when it's a string */
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("unknown panic")
+    }
+}
+
+/* State of a machine which panicked while executing the instruction at pc: the instruction, the
+ * registers and the whole memory */
+pub fn report(message: &str, pc: u16, snapshot: &Snapshot) -> String {
+    let byte = |address: u16| snapshot.memory.get(address as usize).copied().unwrap_or(0);
+    let opcode = u16::from_be_bytes([byte(pc), byte(pc.wrapping_add(1))]);
+    let instr = Instruction::decode(opcode).map_or(String::from("unknown instruction"), |instr| instr.to_string());
+
+    let mut report = String::new();
+    let _ = writeln!(report, "chip8rs crash dump");
+    let _ = writeln!(report, "Panic: {}", message);
+    let _ = writeln!(report, "Instruction: {:03X}: {:04X} {}", pc, opcode, instr);
+    report.push('\n');
+    report.push_str(&format_registers(snapshot));
+    report.push('\n');
+    report.push_str(&annotated_hexdump(&snapshot.memory, 0, snapshot.memory.len(), &[]));
+
+    report
+}
+
+/* Write the report to a new file in the current directory, named after the time; returns its path */
+pub fn write(report: &str) -> io::Result<PathBuf> {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+    let path = PathBuf::from(format!("chip8rs-crash-{}.txt", seconds));
+
+    fs::write(&path, report)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crash_report() {
+        let mut memory = vec![0; 0x20];
+        memory[0x10..0x12].copy_from_slice(&[0xD0, 0x15]);
+        let snapshot = Snapshot {
+            memory,
+            pc: 0x12,
+            i: 0x300,
+            regs: [0; 16],
+            stack: vec![0x202],
+            delay_timer: 0,
+            sound_timer: 0,
+            display: Vec::new(),
+            hires: false,
+        };
+
+        let payload: Box<dyn Any + Send> = Box::new(String::from("index out of bounds"));
+        let report = report(&panic_message(&*payload), 0x10, &snapshot);
+        let lines: Vec<&str> = report.lines().collect();
+
+        assert_eq!(lines[1], "Panic: index out of bounds");
+        assert_eq!(lines[2], "Instruction: 010: D015 DRW V0, V1, 5");
+        assert_eq!(lines[4], "PC: 012  I: 300  DT: 00  ST: 00");
+        assert_eq!(lines[7], "Stack: [202]");
+        assert!(lines[10].starts_with("010: D0 15 00"));
+    }
+}
@@ -0,0 +1,689 @@
+use crate::fonts::{BIG_FONTS_SIZE, BIG_FONT_SIZE, FONTS_SIZE, FONT_SIZE};
+use crate::heatmap::Heatmap;
+use crate::instruction::Instruction;
+use crate::platform::MachineConfig;
+use crate::symbols::Symbols;
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fmt::Write;
+use std::str::FromStr;
+
+const BYTES_PER_ROW: usize = 16;
+const ROWS_PER_PAGE: usize = 16;
+const PAGE_SIZE: usize = BYTES_PER_ROW * ROWS_PER_PAGE;
+
+/* Instructions shown before and after the current one by the disassembly view */
+const CODE_CONTEXT: usize = 6;
+
+/* ANSI escape sequences used to highlight the PC and I locations in the terminal */
+const ANSI_CLEAR: &str = "\x1b[2J\x1b[H";
+const ANSI_PC: &str = "\x1b[7m";
+const ANSI_INDEX: &str = "\x1b[4;33m";
+const ANSI_RESET: &str = "\x1b[0m";
+
+/* Copy of the machine state published by the CHIP-8 thread while paused; it's also the content of
+ * the save states */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /* Left out of the serialized state when empty, like in the messages of the server */
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub memory: Vec<u8>,
+    pub pc: u16,
+    pub i: u16,
+    pub regs: [u8; 16],
+    pub stack: Vec<u16>,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    /* Value of each pixel, indexed by row and column */
+    pub display: Vec<Vec<u8>>,
+    /* SUPER-CHIP high resolution mode */
+    pub hires: bool,
+}
+
+/* Location watched by the debugger; the execution stops when it's read or written by an instruction */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Watchpoint {
+    Memory(u16),
+    Register(u8),
+}
+
+/* Parsed from "V0" to "VF" for the registers, or from an hexadecimal address */
+impl FromStr for Watchpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let upper = s.to_ascii_uppercase();
+
+        if let Some(reg) = upper.strip_prefix('V') {
+            return match u8::from_str_radix(reg, 16) {
+                Ok(reg) if reg < 0x10 => Ok(Watchpoint::Register(reg)),
+                _ => Err(format!("Invalid register: {}", s)),
+            };
+        }
+
+        u16::from_str_radix(upper.trim_start_matches("0X"), 16)
+            .map(Watchpoint::Memory)
+            .map_err(|_| format!("Invalid watchpoint: {}", s))
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Watchpoint::Memory(address) => write!(f, "0x{:03X}", address),
+            Watchpoint::Register(reg) => write!(f, "V{:X}", reg),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/* Access which triggered a watchpoint; the PC is the address of the instruction */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct WatchHit {
+    pub watchpoint: Watchpoint,
+    pub access: Access,
+    pub pc: u16,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let access = match self.access {
+            Access::Read => "read",
+            Access::Write => "written",
+        };
+        write!(f, "{} {} at {:03X}", self.watchpoint, access, self.pc)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StepMode {
+    /* Execute a single instruction */
+    Into,
+    /* Execute a single instruction, running a subroutine call to completion */
+    Over,
+    /* Run until the current subroutine returns */
+    Out,
+}
+
+/* Instructions around the PC, shown by the disassembly view; the CHIP-8 thread publishes them while
+ * running, since the snapshots are only available while paused */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeWindow {
+    pub pc: u16,
+    /* Address of the first byte */
+    pub start: u16,
+    pub bytes: Vec<u8>,
+}
+
+impl CodeWindow {
+    /* Addresses to copy for the instruction at pc; the instructions before it are aligned to the PC,
+     * since the disassembly can't tell code from data going backwards */
+    pub fn range(pc: u16, memory_size: usize) -> (usize, usize) {
+        let pc = pc as usize;
+        let start = (pc - 2 * CODE_CONTEXT.min(pc / 2)).min(memory_size);
+        let end = (pc + 2 * (CODE_CONTEXT + 1)).min(memory_size);
+        (start, end)
+    }
+
+    pub fn from_snapshot(snapshot: &Snapshot) -> CodeWindow {
+        let (start, end) = CodeWindow::range(snapshot.pc, snapshot.memory.len());
+        CodeWindow {
+            pc: snapshot.pc,
+            start: start as u16,
+            bytes: snapshot.memory[start..end].to_vec(),
+        }
+    }
+
+    /* One line per instruction, with its address and opcode */
+    pub fn lines(&self) -> Vec<String> {
+        self.bytes
+            .chunks_exact(2)
+            .enumerate()
+            .map(|(offset, bytes)| {
+                let opcode = u16::from_be_bytes([bytes[0], bytes[1]]);
+                let text = match Instruction::decode(opcode) {
+                    Some(instr) => instr.to_string(),
+                    None => String::from("DATA"),
+                };
+                format!("{:03X}: {:04X}  {}", self.start as usize + 2 * offset, opcode, text)
+            })
+            .collect()
+    }
+
+    /* Index of the line of the instruction at the PC */
+    pub fn current_line(&self) -> usize {
+        ((self.pc - self.start) / 2) as usize
+    }
+}
+
+/* Change to the machine state made from the debugger while paused */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Edit {
+    /* Bytes written starting from the address */
+    Memory(u16, Vec<u8>),
+    Register(u8, u8),
+    Pc(u16),
+    Index(u16),
+    DelayTimer(u8),
+    SoundTimer(u8),
+}
+
+impl Edit {
+    /* Apply the change to a copy of the state; fails if it's outside of the memory */
+    pub fn apply(&self, snapshot: &mut Snapshot) -> Result<(), String> {
+        match self {
+            Edit::Memory(address, bytes) => {
+                let start = *address as usize;
+                let end = start + bytes.len();
+                if end > snapshot.memory.len() {
+                    return Err(format!("Write outside of the memory at {:03X}", snapshot.memory.len()));
+                }
+                snapshot.memory[start..end].copy_from_slice(bytes);
+            }
+            Edit::Register(reg, value) => snapshot.regs[*reg as usize & 0xF] = *value,
+            Edit::Pc(pc) => {
+                if *pc as usize >= snapshot.memory.len() {
+                    return Err(format!("PC outside of the memory: {:03X}", pc));
+                }
+                snapshot.pc = *pc;
+            }
+            Edit::Index(i) => snapshot.i = *i,
+            Edit::DelayTimer(value) => snapshot.delay_timer = *value,
+            Edit::SoundTimer(value) => snapshot.sound_timer = *value,
+        }
+
+        Ok(())
+    }
+}
+
+/* State shared between the CHIP-8 thread and the frontend to control the execution */
+pub struct Debugger {
+    paused: bool,
+    snapshot: Option<Snapshot>,
+    watchpoints: Vec<Watchpoint>,
+    /* Set when the execution was stopped by a watchpoint, until it's resumed */
+    watch_hit: Option<WatchHit>,
+    breakpoints: Vec<u16>,
+    /* Set when resuming, so that the execution doesn't stop again on the breakpoint it's paused on */
+    skip_breakpoint: bool,
+    /* Step in progress, with the stack depth when it started and the number of steps left */
+    step: Option<(StepMode, usize, u32)>,
+    /* Set to run a single frame while paused */
+    frame_step: bool,
+    /* Instructions to undo while paused, and the ones which can be, published with the snapshot */
+    back_steps: u32,
+    history_len: usize,
+    /* Number of times the execution was paused, so that the frontends can notice a pause even when it
+     * was short */
+    pauses: u64,
+    /* Changes waiting to be applied by the CHIP-8 thread */
+    edits: Vec<Edit>,
+    /* Counts of the memory accesses, when enabled */
+    heatmap: Option<Heatmap>,
+    /* Set while a disassembly view is shown, so that the CHIP-8 thread publishes the code */
+    code_view: bool,
+    code: Option<CodeWindow>,
+    /* Labels of the program, to show and accept names instead of addresses */
+    symbols: Symbols,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger {
+            paused: false,
+            snapshot: None,
+            watchpoints: Vec::new(),
+            watch_hit: None,
+            breakpoints: Vec::new(),
+            skip_breakpoint: false,
+            step: None,
+            frame_step: false,
+            back_steps: 0,
+            history_len: 0,
+            pauses: 0,
+            edits: Vec::new(),
+            heatmap: None,
+            code_view: false,
+            code: None,
+            symbols: Symbols::new(),
+        }
+    }
+
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        if !self.breakpoints.contains(&address) {
+            self.breakpoints.push(address);
+        }
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) {
+        self.breakpoints.retain(|&a| a != address);
+    }
+
+    pub fn breakpoints(&self) -> &[u16] {
+        &self.breakpoints
+    }
+
+    /* Called by the CHIP-8 thread before executing the instruction at pc; returns true if the
+     * execution has been paused */
+    pub fn check_breakpoint(&mut self, pc: u16) -> bool {
+        let skip = self.skip_breakpoint;
+        self.skip_breakpoint = false;
+
+        if !skip && self.breakpoints.contains(&pc) {
+            self.set_paused(true);
+            return true;
+        }
+
+        false
+    }
+
+    pub fn pauses(&self) -> u64 {
+        self.pauses
+    }
+
+    /* Resume a paused execution for a step, repeated count times; returns false if not paused, or if
+     * there's no subroutine to step out of */
+    pub fn step(&mut self, mode: StepMode, count: u32) -> bool {
+        let depth = match (&self.snapshot, self.paused) {
+            (Some(snapshot), true) => snapshot.stack.len(),
+            _ => return false,
+        };
+
+        if mode == StepMode::Out && depth == 0 {
+            return false;
+        }
+
+        self.set_paused(false);
+        self.step = Some((mode, depth, count));
+        true
+    }
+
+    /* Run a single 60Hz frame while paused; returns false if not paused */
+    pub fn step_frame(&mut self) -> bool {
+        if !self.paused {
+            return false;
+        }
+
+        self.frame_step = true;
+        true
+    }
+
+    /* Called by the CHIP-8 thread while paused; returns true if a frame must be run */
+    pub fn take_frame_step(&mut self) -> bool {
+        let frame_step = self.frame_step;
+        self.frame_step = false;
+        frame_step
+    }
+
+    /* Called by the CHIP-8 thread after running a frame, still paused, so that a new snapshot is
+     * published */
+    pub fn frame_done(&mut self) {
+        self.snapshot = None;
+        self.pauses += 1;
+    }
+
+    /* Undo count instructions while paused; returns false if not paused, or if there's no instruction
+     * to undo */
+    pub fn step_back(&mut self, count: u32) -> bool {
+        if !self.paused || self.snapshot.is_none() || self.history_len == 0 {
+            return false;
+        }
+
+        self.back_steps = count;
+        true
+    }
+
+    /* Called by the CHIP-8 thread while paused; returns the number of instructions to undo */
+    pub fn take_back_steps(&mut self) -> u32 {
+        std::mem::take(&mut self.back_steps)
+    }
+
+    /* Called by the CHIP-8 thread when publishing a snapshot, with the number of instructions it
+     * recorded */
+    pub fn set_history_len(&mut self, len: usize) {
+        self.history_len = len;
+    }
+
+    pub fn history_len(&self) -> usize {
+        self.history_len
+    }
+
+    /* Called by the CHIP-8 thread after undoing the instructions, still paused, so that a new snapshot
+     * is published */
+    pub fn stepped_back(&mut self, history_len: usize) {
+        self.history_len = history_len;
+        self.snapshot = None;
+        self.pauses += 1;
+    }
+
+    /* Called by the CHIP-8 thread after each instruction, with the current stack depth; pauses the
+     * execution when the step in progress is complete */
+    pub fn check_step(&mut self, depth: usize) {
+        let (mode, start, count) = match self.step {
+            Some(step) => step,
+            None => return,
+        };
+
+        let done = match mode {
+            StepMode::Into => true,
+            StepMode::Over => depth <= start,
+            StepMode::Out => depth < start,
+        };
+
+        if !done {
+            return;
+        }
+
+        /* The next step starts from the current depth */
+        if count <= 1 || (mode == StepMode::Out && depth == 0) {
+            self.set_paused(true);
+        } else {
+            self.step = Some((mode, depth, count - 1));
+        }
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        if !self.watchpoints.contains(&watchpoint) {
+            self.watchpoints.push(watchpoint);
+        }
+    }
+
+    pub fn remove_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.retain(|w| *w != watchpoint);
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn watch_hit(&self) -> Option<WatchHit> {
+        self.watch_hit
+    }
+
+    /* Start counting the memory accesses, see check_accesses */
+    pub fn enable_heatmap(&mut self, memory_size: usize) {
+        self.heatmap = Some(Heatmap::new(memory_size));
+    }
+
+    pub fn heatmap(&self) -> Option<&Heatmap> {
+        self.heatmap.as_ref()
+    }
+
+    /* Check the accesses done by the instruction at pc, counting them in the heatmap and pausing the
+     * execution on the first one hitting a watchpoint */
+    pub fn check_accesses(&mut self, accesses: &[(Watchpoint, Access)], pc: u16) -> Option<WatchHit> {
+        if let Some(heatmap) = &mut self.heatmap {
+            for &(watchpoint, access) in accesses {
+                if let Watchpoint::Memory(address) = watchpoint {
+                    heatmap.record(address, access);
+                }
+            }
+        }
+
+        let &(watchpoint, access) = accesses.iter().find(|(w, _)| self.watchpoints.contains(w))?;
+        let hit = WatchHit { watchpoint, access, pc };
+
+        self.set_paused(true);
+        self.watch_hit = Some(hit);
+        Some(hit)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        if paused && !self.paused {
+            self.pauses += 1;
+        }
+
+        self.paused = paused;
+        self.watch_hit = None;
+        self.step = None;
+        self.back_steps = 0;
+        self.skip_breakpoint = !paused;
+
+        /* A new snapshot will be published by the CHIP-8 thread when it stops */
+        self.snapshot = None;
+    }
+
+    /* Change the state of the paused machine; the published snapshot is updated right away, and the
+     * CHIP-8 thread applies the change before executing anything else */
+    pub fn edit(&mut self, edit: Edit) -> Result<(), String> {
+        let snapshot = match (&mut self.snapshot, self.paused) {
+            (Some(snapshot), true) => snapshot,
+            _ => return Err(String::from("The execution is not paused")),
+        };
+
+        edit.apply(snapshot)?;
+        self.edits.push(edit);
+        Ok(())
+    }
+
+    /* Called by the CHIP-8 thread to get the changes to apply */
+    pub fn take_edits(&mut self) -> Vec<Edit> {
+        std::mem::take(&mut self.edits)
+    }
+
+    pub fn set_code_view(&mut self, visible: bool) {
+        self.code_view = visible;
+        if !visible {
+            self.code = None;
+        }
+    }
+
+    pub fn is_code_view(&self) -> bool {
+        self.code_view
+    }
+
+    /* Called by the CHIP-8 thread on each frame while running and the code view is shown */
+    pub fn publish_code(&mut self, code: CodeWindow) {
+        self.code = Some(code);
+    }
+
+    /* Code around the PC: the one of the snapshot while paused, the last published while running */
+    pub fn code(&self) -> Option<CodeWindow> {
+        match (&self.snapshot, self.paused) {
+            (Some(snapshot), true) => Some(CodeWindow::from_snapshot(snapshot)),
+            _ => self.code.clone(),
+        }
+    }
+
+    pub fn publish(&mut self, snapshot: Snapshot) {
+        self.snapshot = Some(snapshot);
+    }
+
+    pub fn snapshot(&self) -> Option<&Snapshot> {
+        self.snapshot.as_ref()
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Debugger::new()
+    }
+}
+
+/* Hexdump of the memory, one page at a time, printed on the terminal */
+pub struct MemoryViewer {
+    offset: usize,
+}
+
+impl MemoryViewer {
+    pub fn new() -> MemoryViewer {
+        MemoryViewer { offset: 0 }
+    }
+
+    /* Move to the page containing the given address */
+    pub fn show_address(&mut self, address: usize) {
+        self.offset = address - (address % PAGE_SIZE);
+    }
+
+    pub fn scroll(&mut self, pages: isize, memory_size: usize) {
+        let offset = self.offset as isize + pages * (PAGE_SIZE as isize);
+        if offset >= 0 && (offset as usize) < memory_size {
+            self.offset = offset as usize;
+        }
+    }
+
+    pub fn print(&self, snapshot: &Snapshot) {
+        print!("{}", ANSI_CLEAR);
+        println!(
+            "PC: {:03X}  I: {:03X}  (PageUp/PageDown to scroll, F10/F11/F12 to step over/into/out, F4 to run a frame, F5 to resume)",
+            snapshot.pc, snapshot.i
+        );
+        print!("{}", hexdump(snapshot, self.offset, PAGE_SIZE));
+
+        println!();
+        let code = CodeWindow::from_snapshot(snapshot);
+        for (index, line) in code.lines().iter().enumerate() {
+            if index == code.current_line() {
+                println!("{}{}{}", ANSI_PC, line, ANSI_RESET);
+            } else {
+                println!("{}", line);
+            }
+        }
+    }
+}
+
+impl Default for MemoryViewer {
+    fn default() -> Self {
+        MemoryViewer::new()
+    }
+}
+
+/* Registers, timers and stack, on a few lines */
+pub fn format_registers(snapshot: &Snapshot) -> String {
+    let mut output = String::new();
+
+    let _ = writeln!(
+        output,
+        "PC: {:03X}  I: {:03X}  DT: {:02X}  ST: {:02X}",
+        snapshot.pc, snapshot.i, snapshot.delay_timer, snapshot.sound_timer
+    );
+    for (row, regs) in snapshot.regs.chunks(8).enumerate() {
+        for (col, value) in regs.iter().enumerate() {
+            let _ = write!(output, "V{:X}: {:02X}  ", row * 8 + col, value);
+        }
+        output.push('\n');
+    }
+    let _ = writeln!(output, "Stack: {:03X?}", snapshot.stack);
+
+    output
+}
+
+/* Format len bytes of memory starting from start, highlighting the instruction at PC and the byte
+ * pointed by I */
+pub fn hexdump(snapshot: &Snapshot, start: usize, len: usize) -> String {
+    let end = usize::min(start.saturating_add(len), snapshot.memory.len());
+    let pc = snapshot.pc as usize;
+    let i = snapshot.i as usize;
+    let mut dump = String::new();
+
+    for row_start in (start..end).step_by(BYTES_PER_ROW) {
+        let row_end = usize::min(row_start + BYTES_PER_ROW, end);
+        let _ = write!(dump, "{:03X}:", row_start);
+
+        for address in row_start..row_end {
+            let byte = snapshot.memory[address];
+            if address == pc || address == pc + 1 {
+                let _ = write!(dump, " {}{:02X}{}", ANSI_PC, byte, ANSI_RESET);
+            } else if address == i {
+                let _ = write!(dump, " {}{:02X}{}", ANSI_INDEX, byte, ANSI_RESET);
+            } else {
+                let _ = write!(dump, " {:02X}", byte);
+            }
+        }
+
+        dump.push_str("  |");
+        for &byte in &snapshot.memory[row_start..row_end] {
+            dump.push(if byte.is_ascii_graphic() { byte as char } else { '.' });
+        }
+        dump.push_str("|\n");
+    }
+
+    dump
+}
+
+/* Named area of the memory, from start included to end excluded */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub start: usize,
+    pub end: usize,
+    pub name: &'static str,
+}
+
+/* Areas where the fonts and a ROM of the given size are loaded */
+pub fn memory_map(config: &MachineConfig, rom_len: usize) -> Vec<Region> {
+    let font_start = config.font_start as usize;
+    let big_font_start = font_start + FONT_SIZE * FONTS_SIZE;
+    let rom_start = config.rom_start as usize;
+
+    vec![
+        Region {
+            start: font_start,
+            end: big_font_start,
+            name: "font",
+        },
+        Region {
+            start: big_font_start,
+            end: big_font_start + BIG_FONT_SIZE * BIG_FONTS_SIZE,
+            name: "big font",
+        },
+        Region {
+            start: rom_start,
+            end: rom_start + rom_len,
+            name: "ROM",
+        },
+    ]
+}
+
+/* Format len bytes of memory starting from start, without colors so that it can be redirected to a
+ * file; each row ends with the names of the regions it overlaps */
+pub fn annotated_hexdump(memory: &[u8], start: usize, len: usize, regions: &[Region]) -> String {
+    let end = usize::min(start.saturating_add(len), memory.len());
+    let mut dump = String::new();
+
+    for row_start in (start..end).step_by(BYTES_PER_ROW) {
+        let row_end = usize::min(row_start + BYTES_PER_ROW, end);
+        let bytes = &memory[row_start..row_end];
+
+        let _ = write!(dump, "{:03X}:", row_start);
+        for byte in bytes {
+            let _ = write!(dump, " {:02X}", byte);
+        }
+
+        let ascii: String = bytes
+            .iter()
+            .map(|&byte| if byte.is_ascii_graphic() { byte as char } else { '.' })
+            .collect();
+        let padding = 3 * (BYTES_PER_ROW - bytes.len());
+        let _ = write!(dump, "{:padding$}  |{:<width$}|", "", ascii, padding = padding, width = BYTES_PER_ROW);
+
+        let names: Vec<&str> = regions
+            .iter()
+            .filter(|region| region.start < row_end && row_start < region.end)
+            .map(|region| region.name)
+            .collect();
+        if !names.is_empty() {
+            let _ = write!(dump, "  {}", names.join(", "));
+        }
+        dump.push('\n');
+    }
+
+    dump
+}
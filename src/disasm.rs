@@ -0,0 +1,58 @@
+use crate::instruction::Instruction;
+use crate::symbols::Symbols;
+
+use std::fmt::Write;
+
+/* Disassemble a ROM loaded at the given address, one instruction per line; the address and the bytes
+ * of each line are in a comment, so the output can be assembled again. Words which are not valid
+ * instructions, usually sprites, are shown as data */
+pub fn disassemble(rom: &[u8], start: u16) -> String {
+    disassemble_with_symbols(rom, start, &Symbols::new())
+}
+
+/* Label names accepted by asm::assemble, which are more restricted than the Octo ones */
+fn asm_label(name: &str) -> String {
+    name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect()
+}
+
+/* Like disassemble, with a "label:" line before each address with a symbol and the labels in place of
+ * the addresses they name in the jumps, the calls and LD I */
+pub fn disassemble_with_symbols(rom: &[u8], start: u16, symbols: &Symbols) -> String {
+    let mut output = String::new();
+
+    for (offset, bytes) in rom.chunks(2).enumerate() {
+        let address = start as usize + 2 * offset;
+
+        let (line, hex) = match *bytes {
+            [first, second] => {
+                let opcode = u16::from_be_bytes([first, second]);
+                let line = match Instruction::decode(opcode) {
+                    Some(instr) => {
+                        let line = instr.to_string();
+                        match instr {
+                            Instruction::Jump(target)
+                            | Instruction::Call(target)
+                            | Instruction::SetIndex(target)
+                            | Instruction::JumpWithRegister(target) => match symbols.label(target) {
+                                Some(name) => line.replace(&format!("0x{:03X}", target), &asm_label(name)),
+                                None => line,
+                            },
+                            _ => line,
+                        }
+                    }
+                    None => format!("DB 0x{:02X}, 0x{:02X}", first, second),
+                };
+                (line, format!("{:04X}", opcode))
+            }
+            [byte] => (format!("DB 0x{:02X}", byte), format!("{:02X}", byte)),
+            _ => unreachable!(),
+        };
+
+        if let Some(name) = symbols.label(address as u16) {
+            let _ = writeln!(output, "{}:", asm_label(name));
+        }
+        let _ = writeln!(output, "{:<20}; {:03X}: {}", line, address, hex);
+    }
+
+    output
+}
@@ -1,14 +1,189 @@
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize, Serializer};
+
+/* Each pixel holds an index in the palette of the renderer; 0 is the background, 1 the foreground
+ * and higher values are additional colors, such as the XO-CHIP planes */
 pub trait Display {
-    fn led_on(&mut self, x: usize, y: usize);
-    fn led_off(&mut self, x: usize, y: usize);
-    fn clear_screen(&mut self, on: bool);
-    fn is_on(&self, x: usize, y: usize) -> bool;
+    fn set_pixel(&mut self, x: usize, y: usize, value: u8);
+    fn pixel(&self, x: usize, y: usize) -> u8;
+    fn width(&self) -> usize;
+    fn height(&self) -> usize;
+
+    fn led_on(&mut self, x: usize, y: usize) {
+        self.set_pixel(x, y, 1);
+    }
+
+    fn led_off(&mut self, x: usize, y: usize) {
+        self.set_pixel(x, y, 0);
+    }
+
+    fn is_on(&self, x: usize, y: usize) -> bool {
+        self.pixel(x, y) != 0
+    }
+
+    /* Copy of all the pixels, indexed by row and column */
+    fn snapshot(&self) -> Vec<Vec<u8>> {
+        (0..self.height())
+            .map(|y| (0..self.width()).map(|x| self.pixel(x, y)).collect())
+            .collect()
+    }
+
+    /* Replace the LEDs of row y, starting from the left border, with the given values */
+    fn set_row(&mut self, y: usize, values: &[u8]) {
+        for (x, &value) in values.iter().enumerate().take(self.width()) {
+            self.set_pixel(x, y, value);
+        }
+    }
+
+    /* XOR the rows of a sprite, width pixels wide and aligned to the MSB, with its top-left corner at
+     * (x, y); returns the number of rows in which an LED has been turned off */
+    fn blit_rows(&mut self, x: usize, y: usize, rows: &[u16], width: usize) -> usize {
+        let mut collisions = 0;
+
+        /* Sprites are clipped at the display borders */
+        for (sprite_row, sprite_data) in rows.iter().enumerate() {
+            let y_pos = y + sprite_row;
+            if y_pos >= self.height() {
+                break;
+            }
+
+            let mut collision = false;
+            for sprite_bit_i in 0..width {
+                let x_pos = x + sprite_bit_i;
+                if x_pos >= self.width() {
+                    break;
+                }
+
+                /* If current pixel is on and bit is high, flip the led */
+                if (sprite_data & (0x8000 >> sprite_bit_i)) != 0 {
+                    if self.is_on(x_pos, y_pos) {
+                        self.led_off(x_pos, y_pos);
+                        collision = true;
+                    } else {
+                        self.led_on(x_pos, y_pos);
+                    }
+                }
+            }
+
+            collisions += collision as usize;
+        }
+
+        collisions
+    }
+
+    /* XOR an 8 pixels wide sprite, each byte being a row; returns true if an LED has been turned off */
+    fn blit_sprite(&mut self, x: usize, y: usize, rows: &[u8]) -> bool {
+        let rows: Vec<u16> = rows.iter().map(|&row| (row as u16) << 8).collect();
+        self.blit_rows(x, y, &rows, 8) > 0
+    }
+
+    fn clear_screen(&mut self, on: bool) {
+        let value = on as u8;
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                self.set_pixel(x, y, value);
+            }
+        }
+    }
+
+    /* The scroll methods move the contents by n LEDs; the ones scrolled out are lost and the uncovered
+     * ones are turned off */
+    fn scroll_down(&mut self, n: usize) {
+        for y in (0..self.height()).rev() {
+            for x in 0..self.width() {
+                let value = if y >= n { self.pixel(x, y - n) } else { 0 };
+                self.set_pixel(x, y, value);
+            }
+        }
+    }
+
+    fn scroll_left(&mut self, n: usize) {
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                let value = if x + n < self.width() { self.pixel(x + n, y) } else { 0 };
+                self.set_pixel(x, y, value);
+            }
+        }
+    }
+
+    fn scroll_right(&mut self, n: usize) {
+        for y in 0..self.height() {
+            for x in (0..self.width()).rev() {
+                let value = if x >= n { self.pixel(x - n, y) } else { 0 };
+                self.set_pixel(x, y, value);
+            }
+        }
+    }
+}
+
+/* Commands sent from the CHIP-8 thread to the renderer, which owns its own copy of the framebuffer */
+#[derive(Debug, Clone)]
+pub enum DrawCommand {
+    Clear,
+    /* XOR a sprite with its top-left corner at (x, y); each byte is a row, read from the MSB */
+    Sprite { x: usize, y: usize, rows: Vec<u8> },
+    /* Same as Sprite, for the 16 pixels wide sprites of SUPER-CHIP */
+    WideSprite { x: usize, y: usize, rows: Vec<u16> },
+    /* Scroll the contents by the given number of LEDs */
+    ScrollDown(usize),
+    ScrollLeft(usize),
+    ScrollRight(usize),
+    /* Replace the whole framebuffer, indexed by row and column, e.g. when restoring a save state */
+    Frame { pixels: Vec<Vec<u8>> },
+}
+
+impl DrawCommand {
+    /* Apply the command to a display, returning the number of sprite rows in which an LED has been
+     * turned off */
+    pub fn apply(&self, display: &mut dyn Display) -> usize {
+        match self {
+            DrawCommand::Clear => {
+                display.clear_screen(false);
+                0
+            }
+            DrawCommand::Sprite { x, y, rows } => {
+                let rows: Vec<u16> = rows.iter().map(|&row| (row as u16) << 8).collect();
+                display.blit_rows(*x, *y, &rows, 8)
+            }
+            DrawCommand::WideSprite { x, y, rows } => display.blit_rows(*x, *y, rows, 16),
+            DrawCommand::ScrollDown(n) => {
+                display.scroll_down(*n);
+                0
+            }
+            DrawCommand::ScrollLeft(n) => {
+                display.scroll_left(*n);
+                0
+            }
+            DrawCommand::ScrollRight(n) => {
+                display.scroll_right(*n);
+                0
+            }
+            DrawCommand::Frame { pixels } => {
+                for (y, row) in pixels.iter().enumerate().take(display.height()) {
+                    display.set_row(y, row);
+                }
+
+                0
+            }
+        }
+    }
 }
 
 pub struct LedsDisplay {
     x_len: usize,
     y_len: usize,
-    leds: Vec<Vec<bool>>,
+    leds: Vec<Vec<u8>>,
+    /* Brightness of each LED, from 0.0 to 1.0; turned off LEDs fade out when a decay is set */
+    intensity: Vec<Vec<f32>>,
+    decay: f32,
+    /* Show the LEDs lit in the current or in the previous frame, see set_frame_blending */
+    blending: bool,
+    /* LEDs at the previous call to fade */
+    previous: Vec<Vec<u8>>,
+    /* Width of each LED relative to its height, for the renderers */
+    aspect: f64,
+    /* LEDs changed since the last call to take_dirty; a renderer only needs to redraw those */
+    dirty: Vec<Vec<bool>>,
 }
 
 impl LedsDisplay {
@@ -16,29 +191,207 @@ impl LedsDisplay {
         LedsDisplay {
             x_len: x_len,
             y_len: y_len,
-            leds: vec![vec![on; x_len]; y_len],
+            leds: vec![vec![on as u8; x_len]; y_len],
+            intensity: vec![vec![if on { 1.0 } else { 0.0 }; x_len]; y_len],
+            decay: 1.0,
+            blending: false,
+            previous: vec![vec![on as u8; x_len]; y_len],
+            aspect: 1.0,
+            dirty: vec![vec![true; x_len]; y_len],
         }
     }
-}
 
-impl Display for LedsDisplay {
-    fn led_on(&mut self, x: usize, y: usize) {
-        self.leds[y][x] = true;
+    /* Make turned off LEDs fade out over the given number of calls to fade, instead of turning off
+     * immediately; this hides the flickering of sprites being erased and drawn again */
+    pub fn set_fade_frames(&mut self, frames: u32) {
+        self.decay = 1.0 / (frames.max(1) as f32);
     }
 
-    fn led_off(&mut self, x: usize, y: usize) {
-        self.leds[y][x] = false;
+    /* OR each frame with the previous one: an LED turned off stays lit for one more frame, so the
+     * sprites erased and drawn again on alternate frames, like in BRIX, stop flickering */
+    pub fn set_frame_blending(&mut self, enabled: bool) {
+        self.blending = enabled;
     }
 
-    fn clear_screen(&mut self, on: bool) {
+    pub fn frame_blending(&self) -> bool {
+        self.blending
+    }
+
+    /* Dim the turned off LEDs which are still fading out; called once per frame */
+    pub fn fade(&mut self) {
         for y in 0..self.y_len {
             for x in 0..self.x_len {
-                self.leds[y][x] = on;
+                let held = self.blending && self.previous[y][x] != 0;
+                if self.leds[y][x] == 0 && self.intensity[y][x] > 0.0 && !held {
+                    self.intensity[y][x] = f32::max(self.intensity[y][x] - self.decay, 0.0);
+                    self.dirty[y][x] = true;
+                }
             }
         }
+
+        self.previous.clone_from(&self.leds);
     }
 
-    fn is_on(&self, x: usize, y: usize) -> bool {
+    /* Draw the LEDs as rectangles instead of squares; 2.0 mimics the stretched pixels of the
+     * interpreters connected to a television */
+    pub fn set_pixel_aspect(&mut self, aspect: f64) {
+        self.aspect = aspect;
+    }
+
+    pub fn pixel_aspect(&self) -> f64 {
+        self.aspect
+    }
+
+    /* Size of the whole display, with LEDs of the given height */
+    pub fn size(&self, led_height: f64) -> [f64; 2] {
+        [
+            (self.x_len as f64) * led_height * self.aspect,
+            (self.y_len as f64) * led_height,
+        ]
+    }
+
+    /* Rectangle covered by an LED as [x, y, width, height], with LEDs of the given height */
+    pub fn led_rect(&self, x: usize, y: usize, led_height: f64) -> [f64; 4] {
+        let led_width = led_height * self.aspect;
+        [(x as f64) * led_width, (y as f64) * led_height, led_width, led_height]
+    }
+
+    pub fn intensity(&self, x: usize, y: usize) -> f32 {
+        self.intensity[y][x]
+    }
+
+    /* Return the coordinates and the current value of the LEDs changed since the last call */
+    pub fn take_dirty(&mut self) -> Vec<(usize, usize, u8)> {
+        let mut changed = Vec::new();
+
+        for y in 0..self.y_len {
+            for x in 0..self.x_len {
+                if self.dirty[y][x] {
+                    changed.push((x, y, self.leds[y][x]));
+                    self.dirty[y][x] = false;
+                }
+            }
+        }
+
+        changed
+    }
+
+    fn write_led(&mut self, x: usize, y: usize, value: u8) {
+        if self.leds[y][x] != value {
+            self.leds[y][x] = value;
+            self.dirty[y][x] = true;
+
+            /* Without a decay or the blending, LEDs are turned off immediately; otherwise fade takes
+             * care of them */
+            let on = value != 0;
+            if on || (self.decay >= 1.0 && !self.blending) {
+                self.intensity[y][x] = if on { 1.0 } else { 0.0 };
+            }
+        }
+    }
+}
+
+/* The batch methods work on the LEDs directly, instead of going through the trait for each pixel */
+impl Display for LedsDisplay {
+    fn set_pixel(&mut self, x: usize, y: usize, value: u8) {
+        self.write_led(x, y, value);
+    }
+
+    fn set_row(&mut self, y: usize, values: &[u8]) {
+        for (x, &value) in values.iter().enumerate().take(self.x_len) {
+            self.write_led(x, y, value);
+        }
+    }
+
+    fn blit_rows(&mut self, x: usize, y: usize, rows: &[u16], width: usize) -> usize {
+        let mut collisions = 0;
+
+        for (y_pos, &sprite_data) in (y..self.y_len).zip(rows) {
+            let mut collision = false;
+            for x_pos in x..usize::min(x + width, self.x_len) {
+                if (sprite_data & (0x8000 >> (x_pos - x))) != 0 {
+                    let on = self.leds[y_pos][x_pos] != 0;
+                    collision |= on;
+                    self.write_led(x_pos, y_pos, !on as u8);
+                }
+            }
+
+            collisions += collision as usize;
+        }
+
+        collisions
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> u8 {
         self.leds[y][x]
     }
+
+    fn width(&self) -> usize {
+        self.x_len
+    }
+
+    fn height(&self) -> usize {
+        self.y_len
+    }
+}
+
+/* Serialized as the value of each pixel, indexed by row and column, like Snapshot::display; the
+ * settings of the renderers, like the fading, are left out */
+impl Serialize for LedsDisplay {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.leds.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for LedsDisplay {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pixels = Vec::<Vec<u8>>::deserialize(deserializer)?;
+        let width = pixels.first().map_or(0, Vec::len);
+        if pixels.iter().any(|row| row.len() != width) {
+            return Err(de::Error::custom("the rows of the display have different lengths"));
+        }
+
+        let mut display = LedsDisplay::new(width, pixels.len(), false);
+        DrawCommand::Frame { pixels }.apply(&mut display);
+        Ok(display)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_blending() {
+        let mut display = LedsDisplay::new(2, 1, false);
+        display.set_frame_blending(true);
+        display.led_on(0, 0);
+        display.fade();
+
+        /* Turned off, it's still lit in this frame, then off in the next one */
+        display.led_off(0, 0);
+        display.led_on(1, 0);
+        display.fade();
+        assert_eq!((display.intensity(0, 0), display.intensity(1, 0)), (1.0, 1.0));
+        display.fade();
+        assert_eq!((display.intensity(0, 0), display.intensity(1, 0)), (0.0, 1.0));
+
+        display.set_frame_blending(false);
+        display.led_off(1, 0);
+        assert_eq!(display.intensity(1, 0), 0.0);
+    }
+
+    #[test]
+    fn serialization() {
+        let mut display = LedsDisplay::new(3, 2, false);
+        display.led_on(1, 0);
+        display.set_pixel(2, 1, 2);
+
+        let json = serde_json::to_string(&display).unwrap();
+        assert_eq!(json, "[[0,1,0],[0,0,2]]");
+        let restored: LedsDisplay = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.snapshot(), display.snapshot());
+        assert_eq!(restored.intensity(1, 0), 1.0);
+        assert!(serde_json::from_str::<LedsDisplay>("[[0,1],[0]]").is_err());
+    }
 }
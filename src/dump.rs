@@ -0,0 +1,92 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/* Maximum number of pixels on a line of a PBM file, whose lines should be shorter than 70 characters */
+const PBM_LINE_PIXELS: usize = 64;
+
+/* Framebuffer, indexed by row and column, as a plain PBM image with the lit pixels in black */
+pub fn to_pbm(pixels: &[Vec<u8>]) -> String {
+    let width = pixels.first().map_or(0, |row| row.len());
+    let mut pbm = format!("P1\n{} {}\n", width, pixels.len());
+
+    for row in pixels {
+        for line in row.chunks(PBM_LINE_PIXELS) {
+            for &pixel in line {
+                pbm.push(if pixel != 0 { '1' } else { '0' });
+            }
+            pbm.push('\n');
+        }
+    }
+
+    pbm
+}
+
+/* Framebuffer drawn with block characters, each one covering two rows, so that the display keeps its
+ * proportions in a terminal */
+pub fn to_text(pixels: &[Vec<u8>]) -> String {
+    let mut text = String::new();
+
+    for rows in pixels.chunks(2) {
+        for (x, &top) in rows[0].iter().enumerate() {
+            let bottom = rows.get(1).map_or(0, |row| row[x]);
+            text.push(match (top != 0, bottom != 0) {
+                (true, true) => '\u{2588}',
+                (true, false) => '\u{2580}',
+                (false, true) => '\u{2584}',
+                (false, false) => ' ',
+            });
+        }
+        text.push('\n');
+    }
+
+    text
+}
+
+/* SHA-1 of the pixel values, row by row, as a lowercase hex string; comparing it with the one of a
+ * known good run tells if a ROM drew the expected output */
+pub fn hash(pixels: &[Vec<u8>]) -> String {
+    let mut sha1 = sha1_smol::Sha1::new();
+    for row in pixels {
+        sha1.update(row);
+    }
+
+    sha1.digest().to_string()
+}
+
+/* Write the framebuffer as a PBM image if the file has the .pbm extension, otherwise as its hash on a
+ * single line */
+pub fn write(path: &Path, pixels: &[Vec<u8>]) -> io::Result<()> {
+    let content = if path.extension().map_or(false, |extension| extension == "pbm") {
+        to_pbm(pixels)
+    } else {
+        format!("{}\n", hash(pixels))
+    };
+
+    fs::write(path, content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pbm() {
+        let pixels = vec![vec![0, 1, 0], vec![2, 0, 1]];
+        assert_eq!(to_pbm(&pixels), "P1\n3 2\n010\n101\n");
+    }
+
+    #[test]
+    fn wide_rows_are_split() {
+        let pixels = vec![vec![1; 128]];
+        let pbm = to_pbm(&pixels);
+        assert!(pbm.lines().skip(2).all(|line| line.len() == PBM_LINE_PIXELS));
+        assert_eq!(pbm.lines().count(), 4);
+    }
+
+    #[test]
+    fn text() {
+        let pixels = vec![vec![1, 0, 1, 0], vec![1, 1, 0, 0], vec![0, 1, 0, 0]];
+        assert_eq!(to_text(&pixels), "\u{2588}\u{2584}\u{2580} \n \u{2580}  \n");
+    }
+}
@@ -0,0 +1,242 @@
+use crate::hsl::*;
+
+/* Hue difference between two consecutive rows of the rainbow */
+const RAINBOW_ROW_STEP: i32 = 6;
+
+
+/* Range of the lightness in the gray pulse */
+const GRAY_PULSE_CENTER: f32 = 0.55;
+const GRAY_PULSE_AMPLITUDE: f32 = 0.35;
+
+pub const NAMES: [&str; 7] = ["static", "hue-cycle", "pastel", "neon", "gray-pulse", "rainbow", "pulse"];
+
+/* Parameters of the hue cycling effects; the saturation and the lightness are only used by
+ * "hue-cycle", the variants have their own */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CycleSettings {
+    /* Degrees added to the hue on every frame; negative values cycle backwards */
+    pub step: i32,
+    pub saturation: f32,
+    pub lightness: f32,
+}
+
+impl Default for CycleSettings {
+    fn default() -> Self {
+        CycleSettings {
+            step: 1,
+            saturation: 1.0,
+            lightness: 0.5,
+        }
+    }
+}
+
+/* Parameters of the "pulse" effect */
+#[derive(Debug, Copy, Clone)]
+pub struct PulseSettings {
+    /* Color the LEDs flash to when a sound starts */
+    pub color: RGBPixel,
+    /* Frames taken to fade back to the base color */
+    pub frames: u32,
+}
+
+impl Default for PulseSettings {
+    fn default() -> Self {
+        PulseSettings {
+            color: RGBPixel { r: 1.0, g: 0.3, b: 0.0 },
+            frames: 20,
+        }
+    }
+}
+
+/* Information about the emulator state, passed to the effects on every frame */
+#[derive(Debug, Copy, Clone, Default)]
+pub struct FrameInfo {
+    /* A sound started since the previous frame */
+    pub beep: bool,
+}
+
+/* Animation of the color of the lit LEDs */
+pub trait Effect {
+    fn name(&self) -> &'static str;
+
+    /* Advance the animation by one frame; returns true if the color of the lit LEDs changed */
+    fn update(&mut self, frame: &FrameInfo) -> bool;
+
+    fn color(&self, x: usize, y: usize) -> RGBPixel;
+}
+
+/* Create the effect with the given name; base is the foreground color of the palette */
+pub fn from_name(name: &str, base: RGBPixel, cycle: CycleSettings, pulse: PulseSettings) -> Option<Box<dyn Effect>> {
+    match name {
+        "static" => Some(Box::new(StaticColor { color: base })),
+        "hue-cycle" => Some(Box::new(HueCycle::new("hue-cycle", cycle))),
+        "pastel" => Some(Box::new(HueCycle::new(
+            "pastel",
+            CycleSettings {
+                saturation: 0.7,
+                lightness: 0.8,
+                ..cycle
+            },
+        ))),
+        "neon" => Some(Box::new(HueCycle::new(
+            "neon",
+            CycleSettings {
+                saturation: 1.0,
+                lightness: 0.6,
+                ..cycle
+            },
+        ))),
+        "gray-pulse" => Some(Box::new(GrayPulse {
+            step: cycle.step,
+            phase: 0,
+        })),
+        "rainbow" => Some(Box::new(Rainbow { hue: 0 })),
+        "pulse" => Some(Box::new(Pulse {
+            base,
+            settings: pulse,
+            level: 0.0,
+        })),
+        _ => None,
+    }
+}
+
+pub struct StaticColor {
+    color: RGBPixel,
+}
+
+impl Effect for StaticColor {
+    fn name(&self) -> &'static str {
+        "static"
+    }
+
+    fn update(&mut self, _frame: &FrameInfo) -> bool {
+        false
+    }
+
+    fn color(&self, _x: usize, _y: usize) -> RGBPixel {
+        self.color
+    }
+}
+
+/* Rotate the hue of every LED, starting from red; with the default settings this is the original nyan
+ * mode */
+pub struct HueCycle {
+    name: &'static str,
+    settings: CycleSettings,
+    hue: i32,
+}
+
+impl HueCycle {
+    pub fn new(name: &'static str, settings: CycleSettings) -> HueCycle {
+        HueCycle { name, settings, hue: 0 }
+    }
+}
+
+impl Default for HueCycle {
+    fn default() -> Self {
+        HueCycle::new("hue-cycle", CycleSettings::default())
+    }
+}
+
+impl Effect for HueCycle {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn update(&mut self, _frame: &FrameInfo) -> bool {
+        self.hue = (self.hue + self.settings.step).rem_euclid(360);
+        self.settings.step % 360 != 0
+    }
+
+    fn color(&self, _x: usize, _y: usize) -> RGBPixel {
+        hsl_to_rgb(&HSLPixel {
+            h: self.hue,
+            s: self.settings.saturation,
+            l: self.settings.lightness,
+        })
+    }
+}
+
+/* Fade the LEDs between dark and light gray; the cycle step is used as the phase step, in degrees */
+pub struct GrayPulse {
+    step: i32,
+    phase: i32,
+}
+
+impl Effect for GrayPulse {
+    fn name(&self) -> &'static str {
+        "gray-pulse"
+    }
+
+    fn update(&mut self, _frame: &FrameInfo) -> bool {
+        self.phase = (self.phase + self.step).rem_euclid(360);
+        self.step % 360 != 0
+    }
+
+    fn color(&self, _x: usize, _y: usize) -> RGBPixel {
+        let lightness = GRAY_PULSE_CENTER + GRAY_PULSE_AMPLITUDE * (self.phase as f32).to_radians().sin();
+        RGBPixel {
+            r: lightness,
+            g: lightness,
+            b: lightness,
+        }
+    }
+}
+
+/* Each row has a different hue, scrolling down one degree per frame */
+pub struct Rainbow {
+    hue: i32,
+}
+
+impl Effect for Rainbow {
+    fn name(&self) -> &'static str {
+        "rainbow"
+    }
+
+    fn update(&mut self, _frame: &FrameInfo) -> bool {
+        self.hue = (self.hue + 359) % 360;
+        true
+    }
+
+    fn color(&self, _x: usize, y: usize) -> RGBPixel {
+        hsl_to_rgb(&HSLPixel {
+            h: (self.hue + (y as i32) * RAINBOW_ROW_STEP) % 360,
+            s: 1.0,
+            l: 0.5,
+        })
+    }
+}
+
+/* Flash the LEDs when a sound starts */
+pub struct Pulse {
+    base: RGBPixel,
+    settings: PulseSettings,
+    level: f32,
+}
+
+impl Effect for Pulse {
+    fn name(&self) -> &'static str {
+        "pulse"
+    }
+
+    fn update(&mut self, frame: &FrameInfo) -> bool {
+        let previous = self.level;
+
+        if frame.beep {
+            self.level = 1.0;
+        } else {
+            self.level = f32::max(self.level - 1.0 / self.settings.frames.max(1) as f32, 0.0);
+        }
+
+        self.level != previous
+    }
+
+    fn color(&self, _x: usize, _y: usize) -> RGBPixel {
+        let color = self.settings.color;
+        RGBPixel {
+            r: self.base.r + (color.r - self.base.r) * self.level,
+            g: self.base.g + (color.g - self.base.g) * self.level,
+            b: self.base.b + (color.b - self.base.b) * self.level,
+        }
+    }
+}
@@ -1,32 +1,175 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
 /* TODO : restore debug trait */
 pub const FONT_SIZE: usize = 5;
 pub const FONTS_SIZE: usize = 16;
 
+/* SUPER-CHIP big font, used by FX30; it only has the decimal digits */
+pub const BIG_FONT_SIZE: usize = 10;
+pub const BIG_FONTS_SIZE: usize = 10;
+
+/* Variants of the built-in font shipped by the different interpreters; serialized with the names in
+ * NAMES */
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FontSet {
+    /* The font of CHIP-48 and SUPER-CHIP, used by most modern interpreters */
+    #[default]
+    Standard,
+    Vip,
+    Dream6800,
+    Eti660,
+    Octo,
+}
+
+impl FontSet {
+    pub const NAMES: [&'static str; 5] = ["standard", "vip", "dream6800", "eti660", "octo"];
+
+    fn glyphs(&self) -> [[u8; FONT_SIZE]; FONTS_SIZE] {
+        match self {
+            FontSet::Standard => [
+                [0xF0, 0x90, 0x90, 0x90, 0xF0],
+                [0x20, 0x60, 0x20, 0x20, 0x70],
+                [0xF0, 0x10, 0xF0, 0x80, 0xF0],
+                [0xF0, 0x10, 0xF0, 0x10, 0xF0],
+                [0x90, 0x90, 0xF0, 0x10, 0x10],
+                [0xF0, 0x80, 0xF0, 0x10, 0xF0],
+                [0xF0, 0x80, 0xF0, 0x90, 0xF0],
+                [0xF0, 0x10, 0x20, 0x40, 0x40],
+                [0xF0, 0x90, 0xF0, 0x90, 0xF0],
+                [0xF0, 0x90, 0xF0, 0x10, 0xF0],
+                [0xF0, 0x90, 0xF0, 0x90, 0x90],
+                [0xE0, 0x90, 0xE0, 0x90, 0xE0],
+                [0xF0, 0x80, 0x80, 0x80, 0xF0],
+                [0xE0, 0x90, 0x90, 0x90, 0xE0],
+                [0xF0, 0x80, 0xF0, 0x80, 0xF0],
+                [0xF0, 0x80, 0xF0, 0x80, 0x80],
+            ],
+            FontSet::Vip => [
+                [0xF0, 0x90, 0x90, 0x90, 0xF0],
+                [0x60, 0x20, 0x20, 0x20, 0x70],
+                [0xF0, 0x10, 0xF0, 0x80, 0xF0],
+                [0xF0, 0x10, 0x70, 0x10, 0xF0],
+                [0xA0, 0xA0, 0xF0, 0x20, 0x20],
+                [0xF0, 0x80, 0xF0, 0x10, 0xF0],
+                [0xF0, 0x80, 0xF0, 0x90, 0xF0],
+                [0xF0, 0x10, 0x10, 0x10, 0x10],
+                [0xF0, 0x90, 0xF0, 0x90, 0xF0],
+                [0xF0, 0x90, 0xF0, 0x10, 0xF0],
+                [0xF0, 0x90, 0xF0, 0x90, 0x90],
+                [0xF0, 0x50, 0x70, 0x50, 0xF0],
+                [0xF0, 0x80, 0x80, 0x80, 0xF0],
+                [0xF0, 0x50, 0x50, 0x50, 0xF0],
+                [0xF0, 0x80, 0xF0, 0x80, 0xF0],
+                [0xF0, 0x80, 0xF0, 0x80, 0x80],
+            ],
+            FontSet::Dream6800 => [
+                [0xE0, 0xA0, 0xA0, 0xA0, 0xE0],
+                [0x40, 0x40, 0x40, 0x40, 0x40],
+                [0xE0, 0x20, 0xE0, 0x80, 0xE0],
+                [0xE0, 0x20, 0xE0, 0x20, 0xE0],
+                [0x80, 0xA0, 0xA0, 0xE0, 0x20],
+                [0xE0, 0x80, 0xE0, 0x20, 0xE0],
+                [0xE0, 0x80, 0xE0, 0xA0, 0xE0],
+                [0xE0, 0x20, 0x20, 0x20, 0x20],
+                [0xE0, 0xA0, 0xE0, 0xA0, 0xE0],
+                [0xE0, 0xA0, 0xE0, 0x20, 0xE0],
+                [0xE0, 0xA0, 0xE0, 0xA0, 0xA0],
+                [0xC0, 0xA0, 0xE0, 0xA0, 0xC0],
+                [0xE0, 0x80, 0x80, 0x80, 0xE0],
+                [0xC0, 0xA0, 0xA0, 0xA0, 0xC0],
+                [0xE0, 0x80, 0xE0, 0x80, 0xE0],
+                [0xE0, 0x80, 0xC0, 0x80, 0x80],
+            ],
+            FontSet::Eti660 => [
+                [0xE0, 0xA0, 0xA0, 0xA0, 0xE0],
+                [0x20, 0x20, 0x20, 0x20, 0x20],
+                [0xE0, 0x20, 0xE0, 0x80, 0xE0],
+                [0xE0, 0x20, 0xE0, 0x20, 0xE0],
+                [0xA0, 0xA0, 0xE0, 0x20, 0x20],
+                [0xE0, 0x80, 0xE0, 0x20, 0xE0],
+                [0xE0, 0x80, 0xE0, 0xA0, 0xE0],
+                [0xE0, 0x20, 0x20, 0x20, 0x20],
+                [0xE0, 0xA0, 0xE0, 0xA0, 0xE0],
+                [0xE0, 0xA0, 0xE0, 0x20, 0xE0],
+                [0xE0, 0xA0, 0xE0, 0xA0, 0xA0],
+                [0x80, 0x80, 0xE0, 0xA0, 0xE0],
+                [0xE0, 0x80, 0x80, 0x80, 0xE0],
+                [0x20, 0x20, 0xE0, 0xA0, 0xE0],
+                [0xE0, 0x80, 0xE0, 0x80, 0xE0],
+                [0xE0, 0x80, 0xC0, 0x80, 0x80],
+            ],
+            FontSet::Octo => [
+                [0xF0, 0x90, 0x90, 0x90, 0xF0],
+                [0x20, 0x60, 0x20, 0x20, 0x70],
+                [0xF0, 0x10, 0xF0, 0x80, 0xF0],
+                [0xF0, 0x10, 0xF0, 0x10, 0xF0],
+                [0xA0, 0xA0, 0xF0, 0x20, 0x20],
+                [0xF0, 0x80, 0xF0, 0x10, 0xF0],
+                [0xF0, 0x80, 0xF0, 0x90, 0xF0],
+                [0xF0, 0x10, 0x10, 0x10, 0x10],
+                [0xF0, 0x90, 0xF0, 0x90, 0xF0],
+                [0xF0, 0x90, 0xF0, 0x10, 0xF0],
+                [0xF0, 0x90, 0xF0, 0x90, 0x90],
+                [0xF0, 0x50, 0x70, 0x50, 0xF0],
+                [0xF0, 0x80, 0x80, 0x80, 0xF0],
+                [0xF0, 0x50, 0x50, 0x50, 0xF0],
+                [0xF0, 0x80, 0xF0, 0x80, 0xF0],
+                [0xF0, 0x80, 0xF0, 0x80, 0x80],
+            ],
+        }
+    }
+}
+
+impl FromStr for FontSet {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "standard" => Ok(FontSet::Standard),
+            "vip" => Ok(FontSet::Vip),
+            "dream6800" => Ok(FontSet::Dream6800),
+            "eti660" => Ok(FontSet::Eti660),
+            "octo" => Ok(FontSet::Octo),
+            _ => Err(format!("Unknown font: {}", name)),
+        }
+    }
+}
+
 pub struct Fonts {
     pub fonts: [[u8; FONT_SIZE]; FONTS_SIZE],
+    pub big_fonts: [[u8; BIG_FONT_SIZE]; BIG_FONTS_SIZE],
 }
 
 impl Fonts {
     pub fn new() -> Fonts {
-        let mut fonts = [[0; FONT_SIZE]; FONTS_SIZE];
-
-        fonts[0x00] = [0xF0, 0x90, 0x90, 0x90, 0xF0];
-        fonts[0x01] = [0x20, 0x60, 0x20, 0x20, 0x70];
-        fonts[0x02] = [0xF0, 0x10, 0xF0, 0x80, 0xF0];
-        fonts[0x03] = [0xF0, 0x10, 0xF0, 0x10, 0xF0];
-        fonts[0x04] = [0x90, 0x90, 0xF0, 0x10, 0x10];
-        fonts[0x05] = [0xF0, 0x80, 0xF0, 0x10, 0xF0];
-        fonts[0x06] = [0xF0, 0x80, 0xF0, 0x90, 0xF0];
-        fonts[0x07] = [0xF0, 0x10, 0x20, 0x40, 0x40];
-        fonts[0x08] = [0xF0, 0x90, 0xF0, 0x90, 0xF0];
-        fonts[0x09] = [0xF0, 0x90, 0xF0, 0x10, 0xF0];
-        fonts[0x0a] = [0xF0, 0x90, 0xF0, 0x90, 0x90];
-        fonts[0x0b] = [0xE0, 0x90, 0xE0, 0x90, 0xE0];
-        fonts[0x0c] = [0xF0, 0x80, 0x80, 0x80, 0xF0];
-        fonts[0x0d] = [0xE0, 0x90, 0x90, 0x90, 0xE0];
-        fonts[0x0e] = [0xF0, 0x80, 0xF0, 0x80, 0xF0];
-        fonts[0x0f] = [0xF0, 0x80, 0xF0, 0x80, 0x80];
-
-        Fonts { fonts }
+        Fonts::with_set(FontSet::default())
+    }
+
+    pub fn with_set(set: FontSet) -> Fonts {
+        let big_fonts = [
+            [0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C],
+            [0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C],
+            [0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF],
+            [0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C],
+            [0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06],
+            [0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C],
+            [0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C],
+            [0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60],
+            [0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C],
+            [0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C],
+        ];
+
+        Fonts {
+            fonts: set.glyphs(),
+            big_fonts,
+        }
+    }
+}
+
+impl Default for Fonts {
+    fn default() -> Self {
+        Fonts::new()
     }
 }
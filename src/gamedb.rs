@@ -0,0 +1,115 @@
+use crate::macros::MacroStep;
+use crate::platform::{MachineConfig, Quirks};
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+
+/* Database bundled with the emulator, empty in the repository; entries from a user provided database take
+ * precedence */
+const BUNDLED_DATABASE: &str = include_str!("../data/games.json");
+
+#[derive(Debug, Copy, Clone, Deserialize)]
+pub struct Palette {
+    pub foreground: [f32; 3],
+    pub background: [f32; 3],
+}
+
+/* Per-game overrides; every field is optional so that an entry only needs to specify what differs
+ * from the defaults */
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct GameSettings {
+    /* Instructions executed per second */
+    pub speed: Option<u32>,
+    pub palette: Option<Palette>,
+    /* Map from a keyboard key (a single letter or digit) to the CHIP-8 key code */
+    pub keymap: Option<HashMap<String, u8>>,
+    /* Map from a keyboard key, named like in the keymap, to a sequence of CHIP-8 key presses */
+    pub macros: Option<HashMap<String, Vec<MacroStep>>>,
+    /* Width of each pixel relative to its height */
+    pub pixel_aspect: Option<f64>,
+    /* Quirks the game needs, replacing the ones of the platform */
+    pub quirks: Option<Quirks>,
+}
+
+impl GameSettings {
+    /* Apply the settings of the emulated machine to the configuration */
+    pub fn apply(&self, config: &mut MachineConfig) {
+        if let Some(quirks) = self.quirks {
+            config.quirks = quirks;
+        }
+    }
+}
+
+pub struct GameDatabase {
+    games: HashMap<String, GameSettings>,
+}
+
+impl GameDatabase {
+    pub fn new() -> GameDatabase {
+        GameDatabase {
+            games: serde_json::from_str(BUNDLED_DATABASE).unwrap(),
+        }
+    }
+
+    /* Load the entries from a user provided JSON file, overriding the bundled ones */
+    pub fn load_file(&mut self, path: &str) -> io::Result<()> {
+        self.load_str(&fs::read_to_string(path)?)
+    }
+
+    fn load_str(&mut self, content: &str) -> io::Result<()> {
+        let games: HashMap<String, GameSettings> = serde_json::from_str(content)?;
+
+        for (hash, settings) in games {
+            self.games.insert(hash.to_lowercase(), settings);
+        }
+
+        Ok(())
+    }
+
+    pub fn lookup(&self, rom: &[u8]) -> Option<&GameSettings> {
+        self.games.get(&rom_hash(rom))
+    }
+}
+
+impl Default for GameDatabase {
+    fn default() -> Self {
+        GameDatabase::new()
+    }
+}
+
+/* SHA-1 of the ROM content as a lowercase hex string, the same key used by the CHIP-8 community database */
+pub fn rom_hash(rom: &[u8]) -> String {
+    sha1_smol::Sha1::from(rom).digest().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup_and_override() {
+        let rom = [0x12, 0x00];
+        let hash = rom_hash(&rom).to_uppercase();
+        let mut db = GameDatabase::new();
+        assert!(db.lookup(&rom).is_none());
+
+        db.load_str(&format!(r#"{{ "{}": {{ "speed": 1000, "quirks": {{ "vf-reset": true }} }} }}"#, hash)).unwrap();
+        let settings = db.lookup(&rom).unwrap();
+        assert_eq!(settings.speed, Some(1000));
+        let mut config = MachineConfig::default();
+        settings.apply(&mut config);
+        assert!(config.quirks.vf_reset);
+
+        db.load_str(&format!(r#"{{ "{}": {{ "speed": 500 }} }}"#, hash)).unwrap();
+        let settings = db.lookup(&rom).unwrap();
+        assert_eq!(settings.speed, Some(500));
+        let mut config = MachineConfig::default();
+        settings.apply(&mut config);
+        assert_eq!(config.quirks, Quirks::default());
+
+        assert_eq!(db.load_str("{ \"key\": 1 }").unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(db.load_file("missing.json").unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+}
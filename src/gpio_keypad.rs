@@ -0,0 +1,92 @@
+use crate::keypad::*;
+
+use rppal::gpio::{Gpio, InputPin, Level, OutputPin};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/* How often the matrix is scanned; fast enough to feel immediate, slow enough to skip the bounces */
+const SCAN_PERIOD_MS: u64 = 10;
+
+/* Time for the column inputs to settle after a row is selected */
+const SETTLE_TIME_US: u64 = 10;
+
+/* 4x4 button matrix laid out like the COSMAC VIP hex keypad: the rows are outputs, selected one at a
+ * time by driving them low, and the columns are inputs with pull-ups, so a pressed button reads low on
+ * its column while its row is selected */
+pub struct GpioKeypad {
+    rows: Vec<OutputPin>,
+    columns: Vec<InputPin>,
+    keys: [bool; 16],
+}
+
+impl GpioKeypad {
+    /* Pins are given with their BCM numbers, from the top row and the left column */
+    pub fn new(row_pins: [u8; 4], column_pins: [u8; 4]) -> rppal::gpio::Result<GpioKeypad> {
+        let gpio = Gpio::new()?;
+
+        let mut rows = Vec::new();
+        for pin in row_pins {
+            rows.push(gpio.get(pin)?.into_output_high());
+        }
+
+        let mut columns = Vec::new();
+        for pin in column_pins {
+            columns.push(gpio.get(pin)?.into_input_pullup());
+        }
+
+        Ok(GpioKeypad {
+            rows,
+            columns,
+            keys: [false; 16],
+        })
+    }
+
+    /* Read all the buttons */
+    pub fn scan(&mut self) {
+        for (row, codes) in LAYOUT.iter().enumerate() {
+            self.rows[row].set_low();
+            thread::sleep(Duration::from_micros(SETTLE_TIME_US));
+
+            for (column, &code) in codes.iter().enumerate() {
+                self.keys[code] = self.columns[column].read() == Level::Low;
+            }
+
+            self.rows[row].set_high();
+        }
+    }
+
+    /* Scan the matrix on a separate thread, copying the buttons to the keypad read by the emulator;
+     * other inputs, like the keyboard, keep working for the keys not pressed on the matrix */
+    pub fn spawn(mut self, keypad: Arc<Mutex<KeyboardKeypad>>) {
+        thread::spawn(move || {
+            let mut previous = [false; 16];
+
+            loop {
+                self.scan();
+
+                /* Only the changes are copied, so that the buttons don't override the other inputs */
+                let mut keypad = keypad.lock().unwrap();
+                for (code, &pressed) in self.keys.iter().enumerate() {
+                    if pressed != previous[code] {
+                        keypad.set_is_pressed(code, pressed);
+                    }
+                }
+                drop(keypad);
+
+                previous = self.keys;
+                thread::sleep(Duration::from_millis(SCAN_PERIOD_MS));
+            }
+        });
+    }
+}
+
+impl Keypad for GpioKeypad {
+    fn set_is_pressed(&mut self, code: usize, is_pressed: bool) {
+        self.keys[code] = is_pressed;
+    }
+
+    fn get_is_pressed(&self, code: usize) -> bool {
+        self.keys[code]
+    }
+}
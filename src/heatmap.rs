@@ -0,0 +1,140 @@
+use crate::debugger::Access;
+
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/* Addresses on each row of the text view and of the image */
+const ROW_LENGTH: usize = 64;
+/* Side of the square drawn for each address in the image */
+const IMAGE_SCALE: usize = 4;
+/* Characters of the text view, from no access to the most accessed address */
+const SHADES: &[u8] = b" .:-=+*#%@";
+
+/* Number of reads and writes of each memory address by the instructions, to see where a program keeps
+ * its data; fetching the instructions is not counted, see coverage::Coverage for that */
+pub struct Heatmap {
+    reads: Vec<u64>,
+    writes: Vec<u64>,
+}
+
+impl Heatmap {
+    pub fn new(memory_size: usize) -> Heatmap {
+        Heatmap {
+            reads: vec![0; memory_size],
+            writes: vec![0; memory_size],
+        }
+    }
+
+    pub fn record(&mut self, address: u16, access: Access) {
+        let counts = match access {
+            Access::Read => &mut self.reads,
+            Access::Write => &mut self.writes,
+        };
+
+        if let Some(count) = counts.get_mut(address as usize) {
+            *count += 1;
+        }
+    }
+
+    pub fn reads(&self, address: usize) -> u64 {
+        self.reads.get(address).copied().unwrap_or(0)
+    }
+
+    pub fn writes(&self, address: usize) -> u64 {
+        self.writes.get(address).copied().unwrap_or(0)
+    }
+
+    pub fn size(&self) -> usize {
+        self.reads.len()
+    }
+
+    /* Rows of ROW_LENGTH addresses, each one shown with a character darker the more it was accessed,
+     * relative to the most accessed address of the memory */
+    pub fn to_text(&self, start: usize, len: usize) -> String {
+        let end = start.saturating_add(len).min(self.size());
+        let max = (0..self.size()).map(|address| self.reads(address) + self.writes(address)).max().unwrap_or(0);
+
+        let mut text = String::new();
+        for row in (start..end).step_by(ROW_LENGTH) {
+            let _ = write!(text, "{:03X} ", row);
+            for address in row..(row + ROW_LENGTH).min(end) {
+                let shade = intensity(self.reads(address) + self.writes(address), max) * (SHADES.len() - 1) as f64;
+                text.push(SHADES[shade.round() as usize] as char);
+            }
+            text.push('\n');
+        }
+
+        text
+    }
+
+    /* Plain PPM image of the whole memory, ROW_LENGTH addresses per row, with the reads in green and the
+     * writes in red, so that the data written and read back shows in yellow */
+    pub fn to_ppm(&self) -> String {
+        let rows = (self.size() + ROW_LENGTH - 1) / ROW_LENGTH;
+        let max_reads = self.reads.iter().copied().max().unwrap_or(0);
+        let max_writes = self.writes.iter().copied().max().unwrap_or(0);
+
+        let mut ppm = format!("P3\n{} {}\n255\n", ROW_LENGTH * IMAGE_SCALE, rows * IMAGE_SCALE);
+        for row in 0..rows {
+            let mut line = String::new();
+            for address in row * ROW_LENGTH..(row + 1) * ROW_LENGTH {
+                let red = (intensity(self.writes(address), max_writes) * 255.0).round() as u8;
+                let green = (intensity(self.reads(address), max_reads) * 255.0).round() as u8;
+                for _ in 0..IMAGE_SCALE {
+                    let _ = write!(line, "{} {} 0 ", red, green);
+                }
+            }
+
+            for _ in 0..IMAGE_SCALE {
+                ppm.push_str(line.trim_end());
+                ppm.push('\n');
+            }
+        }
+
+        ppm
+    }
+}
+
+/* Count relative to the maximum, on a logarithmic scale so that the addresses accessed a few times
+ * are still visible next to the ones accessed every frame */
+fn intensity(count: u64, max: u64) -> f64 {
+    if count == 0 {
+        return 0.0;
+    }
+
+    ((count as f64).ln_1p() / (max as f64).ln_1p()).min(1.0)
+}
+
+pub fn write(path: &Path, heatmap: &Heatmap) -> io::Result<()> {
+    fs::write(path, heatmap.to_ppm())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accesses() {
+        let mut heatmap = Heatmap::new(128);
+        for _ in 0..100 {
+            heatmap.record(0x02, Access::Write);
+        }
+        heatmap.record(0x41, Access::Read);
+        heatmap.record(0x1000, Access::Read);
+
+        assert_eq!((heatmap.writes(0x02), heatmap.reads(0x41), heatmap.reads(0x1000)), (100, 1, 0));
+
+        let text = heatmap.to_text(0, 128);
+        let rows: Vec<&str> = text.lines().collect();
+        assert_eq!(rows.len(), 2);
+        assert!(rows[0].starts_with("000   @ "));
+        assert!(rows[1].starts_with("040  ."));
+        assert_eq!(heatmap.to_text(1, usize::MAX).lines().count(), 2);
+
+        let ppm = heatmap.to_ppm();
+        assert!(ppm.starts_with("P3\n256 8\n255\n0 0 0 "));
+        assert!(ppm.contains("255 0 0"));
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::VecDeque;
+
+/* Instructions recorded by default, a couple of minutes of a game at the usual speeds */
+pub const HISTORY_SIZE: usize = 100_000;
+
+/* State of the CPU before an instruction, and the memory and the pixels the instruction changed, with
+ * their previous values; enough to undo it */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delta {
+    pub pc: u16,
+    pub i: u16,
+    pub regs: [u8; 16],
+    /* An instruction pushes or pops a single address, so the stack is restored from its depth and the
+     * address on top */
+    pub stack_len: usize,
+    pub stack_top: Option<u16>,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub hires: bool,
+    pub exited: bool,
+    /* Addresses written, with the byte they held, in the order of the writes */
+    pub memory: Vec<(u16, u8)>,
+    /* Pixels changed, by column and row, with the value they had */
+    pub pixels: Vec<(usize, usize, u8)>,
+}
+
+/* Last instructions executed, up to a capacity, so that the debugger can step backwards; the oldest
+ * ones are dropped first */
+pub struct History {
+    deltas: VecDeque<Delta>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> History {
+        History {
+            deltas: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /* Start recording an instruction, given the state before it */
+    pub fn begin(&mut self, delta: Delta) {
+        if self.deltas.len() >= self.capacity {
+            self.deltas.pop_front();
+        }
+        self.deltas.push_back(delta);
+    }
+
+    /* Called before the instruction being recorded writes the memory */
+    pub fn record_memory(&mut self, address: u16, previous: u8) {
+        if let Some(delta) = self.deltas.back_mut() {
+            delta.memory.push((address, previous));
+        }
+    }
+
+    /* Called with the display before and after the instruction being recorded changed it */
+    pub fn record_pixels(&mut self, before: &[Vec<u8>], after: &[Vec<u8>]) {
+        let delta = match self.deltas.back_mut() {
+            Some(delta) => delta,
+            None => return,
+        };
+
+        for (y, (before_row, after_row)) in before.iter().zip(after).enumerate() {
+            for (x, (&previous, &current)) in before_row.iter().zip(after_row).enumerate() {
+                /* A pixel changed twice by the same instruction keeps its first value */
+                if previous != current && !delta.pixels.iter().any(|&(px, py, _)| (px, py) == (x, y)) {
+                    delta.pixels.push((x, y, previous));
+                }
+            }
+        }
+    }
+
+    /* Remove the last instruction, to undo it */
+    pub fn pop(&mut self) -> Option<Delta> {
+        self.deltas.pop_back()
+    }
+
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    /* Forget the instructions, e.g. when the whole state is replaced */
+    pub fn clear(&mut self) {
+        self.deltas.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delta(pc: u16) -> Delta {
+        Delta {
+            pc,
+            i: 0,
+            regs: [0; 16],
+            stack_len: 0,
+            stack_top: None,
+            delay_timer: 0,
+            sound_timer: 0,
+            hires: false,
+            exited: false,
+            memory: Vec::new(),
+            pixels: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn bounded() {
+        let mut history = History::new(2);
+        history.record_memory(0x300, 1);
+
+        history.begin(delta(0x200));
+        history.begin(delta(0x202));
+        history.record_memory(0x300, 5);
+        history.record_memory(0x300, 6);
+        history.record_pixels(&[vec![0, 1]], &[vec![1, 1]]);
+        history.record_pixels(&[vec![1, 1]], &[vec![0, 1]]);
+        history.begin(delta(0x204));
+        assert_eq!(history.len(), 2);
+
+        assert_eq!(history.pop().map(|delta| delta.pc), Some(0x204));
+        let delta = history.pop().unwrap();
+        assert_eq!(delta.pc, 0x202);
+        assert_eq!(delta.memory, vec![(0x300, 5), (0x300, 6)]);
+        assert_eq!(delta.pixels, vec![(0, 0, 0)]);
+        assert!(history.pop().is_none());
+    }
+}
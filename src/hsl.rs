@@ -1,5 +1,6 @@
+use std::str::FromStr;
+
 /* TODO : move to extern crate? */
-/* TODO : make algorithm templated to work with u8 representation of a rgb */
 #[derive(Debug, Copy, Clone)]
 pub struct RGBPixel {
     pub r: f32,
@@ -7,6 +8,58 @@ pub struct RGBPixel {
     pub b: f32,
 }
 
+/* Same as RGBPixel, with the components in the 0-255 range */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RGB8Pixel {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+fn component_to_u8(value: f32) -> u8 {
+    (value.max(0.0).min(1.0) * 255.0).round() as u8
+}
+
+impl From<RGB8Pixel> for RGBPixel {
+    fn from(rgb: RGB8Pixel) -> Self {
+        RGBPixel {
+            r: (rgb.r as f32) / 255.0,
+            g: (rgb.g as f32) / 255.0,
+            b: (rgb.b as f32) / 255.0,
+        }
+    }
+}
+
+/* Components outside of the 0.0-1.0 range are clamped */
+impl From<RGBPixel> for RGB8Pixel {
+    fn from(rgb: RGBPixel) -> Self {
+        RGB8Pixel {
+            r: component_to_u8(rgb.r),
+            g: component_to_u8(rgb.g),
+            b: component_to_u8(rgb.b),
+        }
+    }
+}
+
+/* Hexadecimal color like "FF8000", optionally starting with "#" */
+impl FromStr for RGB8Pixel {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let hex = text.strip_prefix('#').unwrap_or(text);
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("invalid color, expected RRGGBB: {}", text));
+        }
+
+        let component = |index: usize| u8::from_str_radix(&hex[index..index + 2], 16).unwrap();
+        Ok(RGB8Pixel {
+            r: component(0),
+            g: component(2),
+            b: component(4),
+        })
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct HSLPixel {
     pub h: i32,
@@ -131,6 +184,14 @@ pub fn hsl_to_rgb(hsl: &HSLPixel) -> RGBPixel {
     rgb
 }
 
+pub fn rgb8_to_hsl(rgb: &RGB8Pixel) -> HSLPixel {
+    rgb_to_hsl(&RGBPixel::from(*rgb))
+}
+
+pub fn hsl_to_rgb8(hsl: &HSLPixel) -> RGB8Pixel {
+    RGB8Pixel::from(hsl_to_rgb(hsl))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -222,4 +283,45 @@ mod tests {
             assert_approx_eq!(result.b, rgb.b);
         }
     }
+
+    #[test]
+    fn rgb8_conversion() {
+        for value in 0..=255 {
+            let rgb = RGB8Pixel {
+                r: value,
+                g: 255 - value,
+                b: value / 2,
+            };
+            assert_eq!(RGB8Pixel::from(RGBPixel::from(rgb)), rgb);
+        }
+
+        let clamped = RGBPixel { r: 1.5, g: -0.5, b: 0.5 };
+        assert_eq!(RGB8Pixel::from(clamped), RGB8Pixel { r: 255, g: 0, b: 128 });
+
+        assert_eq!("#FF8000".parse(), Ok(RGB8Pixel { r: 255, g: 128, b: 0 }));
+        assert_eq!("00ff7f".parse(), Ok(RGB8Pixel { r: 0, g: 255, b: 127 }));
+        assert!("FF80".parse::<RGB8Pixel>().is_err());
+        assert!("GG0000".parse::<RGB8Pixel>().is_err());
+    }
+
+    #[test]
+    fn rgb8_hsl_round_trip() {
+        /* The hue is rounded to a degree, so only the colors with an exact hue come back unchanged */
+        let colors = [
+            RGB8Pixel { r: 255, g: 0, b: 0 },
+            RGB8Pixel { r: 0, g: 128, b: 0 },
+            RGB8Pixel { r: 0, g: 255, b: 255 },
+            RGB8Pixel { r: 255, g: 128, b: 0 },
+            RGB8Pixel { r: 51, g: 51, b: 51 },
+        ];
+
+        for rgb in colors {
+            assert_eq!(hsl_to_rgb8(&rgb8_to_hsl(&rgb)), rgb);
+        }
+
+        let hsl = rgb8_to_hsl(&RGB8Pixel { r: 0, g: 0, b: 255 });
+        assert_eq!(hsl.h, 240);
+        assert_approx_eq!(hsl.s, 1.0);
+        assert_approx_eq!(hsl.l, 0.5);
+    }
 }
@@ -1,6 +1,15 @@
+use std::fmt;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Instruction {
     ClearScreen,
     Return,
+    ScrollDown(u8),
+    ScrollRight,
+    ScrollLeft,
+    Exit,
+    LowResolution,
+    HighResolution,
     Jump(u16),
     Call(u16),
     SkipIfEqual(u8, u8),
@@ -15,9 +24,9 @@ pub enum Instruction {
     LogicalXor(u8, u8),
     LogicalAdd(u8, u8),
     LogicalSubtract(u8, u8),
-    LogicalRightShift(u8),
+    LogicalRightShift(u8, u8),
     LogicalSubtractInverse(u8, u8),
-    LogicalLeftShift(u8),
+    LogicalLeftShift(u8, u8),
     SetIndex(u16),
     JumpWithRegister(u16),
     Random(u8, u8),
@@ -30,63 +39,282 @@ pub enum Instruction {
     SetSoundTimer(u8),
     AddToIndex(u8),
     SetIndexToFont(u8),
+    SetIndexToBigFont(u8),
     BinaryConversion(u8),
     Store(u8),
     Load(u8),
+    SaveFlags(u8),
+    LoadFlags(u8),
 }
 
-impl From<u16> for Instruction {
-    fn from(instr: u16) -> Self {
+impl Instruction {
+    /* Name of the instruction variant, without its operands */
+    pub fn name(&self) -> &'static str {
+        match self {
+            Instruction::ClearScreen => "ClearScreen",
+            Instruction::Return => "Return",
+            Instruction::ScrollDown(..) => "ScrollDown",
+            Instruction::ScrollRight => "ScrollRight",
+            Instruction::ScrollLeft => "ScrollLeft",
+            Instruction::Exit => "Exit",
+            Instruction::LowResolution => "LowResolution",
+            Instruction::HighResolution => "HighResolution",
+            Instruction::Jump(..) => "Jump",
+            Instruction::Call(..) => "Call",
+            Instruction::SkipIfEqual(..) => "SkipIfEqual",
+            Instruction::SkipIfDifferent(..) => "SkipIfDifferent",
+            Instruction::SkipIfContentEqual(..) => "SkipIfContentEqual",
+            Instruction::SkipIfContentDifferent(..) => "SkipIfContentDifferent",
+            Instruction::SetRegister(..) => "SetRegister",
+            Instruction::AddToRegister(..) => "AddToRegister",
+            Instruction::LogicalCopy(..) => "LogicalCopy",
+            Instruction::LogicalOr(..) => "LogicalOr",
+            Instruction::LogicalAnd(..) => "LogicalAnd",
+            Instruction::LogicalXor(..) => "LogicalXor",
+            Instruction::LogicalAdd(..) => "LogicalAdd",
+            Instruction::LogicalSubtract(..) => "LogicalSubtract",
+            Instruction::LogicalRightShift(..) => "LogicalRightShift",
+            Instruction::LogicalSubtractInverse(..) => "LogicalSubtractInverse",
+            Instruction::LogicalLeftShift(..) => "LogicalLeftShift",
+            Instruction::SetIndex(..) => "SetIndex",
+            Instruction::JumpWithRegister(..) => "JumpWithRegister",
+            Instruction::Random(..) => "Random",
+            Instruction::Display(..) => "Display",
+            Instruction::SkipIfPressed(..) => "SkipIfPressed",
+            Instruction::SkipIfNotPressed(..) => "SkipIfNotPressed",
+            Instruction::CopyDelayTimer(..) => "CopyDelayTimer",
+            Instruction::WaitForKey(..) => "WaitForKey",
+            Instruction::SetDelayTimer(..) => "SetDelayTimer",
+            Instruction::SetSoundTimer(..) => "SetSoundTimer",
+            Instruction::AddToIndex(..) => "AddToIndex",
+            Instruction::SetIndexToFont(..) => "SetIndexToFont",
+            Instruction::SetIndexToBigFont(..) => "SetIndexToBigFont",
+            Instruction::BinaryConversion(..) => "BinaryConversion",
+            Instruction::Store(..) => "Store",
+            Instruction::Load(..) => "Load",
+            Instruction::SaveFlags(..) => "SaveFlags",
+            Instruction::LoadFlags(..) => "LoadFlags",
+        }
+    }
+
+    /* Approximate machine cycles taken by the COSMAC VIP interpreter; skips taken and sprites
+     * crossing a byte boundary take a few more, which is not counted. The
+     * SUPER-CHIP instructions didn't exist on the VIP and are counted like the simplest ones */
+    pub fn vip_cycles(&self) -> u32 {
+        match *self {
+            Instruction::ClearScreen => 3078,
+            Instruction::Return => 10,
+            Instruction::Jump(_) => 12,
+            Instruction::Call(_) => 26,
+            Instruction::SkipIfEqual(..) | Instruction::SkipIfDifferent(..) => 10,
+            Instruction::SkipIfContentEqual(..) | Instruction::SkipIfContentDifferent(..) => 14,
+            Instruction::SetRegister(..) => 6,
+            Instruction::AddToRegister(..) => 10,
+            Instruction::LogicalCopy(..)
+            | Instruction::LogicalOr(..)
+            | Instruction::LogicalAnd(..)
+            | Instruction::LogicalXor(..)
+            | Instruction::LogicalAdd(..)
+            | Instruction::LogicalSubtract(..)
+            | Instruction::LogicalRightShift(..)
+            | Instruction::LogicalSubtractInverse(..)
+            | Instruction::LogicalLeftShift(..) => 20,
+            Instruction::SetIndex(_) => 12,
+            Instruction::JumpWithRegister(_) => 22,
+            Instruction::Random(..) => 36,
+            /* Each row is shifted into place and XORed with the two bytes of the display it covers */
+            Instruction::Display(_, _, n) => 68 + 46 * (n as u32),
+            Instruction::SkipIfPressed(_) | Instruction::SkipIfNotPressed(_) => 14,
+            Instruction::CopyDelayTimer(_) => 10,
+            Instruction::WaitForKey(_) => 12,
+            Instruction::SetDelayTimer(_) | Instruction::SetSoundTimer(_) => 10,
+            Instruction::AddToIndex(_) | Instruction::SetIndexToFont(_) => 16,
+            Instruction::BinaryConversion(_) => 84,
+            Instruction::Store(x) | Instruction::Load(x) => 14 + 14 * (x as u32 + 1),
+            Instruction::ScrollDown(_)
+            | Instruction::ScrollRight
+            | Instruction::ScrollLeft
+            | Instruction::Exit
+            | Instruction::LowResolution
+            | Instruction::HighResolution
+            | Instruction::SetIndexToBigFont(_)
+            | Instruction::SaveFlags(_)
+            | Instruction::LoadFlags(_) => 6,
+        }
+    }
+
+    /* Encode the instruction, the inverse of decode */
+    pub fn opcode(&self) -> u16 {
+        let x = |reg: u8| (reg as u16 & 0x0F) << 8;
+        let xy = |reg_x: u8, reg_y: u8| x(reg_x) | (reg_y as u16 & 0x0F) << 4;
+
+        match *self {
+            Instruction::ClearScreen => 0x00E0,
+            Instruction::Return => 0x00EE,
+            Instruction::ScrollDown(n) => 0x00C0 | (n as u16 & 0x0F),
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::LowResolution => 0x00FE,
+            Instruction::HighResolution => 0x00FF,
+            Instruction::Jump(addr) => 0x1000 | (addr & 0x0FFF),
+            Instruction::Call(addr) => 0x2000 | (addr & 0x0FFF),
+            Instruction::SkipIfEqual(reg, byte) => 0x3000 | x(reg) | byte as u16,
+            Instruction::SkipIfDifferent(reg, byte) => 0x4000 | x(reg) | byte as u16,
+            Instruction::SkipIfContentEqual(reg_x, reg_y) => 0x5000 | xy(reg_x, reg_y),
+            Instruction::SetRegister(reg, byte) => 0x6000 | x(reg) | byte as u16,
+            Instruction::AddToRegister(reg, byte) => 0x7000 | x(reg) | byte as u16,
+            Instruction::LogicalCopy(reg_x, reg_y) => 0x8000 | xy(reg_x, reg_y),
+            Instruction::LogicalOr(reg_x, reg_y) => 0x8001 | xy(reg_x, reg_y),
+            Instruction::LogicalAnd(reg_x, reg_y) => 0x8002 | xy(reg_x, reg_y),
+            Instruction::LogicalXor(reg_x, reg_y) => 0x8003 | xy(reg_x, reg_y),
+            Instruction::LogicalAdd(reg_x, reg_y) => 0x8004 | xy(reg_x, reg_y),
+            Instruction::LogicalSubtract(reg_x, reg_y) => 0x8005 | xy(reg_x, reg_y),
+            Instruction::LogicalRightShift(reg_x, reg_y) => 0x8006 | xy(reg_x, reg_y),
+            Instruction::LogicalSubtractInverse(reg_x, reg_y) => 0x8007 | xy(reg_x, reg_y),
+            Instruction::LogicalLeftShift(reg_x, reg_y) => 0x800E | xy(reg_x, reg_y),
+            Instruction::SkipIfContentDifferent(reg_x, reg_y) => 0x9000 | xy(reg_x, reg_y),
+            Instruction::SetIndex(addr) => 0xA000 | (addr & 0x0FFF),
+            Instruction::JumpWithRegister(addr) => 0xB000 | (addr & 0x0FFF),
+            Instruction::Random(reg, byte) => 0xC000 | x(reg) | byte as u16,
+            Instruction::Display(reg_x, reg_y, n) => 0xD000 | xy(reg_x, reg_y) | (n as u16 & 0x0F),
+            Instruction::SkipIfPressed(reg) => 0xE09E | x(reg),
+            Instruction::SkipIfNotPressed(reg) => 0xE0A1 | x(reg),
+            Instruction::CopyDelayTimer(reg) => 0xF007 | x(reg),
+            Instruction::WaitForKey(reg) => 0xF00A | x(reg),
+            Instruction::SetDelayTimer(reg) => 0xF015 | x(reg),
+            Instruction::SetSoundTimer(reg) => 0xF018 | x(reg),
+            Instruction::AddToIndex(reg) => 0xF01E | x(reg),
+            Instruction::SetIndexToFont(reg) => 0xF029 | x(reg),
+            Instruction::SetIndexToBigFont(reg) => 0xF030 | x(reg),
+            Instruction::BinaryConversion(reg) => 0xF033 | x(reg),
+            Instruction::Store(reg) => 0xF055 | x(reg),
+            Instruction::Load(reg) => 0xF065 | x(reg),
+            Instruction::SaveFlags(reg) => 0xF075 | x(reg),
+            Instruction::LoadFlags(reg) => 0xF085 | x(reg),
+        }
+    }
+}
+
+/* Standard mnemonics, as used by most CHIP-8 assemblers; addresses and bytes are printed in hex */
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::ClearScreen => write!(f, "CLS"),
+            Instruction::Return => write!(f, "RET"),
+            Instruction::ScrollDown(n) => write!(f, "SCD {}", n),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::LowResolution => write!(f, "LOW"),
+            Instruction::HighResolution => write!(f, "HIGH"),
+            Instruction::Jump(addr) => write!(f, "JP 0x{:03X}", addr),
+            Instruction::Call(addr) => write!(f, "CALL 0x{:03X}", addr),
+            Instruction::SkipIfEqual(x, byte) => write!(f, "SE V{:X}, 0x{:02X}", x, byte),
+            Instruction::SkipIfDifferent(x, byte) => write!(f, "SNE V{:X}, 0x{:02X}", x, byte),
+            Instruction::SkipIfContentEqual(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::SkipIfContentDifferent(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::SetRegister(x, byte) => write!(f, "LD V{:X}, 0x{:02X}", x, byte),
+            Instruction::AddToRegister(x, byte) => write!(f, "ADD V{:X}, 0x{:02X}", x, byte),
+            Instruction::LogicalCopy(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::LogicalOr(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::LogicalAnd(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::LogicalXor(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::LogicalAdd(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::LogicalSubtract(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::LogicalRightShift(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::LogicalSubtractInverse(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::LogicalLeftShift(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SetIndex(addr) => write!(f, "LD I, 0x{:03X}", addr),
+            Instruction::JumpWithRegister(addr) => write!(f, "JP V0, 0x{:03X}", addr),
+            Instruction::Random(x, byte) => write!(f, "RND V{:X}, 0x{:02X}", x, byte),
+            Instruction::Display(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkipIfPressed(x) => write!(f, "SKP V{:X}", x),
+            Instruction::SkipIfNotPressed(x) => write!(f, "SKNP V{:X}", x),
+            Instruction::CopyDelayTimer(x) => write!(f, "LD V{:X}, DT", x),
+            Instruction::WaitForKey(x) => write!(f, "LD V{:X}, K", x),
+            Instruction::SetDelayTimer(x) => write!(f, "LD DT, V{:X}", x),
+            Instruction::SetSoundTimer(x) => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddToIndex(x) => write!(f, "ADD I, V{:X}", x),
+            Instruction::SetIndexToFont(x) => write!(f, "LD F, V{:X}", x),
+            Instruction::SetIndexToBigFont(x) => write!(f, "LD HF, V{:X}", x),
+            Instruction::BinaryConversion(x) => write!(f, "LD B, V{:X}", x),
+            Instruction::Store(x) => write!(f, "LD [I], V{:X}", x),
+            Instruction::Load(x) => write!(f, "LD V{:X}, [I]", x),
+            Instruction::SaveFlags(x) => write!(f, "LD R, V{:X}", x),
+            Instruction::LoadFlags(x) => write!(f, "LD V{:X}, R", x),
+        }
+    }
+}
+
+impl Instruction {
+    /* Decode an opcode, returning None if it's not a valid instruction */
+    pub fn decode(instr: u16) -> Option<Instruction> {
         match instr & 0xF000 {
             0x0000 => {
                 /* A 0NNN instruction exists to execute native 1802 machine code in the COSMAC VIP; it
                  * has not been implemented */
                 if instr == 0x00E0 {
-                    return Instruction::ClearScreen;
+                    return Some(Instruction::ClearScreen);
                 } else if instr == 0x00EE {
-                    return Instruction::Return;
+                    return Some(Instruction::Return);
+                } else if instr & 0xFFF0 == 0x00C0 {
+                    /* 00CN, 00FB and 00FC: SUPER-CHIP, scroll down by N pixels, right and left by 4 */
+                    return Some(Instruction::ScrollDown((instr & 0x000F) as u8));
+                } else if instr == 0x00FB {
+                    return Some(Instruction::ScrollRight);
+                } else if instr == 0x00FC {
+                    return Some(Instruction::ScrollLeft);
+                } else if instr == 0x00FD {
+                    /* 00FD: SUPER-CHIP, exit the interpreter */
+                    return Some(Instruction::Exit);
+                } else if instr == 0x00FE {
+                    /* 00FE and 00FF: SUPER-CHIP, switch to low and high resolution */
+                    return Some(Instruction::LowResolution);
+                } else if instr == 0x00FF {
+                    return Some(Instruction::HighResolution);
                 } else {
-                    panic!("Unknown instruction found: {:X?}", instr);
+                    return None;
                 }
             },
             0x1000 => {
                 /* 1NNN: jump, set the PC to NNN */
-                return Instruction::Jump(instr & 0x0FFF);
+                return Some(Instruction::Jump(instr & 0x0FFF));
             },
             0x2000 => {
                 /* 2NNN: call subroutine, push the PC and set the PC to NNN */
-                return Instruction::Call(instr & 0x0FFF);
+                return Some(Instruction::Call(instr & 0x0FFF));
             },
             0x3000 => {
                 /* 3XNN: skip one instruction if VX content is equal to NN */
                 let reg_x = (instr & 0x0F00) >> 8;
-                return Instruction::SkipIfEqual(reg_x as u8, (instr & 0x00FF) as u8);
+                return Some(Instruction::SkipIfEqual(reg_x as u8, (instr & 0x00FF) as u8));
             },
             0x4000 => {
                 /* 4XNN: skip one instruction if VX content is NOT equal to NN */
                 let reg_x = (instr & 0x0F00) >> 8;
-                return Instruction::SkipIfDifferent(reg_x as u8, (instr & 0x00FF) as u8);
+                return Some(Instruction::SkipIfDifferent(reg_x as u8, (instr & 0x00FF) as u8));
             },
             0x5000 => {
                 if (instr & 0xF00F) == 0x5000 {
                     /* 5XY0: skip one instruction if VX and VY values are equal */
                     let reg_x = (instr & 0x0F00) >> 8;
                     let reg_y = (instr & 0x00F0) >> 4;
-                    return Instruction::SkipIfContentEqual(reg_x as u8, reg_y as u8);
+                    return Some(Instruction::SkipIfContentEqual(reg_x as u8, reg_y as u8));
                 } else {
-                    panic!("Unknown instruction found: {:X?}", instr);
+                    return None;
                 }
             },
             0x6000 => {
                 /* 6XNN: set register X to value NN */
                 let reg = (instr & 0x0F00) >> 8;
-                return Instruction::SetRegister(reg as u8, (instr & 0x00FF) as u8);
+                return Some(Instruction::SetRegister(reg as u8, (instr & 0x00FF) as u8));
             },
             0x7000 => {
                 /* 7XNN: add value to register X; this can overflow, so a helper variable is used */
                 let reg = (instr & 0x0F00) >> 8;
                 let value = instr & 0x00FF;
-                return Instruction::AddToRegister(reg as u8, value as u8);
+                return Some(Instruction::AddToRegister(reg as u8, value as u8));
             },
             0x8000 => {
                 /* Process logical instruction */
@@ -95,44 +323,46 @@ impl From<u16> for Instruction {
                 match instr & 0xF00F {
                     0x8000 => {
                         /* 8XY0: set instruction; copy VY to VX */
-                        return Instruction::LogicalCopy(reg_x, reg_y);
+                        return Some(Instruction::LogicalCopy(reg_x, reg_y));
                     },
                     0x8001 => {
                         /* 8XY1: binary OR, set VX to the OR of VX and VY */
-                        return Instruction::LogicalOr(reg_x, reg_y);
+                        return Some(Instruction::LogicalOr(reg_x, reg_y));
                     },
                     0x8002 => {
                         /* 8XY2: binary AND, set VX to the AND of VX and VY */
-                        return Instruction::LogicalAnd(reg_x, reg_y);
+                        return Some(Instruction::LogicalAnd(reg_x, reg_y));
                     },
                     0x8003 => {
                         /* 8XY3: binary XOR, set VX to the XOR of VX and VY */
-                        return Instruction::LogicalXor(reg_x, reg_y);
+                        return Some(Instruction::LogicalXor(reg_x, reg_y));
                     },
                     0x8004 => {
                         /* 8XY4: ADD, VX is set to the value of VX plus VY; if overflow occurs, set the flag register */
-                        return Instruction::LogicalAdd(reg_x, reg_y);
+                        return Some(Instruction::LogicalAdd(reg_x, reg_y));
                     },
                     0x8005 => {
                         /* 8XY5: SUBTRACT, VX is set to the value of VX minus VY;
                          * in this case, the flag register is set if the first operand is larger than the second operand */
-                        return Instruction::LogicalSubtract(reg_x, reg_y);
+                        return Some(Instruction::LogicalSubtract(reg_x, reg_y));
                     },
                     0x8006 => {
-                        /* 8XY6: SHIFT; shift VX one bit to the right */
-                        return Instruction::LogicalRightShift(reg_x);
+                        /* 8XY6: SHIFT; shift VX one bit to the right; the original interpreter shifted VY
+                         * into VX */
+                        return Some(Instruction::LogicalRightShift(reg_x, reg_y));
                     },
                     0x8007 => {
                         /* 8XY7: SUBTRACT, VX is set to the value of VY minus VX;
                          * in this case, the flag register is set if the first operand is larger than the second operand */
-                        return Instruction::LogicalSubtractInverse(reg_x, reg_y);
+                        return Some(Instruction::LogicalSubtractInverse(reg_x, reg_y));
                     },
                     0x800E => {
-                        /* 8XYE: SHIFT; shift VX one bit to the left */
-                        return Instruction::LogicalLeftShift(reg_x);
+                        /* 8XYE: SHIFT; shift VX one bit to the left; the original interpreter shifted VY
+                         * into VX */
+                        return Some(Instruction::LogicalLeftShift(reg_x, reg_y));
                     },
                     _ => {
-                        panic!("Unknown logical instruction found: {:X?}", instr);
+                        return None;
                     }
                 }
             },
@@ -142,42 +372,42 @@ impl From<u16> for Instruction {
                     let reg_x = (instr & 0x0F00) >> 8;
                     let reg_y = (instr & 0x00F0) >> 4;
 
-                    return Instruction::SkipIfContentDifferent(reg_x as u8, reg_y as u8);
+                    return Some(Instruction::SkipIfContentDifferent(reg_x as u8, reg_y as u8));
                 } else {
-                    panic!("Unknown skip instruction found: {:X?}", instr);
+                    return None;
                 }
             },
             0xA000 => {
                 /* ANNN: set index to value NNN */
-                return Instruction::SetIndex(instr & 0x0FFF);
+                return Some(Instruction::SetIndex(instr & 0x0FFF));
             },
             0xB000 => {
                 /* BNNN: JUMP, set PC to NNN plus the value of V0 */
-                return Instruction::JumpWithRegister(instr & 0x0FFF);
+                return Some(Instruction::JumpWithRegister(instr & 0x0FFF));
             },
             0xC000 => {
                 /* CXNN: RANDOM, generate a random number, binary AND with NN and set the result in VX */
                 let reg = (instr & 0x0F00) >> 8;
-                return Instruction::Random(reg as u8, (instr & 0x00FF) as u8);
+                return Some(Instruction::Random(reg as u8, (instr & 0x00FF) as u8));
             },
             0xD000 => {
                 /* DXYN: display */
                 let x = (instr & 0x0F00) >> 8;
                 let y = (instr & 0x00F0) >> 4;
                 let n = instr & 0x000F;
-                return Instruction::Display(x as u8, y as u8, n as u8);
+                return Some(Instruction::Display(x as u8, y as u8, n as u8));
             },
             0xE000 => {
                 if (instr & 0xF0FF) == 0xE09E {
                     /* EX9E: skip instruction if key value from VX is currenty pressed */
                     let reg = (instr & 0x0F00) >> 8;
-                    return Instruction::SkipIfPressed(reg as u8);
+                    return Some(Instruction::SkipIfPressed(reg as u8));
                 } else if (instr & 0xF0FF) == 0xE0A1 {
                     /* EXA1: skip instruction if key value from VX is NOT currenty pressed */
                     let reg = (instr & 0x0F00) >> 8;
-                    return Instruction::SkipIfNotPressed(reg as u8);
+                    return Some(Instruction::SkipIfNotPressed(reg as u8));
                 } else {
-                    panic!("Unknown keypad skip instruction found: {:X?}", instr);
+                    return None;
                 }
             },
             0xF000 => {
@@ -186,48 +416,65 @@ impl From<u16> for Instruction {
                 match instr & 0xF0FF {
                     0xF007 => {
                         /* FX07: copy timer; set VX to the current value of the delay timer */
-                        return Instruction::CopyDelayTimer(reg as u8);
+                        return Some(Instruction::CopyDelayTimer(reg as u8));
                     }
                     0xF00A => {
                         /* FX0A: wait for a key press and set its value to VX */
-                        return Instruction::WaitForKey(reg as u8);
+                        return Some(Instruction::WaitForKey(reg as u8));
                     }
                     0xF015 => {
                         /* FX15: set timer; set the delay timer to the value in VX */
-                        return Instruction::SetDelayTimer(reg as u8);
+                        return Some(Instruction::SetDelayTimer(reg as u8));
                     }
                     0xF018 => {
                         /* FX18: set timer; set the sound timer to the value in VX */
-                        return Instruction::SetSoundTimer(reg as u8);
+                        return Some(Instruction::SetSoundTimer(reg as u8));
                     }
                     0xF01E => {
                         /* FX1E: add to index; add the content of VX to the index, checking for overflows */
-                        return Instruction::AddToIndex(reg as u8);
+                        return Some(Instruction::AddToIndex(reg as u8));
                     }
                     0xF029 => {
                         /* FX29: font character; set I to the address of the "char" contained in VX */
-                        return Instruction::SetIndexToFont(reg as u8);
+                        return Some(Instruction::SetIndexToFont(reg as u8));
+                    }
+                    0xF030 => {
+                        /* FX30: SUPER-CHIP, set I to the address of the big font digit in VX */
+                        return Some(Instruction::SetIndexToBigFont(reg as u8));
                     }
                     0xF033 => {
                         /* FX33: binary-coded decimal conversion; take the value of VX and convert it in 3 decimal digits */
-                        return Instruction::BinaryConversion(reg as u8);
+                        return Some(Instruction::BinaryConversion(reg as u8));
                     }
                     0xF055 => {
                         /* FX55: store in memory; save value from V0 to VX to index from I to I * X in memory */
-                        return Instruction::Store(reg as u8);
+                        return Some(Instruction::Store(reg as u8));
                     }
                     0xF065 => {
                         /* FX65: load from memory; save value from index I to I * X to V0 to VX  */
-                        return Instruction::Load(reg as u8);
+                        return Some(Instruction::Load(reg as u8));
+                    }
+                    0xF075 => {
+                        /* FX75: SUPER-CHIP, save V0 to VX in the HP48 RPL user flags */
+                        return Some(Instruction::SaveFlags(reg as u8));
+                    }
+                    0xF085 => {
+                        /* FX85: SUPER-CHIP, load V0 to VX from the HP48 RPL user flags */
+                        return Some(Instruction::LoadFlags(reg as u8));
                     }
                     _ => {
-                        panic!("Unknown instruction found: {:X?}", instr);
+                        return None;
                     }
                 }
             },
             _ => {
-                panic!("Unknown instruction found: {:X?}", instr);
+                return None;
             }
         }
     }
-}
\ No newline at end of file
+}
+impl From<u16> for Instruction {
+    fn from(instr: u16) -> Self {
+        Instruction::decode(instr).unwrap_or_else(|| panic!("Unknown instruction found: {:X?}", instr))
+    }
+}
@@ -0,0 +1,108 @@
+use piston::input::Key;
+
+use std::str::FromStr;
+
+pub const NAMES: [&str; 4] = ["qwerty", "azerty", "qwertz", "dvorak"];
+
+/* Layout of the host keyboard. The keymaps are written for the positions of the keys on a QWERTY
+ * keyboard; the window only reports the symbols of the keys, so the keymaps are translated to the
+ * symbols found at the same positions on the other layouts */
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum KeyboardLayout {
+    /* The keys are mapped by their symbols */
+    #[default]
+    Qwerty,
+    Azerty,
+    Qwertz,
+    Dvorak,
+}
+
+impl FromStr for KeyboardLayout {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "qwerty" => Ok(KeyboardLayout::Qwerty),
+            "azerty" => Ok(KeyboardLayout::Azerty),
+            "qwertz" => Ok(KeyboardLayout::Qwertz),
+            "dvorak" => Ok(KeyboardLayout::Dvorak),
+            _ => Err(format!("unknown keyboard layout: {}", name)),
+        }
+    }
+}
+
+/* Keys whose symbol differs from QWERTY, as (QWERTY key, key at the same position); None for the
+ * symbols the window can't report, like the apostrophe. The digits keep their symbols, which need
+ * Shift on AZERTY */
+const AZERTY: [(Key, Option<Key>); 9] = [
+    (Key::Q, Some(Key::A)),
+    (Key::A, Some(Key::Q)),
+    (Key::W, Some(Key::Z)),
+    (Key::Z, Some(Key::W)),
+    (Key::Semicolon, Some(Key::M)),
+    (Key::M, Some(Key::Comma)),
+    (Key::Comma, Some(Key::Semicolon)),
+    (Key::Period, None),
+    (Key::Slash, None),
+];
+
+const QWERTZ: [(Key, Option<Key>); 3] = [(Key::Y, Some(Key::Z)), (Key::Z, Some(Key::Y)), (Key::Semicolon, None)];
+
+const DVORAK: [(Key, Option<Key>); 22] = [
+    (Key::Q, None),
+    (Key::W, Some(Key::Comma)),
+    (Key::E, Some(Key::Period)),
+    (Key::R, Some(Key::P)),
+    (Key::S, Some(Key::O)),
+    (Key::D, Some(Key::E)),
+    (Key::F, Some(Key::U)),
+    (Key::Z, Some(Key::Semicolon)),
+    (Key::X, Some(Key::Q)),
+    (Key::C, Some(Key::J)),
+    (Key::V, Some(Key::K)),
+    (Key::U, Some(Key::G)),
+    (Key::I, Some(Key::C)),
+    (Key::O, Some(Key::R)),
+    (Key::P, Some(Key::L)),
+    (Key::J, Some(Key::H)),
+    (Key::K, Some(Key::T)),
+    (Key::L, Some(Key::N)),
+    (Key::Semicolon, Some(Key::S)),
+    (Key::Comma, Some(Key::W)),
+    (Key::Period, Some(Key::V)),
+    (Key::Slash, Some(Key::Z)),
+];
+
+impl KeyboardLayout {
+    /* Key at the position of the given QWERTY key, if the window can report it */
+    pub fn translate(self, key: Key) -> Option<Key> {
+        let differences: &[(Key, Option<Key>)] = match self {
+            KeyboardLayout::Qwerty => &[],
+            KeyboardLayout::Azerty => &AZERTY,
+            KeyboardLayout::Qwertz => &QWERTZ,
+            KeyboardLayout::Dvorak => &DVORAK,
+        };
+
+        match differences.iter().find(|(qwerty, _)| *qwerty == key) {
+            Some(&(_, translated)) => translated,
+            None => Some(key),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translate() {
+        assert_eq!(KeyboardLayout::Qwerty.translate(Key::Q), Some(Key::Q));
+        assert_eq!(KeyboardLayout::Azerty.translate(Key::Q), Some(Key::A));
+        assert_eq!(KeyboardLayout::Azerty.translate(Key::D1), Some(Key::D1));
+        assert_eq!(KeyboardLayout::Qwertz.translate(Key::Z), Some(Key::Y));
+        assert_eq!(KeyboardLayout::Dvorak.translate(Key::X), Some(Key::Q));
+        assert_eq!(KeyboardLayout::Dvorak.translate(Key::Q), None);
+        assert_eq!("dvorak".parse(), Ok(KeyboardLayout::Dvorak));
+        assert!("colemak".parse::<KeyboardLayout>().is_err());
+    }
+}
@@ -1,26 +1,77 @@
+/* Layout of the COSMAC VIP hex keypad */
+pub const LAYOUT: [[usize; 4]; 4] = [
+    [0x01, 0x02, 0x03, 0x0C],
+    [0x04, 0x05, 0x06, 0x0D],
+    [0x07, 0x08, 0x09, 0x0E],
+    [0x0A, 0x00, 0x0B, 0x0F],
+];
+
+use std::sync::mpsc::{self, Receiver, Sender};
+
+/* Change of the state of a key */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub code: usize,
+    pub pressed: bool,
+    /* Received from another instance, see netplay, and not to be sent back */
+    pub remote: bool,
+}
+
 pub trait Keypad {
     fn set_is_pressed(&mut self, code: usize, is_pressed: bool);
     fn get_is_pressed(&self, code: usize) -> bool;
+
+    /* Events of the keys changing state, if the keypad can report them; the machine waits for them
+     * instead of polling the keys while idle */
+    fn key_events(&mut self) -> Option<Receiver<KeyEvent>> {
+        None
+    }
 }
 
 pub struct KeyboardKeypad {
     keys: Vec<bool>,
+    listeners: Vec<Sender<KeyEvent>>,
 }
 
 impl KeyboardKeypad {
     pub fn new(codes: usize) -> KeyboardKeypad {
         KeyboardKeypad {
             keys: vec![false; codes],
+            listeners: Vec::new(),
+        }
+    }
+
+    /* Receive an event each time a key changes state; the listener is dropped with the receiver */
+    pub fn subscribe(&mut self) -> Receiver<KeyEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.listeners.push(sender);
+        receiver
+    }
+
+    pub fn apply(&mut self, event: KeyEvent) {
+        if self.keys[event.code] == event.pressed {
+            return;
         }
+
+        self.keys[event.code] = event.pressed;
+        self.listeners.retain(|listener| listener.send(event).is_ok());
     }
 }
 
 impl Keypad for KeyboardKeypad {
     fn set_is_pressed(&mut self, code: usize, is_pressed: bool) {
-        self.keys[code] = is_pressed;
+        self.apply(KeyEvent {
+            code,
+            pressed: is_pressed,
+            remote: false,
+        });
     }
 
     fn get_is_pressed(&self, code: usize) -> bool {
         self.keys[code]
     }
+
+    fn key_events(&mut self) -> Option<Receiver<KeyEvent>> {
+        Some(self.subscribe())
+    }
 }
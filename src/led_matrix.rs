@@ -0,0 +1,133 @@
+use crate::display::Display;
+
+use embedded_hal::blocking::spi::Write;
+use embedded_hal::digital::v2::OutputPin;
+
+/* Each MAX7219 drives an 8x8 matrix */
+const MODULE_SIZE: usize = 8;
+
+/* MAX7219 registers; the rows of the matrix are the digit registers 1 to 8 */
+const REG_DIGIT_0: u8 = 0x01;
+const REG_DECODE_MODE: u8 = 0x09;
+const REG_INTENSITY: u8 = 0x0A;
+const REG_SCAN_LIMIT: u8 = 0x0B;
+const REG_SHUTDOWN: u8 = 0x0C;
+const REG_DISPLAY_TEST: u8 = 0x0F;
+
+const DEFAULT_INTENSITY: u8 = 0x04;
+
+#[derive(Debug)]
+pub enum LedMatrixError<S, P> {
+    Spi(S),
+    Pin(P),
+    /* The display size is not a multiple of the module size */
+    Size,
+}
+
+/* Daisy chained MAX7219 modules, covering the display row by row starting from the top left corner;
+ * the first module of the chain is the one connected to the SPI bus. The LEDs are only sent to the
+ * modules by flush, so the owner decides how often the matrix is refreshed */
+pub struct Max7219Display<SPI, CS> {
+    spi: SPI,
+    chip_select: CS,
+    width: usize,
+    height: usize,
+    leds: Vec<Vec<u8>>,
+}
+
+impl<SPI, CS, S, P> Max7219Display<SPI, CS>
+where
+    SPI: Write<u8, Error = S>,
+    CS: OutputPin<Error = P>,
+{
+    pub fn new(spi: SPI, chip_select: CS, width: usize, height: usize) -> Result<Self, LedMatrixError<S, P>> {
+        if width == 0 || height == 0 || width % MODULE_SIZE != 0 || height % MODULE_SIZE != 0 {
+            return Err(LedMatrixError::Size);
+        }
+
+        let mut display = Max7219Display {
+            spi,
+            chip_select,
+            width,
+            height,
+            leds: vec![vec![0; width]; height],
+        };
+
+        display.write_all(REG_DISPLAY_TEST, 0x00)?;
+        display.write_all(REG_SCAN_LIMIT, (MODULE_SIZE - 1) as u8)?;
+        display.write_all(REG_DECODE_MODE, 0x00)?;
+        display.write_all(REG_INTENSITY, DEFAULT_INTENSITY)?;
+        display.flush()?;
+        display.write_all(REG_SHUTDOWN, 0x01)?;
+
+        Ok(display)
+    }
+
+    fn modules(&self) -> usize {
+        (self.width / MODULE_SIZE) * (self.height / MODULE_SIZE)
+    }
+
+    /* Write a register of every module, each one with its own value */
+    fn write_chain<F: Fn(usize) -> u8>(&mut self, register: u8, value: F) -> Result<(), LedMatrixError<S, P>> {
+        /* The data shifts through the chain, so the last module has to be sent first */
+        let frame: Vec<u8> = (0..self.modules())
+            .rev()
+            .flat_map(|module| [register, value(module)])
+            .collect();
+
+        self.chip_select.set_low().map_err(LedMatrixError::Pin)?;
+        let result = self.spi.write(&frame).map_err(LedMatrixError::Spi);
+        self.chip_select.set_high().map_err(LedMatrixError::Pin)?;
+
+        result
+    }
+
+    fn write_all(&mut self, register: u8, value: u8) -> Result<(), LedMatrixError<S, P>> {
+        self.write_chain(register, |_| value)
+    }
+
+    /* 0 is the dimmest level, 15 the brightest */
+    pub fn set_intensity(&mut self, intensity: u8) -> Result<(), LedMatrixError<S, P>> {
+        self.write_all(REG_INTENSITY, intensity.min(0x0F))
+    }
+
+    /* Send the LEDs to the modules */
+    pub fn flush(&mut self) -> Result<(), LedMatrixError<S, P>> {
+        let modules_per_row = self.width / MODULE_SIZE;
+
+        for row in 0..MODULE_SIZE {
+            let leds = &self.leds;
+            let columns: Vec<u8> = (0..self.modules())
+                .map(|module| {
+                    let y = (module / modules_per_row) * MODULE_SIZE + row;
+                    let x = (module % modules_per_row) * MODULE_SIZE;
+                    (0..MODULE_SIZE)
+                        .filter(|column| leds[y][x + column] != 0)
+                        .fold(0, |bits, column| bits | (0x80 >> column))
+                })
+                .collect();
+
+            self.write_chain(REG_DIGIT_0 + row as u8, |module| columns[module])?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<SPI, CS> Display for Max7219Display<SPI, CS> {
+    fn set_pixel(&mut self, x: usize, y: usize, value: u8) {
+        self.leds[y][x] = value;
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.leds[y][x]
+    }
+
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+}
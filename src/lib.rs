@@ -0,0 +1,63 @@
+#[cfg(feature = "native")]
+pub mod app;
+pub mod analysis;
+pub mod asm;
+pub mod bench;
+pub mod bus;
+pub mod chip8;
+#[cfg(feature = "native")]
+pub mod cli;
+#[cfg(feature = "native")]
+pub mod console;
+pub mod coverage;
+pub mod crash;
+pub mod debugger;
+pub mod disasm;
+pub mod display;
+pub mod dump;
+pub mod effects;
+pub mod fonts;
+pub mod gamedb;
+pub mod heatmap;
+pub mod history;
+#[cfg(feature = "gpio-keypad")]
+pub mod gpio_keypad;
+pub mod hsl;
+pub mod keypad;
+#[cfg(feature = "native")]
+pub mod keyboard_layout;
+#[cfg(feature = "led-matrix")]
+pub mod led_matrix;
+pub mod logger;
+pub mod macros;
+pub mod metadata;
+#[cfg(feature = "native")]
+pub mod netplay;
+pub mod octo;
+pub mod platform;
+pub mod probe;
+pub mod instruction;
+pub mod profiler;
+pub mod reference;
+#[cfg(feature = "native")]
+pub mod render_style;
+pub mod rng;
+pub mod rom;
+pub mod savestate;
+pub mod sprites;
+pub mod statediff;
+pub mod symbols;
+pub mod sound;
+#[cfg(feature = "native")]
+pub mod scripting;
+#[cfg(feature = "native")]
+pub mod server;
+pub mod text;
+pub mod timer;
+pub mod trace;
+pub mod turbo;
+pub mod verify;
+pub mod virtual_keypad;
+pub mod wav;
+#[cfg(feature = "web")]
+pub mod web;
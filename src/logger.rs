@@ -1,35 +1,358 @@
+use serde_json::{json, Value};
+use std::cell::{Cell, OnceCell};
 use std::fs;
 use std::fs::OpenOptions;
 use std::io::prelude::*;
+use std::io::BufWriter;
+use std::str::FromStr;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/* Written in the current directory by the machine running in the window */
+pub const LOG_FILE: &str = "chip8rs.log";
+
+/* Longest time a line written to the log file stays in the buffer */
+const FLUSH_PERIOD: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    #[default]
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    /* Map the number of "-v" flags to the lowest level which gets logged */
+    pub fn from_verbosity(verbosity: u64) -> LogLevel {
+        match verbosity {
+            0 => LogLevel::Info,
+            1 => LogLevel::Debug,
+            _ => LogLevel::Trace,
+        }
+    }
+}
+
+impl LogLevel {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LogLevel::Trace => "trace",
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error => "error",
+        }
+    }
+}
+
+/* How the messages are written to the log file */
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum LogFormat {
+    /* One message per line */
+    #[default]
+    Text,
+    /* One JSON object per line, see JsonLogger */
+    Json,
+}
+
+impl LogFormat {
+    pub const NAMES: [&'static str; 2] = ["text", "json"];
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("Unknown log format: {}", name)),
+        }
+    }
+}
+
+impl From<LogLevel> for log::Level {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Trace => log::Level::Trace,
+            LogLevel::Debug => log::Level::Debug,
+            LogLevel::Info => log::Level::Info,
+            LogLevel::Warn => log::Level::Warn,
+            LogLevel::Error => log::Level::Error,
+        }
+    }
+}
 
 pub trait Logger {
-    fn log(&self, message: String);
+    fn log(&self, level: LogLevel, message: String);
+
+    /* Whether the messages of the level are written, to skip formatting the ones which are discarded */
+    fn enabled(&self, _level: LogLevel) -> bool {
+        true
+    }
+
+    /* Called before executing each instruction, so that the messages can tell where they come from */
+    fn set_instruction(&self, _pc: u16, _opcode: u16) {}
+
+    fn trace(&self, message: String) {
+        self.log(LogLevel::Trace, message);
+    }
+
+    fn debug(&self, message: String) {
+        self.log(LogLevel::Debug, message);
+    }
+
+    fn info(&self, message: String) {
+        self.log(LogLevel::Info, message);
+    }
+
+    fn warn(&self, message: String) {
+        self.log(LogLevel::Warn, message);
+    }
+
+    fn error(&self, message: String) {
+        self.log(LogLevel::Error, message);
+    }
 }
 
-pub struct FileLogger {
+/* Append lines to a file from a worker thread, so that logging, even every instruction, doesn't wait for
+ * the disk. The file stays open, and the lines are buffered and flushed periodically and when the
+ * writer is dropped. The thread is started with the first line, so that nothing is spawned on
+ * platforms without threads if nothing is logged */
+struct LogWriter {
     file_path: String,
+    worker: OnceCell<(Sender<String>, JoinHandle<()>)>,
 }
 
-impl FileLogger {
-    pub fn new(file_path: String) -> FileLogger {
+impl LogWriter {
+    fn new(file_path: String) -> LogWriter {
         /* Try to remove the file, ignore the error in case */
         let _ = fs::remove_file(&file_path);
 
+        LogWriter {
+            file_path,
+            worker: OnceCell::new(),
+        }
+    }
+
+    fn write(&self, line: String) {
+        let (sender, _) = self.worker.get_or_init(|| {
+            let (sender, receiver) = mpsc::channel::<String>();
+            let file_path = self.file_path.clone();
+
+            let worker = thread::spawn(move || {
+                let file = match OpenOptions::new().append(true).create(true).open(&file_path) {
+                    Ok(file) => file,
+                    Err(error) => {
+                        eprintln!("Cannot write the log to {}: {}", file_path, error);
+                        return;
+                    }
+                };
+                let mut writer = BufWriter::new(file);
+                let mut last_flush = Instant::now();
+
+                loop {
+                    match receiver.recv_timeout(FLUSH_PERIOD) {
+                        Ok(line) => {
+                            let _ = writeln!(writer, "{}", line);
+                        }
+                        Err(RecvTimeoutError::Timeout) => (),
+                        /* The writer was dropped, after sending all its lines */
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+
+                    if last_flush.elapsed() >= FLUSH_PERIOD {
+                        let _ = writer.flush();
+                        last_flush = Instant::now();
+                    }
+                }
+
+                let _ = writer.flush();
+            });
+
+            (sender, worker)
+        });
+
+        /* The worker is gone only if the file could not be opened */
+        let _ = sender.send(line);
+    }
+}
+
+impl Drop for LogWriter {
+    fn drop(&mut self) {
+        if let Some((sender, worker)) = self.worker.take() {
+            drop(sender);
+            let _ = worker.join();
+        }
+    }
+}
+
+pub struct FileLogger {
+    writer: LogWriter,
+    level: LogLevel,
+}
+
+impl FileLogger {
+    pub fn new(file_path: String, level: LogLevel) -> FileLogger {
         FileLogger {
-            file_path: file_path,
+            writer: LogWriter::new(file_path),
+            level,
         }
     }
 }
 
 impl Logger for FileLogger {
-    fn log(&self, message: String) {
-        let mut file = OpenOptions::new()
-            .write(true)
-            .append(true)
-            .create(true)
-            .open(&self.file_path)
-            .unwrap();
-        file.write_all(message.as_bytes()).unwrap();
-        file.write_all("\n".as_bytes()).unwrap();
+    fn log(&self, level: LogLevel, message: String) {
+        if !self.enabled(level) {
+            return;
+        }
+
+        /* Messages are also routed through the log crate, so that they reach any installed logger */
+        log::log!(level.into(), "{}", message);
+
+        self.writer.write(message);
+    }
+
+    fn enabled(&self, level: LogLevel) -> bool {
+        level >= self.level
+    }
+}
+
+/* Write each message as a JSON object on its own line, with the time in seconds since the Unix epoch,
+ * the level and the instruction being executed, null before the first one, so that the log can be
+ * processed with tools like jq:
+ * {"level":"info","message":"...","opcode":"D015","pc":"2A0","timestamp":1700000000.123} */
+pub struct JsonLogger {
+    writer: LogWriter,
+    level: LogLevel,
+    instruction: Cell<Option<(u16, u16)>>,
+}
+
+impl JsonLogger {
+    pub fn new(file_path: String, level: LogLevel) -> JsonLogger {
+        JsonLogger {
+            writer: LogWriter::new(file_path),
+            level,
+            instruction: Cell::new(None),
+        }
+    }
+
+    fn format(&self, level: LogLevel, message: &str) -> String {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0.0, |time| time.as_secs_f64());
+        let (pc, opcode) = match self.instruction.get() {
+            Some((pc, opcode)) => (json!(format!("{:03X}", pc)), json!(format!("{:04X}", opcode))),
+            None => (Value::Null, Value::Null),
+        };
+
+        json!({
+            "timestamp": (timestamp * 1000.0).round() / 1000.0,
+            "level": level.name(),
+            "pc": pc,
+            "opcode": opcode,
+            "message": message,
+        })
+        .to_string()
+    }
+}
+
+impl Logger for JsonLogger {
+    fn log(&self, level: LogLevel, message: String) {
+        if !self.enabled(level) {
+            return;
+        }
+
+        log::log!(level.into(), "{}", message);
+
+        self.writer.write(self.format(level, &message));
+    }
+
+    fn enabled(&self, level: LogLevel) -> bool {
+        level >= self.level
+    }
+
+    fn set_instruction(&self, pc: u16, opcode: u16) {
+        self.instruction.set(Some((pc, opcode)));
+    }
+}
+
+/* Minimal implementation of the log crate facade printing the records on the standard error */
+pub struct StderrLog;
+
+impl StderrLog {
+    pub fn install(level: LogLevel) {
+        let level: log::Level = level.into();
+        log::set_boxed_logger(Box::new(StderrLog)).unwrap();
+        log::set_max_level(level.to_level_filter());
+    }
+}
+
+impl log::Log for StderrLog {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{}] {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/* Discard every message, for platforms without a file system */
+pub struct NullLogger;
+
+impl Logger for NullLogger {
+    fn log(&self, _level: LogLevel, _message: String) {}
+
+    fn enabled(&self, _level: LogLevel) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_events() {
+        let logger = JsonLogger::new(String::from("/nonexistent/chip8rs.log"), LogLevel::Info);
+
+        let event: Value = serde_json::from_str(&logger.format(LogLevel::Info, "ROM loaded")).unwrap();
+        assert_eq!(event["level"], "info");
+        assert_eq!(event["message"], "ROM loaded");
+        assert_eq!(event["pc"], Value::Null);
+        assert!(event["timestamp"].as_f64().unwrap() > 0.0);
+
+        logger.set_instruction(0x2A0, 0xD015);
+        let event: Value = serde_json::from_str(&logger.format(LogLevel::Warn, "\"quoted\"")).unwrap();
+        assert_eq!(event["level"], "warn");
+        assert_eq!(event["message"], "\"quoted\"");
+        assert_eq!((&event["pc"], &event["opcode"]), (&json!("2A0"), &json!("D015")));
+    }
+
+    #[test]
+    fn lines_are_written_when_dropped() {
+        let path = std::env::temp_dir().join(format!("chip8rs-test-{}.log", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+
+        let logger = FileLogger::new(path.clone(), LogLevel::Debug);
+        assert!(logger.enabled(LogLevel::Debug) && !logger.enabled(LogLevel::Trace));
+        logger.trace(String::from("not logged"));
+        for line in 0..1000 {
+            logger.debug(format!("line {}", line));
+        }
+        drop(logger);
+
+        let content = fs::read_to_string(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(content.lines().count(), 1000);
+        assert_eq!(content.lines().last(), Some("line 999"));
     }
 }
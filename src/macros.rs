@@ -0,0 +1,106 @@
+use serde::Deserialize;
+
+use std::collections::BTreeSet;
+use std::time::{Duration, Instant};
+
+fn default_hold() -> u64 {
+    100
+}
+
+fn default_wait() -> u64 {
+    100
+}
+
+/* Key press of a macro: the CHIP-8 key is held for hold milliseconds, then released for wait
+ * milliseconds before the next step */
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+pub struct MacroStep {
+    pub key: u8,
+    #[serde(default = "default_hold")]
+    pub hold: u64,
+    #[serde(default = "default_wait")]
+    pub wait: u64,
+}
+
+/* Key held by the steps at the given time since their start, Some(None) between two keys, None once
+ * they're over */
+fn key_at(steps: &[MacroStep], elapsed: Duration) -> Option<Option<u8>> {
+    let mut elapsed = elapsed.as_millis() as u64;
+
+    for step in steps {
+        if elapsed < step.hold {
+            return Some(Some(step.key));
+        }
+        elapsed -= step.hold;
+        if elapsed < step.wait {
+            return Some(None);
+        }
+        elapsed -= step.wait;
+    }
+
+    None
+}
+
+/* Plays the macros started by the host keys, alongside the keys pressed by the player; update must be
+ * called regularly, e.g. on every frame, to press and release their keys */
+#[derive(Debug, Default)]
+pub struct MacroPlayer {
+    running: Vec<(Vec<MacroStep>, Instant)>,
+    /* Keys held by the macros at the last update */
+    held: BTreeSet<u8>,
+}
+
+impl MacroPlayer {
+    pub fn new() -> MacroPlayer {
+        MacroPlayer::default()
+    }
+
+    /* Start playing a macro; it runs at the same time as the ones already playing */
+    pub fn start(&mut self, steps: &[MacroStep], now: Instant) {
+        self.running.push((steps.to_vec(), now));
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.running.is_empty()
+    }
+
+    /* Keys the macros pressed or released since the last update, as (key, pressed) */
+    pub fn update(&mut self, now: Instant) -> Vec<(u8, bool)> {
+        let mut held = BTreeSet::new();
+
+        self.running.retain(|(steps, start)| match key_at(steps, now.saturating_duration_since(*start)) {
+            Some(key) => {
+                held.extend(key);
+                true
+            }
+            None => false,
+        });
+
+        let mut changes: Vec<(u8, bool)> = self.held.difference(&held).map(|&key| (key, false)).collect();
+        changes.extend(held.difference(&self.held).map(|&key| (key, true)));
+        self.held = held;
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn play() {
+        let steps: Vec<MacroStep> = serde_json::from_str(r#"[{"key": 5}, {"key": 6, "hold": 50, "wait": 0}]"#).unwrap();
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+
+        let mut player = MacroPlayer::new();
+        player.start(&steps, start);
+        assert_eq!(player.update(at(0)), vec![(5, true)]);
+        assert_eq!(player.update(at(99)), vec![]);
+        assert_eq!(player.update(at(100)), vec![(5, false)]);
+        assert_eq!(player.update(at(210)), vec![(6, true)]);
+        assert!(player.is_running());
+        assert_eq!(player.update(at(250)), vec![(6, false)]);
+        assert!(!player.is_running());
+    }
+}
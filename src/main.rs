@@ -1,42 +1,506 @@
-mod app;
-mod chip8;
-mod display;
-mod fonts;
-mod hsl;
-mod keypad;
-mod logger;
-mod instruction;
-mod timer;
+use chip8rs::analysis;
+use chip8rs::app::*;
+use chip8rs::asm;
+use chip8rs::bench;
+use chip8rs::cli;
+use chip8rs::coverage::{self, Coverage};
+use chip8rs::debugger;
+use chip8rs::disasm;
+use chip8rs::dump;
+use chip8rs::effects::{CycleSettings, PulseSettings};
+use chip8rs::hsl::{RGB8Pixel, RGBPixel};
+use chip8rs::gamedb::{self, GameDatabase};
+use chip8rs::logger::{LogLevel, StderrLog};
+use chip8rs::metadata::{self, MetadataDatabase, RomMetadata};
+use chip8rs::netplay::NetplayMode;
+use chip8rs::platform::{MachineConfig, Quirks};
+use chip8rs::probe;
+use chip8rs::render_style::{self, RenderStyle};
+use chip8rs::rom::{self, RomFormat};
+use chip8rs::savestate;
+use chip8rs::sprites;
+use chip8rs::statediff;
+use chip8rs::symbols::{self, Symbols};
+use chip8rs::trace::Tracer;
+use chip8rs::verify::{self, Against};
 
-use clap;
-
-use app::*;
+use std::env;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Arc, Mutex};
 
 fn main() {
-    let matches = clap::App::new("chip8rs")
-        .version("0.0.1")
-        .author("Lorenzo A.")
-        .about("CHIP-8 emulator written in Rust")
-        .arg(
-            clap::Arg::with_name("rom")
-                .short("r")
-                .long("rom")
-                .value_name("FILE")
-                .help("Path to the CHIP-8 ROM file")
-                .required(true)
-                .takes_value(true),
-        )
-        .arg(
-            clap::Arg::with_name("nyan")
-                .long("nyan")
-                .help("Enter \"Nyan Cat\" mode")
-                .takes_value(false),
-        )
-        .get_matches();
-
-    let rom_path = matches.value_of("rom").unwrap();
-    let nyan_mode = matches.is_present("nyan");
-
-    let mut app = App::new(nyan_mode);
-    app.run(rom_path.to_string());
+    let matches = cli::build().get_matches_from(cli::with_default_subcommand(env::args_os()));
+
+    match matches.subcommand() {
+        ("run", Some(matches)) => run(matches),
+        ("disasm", Some(matches)) => run_disasm(matches),
+        ("analyze", Some(matches)) => run_analyze(matches),
+        ("sprites", Some(matches)) => run_sprites(matches),
+        ("memdump", Some(matches)) => run_memdump(matches),
+        ("asm", Some(matches)) => run_asm(matches),
+        ("test", Some(matches)) => run_test(matches),
+        ("verify", Some(matches)) => run_verify(matches),
+        ("diffstate", Some(matches)) => run_diffstate(matches),
+        ("bench", Some(matches)) => run_bench(matches),
+        ("info", Some(matches)) => run_info(matches),
+        ("import-archive", Some(matches)) => run_import_archive(matches),
+        ("completions", Some(matches)) => run_completions(matches),
+        _ => unreachable!(),
+    }
+}
+
+/* Read and decode the ROM and, given the machine, check that it can be loaded; errors end the program,
+ * since nothing can be done without the ROM */
+fn load_rom(source: &str, format: RomFormat, config: Option<&MachineConfig>) -> Vec<u8> {
+    let result = rom::read_rom(source).and_then(|content| rom::decode_rom(source, content, format));
+    let result = result.and_then(|rom| match config {
+        Some(config) => rom::check_rom(source, &rom, config).map(|_| rom),
+        None => Ok(rom),
+    });
+
+    result.unwrap_or_else(|error| {
+        eprintln!("Cannot load the ROM: {}", error);
+        process::exit(1);
+    })
+}
+
+/* Labels of the --symbols file, if given; an unreadable file ends the program, like the ROM */
+fn load_symbols(matches: &clap::ArgMatches) -> Symbols {
+    match matches.value_of("symbols") {
+        Some(path) => symbols::load(Path::new(path)).unwrap_or_else(|error| {
+            eprintln!("Cannot load the symbols from {}: {}", path, error);
+            process::exit(1);
+        }),
+        None => Symbols::new(),
+    }
+}
+
+/* The bundled game database, with the entries of --game-db if given; an unreadable file ends the
+ * program, like the ROM */
+fn load_game_db(matches: &clap::ArgMatches) -> GameDatabase {
+    let mut game_db = GameDatabase::new();
+    if let Some(path) = matches.value_of("game-db") {
+        game_db.load_file(path).unwrap_or_else(|error| {
+            eprintln!("Cannot load the game database {}: {}", path, error);
+            process::exit(1);
+        });
+    }
+
+    game_db
+}
+
+/* The bundled ROM metadata, with the entries of --metadata if given */
+fn load_metadata_db(matches: &clap::ArgMatches) -> MetadataDatabase {
+    let mut metadata_db = MetadataDatabase::new();
+    if let Some(path) = matches.value_of("metadata") {
+        metadata_db.load_file(path).unwrap_or_else(|error| {
+            eprintln!("Cannot load the ROM metadata {}: {}", path, error);
+            process::exit(1);
+        });
+    }
+
+    metadata_db
+}
+
+fn print_metadata(metadata: &RomMetadata) {
+    println!("Title: {}", metadata.summary());
+    if let Some(description) = &metadata.description {
+        println!("{}", description);
+    }
+
+    let args = metadata.recommended_args();
+    if !args.is_empty() {
+        println!("Recommended options: {}", args.join(" "));
+    }
+}
+
+/* With --detect-quirks, replace the quirks with the ones of the probe which got the farthest; the
+ * probes are shown on the standard error, since the standard output of test is the display hash */
+fn detect_quirks(matches: &clap::ArgMatches, config: &mut MachineConfig, rom: &[u8]) {
+    if !matches.is_present("detect-quirks") {
+        return;
+    }
+
+    let probes = probe::run(*config, rom, probe::PROBE_CYCLES);
+    eprint!("{}", probe::report(&probes));
+
+    match probe::best(&probes) {
+        Some(quirks) => {
+            config.quirks = quirks;
+            let options: Vec<String> = quirks.names().iter().map(|name| format!("--quirk {}", name)).collect();
+            eprintln!("Detected quirks: {}", if options.is_empty() { String::from("none") } else { options.join(" ") });
+        }
+        None => eprintln!("The ROM fails with every combination of quirks"),
+    }
+}
+
+fn run(matches: &clap::ArgMatches) {
+    let rom_source = matches.value_of("rom").unwrap();
+    let rom_format: RomFormat = matches.value_of("format").unwrap().parse().unwrap();
+    let effect = if matches.is_present("nyan") {
+        Some(String::from("hue-cycle"))
+    } else {
+        matches.value_of("effect").map(String::from)
+    };
+    let mut cycle = CycleSettings::default();
+    if let Some(step) = matches.value_of("cycle-step") {
+        cycle.step = step.parse().unwrap();
+    }
+    if let Some(saturation) = matches.value_of("cycle-saturation") {
+        cycle.saturation = saturation.parse().unwrap();
+    }
+    if let Some(lightness) = matches.value_of("cycle-lightness") {
+        cycle.lightness = lightness.parse().unwrap();
+    }
+    let mut pulse = PulseSettings::default();
+    if let Some(color) = matches.value_of("pulse-color") {
+        pulse.color = RGBPixel::from(color.parse::<RGB8Pixel>().unwrap());
+    }
+    if let Some(frames) = matches.value_of("pulse-frames") {
+        pulse.frames = frames.parse().unwrap();
+    }
+
+    let mut config = cli::machine_config(matches);
+    let log_level = LogLevel::from_verbosity(matches.occurrences_of("verbose"));
+
+    if matches.is_present("log-stderr") {
+        StderrLog::install(log_level);
+    }
+
+    if matches.is_present("watch") && !rom::is_watchable(rom_source) {
+        eprintln!("Only ROM files can be watched for changes");
+        process::exit(1);
+    }
+    let watch_path = if matches.is_present("watch") {
+        Some(rom_source.to_string())
+    } else {
+        None
+    };
+
+    let rom = load_rom(rom_source, rom_format, Some(&config));
+
+    let game_db = load_game_db(matches);
+    let settings = game_db.lookup(&rom).cloned().unwrap_or_default();
+    /* The quirks given on the command line take precedence over the ones of the database */
+    if !matches.is_present("quirk") {
+        settings.apply(&mut config);
+    }
+    detect_quirks(matches, &mut config, &rom);
+
+    let metadata_db = load_metadata_db(matches);
+    let metadata = metadata_db.lookup(&rom);
+    if let Some(metadata) = metadata {
+        print_metadata(metadata);
+    }
+
+    let netplay = if let Some(port) = matches.value_of("netplay-host") {
+        Some(NetplayMode::Host(port.parse().unwrap()))
+    } else {
+        matches.value_of("netplay-connect").map(|address| NetplayMode::Connect(address.to_string()))
+    };
+
+    let options = AppOptions {
+        effect,
+        title: metadata.map(|metadata| metadata.summary()),
+        cycle,
+        pulse,
+        virtual_keypad: matches.is_present("virtual-keypad"),
+        crt: matches.is_present("crt"),
+        visual_beep: matches.is_present("visual-beep"),
+        pixel_aspect: matches.value_of("pixel-aspect").map(|ratio| ratio.parse().unwrap()),
+        keyboard_layout: matches.value_of("keyboard-layout").unwrap().parse().unwrap(),
+        turbo_keys: matches
+            .values_of("turbo")
+            .map_or_else(Vec::new, |keys| keys.map(|key| usize::from_str_radix(key, 16).unwrap()).collect()),
+        turbo_rate: matches.value_of("turbo-rate").map(|rate| rate.parse().unwrap()),
+        immediate_draw: matches.is_present("immediate-draw"),
+        render_style: RenderStyle {
+            shape: render_style::shape_from_name(matches.value_of("pixel-shape").unwrap()).unwrap(),
+            grid: matches.is_present("grid"),
+            glow: matches.is_present("glow"),
+        },
+        rotation: matches.value_of("rotate").map_or(0, |degrees| degrees.parse().unwrap()),
+        frame_blending: matches.is_present("blend"),
+        ghosting_frames: matches.value_of("ghosting").map_or(0, |frames| frames.parse().unwrap()),
+        resume: matches.is_present("resume"),
+        watchpoints: matches
+            .values_of("watchpoint")
+            .map_or_else(Vec::new, |values| values.map(|value| value.parse().unwrap()).collect()),
+        history: matches.value_of("history").unwrap().parse().unwrap(),
+        seed: matches.value_of("seed").map(|seed| seed.parse().unwrap()),
+        console: matches.is_present("console"),
+        serve: matches.value_of("serve").map(|port| {
+            SocketAddr::new(matches.value_of("serve-address").unwrap().parse().unwrap(), port.parse().unwrap())
+        }),
+        netplay,
+        script: matches.value_of("script").map(PathBuf::from),
+        exit_code: matches.value_of("exit-code").map_or(0, |code| code.parse().unwrap()),
+        dump_display: matches.value_of("dump-display").map(PathBuf::from),
+        trace: matches.value_of("trace").map(PathBuf::from),
+        record_audio: matches.value_of("record-audio").map(PathBuf::from),
+        coverage: matches.value_of("coverage").map(PathBuf::from),
+        heatmap: matches.value_of("heatmap").map(PathBuf::from),
+        symbols: load_symbols(matches),
+        rom_format,
+        log_level,
+        log_format: matches.value_of("log-format").unwrap().parse().unwrap(),
+    };
+
+    let mut app = App::new(config, &settings, &options);
+    if let Some(source) = matches.value_of("split-screen") {
+        let second_rom = load_rom(source, rom_format, Some(&config));
+        let second_settings = game_db.lookup(&second_rom).cloned().unwrap_or_default();
+        let mut second_config = cli::machine_config(matches);
+        if !matches.is_present("quirk") {
+            second_settings.apply(&mut second_config);
+        }
+        app.add_machine(second_config, &second_settings, second_rom);
+    }
+    if matches.is_present("profile") {
+        app.enable_profiler();
+    }
+    app.run(rom, watch_path);
+}
+
+fn run_disasm(matches: &clap::ArgMatches) {
+    let rom = load_rom(matches.value_of("rom").unwrap(), matches.value_of("format").unwrap().parse().unwrap(), None);
+    let start = cli::parse_address(matches.value_of("start").unwrap()).unwrap();
+
+    print!("{}", disasm::disassemble_with_symbols(&rom, start, &load_symbols(matches)));
+}
+
+fn run_analyze(matches: &clap::ArgMatches) {
+    let rom = load_rom(matches.value_of("rom").unwrap(), matches.value_of("format").unwrap().parse().unwrap(), None);
+    let start = cli::parse_address(matches.value_of("start").unwrap()).unwrap();
+
+    print!("{}", analysis::analyze(&rom, start).report());
+}
+
+fn run_sprites(matches: &clap::ArgMatches) {
+    let rom = load_rom(matches.value_of("rom").unwrap(), matches.value_of("format").unwrap().parse().unwrap(), None);
+    let start = cli::parse_address(matches.value_of("start").unwrap()).unwrap();
+
+    let sprites = match matches.value_of("height") {
+        Some(height) => sprites::split(&rom, start, height.parse().unwrap()),
+        None => sprites::find(&rom, start),
+    };
+
+    for sprite in &sprites {
+        println!("{:03X}: {}x{}", sprite.address, sprite.width(), sprite.height());
+        print!("{}", dump::to_text(&sprite.pixels));
+    }
+
+    if let Some(path) = matches.value_of("output") {
+        if let Err(error) = fs::write(path, dump::to_pbm(&sprites::sheet(&sprites))) {
+            eprintln!("Could not write {}: {}", path, error);
+            process::exit(1);
+        }
+    }
+}
+
+fn run_memdump(matches: &clap::ArgMatches) {
+    let config = cli::machine_config(matches);
+    let rom = load_rom(matches.value_of("rom").unwrap(), matches.value_of("format").unwrap().parse().unwrap(), Some(&config));
+    let at = cli::parse_address(matches.value_of("at").unwrap()).unwrap() as usize;
+    let len = matches.value_of("len").map_or(config.memory_size, |len| len.parse().unwrap());
+
+    let snapshot = bench::run_headless(config, &rom, 0, None, None).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        process::exit(1);
+    });
+
+    let regions = debugger::memory_map(&config, rom.len());
+    for region in &regions {
+        println!("{:<10}{:03X}-{:03X}", region.name, region.start, region.end - 1);
+    }
+    println!();
+    print!("{}", debugger::annotated_hexdump(&snapshot.memory, at, len, &regions));
+}
+
+fn run_asm(matches: &clap::ArgMatches) {
+    let source_path = matches.value_of("source").unwrap();
+    let output_path = matches.value_of("output").unwrap();
+    let start = cli::parse_address(matches.value_of("start").unwrap()).unwrap();
+
+    let source = fs::read_to_string(source_path).unwrap_or_else(|error| {
+        eprintln!("Could not read {}: {}", source_path, error);
+        process::exit(1);
+    });
+    let program = asm::assemble(&source, start).unwrap_or_else(|error| {
+        eprintln!("{}:{}", source_path, error);
+        process::exit(1);
+    });
+
+    if let Err(error) = fs::write(output_path, program) {
+        eprintln!("Could not write {}: {}", output_path, error);
+        process::exit(1);
+    }
+}
+
+fn run_test(matches: &clap::ArgMatches) {
+    let mut config = cli::machine_config(matches);
+    let rom = load_rom(matches.value_of("rom").unwrap(), matches.value_of("format").unwrap().parse().unwrap(), Some(&config));
+    detect_quirks(matches, &mut config, &rom);
+    let cycles: u64 = matches.value_of("cycles").unwrap().parse().unwrap();
+
+    let tracer = matches.value_of("trace").map(|path| {
+        let mut tracer = Tracer::create(Path::new(path)).unwrap_or_else(|error| {
+            eprintln!("Cannot write the trace to {}: {}", path, error);
+            process::exit(1);
+        });
+        tracer.set_symbols(load_symbols(matches));
+        tracer
+    });
+
+    let coverage = matches
+        .value_of("coverage")
+        .map(|path| (Path::new(path), Arc::new(Mutex::new(Coverage::new(config.memory_size)))));
+
+    let result = bench::run_headless(config, &rom, cycles, tracer, coverage.as_ref().map(|(_, coverage)| coverage));
+
+    /* The coverage is written even if the program failed, to see the path leading to the error */
+    if let Some((path, coverage)) = &coverage {
+        if let Err(error) = coverage::write(path, &coverage.lock().unwrap(), &rom, config.rom_start) {
+            eprintln!("Could not write the coverage to {}: {}", path.display(), error);
+            process::exit(1);
+        }
+    }
+
+    let snapshot = match result {
+        Ok(snapshot) => snapshot,
+        Err(error) => {
+            eprintln!("CHIP-8 error: {}", error);
+            process::exit(1);
+        }
+    };
+
+    if let Some(path) = matches.value_of("dump-display") {
+        let path = Path::new(path);
+        if let Err(error) = dump::write(path, &snapshot.display) {
+            eprintln!("Could not write the display to {}: {}", path.display(), error);
+            process::exit(1);
+        }
+    }
+
+    let hash = dump::hash(&snapshot.display);
+    println!("{}", hash);
+    if let Some(expected) = matches.value_of("expect") {
+        if !hash.eq_ignore_ascii_case(expected) {
+            eprintln!("Display mismatch: expected {}", expected);
+            process::exit(1);
+        }
+    }
+}
+
+fn run_verify(matches: &clap::ArgMatches) {
+    let config = cli::machine_config(matches);
+    let rom = load_rom(matches.value_of("rom").unwrap(), matches.value_of("format").unwrap().parse().unwrap(), Some(&config));
+    let cycles: u64 = matches.value_of("cycles").unwrap().parse().unwrap();
+    let every: u64 = matches.value_of("every").unwrap().parse().unwrap();
+
+    let against = match matches.values_of("against-quirk") {
+        Some(quirks) => {
+            let mut other = config;
+            other.quirks = Quirks::default();
+            for quirk in quirks {
+                other.quirks.enable(quirk).unwrap();
+            }
+            Against::Config(other)
+        }
+        None => Against::Reference,
+    };
+
+    match verify::verify(config, &rom, against, cycles, every) {
+        Ok(None) => println!("No divergence in {} instructions", cycles),
+        Ok(Some(divergence)) => {
+            print!("{}", divergence);
+            process::exit(1);
+        }
+        Err(error) => {
+            eprintln!("Verification stopped: {}", error);
+            process::exit(2);
+        }
+    }
+}
+
+fn run_diffstate(matches: &clap::ArgMatches) {
+    let load = |name| {
+        let path = Path::new(matches.value_of(name).unwrap());
+        savestate::load(path, None).unwrap_or_else(|error| {
+            eprintln!("Cannot load the save state {}: {}", path.display(), error);
+            process::exit(2);
+        })
+    };
+    let (first, second) = (load("first"), load("second"));
+
+    /* Like diff, the exit status is 1 when the states differ */
+    let differences = statediff::diff(&first, &second);
+    if differences.is_empty() {
+        println!("The states are the same");
+        return;
+    }
+    for difference in &differences {
+        println!("{}", difference);
+    }
+    process::exit(1);
+}
+
+fn run_bench(matches: &clap::ArgMatches) {
+    let config = cli::machine_config(matches);
+    let rom = load_rom(matches.value_of("rom").unwrap(), matches.value_of("format").unwrap().parse().unwrap(), Some(&config));
+    let cycles: u64 = matches.value_of("cycles").unwrap().parse().unwrap();
+
+    match bench::run(config, &rom, cycles) {
+        Ok(report) => print!("{}", report.summary()),
+        Err(error) => {
+            eprintln!("CHIP-8 error: {}", error);
+            process::exit(1);
+        }
+    }
+}
+
+fn run_info(matches: &clap::ArgMatches) {
+    let rom = load_rom(matches.value_of("rom").unwrap(), matches.value_of("format").unwrap().parse().unwrap(), None);
+
+    println!("Size: {} bytes", rom.len());
+    println!("SHA-1: {}", gamedb::rom_hash(&rom));
+
+    let metadata_db = load_metadata_db(matches);
+    if let Some(metadata) = metadata_db.lookup(&rom) {
+        print_metadata(metadata);
+    }
+
+    if let Some(settings) = load_game_db(matches).lookup(&rom) {
+        if let Some(speed) = settings.speed {
+            println!("Speed: {} instructions per second", speed);
+        }
+        if let Some(quirks) = settings.quirks {
+            println!("Quirks: {}", quirks.names().join(", "));
+        }
+    }
+}
+
+fn run_import_archive(matches: &clap::ArgMatches) {
+    let programs_path = matches.value_of("programs").unwrap();
+    let programs = fs::read_to_string(programs_path).unwrap_or_else(|error| {
+        eprintln!("Could not read {}: {}", programs_path, error);
+        process::exit(1);
+    });
+
+    match metadata::from_archive(&programs, Path::new(matches.value_of("roms").unwrap())) {
+        Ok(roms) => println!("{}", serde_json::to_string_pretty(&roms).unwrap()),
+        Err(error) => {
+            eprintln!("Cannot convert the archive: {}", error);
+            process::exit(1);
+        }
+    }
+}
+
+fn run_completions(matches: &clap::ArgMatches) {
+    let shell = matches.value_of("shell").unwrap().parse::<clap::Shell>().unwrap();
+    cli::build().gen_completions_to("chip8rs", shell, &mut io::stdout());
 }
@@ -0,0 +1,236 @@
+use crate::gamedb::rom_hash;
+use crate::platform::Platform;
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/* Metadata bundled with the emulator; entries from a user provided file take precedence */
+const BUNDLED_METADATA: &str = include_str!("../data/metadata.json");
+
+/* Description of a ROM and the options it was written for */
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RomMetadata {
+    pub title: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub authors: Vec<String>,
+    /* Release date, as written by the authors, usually the year */
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub release: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /* Recommended platform, one of platform::Platform::NAMES */
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platform: Option<String>,
+    /* Recommended quirks, from platform::Quirks::NAMES */
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub quirks: Vec<String>,
+}
+
+impl RomMetadata {
+    /* Title, authors and release, e.g. "Some Game by Jane Doe, John Doe (2015)" */
+    pub fn summary(&self) -> String {
+        let mut summary = self.title.clone();
+
+        if !self.authors.is_empty() {
+            summary += &format!(" by {}", self.authors.join(", "));
+        }
+        if let Some(release) = &self.release {
+            summary += &format!(" ({})", release);
+        }
+
+        summary
+    }
+
+    /* Command line options to run the ROM as intended */
+    pub fn recommended_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+
+        if let Some(platform) = &self.platform {
+            args.push(format!("--platform {}", platform));
+        }
+        for quirk in &self.quirks {
+            args.push(format!("--quirk {}", quirk));
+        }
+
+        args
+    }
+}
+
+pub struct MetadataDatabase {
+    roms: HashMap<String, RomMetadata>,
+}
+
+impl MetadataDatabase {
+    pub fn new() -> MetadataDatabase {
+        MetadataDatabase {
+            roms: serde_json::from_str(BUNDLED_METADATA).unwrap(),
+        }
+    }
+
+    /* Load the entries from a JSON file keyed by the ROM SHA-1, overriding the bundled ones */
+    pub fn load_file(&mut self, path: &str) -> io::Result<()> {
+        self.load_str(&fs::read_to_string(path)?)
+    }
+
+    fn load_str(&mut self, content: &str) -> io::Result<()> {
+        let roms: HashMap<String, RomMetadata> = serde_json::from_str(content)?;
+
+        for (hash, metadata) in roms {
+            self.roms.insert(hash.to_lowercase(), metadata);
+        }
+
+        Ok(())
+    }
+
+    pub fn lookup(&self, rom: &[u8]) -> Option<&RomMetadata> {
+        self.roms.get(&rom_hash(rom))
+    }
+}
+
+impl Default for MetadataDatabase {
+    fn default() -> Self {
+        MetadataDatabase::new()
+    }
+}
+
+/* Program listed in the programs.json of the CHIP-8 Archive (https://github.com/JohnEarnest/chip8Archive) */
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ArchiveProgram {
+    title: String,
+    authors: Vec<String>,
+    release: Option<String>,
+    desc: Option<String>,
+    platform: Option<String>,
+    options: ArchiveOptions,
+}
+
+/* Quirks of Octo, in which the archived programs were written; a missing one is left to the platform */
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct ArchiveOptions {
+    /* 8XY6 and 8XYE shift VX in place */
+    shift_quirks: Option<bool>,
+    /* FX55 and FX65 leave I unchanged */
+    load_store_quirk: Option<bool>,
+    /* BXNN jumps to XNN plus VX */
+    jump_quirks: Option<bool>,
+    /* 8XY1, 8XY2 and 8XY3 set VF to 0 */
+    logic_quirks: Option<bool>,
+}
+
+impl ArchiveProgram {
+    fn metadata(self) -> RomMetadata {
+        let options = &self.options;
+        let quirks = [
+            ("load-store-increment", options.load_store_quirk == Some(false)),
+            ("shift-copies-vy", options.shift_quirks == Some(false)),
+            ("jump-uses-vx", options.jump_quirks == Some(true)),
+            ("vf-reset", options.logic_quirks == Some(true)),
+        ];
+
+        RomMetadata {
+            title: self.title,
+            authors: self.authors,
+            release: self.release,
+            description: self.desc,
+            /* XO-CHIP programs get no platform, since it isn't emulated */
+            platform: self.platform.filter(|platform| Platform::NAMES.contains(&platform.as_str())),
+            quirks: quirks.iter().filter(|(_, enabled)| *enabled).map(|(name, _)| name.to_string()).collect(),
+        }
+    }
+}
+
+/* Convert the programs.json of the CHIP-8 Archive to metadata keyed by the SHA-1 of the ROMs, read from
+ * roms_dir where each one is named after its program; the programs without a ROM are left out */
+pub fn from_archive(programs: &str, roms_dir: &Path) -> io::Result<BTreeMap<String, RomMetadata>> {
+    let programs: BTreeMap<String, ArchiveProgram> = serde_json::from_str(programs)?;
+    let mut roms = BTreeMap::new();
+
+    for (name, program) in programs {
+        let rom = match fs::read(roms_dir.join(format!("{}.ch8", name))) {
+            Ok(rom) => rom,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+            Err(error) => return Err(error),
+        };
+        roms.insert(rom_hash(&rom), program.metadata());
+    }
+
+    Ok(roms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup() {
+        let rom = [0x12, 0x00];
+        let mut db = MetadataDatabase::new();
+        assert!(db.lookup(&rom).is_none());
+
+        let entry = r#"{ "title": "Loop", "authors": ["Jane Doe"], "platform": "schip", "quirks": ["vf-reset"] }"#;
+        db.load_str(&format!(r#"{{ "{}": {} }}"#, rom_hash(&rom).to_uppercase(), entry)).unwrap();
+        let metadata = db.lookup(&rom).unwrap();
+        assert_eq!(metadata.title, "Loop");
+        assert_eq!(metadata.release, None);
+
+        assert_eq!(db.load_str("[]").unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(db.load_file("missing.json").unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn summary() {
+        let mut metadata = RomMetadata {
+            title: String::from("Some Game"),
+            ..RomMetadata::default()
+        };
+        assert_eq!(metadata.summary(), "Some Game");
+
+        metadata.authors = vec![String::from("Jane Doe"), String::from("John Doe")];
+        metadata.release = Some(String::from("2015"));
+        assert_eq!(metadata.summary(), "Some Game by Jane Doe, John Doe (2015)");
+    }
+
+    #[test]
+    fn recommended_args() {
+        let mut metadata = RomMetadata::default();
+        assert!(metadata.recommended_args().is_empty());
+
+        metadata.platform = Some(String::from("schip"));
+        metadata.quirks = vec![String::from("vf-reset"), String::from("jump-uses-vx")];
+        assert_eq!(metadata.recommended_args(), ["--platform schip", "--quirk vf-reset", "--quirk jump-uses-vx"]);
+    }
+
+    #[test]
+    fn archive() {
+        let roms_dir = std::env::temp_dir().join(format!("chip8rs-archive-{}", std::process::id()));
+        fs::create_dir_all(&roms_dir).unwrap();
+        fs::write(roms_dir.join("loop.ch8"), [0x12, 0x00]).unwrap();
+
+        let programs = r#"{
+            "loop": {
+                "title": "Loop",
+                "authors": ["Jane Doe"],
+                "release": "2015-10-13",
+                "desc": "Nothing happens",
+                "platform": "xochip",
+                "options": { "tickrate": 20, "shiftQuirks": false, "loadStoreQuirk": true, "logicQuirks": true }
+            },
+            "missing": { "title": "Missing" }
+        }"#;
+        let roms = from_archive(programs, &roms_dir);
+        let _ = fs::remove_dir_all(&roms_dir);
+
+        let roms = roms.unwrap();
+        assert_eq!(roms.len(), 1);
+        let metadata = &roms[&rom_hash(&[0x12, 0x00])];
+        assert_eq!(metadata.summary(), "Loop by Jane Doe (2015-10-13)");
+        assert_eq!(metadata.description.as_deref(), Some("Nothing happens"));
+        assert_eq!(metadata.recommended_args(), ["--quirk shift-copies-vy", "--quirk vf-reset"]);
+    }
+}
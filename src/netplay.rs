@@ -0,0 +1,159 @@
+use crate::keypad::*;
+
+use std::io;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+const KEYPAD_SIZE: usize = 0x10;
+
+/* Each key event is sent as two bytes: the key code and 1 if pressed or 0 if released */
+const MESSAGE_SIZE: usize = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetplayMode {
+    /* Wait for the other instances on the given port */
+    Host(u16),
+    /* Connect to an instance hosting, given as "host:port" */
+    Connect(String),
+}
+
+fn encode(event: KeyEvent) -> [u8; MESSAGE_SIZE] {
+    [event.code as u8, event.pressed as u8]
+}
+
+fn decode(message: [u8; MESSAGE_SIZE]) -> Option<KeyEvent> {
+    let code = message[0] as usize;
+    if code >= KEYPAD_SIZE || message[1] > 1 {
+        return None;
+    }
+
+    Some(KeyEvent {
+        code,
+        pressed: message[1] == 1,
+        remote: true,
+    })
+}
+
+/* Mirror the key events between the keypad and the peer: the local ones are sent, the received ones
+ * are pressed on the keypad, each direction on its own thread */
+fn share(stream: TcpStream, keypad: &Arc<Mutex<KeyboardKeypad>>) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+
+    let mut writer = stream.try_clone()?;
+    let events = keypad.lock().unwrap().subscribe();
+    thread::spawn(move || {
+        for event in events.iter().filter(|event| !event.remote) {
+            if writer.write_all(&encode(event)).is_err() {
+                break;
+            }
+        }
+    });
+
+    let keypad = Arc::clone(keypad);
+    let mut reader = stream;
+    thread::spawn(move || {
+        let mut pressed = [false; KEYPAD_SIZE];
+        let mut message = [0; MESSAGE_SIZE];
+
+        while reader.read_exact(&mut message).is_ok() {
+            match decode(message) {
+                Some(event) => {
+                    pressed[event.code] = event.pressed;
+                    keypad.lock().unwrap().apply(event);
+                }
+                None => eprintln!("Invalid key event from the netplay peer: {:02X?}", message),
+            }
+        }
+
+        /* Don't leave the keys of a peer which went away stuck */
+        let mut keypad = keypad.lock().unwrap();
+        for code in (0..KEYPAD_SIZE).filter(|&code| pressed[code]) {
+            keypad.apply(KeyEvent {
+                code,
+                pressed: false,
+                remote: true,
+            });
+        }
+        eprintln!("Netplay peer disconnected");
+    });
+
+    Ok(())
+}
+
+/* Share the keypad with other instances; hosting doesn't wait for them, while connecting fails if the
+ * host can't be reached */
+pub fn start(mode: &NetplayMode, keypad: &Arc<Mutex<KeyboardKeypad>>) -> io::Result<()> {
+    match mode {
+        NetplayMode::Host(port) => {
+            let listener = TcpListener::bind(("0.0.0.0", *port))?;
+            let keypad = Arc::clone(keypad);
+
+            thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    if let Err(error) = share(stream, &keypad) {
+                        eprintln!("Cannot share the keypad: {}", error);
+                    }
+                }
+            });
+
+            Ok(())
+        }
+        NetplayMode::Connect(address) => share(TcpStream::connect(address.as_str())?, keypad),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn wait_for<F: Fn() -> bool>(condition: F) -> bool {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(5) {
+            if condition() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        false
+    }
+
+    #[test]
+    fn messages() {
+        let event = KeyEvent {
+            code: 0x0A,
+            pressed: true,
+            remote: false,
+        };
+        assert_eq!(encode(event), [0x0A, 1]);
+        assert_eq!(decode([0x0A, 1]), Some(KeyEvent { remote: true, ..event }));
+
+        assert_eq!(decode([0x10, 1]), None);
+        assert_eq!(decode([0x01, 2]), None);
+    }
+
+    #[test]
+    fn keys_are_mirrored() {
+        let host = Arc::new(Mutex::new(KeyboardKeypad::new(KEYPAD_SIZE)));
+        let guest = Arc::new(Mutex::new(KeyboardKeypad::new(KEYPAD_SIZE)));
+
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        start(&NetplayMode::Connect(address), &guest).unwrap();
+        share(listener.accept().unwrap().0, &host).unwrap();
+
+        guest.lock().unwrap().set_is_pressed(0x05, true);
+        assert!(wait_for(|| host.lock().unwrap().get_is_pressed(0x05)));
+
+        host.lock().unwrap().set_is_pressed(0x0C, true);
+        assert!(wait_for(|| guest.lock().unwrap().get_is_pressed(0x0C)));
+
+        /* The remote keys aren't echoed back, which would press them again after a release */
+        guest.lock().unwrap().set_is_pressed(0x05, false);
+        assert!(wait_for(|| !host.lock().unwrap().get_is_pressed(0x05)));
+        thread::sleep(Duration::from_millis(100));
+        assert!(!guest.lock().unwrap().get_is_pressed(0x05));
+    }
+}
@@ -0,0 +1,530 @@
+use crate::asm::AsmError;
+use crate::instruction::Instruction;
+
+use std::collections::HashMap;
+
+/* Octo programs are always assembled for the standard CHIP-8 memory layout */
+const START: u16 = 0x200;
+const MAIN: &str = "main";
+
+/* Structured statement waiting for its closing keyword */
+enum Block {
+    /* "loop": address of the first instruction, and the "while" jumps leaving the loop */
+    Loop { start: u16, exits: Vec<usize> },
+    /* "if ... begin": position of the jump to the "else" or "end" */
+    If { jump: usize },
+    /* "else": position of the jump to the "end" */
+    Else { jump: usize },
+}
+
+/* Reference to a label which was not defined yet, patched at the end */
+struct Fixup {
+    line: usize,
+    position: usize,
+    label: String,
+}
+
+struct Assembler<'a> {
+    tokens: Vec<(usize, &'a str)>,
+    next: usize,
+    rom: Vec<u8>,
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, i32>,
+    aliases: HashMap<String, u8>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<(usize, Block)>,
+}
+
+fn error(line: usize, message: String) -> AsmError {
+    AsmError { line, message }
+}
+
+fn is_identifier(token: &str) -> bool {
+    token.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+}
+
+/* Parse a decimal, "0x" hexadecimal or "0b" binary number, optionally negative */
+fn literal(token: &str) -> Option<i32> {
+    let (negative, digits) = match token.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, token),
+    };
+
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i32::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i32::from_str_radix(binary, 2).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+
+    Some(if negative { -value } else { value })
+}
+
+impl<'a> Assembler<'a> {
+    fn new(source: &'a str) -> Assembler<'a> {
+        /* Tokens are separated by whitespace, and comments start with "#" */
+        let tokens = source
+            .lines()
+            .enumerate()
+            .flat_map(|(index, line)| {
+                let code = line.split('#').next().unwrap_or("");
+                code.split_whitespace().map(move |token| (index + 1, token))
+            })
+            .collect();
+
+        Assembler {
+            tokens,
+            next: 0,
+            rom: Vec::new(),
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+        }
+    }
+
+    fn address(&self) -> u16 {
+        START + self.rom.len() as u16
+    }
+
+    fn token(&mut self, line: usize) -> Result<&'a str, AsmError> {
+        let (_, token) = self
+            .tokens
+            .get(self.next)
+            .copied()
+            .ok_or_else(|| error(line, String::from("Unexpected end of the source")))?;
+        self.next += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, line: usize, expected: &str) -> Result<(), AsmError> {
+        match self.token(line)? {
+            token if token == expected => Ok(()),
+            token => Err(error(line, format!("Expected \"{}\", found \"{}\"", expected, token))),
+        }
+    }
+
+    fn register(&mut self, line: usize) -> Result<u8, AsmError> {
+        let token = self.token(line)?;
+        self.as_register(token)
+            .ok_or_else(|| error(line, format!("Invalid register: {}", token)))
+    }
+
+    /* "v0" to "vf", or a name given with :alias */
+    fn as_register(&self, token: &str) -> Option<u8> {
+        if let Some(&reg) = self.aliases.get(token) {
+            return Some(reg);
+        }
+
+        let digit = token.strip_prefix('v').or_else(|| token.strip_prefix('V'))?;
+        if digit.len() != 1 {
+            return None;
+        }
+        u8::from_str_radix(digit, 16).ok()
+    }
+
+    /* A literal, a constant or a label defined before */
+    fn as_number(&self, token: &str) -> Option<i32> {
+        literal(token)
+            .or_else(|| self.constants.get(token).copied())
+            .or_else(|| self.labels.get(token).map(|&address| address as i32))
+    }
+
+    fn number(&mut self, line: usize, min: i32, max: i32) -> Result<i32, AsmError> {
+        let token = self.token(line)?;
+        match self.as_number(token) {
+            Some(value) if (min..=max).contains(&value) => Ok(value),
+            Some(value) => Err(error(line, format!("Value out of range: {} (from {} to {})", value, min, max))),
+            None => Err(error(line, format!("Invalid number: {}", token))),
+        }
+    }
+
+    /* Bytes can be negative, e.g. "v0 += -1" */
+    fn byte(&mut self, line: usize) -> Result<u8, AsmError> {
+        self.number(line, -128, 0xFF).map(|value| value as u8)
+    }
+
+    fn emit(&mut self, instr: Instruction) {
+        self.rom.extend_from_slice(&instr.opcode().to_be_bytes());
+    }
+
+    /* Emit an instruction taking an address, which can be a label defined later */
+    fn emit_address(&mut self, line: usize, token: &str, instr: fn(u16) -> Instruction) -> Result<(), AsmError> {
+        match self.as_number(token) {
+            Some(value) if (0..=0xFFF).contains(&value) => self.emit(instr(value as u16)),
+            Some(value) => return Err(error(line, format!("Address out of range: {}", value))),
+            None if is_identifier(token) => {
+                self.fixups.push(Fixup {
+                    line,
+                    position: self.rom.len(),
+                    label: token.to_string(),
+                });
+                self.emit(instr(0));
+            }
+            None => return Err(error(line, format!("Invalid address: {}", token))),
+        }
+
+        Ok(())
+    }
+
+    /* Emit a jump whose target is set later with patch; returns its position */
+    fn emit_jump(&mut self) -> usize {
+        self.emit(Instruction::Jump(0));
+        self.rom.len() - 2
+    }
+
+    fn patch(&mut self, position: usize, address: u16) {
+        self.rom[position] = (self.rom[position] & 0xF0) | (address >> 8) as u8;
+        self.rom[position + 1] = address as u8;
+    }
+
+    /* Parse a condition, like "v0 == 3" or "v1 -key"; returns the instruction skipping the next one
+     * when the condition is true, and the one skipping it when the condition is false */
+    fn condition(&mut self, line: usize) -> Result<(Instruction, Instruction), AsmError> {
+        let x = self.register(line)?;
+        let operator = self.token(line)?;
+
+        let (equal, different) = match operator {
+            "key" => return Ok((Instruction::SkipIfPressed(x), Instruction::SkipIfNotPressed(x))),
+            "-key" => return Ok((Instruction::SkipIfNotPressed(x), Instruction::SkipIfPressed(x))),
+            "==" | "!=" => {
+                let token = self.tokens.get(self.next).map_or("", |&(_, token)| token);
+                if let Some(y) = self.as_register(token) {
+                    self.next += 1;
+                    (Instruction::SkipIfContentEqual(x, y), Instruction::SkipIfContentDifferent(x, y))
+                } else {
+                    let nn = self.byte(line)?;
+                    (Instruction::SkipIfEqual(x, nn), Instruction::SkipIfDifferent(x, nn))
+                }
+            }
+            _ => return Err(error(line, format!("Unsupported comparison: {}", operator))),
+        };
+
+        if operator == "==" {
+            Ok((equal, different))
+        } else {
+            Ok((different, equal))
+        }
+    }
+
+    /* Assignments and arithmetic on a register, like "v0 := random 0xFF" or "v1 += v2" */
+    fn register_statement(&mut self, line: usize, x: u8) -> Result<(), AsmError> {
+        let operator = self.token(line)?;
+        let token = self.token(line)?;
+        let y = self.as_register(token);
+
+        let instr = match (operator, y) {
+            (":=", Some(y)) => Instruction::LogicalCopy(x, y),
+            (":=", None) if token == "key" => Instruction::WaitForKey(x),
+            (":=", None) if token == "delay" => Instruction::CopyDelayTimer(x),
+            (":=", None) if token == "random" => Instruction::Random(x, self.byte(line)?),
+            ("+=", Some(y)) => Instruction::LogicalAdd(x, y),
+            ("-=", Some(y)) => Instruction::LogicalSubtract(x, y),
+            ("=-", Some(y)) => Instruction::LogicalSubtractInverse(x, y),
+            ("|=", Some(y)) => Instruction::LogicalOr(x, y),
+            ("&=", Some(y)) => Instruction::LogicalAnd(x, y),
+            ("^=", Some(y)) => Instruction::LogicalXor(x, y),
+            (">>=", Some(y)) => Instruction::LogicalRightShift(x, y),
+            ("<<=", Some(y)) => Instruction::LogicalLeftShift(x, y),
+            (":=", None) | ("+=", None) | ("-=", None) => {
+                self.next -= 1;
+                let nn = self.byte(line)?;
+                match operator {
+                    ":=" => Instruction::SetRegister(x, nn),
+                    "+=" => Instruction::AddToRegister(x, nn),
+                    _ => Instruction::AddToRegister(x, nn.wrapping_neg()),
+                }
+            }
+            _ => return Err(error(line, format!("Invalid operation: v{:x} {} {}", x, operator, token))),
+        };
+
+        self.emit(instr);
+        Ok(())
+    }
+
+    fn statement(&mut self, line: usize, token: &'a str) -> Result<(), AsmError> {
+        match token {
+            ":" => {
+                let name = self.token(line)?;
+                if !is_identifier(name) {
+                    return Err(error(line, format!("Invalid label: {}", name)));
+                }
+                if self.labels.insert(name.to_string(), self.address()).is_some() {
+                    return Err(error(line, format!("Label defined twice: {}", name)));
+                }
+            }
+            ":const" => {
+                let name = self.token(line)?;
+                let value = self.number(line, -0x8000, 0xFFFF)?;
+                self.constants.insert(name.to_string(), value);
+            }
+            ":alias" => {
+                let name = self.token(line)?;
+                let reg = self.register(line)?;
+                self.aliases.insert(name.to_string(), reg);
+            }
+            ":org" => {
+                let address = self.number(line, 0, 0xFFFF)? as u16;
+                if address < self.address() {
+                    return Err(error(line, format!(":org moves back to {:03X}", address)));
+                }
+                self.rom.resize((address - START) as usize, 0);
+            }
+            ":byte" => {
+                let value = self.byte(line)?;
+                self.rom.push(value);
+            }
+            ":call" => {
+                let target = self.token(line)?;
+                self.emit_address(line, target, Instruction::Call)?;
+            }
+            /* Debugging hints for the Octo IDE */
+            ":breakpoint" => {
+                self.token(line)?;
+            }
+            ":monitor" => {
+                self.token(line)?;
+                self.token(line)?;
+            }
+            "clear" => self.emit(Instruction::ClearScreen),
+            "return" | ";" => self.emit(Instruction::Return),
+            "exit" => self.emit(Instruction::Exit),
+            "hires" => self.emit(Instruction::HighResolution),
+            "lores" => self.emit(Instruction::LowResolution),
+            "scroll-left" => self.emit(Instruction::ScrollLeft),
+            "scroll-right" => self.emit(Instruction::ScrollRight),
+            "scroll-down" => {
+                let n = self.number(line, 0, 0xF)? as u8;
+                self.emit(Instruction::ScrollDown(n));
+            }
+            "jump" | "jump0" => {
+                let target = self.token(line)?;
+                let instr = if token == "jump" {
+                    Instruction::Jump
+                } else {
+                    Instruction::JumpWithRegister
+                };
+                self.emit_address(line, target, instr)?;
+            }
+            "bcd" | "save" | "load" | "saveflags" | "loadflags" => {
+                let x = self.register(line)?;
+                self.emit(match token {
+                    "bcd" => Instruction::BinaryConversion(x),
+                    "save" => Instruction::Store(x),
+                    "load" => Instruction::Load(x),
+                    "saveflags" => Instruction::SaveFlags(x),
+                    _ => Instruction::LoadFlags(x),
+                });
+            }
+            "sprite" => {
+                let x = self.register(line)?;
+                let y = self.register(line)?;
+                let n = self.number(line, 0, 0xF)? as u8;
+                self.emit(Instruction::Display(x, y, n));
+            }
+            "delay" | "buzzer" => {
+                self.expect(line, ":=")?;
+                let x = self.register(line)?;
+                self.emit(if token == "delay" {
+                    Instruction::SetDelayTimer(x)
+                } else {
+                    Instruction::SetSoundTimer(x)
+                });
+            }
+            "i" => match self.token(line)? {
+                ":=" => match self.token(line)? {
+                    "hex" => {
+                        let x = self.register(line)?;
+                        self.emit(Instruction::SetIndexToFont(x));
+                    }
+                    "bighex" => {
+                        let x = self.register(line)?;
+                        self.emit(Instruction::SetIndexToBigFont(x));
+                    }
+                    target => self.emit_address(line, target, Instruction::SetIndex)?,
+                },
+                "+=" => {
+                    let x = self.register(line)?;
+                    self.emit(Instruction::AddToIndex(x));
+                }
+                operator => return Err(error(line, format!("Invalid operation: i {}", operator))),
+            },
+            "if" => {
+                let (skip_if_true, skip_if_false) = self.condition(line)?;
+                match self.token(line)? {
+                    "then" => self.emit(skip_if_false),
+                    "begin" => {
+                        self.emit(skip_if_true);
+                        let jump = self.emit_jump();
+                        self.blocks.push((line, Block::If { jump }));
+                    }
+                    keyword => return Err(error(line, format!("Expected \"then\" or \"begin\", found \"{}\"", keyword))),
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some((_, Block::If { jump })) => {
+                    let end = self.emit_jump();
+                    let address = self.address();
+                    self.patch(jump, address);
+                    self.blocks.push((line, Block::Else { jump: end }));
+                }
+                _ => return Err(error(line, String::from("\"else\" without \"if ... begin\""))),
+            },
+            "end" => match self.blocks.pop() {
+                Some((_, Block::If { jump })) | Some((_, Block::Else { jump })) => {
+                    let address = self.address();
+                    self.patch(jump, address);
+                }
+                _ => return Err(error(line, String::from("\"end\" without \"if ... begin\""))),
+            },
+            "loop" => {
+                let start = self.address();
+                self.blocks.push((line, Block::Loop { start, exits: Vec::new() }));
+            }
+            "while" => {
+                let (skip_if_true, _) = self.condition(line)?;
+                self.emit(skip_if_true);
+                let jump = self.emit_jump();
+                match self.blocks.iter_mut().rev().find_map(|(_, block)| match block {
+                    Block::Loop { exits, .. } => Some(exits),
+                    _ => None,
+                }) {
+                    Some(exits) => exits.push(jump),
+                    None => return Err(error(line, String::from("\"while\" outside of a loop"))),
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some((_, Block::Loop { start, exits })) => {
+                    self.emit(Instruction::Jump(start));
+                    let address = self.address();
+                    for exit in exits {
+                        self.patch(exit, address);
+                    }
+                }
+                _ => return Err(error(line, String::from("\"again\" without \"loop\""))),
+            },
+            _ if token.starts_with(':') => {
+                return Err(error(line, format!("Unsupported directive: {}", token)));
+            }
+            _ => {
+                if let Some(x) = self.as_register(token) {
+                    self.register_statement(line, x)?;
+                } else if let Some(value) = literal(token).or_else(|| self.constants.get(token).copied()) {
+                    if !(-128..=0xFF).contains(&value) {
+                        return Err(error(line, format!("Byte out of range: {}", value)));
+                    }
+                    self.rom.push(value as u8);
+                } else {
+                    /* A bare name calls the subroutine with that label */
+                    self.emit_address(line, token, Instruction::Call)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /* Assemble the program; reserving the first instruction for a jump to main */
+    fn assemble(mut self, jump_to_main: bool) -> Result<(Vec<u8>, u16), AsmError> {
+        if jump_to_main {
+            self.emit_address(1, MAIN, Instruction::Jump)?;
+        }
+
+        while let Some(&(line, token)) = self.tokens.get(self.next) {
+            self.next += 1;
+            self.statement(line, token)?;
+        }
+
+        if let Some((line, _)) = self.blocks.last() {
+            return Err(error(*line, String::from("Block not closed")));
+        }
+
+        let main = *self
+            .labels
+            .get(MAIN)
+            .ok_or_else(|| error(1, String::from("The program has no \"main\" label")))?;
+
+        for fixup in std::mem::take(&mut self.fixups) {
+            let address = *self
+                .labels
+                .get(&fixup.label)
+                .ok_or_else(|| error(fixup.line, format!("Unknown label: {}", fixup.label)))?;
+            self.patch(fixup.position, address);
+        }
+
+        Ok((self.rom, main))
+    }
+}
+
+/* Assemble a program written in Octo, the most popular language for modern CHIP-8 development. The
+ * execution starts from the "main" label, jumped to from the first instruction unless it's already
+ * there. Macros, :calc, :stringmode and the comparisons using VF, like "<", are not supported */
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let (rom, main) = Assembler::new(source).assemble(true)?;
+
+    /* The jump is not needed if main directly follows it: assemble again without it */
+    if main == START + 2 {
+        Assembler::new(source).assemble(false).map(|(rom, _)| rom)
+    } else {
+        Ok(rom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn program() {
+        let source = "
+            # Draw the digits from 0 to 9, forever
+            :alias counter v0
+            : main
+                counter := 0
+                loop
+                    i := digit
+                    sprite counter v1 5
+                    counter += 1
+                    if counter == 10 then counter := 0
+                again
+            : digit
+                0xF0 0x90 0xF0
+        ";
+
+        assert_eq!(
+            assemble(source),
+            Ok(vec![
+                0x60, 0x00, 0xA2, 0x0E, 0xD0, 0x15, 0x70, 0x01, 0x40, 0x0A, 0x60, 0x00, 0x12, 0x02, 0xF0, 0x90, 0xF0
+            ])
+        );
+
+        let source = "
+            : wait
+                if v0 == v1 begin v2 := 1 else v2 -= 1 end
+                loop while v3 key again
+                return
+            : main wait
+        ";
+        assert_eq!(
+            assemble(source),
+            Ok(vec![
+                0x12, 0x14, 0x50, 0x10, 0x12, 0x0A, 0x62, 0x01, 0x12, 0x0C, 0x72, 0xFF, 0xE3, 0x9E, 0x12, 0x12,
+                0x12, 0x0C, 0x00, 0xEE, 0x22, 0x02
+            ])
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let error = assemble(": main\n  v0 := 1\n  jump nowhere\n").unwrap_err();
+        assert_eq!(error.line, 3);
+        assert_eq!(error.message, "Unknown label: nowhere");
+
+        assert_eq!(assemble(": main\n  v0 := 256").unwrap_err().line, 2);
+        assert_eq!(assemble(": main\n  loop\n  clear").unwrap_err().line, 2);
+        assert_eq!(assemble(": start clear").unwrap_err().message, "The program has no \"main\" label");
+        assert!(assemble(": main :macro x { clear }").is_err());
+    }
+}
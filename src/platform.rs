@@ -0,0 +1,178 @@
+use crate::fonts::FontSet;
+
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/* Machines running a CHIP-8 interpreter, which differ in memory layout and display size; serialized
+ * with the names in NAMES */
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    /* The original interpreter for the COSMAC VIP */
+    #[default]
+    Chip8,
+    /* The ETI-660 learning computer, loading programs at 0x600 and with a taller display */
+    Eti660,
+    /* SUPER-CHIP 1.1 on the HP48 calculators, with a 128x64 high resolution mode */
+    Schip,
+}
+
+/* Behaviors on which the interpreters disagree; when disabled, instructions behave like most modern
+ * ROMs expect. Serialized with the names in NAMES, the missing ones being disabled */
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Quirks {
+    /* FX55 and FX65 leave I pointing past the last register, like the COSMAC VIP interpreter */
+    pub load_store_increment: bool,
+    /* 8XY6 and 8XYE shift VY and store the result in VX, like the COSMAC VIP interpreter */
+    pub shift_copies_vy: bool,
+    /* BXNN jumps to XNN plus VX instead of NNN plus V0, like CHIP-48 and SUPER-CHIP */
+    pub jump_uses_vx: bool,
+    /* 8XY1, 8XY2 and 8XY3 set VF to 0, like the COSMAC VIP interpreter */
+    pub vf_reset: bool,
+}
+
+impl Quirks {
+    pub const NAMES: [&'static str; 4] = ["load-store-increment", "shift-copies-vy", "jump-uses-vx", "vf-reset"];
+
+    pub fn enable(&mut self, name: &str) -> Result<(), String> {
+        self.set(name, true)
+    }
+
+    pub fn set(&mut self, name: &str, enabled: bool) -> Result<(), String> {
+        match name {
+            "load-store-increment" => self.load_store_increment = enabled,
+            "shift-copies-vy" => self.shift_copies_vy = enabled,
+            "jump-uses-vx" => self.jump_uses_vx = enabled,
+            "vf-reset" => self.vf_reset = enabled,
+            _ => return Err(format!("Unknown quirk: {}", name)),
+        }
+
+        Ok(())
+    }
+
+    /* Names of the enabled quirks, in the order of NAMES */
+    pub fn names(&self) -> Vec<&'static str> {
+        let enabled = [self.load_store_increment, self.shift_copies_vy, self.jump_uses_vx, self.vf_reset];
+        Quirks::NAMES
+            .iter()
+            .zip(enabled)
+            .filter(|(_, enabled)| *enabled)
+            .map(|(&name, _)| name)
+            .collect()
+    }
+}
+
+/* Memory layout and display geometry of the emulated machine */
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MachineConfig {
+    pub memory_size: usize,
+    /* Address where the ROM is loaded and the execution starts */
+    pub rom_start: u16,
+    /* Address of the built-in font */
+    pub font_start: u16,
+    pub display_width: usize,
+    pub display_height: usize,
+    /* The display is the SUPER-CHIP high resolution one; programs start in low resolution, where
+     * each pixel is drawn with 2x2 LEDs */
+    pub hires: bool,
+    pub font: FontSet,
+    pub quirks: Quirks,
+    /* Each instruction takes the time it took on the COSMAC VIP, instead of running a fixed number
+     * of instructions per second */
+    pub vip_timing: bool,
+}
+
+impl Default for MachineConfig {
+    fn default() -> Self {
+        Platform::default().config()
+    }
+}
+
+impl Platform {
+    pub const NAMES: [&'static str; 3] = ["chip8", "eti660", "schip"];
+
+    pub fn config(&self) -> MachineConfig {
+        match self {
+            Platform::Chip8 => MachineConfig {
+                memory_size: 4096,
+                rom_start: 0x200,
+                font_start: 0x50,
+                display_width: 64,
+                display_height: 32,
+                hires: false,
+                font: FontSet::default(),
+                quirks: Quirks::default(),
+                vip_timing: false,
+            },
+            Platform::Eti660 => MachineConfig {
+                memory_size: 4096,
+                rom_start: 0x600,
+                font_start: 0x50,
+                display_width: 64,
+                display_height: 48,
+                hires: false,
+                font: FontSet::Eti660,
+                /* Its interpreter was derived from the COSMAC VIP one */
+                quirks: Quirks {
+                    load_store_increment: true,
+                    shift_copies_vy: true,
+                    vf_reset: true,
+                    ..Quirks::default()
+                },
+                vip_timing: false,
+            },
+            Platform::Schip => MachineConfig {
+                memory_size: 4096,
+                rom_start: 0x200,
+                font_start: 0x50,
+                display_width: 128,
+                display_height: 64,
+                hires: true,
+                font: FontSet::default(),
+                quirks: Quirks {
+                    jump_uses_vx: true,
+                    ..Quirks::default()
+                },
+                vip_timing: false,
+            },
+        }
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "chip8" => Ok(Platform::Chip8),
+            "eti660" => Ok(Platform::Eti660),
+            "schip" => Ok(Platform::Schip),
+            _ => Err(format!("Unknown platform: {}", name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialization() {
+        let mut config = Platform::Schip.config();
+        config.quirks.enable("vf-reset").unwrap();
+
+        let json = serde_json::to_value(config).unwrap();
+        assert_eq!(json["quirks"]["vf-reset"], true);
+        assert_eq!(serde_json::from_value::<MachineConfig>(json).unwrap(), config);
+        assert_eq!(serde_json::to_string(&Platform::Eti660).unwrap(), "\"eti660\"");
+        assert_eq!(serde_json::from_str::<Quirks>(r#"{"jump-uses-vx": true}"#).unwrap().names(), ["jump-uses-vx"]);
+    }
+
+    #[test]
+    fn eti660() {
+        let config = Platform::Eti660.config();
+        assert_eq!(config.font, FontSet::Eti660);
+        assert_eq!(config.quirks.names(), ["load-store-increment", "shift-copies-vy", "vf-reset"]);
+    }
+}
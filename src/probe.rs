@@ -0,0 +1,129 @@
+use crate::analysis;
+use crate::bench;
+use crate::chip8::Chip8Error;
+use crate::coverage::Coverage;
+use crate::platform::{MachineConfig, Quirks};
+
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+/* Instructions run by each probe, a few seconds of a game at the usual speeds */
+pub const PROBE_CYCLES: u64 = 100_000;
+
+/* How far a probe got: a program running with the wrong quirks usually crashes, or gets stuck early and
+ * draws less */
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Score {
+    /* Words of the ROM executed at least once */
+    pub executed: usize,
+    pub lit_pixels: usize,
+}
+
+/* Headless run of the ROM with a combination of quirks */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Probe {
+    pub quirks: Quirks,
+    pub outcome: Result<Score, Chip8Error>,
+}
+
+/* Run the ROM with each combination of the quirks its instructions depend on, see analysis::Analysis;
+ * the quirks of the configuration are tried first */
+pub fn run(config: MachineConfig, rom: &[u8], cycles: u64) -> Vec<Probe> {
+    let names = analysis::analyze(rom, config.rom_start).quirks;
+
+    let mut combinations = vec![config.quirks];
+    for mask in 0..1 << names.len() {
+        let mut quirks = config.quirks;
+        for (bit, name) in names.iter().enumerate() {
+            quirks.set(name, mask & (1 << bit) != 0).unwrap();
+        }
+        if !combinations.contains(&quirks) {
+            combinations.push(quirks);
+        }
+    }
+
+    combinations
+        .into_iter()
+        .map(|quirks| {
+            let coverage = Arc::new(Mutex::new(Coverage::new(config.memory_size)));
+            let outcome = bench::run_headless(MachineConfig { quirks, ..config }, rom, cycles, None, Some(&coverage))
+                .map(|snapshot| Score {
+                    executed: coverage.lock().unwrap().executed_words(rom.len(), config.rom_start),
+                    lit_pixels: snapshot.display.iter().flatten().filter(|&&pixel| pixel != 0).count(),
+                });
+
+            Probe { quirks, outcome }
+        })
+        .collect()
+}
+
+/* Quirks of the probe which got the farthest; on a tie the first one wins, so the configuration is
+ * only changed when another combination does better */
+pub fn best(probes: &[Probe]) -> Option<Quirks> {
+    let mut best: Option<(&Probe, Score)> = None;
+
+    for probe in probes {
+        if let Ok(score) = probe.outcome {
+            if best.map_or(true, |(_, best_score)| score > best_score) {
+                best = Some((probe, score));
+            }
+        }
+    }
+
+    best.map(|(probe, _)| probe.quirks)
+}
+
+/* Outcome of each probe, one per line */
+pub fn report(probes: &[Probe]) -> String {
+    let mut report = String::new();
+
+    for probe in probes {
+        let names = probe.quirks.names();
+        let quirks = if names.is_empty() { String::from("no quirks") } else { names.join(", ") };
+
+        let _ = match &probe.outcome {
+            Ok(score) => writeln!(
+                report,
+                "{}: executed {} ROM words, {} pixels lit",
+                quirks, score.executed, score.lit_pixels
+            ),
+            Err(error) => writeln!(report, "{}: {}", quirks, error),
+        };
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shift_quirk() {
+        /* LD V1, 0x02; SHR V0, V1; SE V0, 0x01; JP 0x206 (stuck); CLS; JP 0x20A */
+        let rom = [0x61, 0x02, 0x80, 0x16, 0x30, 0x01, 0x12, 0x06, 0x00, 0xE0, 0x12, 0x0A];
+        let probes = run(MachineConfig::default(), &rom, 100);
+
+        assert_eq!(probes.len(), 2);
+        assert_eq!(probes[0].quirks, Quirks::default());
+
+        let quirks = best(&probes).unwrap();
+        assert_eq!(quirks.names(), vec!["shift-copies-vy"]);
+        assert!(report(&probes).contains("shift-copies-vy: executed 5 ROM words"));
+    }
+
+    #[test]
+    fn crashing_probe() {
+        /* LD V2, 0x10; JP V0, 0x204; JP 0x204: with the jump-uses-vx quirk the jump lands on 0x214, where
+         * the zeros past the ROM aren't instructions */
+        let rom = [0x62, 0x10, 0xB2, 0x04, 0x12, 0x04];
+        let mut config = MachineConfig::default();
+        config.quirks.enable("jump-uses-vx").unwrap();
+        let probes = run(config, &rom, 100);
+
+        assert_eq!(probes.len(), 2);
+        assert!(matches!(probes[0].outcome, Err(Chip8Error::UnknownInstruction { pc: 0x214, .. })));
+        assert!(probes[1].outcome.is_ok());
+        assert_eq!(best(&probes), Some(Quirks::default()));
+    }
+}
@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+#[derive(Debug, Default, Copy, Clone)]
+pub struct OpcodeStats {
+    pub count: u64,
+    pub time: Duration,
+}
+
+/* Accesses to the keypad shared with the frontend; a contention happens when the lock is held by
+ * another thread */
+#[derive(Debug, Default, Copy, Clone)]
+pub struct LockStats {
+    pub acquisitions: u64,
+    pub contentions: u64,
+    pub wait: Duration,
+}
+
+/* Execution count and cumulative execution time of each instruction */
+pub struct Profiler {
+    stats: HashMap<&'static str, OpcodeStats>,
+    /* Cumulative time spent fetching and decoding the instructions */
+    decode_time: Duration,
+    lock_stats: LockStats,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            stats: HashMap::new(),
+            decode_time: Duration::new(0, 0),
+            lock_stats: LockStats::default(),
+        }
+    }
+
+    pub fn record_decode(&mut self, elapsed: Duration) {
+        self.decode_time += elapsed;
+    }
+
+    /* Record a lock acquisition; wait is set if the lock was contended */
+    pub fn record_lock(&mut self, wait: Option<Duration>) {
+        self.lock_stats.acquisitions += 1;
+
+        if let Some(wait) = wait {
+            self.lock_stats.contentions += 1;
+            self.lock_stats.wait += wait;
+        }
+    }
+
+    pub fn decode_time(&self) -> Duration {
+        self.decode_time
+    }
+
+    /* Cumulative execution time of all the instructions */
+    pub fn execute_time(&self) -> Duration {
+        self.stats.values().map(|s| s.time).sum()
+    }
+
+    pub fn instructions(&self) -> u64 {
+        self.stats.values().map(|s| s.count).sum()
+    }
+
+    pub fn lock_stats(&self) -> LockStats {
+        self.lock_stats
+    }
+
+    pub fn record(&mut self, instruction: &'static str, elapsed: Duration) {
+        let stats = self.stats.entry(instruction).or_default();
+        stats.count += 1;
+        stats.time += elapsed;
+    }
+
+    /* Table of the executed instructions, sorted by cumulative time */
+    pub fn report(&self) -> String {
+        let mut stats: Vec<(&&str, &OpcodeStats)> = self.stats.iter().collect();
+        stats.sort_by(|a, b| b.1.time.cmp(&a.1.time));
+
+        let total_count: u64 = stats.iter().map(|(_, s)| s.count).sum();
+        let mut report = String::new();
+
+        let _ = writeln!(
+            report,
+            "{:<24} {:>10} {:>7} {:>12} {:>10}",
+            "Instruction", "Count", "%", "Total (us)", "Avg (ns)"
+        );
+
+        for (name, s) in stats {
+            let _ = writeln!(
+                report,
+                "{:<24} {:>10} {:>6.2}% {:>12} {:>10}",
+                name,
+                s.count,
+                (s.count as f64) * 100.0 / (total_count as f64),
+                s.time.as_micros(),
+                s.time.as_nanos() / (s.count as u128)
+            );
+        }
+
+        let _ = writeln!(
+            report,
+            "Decode: {} us, execute: {} us",
+            self.decode_time.as_micros(),
+            self.execute_time().as_micros()
+        );
+        let _ = writeln!(
+            report,
+            "Keypad locks: {}, contended: {}, waiting {} us",
+            self.lock_stats.acquisitions,
+            self.lock_stats.contentions,
+            self.lock_stats.wait.as_micros()
+        );
+
+        report
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Profiler::new()
+    }
+}
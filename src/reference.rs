@@ -0,0 +1,236 @@
+use crate::debugger::Snapshot;
+use crate::fonts::FONT_SIZE;
+use crate::instruction::Instruction;
+use crate::platform::{MachineConfig, Quirks};
+
+const STACK_SIZE: usize = 16;
+
+/* A deliberately plain interpreter of the original CHIP-8 instructions, sharing nothing with Chip8 but
+ * the decoder, used as a reference when verifying the execution engine. It works directly on a Snapshot,
+ * so that the two states can be compared; SUPER-CHIP instructions and high resolution displays are not
+ * supported, and no key is ever pressed */
+pub struct Reference {
+    pub state: Snapshot,
+    quirks: Quirks,
+    font_start: u16,
+    exited: bool,
+}
+
+impl Reference {
+    /* Start from the state of the other machine after loading the program, so that the memory layout
+     * and the fonts are the same */
+    pub fn new(config: &MachineConfig, initial: Snapshot) -> Result<Reference, String> {
+        if config.hires {
+            return Err(String::from("the reference interpreter only supports low resolution displays"));
+        }
+
+        Ok(Reference {
+            state: initial,
+            quirks: config.quirks,
+            font_start: config.font_start,
+            exited: false,
+        })
+    }
+
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    pub fn tick_timers(&mut self) {
+        self.state.delay_timer = self.state.delay_timer.saturating_sub(1);
+        self.state.sound_timer = self.state.sound_timer.saturating_sub(1);
+    }
+
+    fn read(&self, address: u16) -> Result<u8, String> {
+        self.state
+            .memory
+            .get(address as usize)
+            .copied()
+            .ok_or(format!("read outside of the memory at {:04X}", address))
+    }
+
+    fn write(&mut self, address: u16, value: u8) -> Result<(), String> {
+        match self.state.memory.get_mut(address as usize) {
+            Some(byte) => {
+                *byte = value;
+                Ok(())
+            }
+            None => Err(format!("write outside of the memory at {:04X}", address)),
+        }
+    }
+
+    /* XOR a sprite on the display, clipping it at the borders; returns true if a pixel was turned off */
+    fn draw(&mut self, x: usize, y: usize, n: u8) -> Result<bool, String> {
+        let height = self.state.display.len();
+        let width = self.state.display.first().map_or(0, |row| row.len());
+        let x = x % width;
+        let y = y % height;
+
+        let mut collision = false;
+        for row in 0..(n as usize) {
+            let bits = self.read(self.state.i.wrapping_add(row as u16))?;
+            if y + row >= height {
+                break;
+            }
+
+            for column in 0..8 {
+                if x + column >= width {
+                    break;
+                }
+
+                if bits & (0x80 >> column) != 0 {
+                    let pixel = &mut self.state.display[y + row][x + column];
+                    collision |= *pixel != 0;
+                    *pixel = (*pixel == 0) as u8;
+                }
+            }
+        }
+
+        Ok(collision)
+    }
+
+    /* Execute an instruction; CXNN leaves VX to 0, since the random values can't match the ones of the
+     * other machine and are copied from it */
+    pub fn step(&mut self) -> Result<(), String> {
+        let pc = self.state.pc;
+        let opcode = (self.read(pc)? as u16) << 8 | self.read(pc.wrapping_add(1))? as u16;
+        let instr = Instruction::decode(opcode).ok_or(format!("unknown instruction {:04X} at {:03X}", opcode, pc))?;
+        self.state.pc = pc.wrapping_add(2);
+
+        let v = &mut self.state.regs;
+        match instr {
+            Instruction::ClearScreen => {
+                for row in &mut self.state.display {
+                    row.iter_mut().for_each(|pixel| *pixel = 0);
+                }
+            }
+            Instruction::Return => {
+                self.state.pc = self.state.stack.pop().ok_or(format!("stack underflow at {:03X}", pc))?;
+            }
+            Instruction::Exit => {
+                self.state.pc = pc;
+                self.exited = true;
+            }
+            Instruction::Jump(addr) => self.state.pc = addr,
+            Instruction::Call(addr) => {
+                if self.state.stack.len() >= STACK_SIZE {
+                    return Err(format!("stack overflow at {:03X}", pc));
+                }
+                self.state.stack.push(self.state.pc);
+                self.state.pc = addr;
+            }
+            Instruction::SkipIfEqual(x, nn) => {
+                if v[x as usize] == nn {
+                    self.state.pc += 2;
+                }
+            }
+            Instruction::SkipIfDifferent(x, nn) => {
+                if v[x as usize] != nn {
+                    self.state.pc += 2;
+                }
+            }
+            Instruction::SkipIfContentEqual(x, y) => {
+                if v[x as usize] == v[y as usize] {
+                    self.state.pc += 2;
+                }
+            }
+            Instruction::SkipIfContentDifferent(x, y) => {
+                if v[x as usize] != v[y as usize] {
+                    self.state.pc += 2;
+                }
+            }
+            Instruction::SetRegister(x, nn) => v[x as usize] = nn,
+            Instruction::AddToRegister(x, nn) => v[x as usize] = v[x as usize].wrapping_add(nn),
+            Instruction::LogicalCopy(x, y) => v[x as usize] = v[y as usize],
+            Instruction::LogicalOr(x, y) | Instruction::LogicalAnd(x, y) | Instruction::LogicalXor(x, y) => {
+                let (vx, vy) = (v[x as usize], v[y as usize]);
+                v[x as usize] = match instr {
+                    Instruction::LogicalOr(..) => vx | vy,
+                    Instruction::LogicalAnd(..) => vx & vy,
+                    _ => vx ^ vy,
+                };
+                if self.quirks.vf_reset {
+                    v[0xF] = 0;
+                }
+            }
+            Instruction::LogicalAdd(x, y) => {
+                let sum = v[x as usize] as u16 + v[y as usize] as u16;
+                v[x as usize] = sum as u8;
+                v[0xF] = (sum > 0xFF) as u8;
+            }
+            Instruction::LogicalSubtract(x, y) => {
+                let (vx, vy) = (v[x as usize], v[y as usize]);
+                v[x as usize] = vx.wrapping_sub(vy);
+                v[0xF] = (vx >= vy) as u8;
+            }
+            Instruction::LogicalSubtractInverse(x, y) => {
+                let (vx, vy) = (v[x as usize], v[y as usize]);
+                v[x as usize] = vy.wrapping_sub(vx);
+                v[0xF] = (vy >= vx) as u8;
+            }
+            Instruction::LogicalRightShift(x, y) => {
+                let value = if self.quirks.shift_copies_vy { v[y as usize] } else { v[x as usize] };
+                v[x as usize] = value >> 1;
+                v[0xF] = value & 1;
+            }
+            Instruction::LogicalLeftShift(x, y) => {
+                let value = if self.quirks.shift_copies_vy { v[y as usize] } else { v[x as usize] };
+                v[x as usize] = value << 1;
+                v[0xF] = value >> 7;
+            }
+            Instruction::SetIndex(addr) => self.state.i = addr,
+            Instruction::JumpWithRegister(addr) => {
+                let reg = if self.quirks.jump_uses_vx { (addr >> 8) as usize } else { 0 };
+                self.state.pc = addr + v[reg] as u16;
+            }
+            Instruction::Random(x, _) => v[x as usize] = 0,
+            Instruction::Display(x, y, n) => {
+                let (x, y) = (v[x as usize] as usize, v[y as usize] as usize);
+                let collision = self.draw(x, y, n)?;
+                self.state.regs[0xF] = collision as u8;
+            }
+            /* No key is ever pressed */
+            Instruction::SkipIfPressed(_) => (),
+            Instruction::SkipIfNotPressed(_) => self.state.pc += 2,
+            Instruction::WaitForKey(_) => self.state.pc = pc,
+            Instruction::CopyDelayTimer(x) => v[x as usize] = self.state.delay_timer,
+            Instruction::SetDelayTimer(x) => self.state.delay_timer = v[x as usize],
+            Instruction::SetSoundTimer(x) => self.state.sound_timer = v[x as usize],
+            Instruction::AddToIndex(x) => {
+                let sum = self.state.i as u32 + v[x as usize] as u32;
+                self.state.i = sum as u16;
+                v[0xF] = (sum > 0xFFFF) as u8;
+            }
+            Instruction::SetIndexToFont(x) => {
+                self.state.i = self.font_start + (FONT_SIZE as u16) * (v[x as usize] as u16);
+            }
+            Instruction::BinaryConversion(x) => {
+                let value = v[x as usize];
+                let i = self.state.i;
+                self.write(i, value / 100)?;
+                self.write(i.wrapping_add(1), (value / 10) % 10)?;
+                self.write(i.wrapping_add(2), value % 10)?;
+            }
+            Instruction::Store(x) => {
+                for reg in 0..=(x as u16) {
+                    let value = self.state.regs[reg as usize];
+                    self.write(self.state.i.wrapping_add(reg), value)?;
+                }
+                if self.quirks.load_store_increment {
+                    self.state.i = self.state.i.wrapping_add(x as u16 + 1);
+                }
+            }
+            Instruction::Load(x) => {
+                for reg in 0..=(x as u16) {
+                    self.state.regs[reg as usize] = self.read(self.state.i.wrapping_add(reg))?;
+                }
+                if self.quirks.load_store_increment {
+                    self.state.i = self.state.i.wrapping_add(x as u16 + 1);
+                }
+            }
+            _ => return Err(format!("{} at {:03X} is not supported by the reference interpreter", instr, pc)),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+use graphics::math::Matrix2d;
+use graphics::{ellipse, rectangle, Graphics};
+
+pub const NAMES: [&str; 2] = ["square", "led"];
+
+/* Space around the round LEDs, relative to the size of their cell */
+const LED_GAP: f64 = 0.15;
+
+/* Lines of the grid, drawn along the top and the left border of each cell, relative to its size */
+const GRID_WIDTH: f64 = 0.08;
+const GRID_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 0.25];
+
+/* The glow is made of translucent discs of the color of the pixel, each one larger and fainter than
+ * the previous, up to GLOW_RADIUS cells from the center of the pixel */
+const GLOW_LAYERS: usize = 4;
+const GLOW_RADIUS: f64 = 1.5;
+const GLOW_ALPHA: f32 = 0.08;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PixelShape {
+    /* Each pixel fills its cell, as on a modern screen */
+    Square,
+    /* Round dots with gaps between them, like an LED matrix */
+    Led,
+}
+
+/* How the pixels are drawn; the grid is only drawn inside the cells, so that a pixel redrawn on its own
+ * looks the same as after a full redraw */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RenderStyle {
+    pub shape: PixelShape,
+    /* Faint lines between the pixels */
+    pub grid: bool,
+    /* Halo around the lit pixels, like the light of LED panels and CRTs bleeding around them */
+    pub glow: bool,
+}
+
+impl Default for RenderStyle {
+    fn default() -> Self {
+        RenderStyle {
+            shape: PixelShape::Square,
+            grid: false,
+            glow: false,
+        }
+    }
+}
+
+pub fn shape_from_name(name: &str) -> Option<PixelShape> {
+    match name {
+        "square" => Some(PixelShape::Square),
+        "led" => Some(PixelShape::Led),
+        _ => None,
+    }
+}
+
+impl RenderStyle {
+    /* Pixels turned off must be drawn too after a full redraw, since their cell has grid lines */
+    pub fn draws_background(&self) -> bool {
+        self.grid
+    }
+
+    /* The glow spills over the neighbouring cells, so it can't be erased by redrawing a single pixel;
+     * the whole display is drawn again on every frame instead */
+    pub fn needs_full_redraw(&self) -> bool {
+        self.glow
+    }
+
+    /* Draw the glow of a lit pixel, below all the pixels; intensity is the brightness of the pixel */
+    pub fn draw_glow<G: Graphics>(
+        &self,
+        color: [f32; 4],
+        intensity: f32,
+        cell: [f64; 4],
+        transform: Matrix2d,
+        gl: &mut G,
+    ) {
+        if !self.glow || intensity == 0.0 {
+            return;
+        }
+
+        let [x, y, width, height] = cell;
+        let [center_x, center_y] = [x + width / 2.0, y + height / 2.0];
+        for layer in 1..=GLOW_LAYERS {
+            let radius = width.max(height) * GLOW_RADIUS * (layer as f64) / (GLOW_LAYERS as f64);
+            let halo = [color[0], color[1], color[2], GLOW_ALPHA * intensity];
+            ellipse(halo, ellipse::circle(center_x, center_y, radius), transform, gl);
+        }
+    }
+
+    /* Draw a pixel over its whole cell, [x, y, width, height], so that it replaces what was there */
+    pub fn draw_pixel<G: Graphics>(
+        &self,
+        color: [f32; 4],
+        background: [f32; 4],
+        cell: [f64; 4],
+        transform: Matrix2d,
+        gl: &mut G,
+    ) {
+        let [x, y, width, height] = cell;
+
+        match self.shape {
+            PixelShape::Square => rectangle(color, cell, transform, gl),
+            PixelShape::Led => {
+                rectangle(background, cell, transform, gl);
+                let gap = width.min(height) * LED_GAP;
+                ellipse(color, [x + gap, y + gap, width - 2.0 * gap, height - 2.0 * gap], transform, gl);
+            }
+        }
+
+        if self.grid {
+            let line = width.min(height) * GRID_WIDTH;
+            rectangle(GRID_COLOR, [x, y, width, line], transform, gl);
+            rectangle(GRID_COLOR, [x, y + line, line, height - line], transform, gl);
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use std::sync::{Arc, Mutex};
+
+/* Source of the random bytes of CXNN, see Chip8::set_rng */
+pub trait RngSource {
+    fn next_byte(&mut self) -> u8;
+}
+
+/* Bytes from the thread RNG of rand, different on each run; used by default */
+#[derive(Debug, Default, Copy, Clone)]
+pub struct ThreadRngSource;
+
+impl RngSource for ThreadRngSource {
+    fn next_byte(&mut self) -> u8 {
+        rand::thread_rng().gen()
+    }
+}
+
+/* Bytes generated from a seed, the same on each run with the same seed */
+#[derive(Debug, Clone)]
+pub struct SeededRngSource {
+    rng: StdRng,
+}
+
+impl SeededRngSource {
+    pub fn new(seed: u64) -> SeededRngSource {
+        SeededRngSource {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl RngSource for SeededRngSource {
+    fn next_byte(&mut self) -> u8 {
+        self.rng.gen()
+    }
+}
+
+/* Plays back the given bytes in order, starting over after the last one, e.g. the ones recorded by
+ * RecordingRngSource; returns 0 if there are none */
+#[derive(Debug, Clone)]
+pub struct RecordedRngSource {
+    bytes: Vec<u8>,
+    position: usize,
+}
+
+impl RecordedRngSource {
+    pub fn new(bytes: Vec<u8>) -> RecordedRngSource {
+        RecordedRngSource { bytes, position: 0 }
+    }
+}
+
+impl RngSource for RecordedRngSource {
+    fn next_byte(&mut self) -> u8 {
+        if self.bytes.is_empty() {
+            return 0;
+        }
+
+        let byte = self.bytes[self.position];
+        self.position = (self.position + 1) % self.bytes.len();
+        byte
+    }
+}
+
+/* Takes the bytes from another source and appends them to a shared list, to replay the run later */
+pub struct RecordingRngSource {
+    inner: Box<dyn RngSource + Send>,
+    bytes: Arc<Mutex<Vec<u8>>>,
+}
+
+impl RecordingRngSource {
+    pub fn new(inner: Box<dyn RngSource + Send>, bytes: &Arc<Mutex<Vec<u8>>>) -> RecordingRngSource {
+        RecordingRngSource {
+            inner,
+            bytes: Arc::clone(bytes),
+        }
+    }
+}
+
+impl RngSource for RecordingRngSource {
+    fn next_byte(&mut self) -> u8 {
+        let byte = self.inner.next_byte();
+        self.bytes.lock().unwrap().push(byte);
+        byte
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(source: &mut dyn RngSource, count: usize) -> Vec<u8> {
+        (0..count).map(|_| source.next_byte()).collect()
+    }
+
+    #[test]
+    fn sources() {
+        assert_eq!(bytes(&mut SeededRngSource::new(42), 16), bytes(&mut SeededRngSource::new(42), 16));
+        assert_eq!(bytes(&mut RecordedRngSource::new(vec![1, 2, 3]), 5), vec![1, 2, 3, 1, 2]);
+        assert_eq!(bytes(&mut RecordedRngSource::new(Vec::new()), 2), vec![0, 0]);
+
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        let mut recording = RecordingRngSource::new(Box::new(SeededRngSource::new(7)), &recorded);
+        let played = bytes(&mut recording, 8);
+        assert_eq!(*recorded.lock().unwrap(), played);
+        assert_eq!(bytes(&mut RecordedRngSource::new(played.clone()), 8), played);
+    }
+}
@@ -0,0 +1,337 @@
+use crate::octo;
+use crate::platform::MachineConfig;
+
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io::prelude::*;
+use std::io;
+use std::str::FromStr;
+use std::sync::mpsc::Sender;
+use std::{thread, time};
+
+const STDIN_SOURCE: &str = "-";
+const OCTO_EXTENSION: &str = ".8o";
+const WATCH_PERIOD_MS: u64 = 500;
+
+/* Bytes looked at to tell if a file is a web page */
+const HTML_SNIFF_SIZE: usize = 1024;
+
+/* How the content of the ROM is encoded */
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum RomFormat {
+    /* Octo source for files ending in ".8o"; hex text if the content is text which can be parsed as such,
+     * binary otherwise */
+    #[default]
+    Auto,
+    Binary,
+    /* Hex digits, as in the listings printed in old magazines; see parse_hex */
+    Hex,
+    /* Source code in the Octo language, assembled when loaded; see octo::assemble */
+    Octo,
+}
+
+impl RomFormat {
+    pub const NAMES: [&'static str; 4] = ["auto", "binary", "hex", "octo"];
+}
+
+impl FromStr for RomFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "auto" => Ok(RomFormat::Auto),
+            "binary" => Ok(RomFormat::Binary),
+            "hex" => Ok(RomFormat::Hex),
+            "octo" => Ok(RomFormat::Octo),
+            _ => Err(format!("Unknown ROM format: {}", name)),
+        }
+    }
+}
+
+/* Reasons why a ROM can't be loaded; each one names the source, usually the path of the file */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RomError {
+    /* The file, the standard input or the URL could not be read */
+    Read { source: String, message: String },
+    Empty { source: String },
+    /* The ROM was given as hex text or Octo source, but a line could not be parsed */
+    Syntax { source: String, line: usize, message: String },
+    /* The content is a web page or a text file, usually saved by mistake instead of the ROM */
+    NotBinary { source: String, kind: &'static str },
+    /* The ROM doesn't fit in the memory after the address where it's loaded */
+    TooLarge { source: String, size: usize, max: usize },
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::Read { source, message } => write!(f, "cannot read {}: {}", source, message),
+            RomError::Empty { source } => write!(f, "{} is empty", source),
+            RomError::Syntax { source, line, message } => write!(f, "{}, line {}: {}", source, line, message),
+            RomError::NotBinary { source, kind } => write!(
+                f,
+                "{} looks like {}, not a CHIP-8 ROM; if it was downloaded from a web site, save the raw file instead of the page",
+                source, kind
+            ),
+            RomError::TooLarge { source, size, max } => write!(
+                f,
+                "{} is {} bytes, but at most {} bytes fit in memory",
+                source, size, max
+            ),
+        }
+    }
+}
+
+impl error::Error for RomError {}
+
+#[derive(Debug, PartialEq)]
+enum SourceKind {
+    File,
+    Stdin,
+    Url,
+}
+
+fn source_kind(source: &str) -> SourceKind {
+    if source == STDIN_SOURCE {
+        SourceKind::Stdin
+    } else if source.starts_with("http://") || source.starts_with("https://") {
+        SourceKind::Url
+    } else {
+        SourceKind::File
+    }
+}
+
+/* Read the ROM content from the given source; this can be a path to a file, "-" to read from the
+ * standard input or an HTTP(S) URL */
+pub fn read_rom(source: &str) -> Result<Vec<u8>, RomError> {
+    let content = match source_kind(source) {
+        SourceKind::File => fs::read(source).map_err(|error| error.to_string()),
+        SourceKind::Stdin => read_from_stdin().map_err(|error| error.to_string()),
+        SourceKind::Url => read_from_url(source),
+    };
+
+    content.map_err(|message| RomError::Read {
+        source: source.to_string(),
+        message,
+    })
+}
+
+/* Parse a ROM written as hex text. Bytes can be written one by one or grouped, e.g. "00 E0" or "00E0",
+ * optionally prefixed by "0x" or "$"; comments start with "#", ";" or "//" and last until the end of the
+ * line, and an address at the beginning of a line, like "0200:", is skipped. Errors report the line
+ * number, starting from 1 */
+pub fn parse_hex(text: &str) -> Result<Vec<u8>, (usize, String)> {
+    let mut rom = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let end = ["#", ";", "//"]
+            .iter()
+            .filter_map(|marker| line.find(marker))
+            .min()
+            .unwrap_or(line.len());
+
+        for (position, token) in line[..end].split_whitespace().enumerate() {
+            if position == 0 && token.ends_with(':') {
+                continue;
+            }
+
+            let digits = token
+                .strip_prefix("0x")
+                .or_else(|| token.strip_prefix("0X"))
+                .or_else(|| token.strip_prefix('$'))
+                .unwrap_or(token);
+            if digits.is_empty() || digits.len() % 2 != 0 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err((index + 1, format!("\"{}\" is not a sequence of hex bytes", token)));
+            }
+
+            for pair in digits.as_bytes().chunks(2) {
+                let pair = std::str::from_utf8(pair).unwrap();
+                rom.push(u8::from_str_radix(pair, 16).unwrap());
+            }
+        }
+    }
+
+    Ok(rom)
+}
+
+/* Turn the content read from the source into the ROM bytes, according to the format */
+pub fn decode_rom(source: &str, content: Vec<u8>, format: RomFormat) -> Result<Vec<u8>, RomError> {
+    let syntax_error = |line, message| RomError::Syntax {
+        source: source.to_string(),
+        line,
+        message,
+    };
+
+    let format = match format {
+        RomFormat::Auto if source.ends_with(OCTO_EXTENSION) => RomFormat::Octo,
+        format => format,
+    };
+
+    match format {
+        RomFormat::Binary => Ok(content),
+        RomFormat::Hex => {
+            let text = String::from_utf8_lossy(&content);
+            parse_hex(&text).map_err(|(line, message)| syntax_error(line, message))
+        }
+        RomFormat::Octo => {
+            let text = String::from_utf8_lossy(&content);
+            octo::assemble(&text).map_err(|error| syntax_error(error.line, error.message))
+        }
+        /* Only text which is entirely hex is taken as such, so that other text files are still reported */
+        RomFormat::Auto => match (text_kind(&content), std::str::from_utf8(&content)) {
+            (Some("a text file"), Ok(text)) => Ok(parse_hex(text).unwrap_or(content)),
+            _ => Ok(content),
+        },
+    }
+}
+
+/* Tell what a ROM which is actually text looks like, e.g. "an HTML page" */
+fn text_kind(rom: &[u8]) -> Option<&'static str> {
+    let start = String::from_utf8_lossy(&rom[..rom.len().min(HTML_SNIFF_SIZE)]).to_lowercase();
+    if start.trim_start().starts_with("<!doctype html") || start.contains("<html") {
+        return Some("an HTML page");
+    }
+
+    let text = std::str::from_utf8(rom).ok()?;
+    if text.chars().all(|c| c.is_ascii_graphic() || c.is_ascii_whitespace()) {
+        Some("a text file")
+    } else {
+        None
+    }
+}
+
+/* Check that the ROM read from the source can be loaded by the machine */
+pub fn check_rom(source: &str, rom: &[u8], config: &MachineConfig) -> Result<(), RomError> {
+    let source = source.to_string();
+
+    if rom.is_empty() {
+        return Err(RomError::Empty { source });
+    }
+
+    if let Some(kind) = text_kind(rom) {
+        return Err(RomError::NotBinary { source, kind });
+    }
+
+    let max = config.memory_size.saturating_sub(config.rom_start as usize);
+    if rom.len() > max {
+        return Err(RomError::TooLarge {
+            source,
+            size: rom.len(),
+            max,
+        });
+    }
+
+    Ok(())
+}
+
+/* Only plain files can be watched for changes */
+pub fn is_watchable(source: &str) -> bool {
+    source_kind(source) == SourceKind::File
+}
+
+/* Poll the modification time of the ROM file and send its new content, decoded according to the format,
+ * each time it changes */
+pub fn watch_rom(path: String, format: RomFormat, sender: Sender<Vec<u8>>) {
+    thread::spawn(move || {
+        let modified = |path: &str| fs::metadata(path).and_then(|m| m.modified()).ok();
+        let mut last_modified = modified(&path);
+
+        loop {
+            thread::sleep(time::Duration::from_millis(WATCH_PERIOD_MS));
+
+            let current = modified(&path);
+            if current.is_some() && current != last_modified {
+                /* The file may be still being written by the assembler; in that case try again on the next poll */
+                if let Ok(content) = fs::read(&path) {
+                    last_modified = current;
+                    let content = match decode_rom(&path, content, format) {
+                        Ok(content) => content,
+                        Err(error) => {
+                            log::error!("{}", error);
+                            continue;
+                        }
+                    };
+                    if sender.send(content).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+fn read_from_stdin() -> io::Result<Vec<u8>> {
+    let mut content = Vec::new();
+    io::stdin().read_to_end(&mut content)?;
+    Ok(content)
+}
+
+#[cfg(feature = "native")]
+fn read_from_url(url: &str) -> Result<Vec<u8>, String> {
+    let response = ureq::get(url).call().map_err(|error| error.to_string())?;
+    let mut content = Vec::new();
+    response
+        .into_reader()
+        .read_to_end(&mut content)
+        .map_err(|error| error.to_string())?;
+    Ok(content)
+}
+
+#[cfg(not(feature = "native"))]
+fn read_from_url(_url: &str) -> Result<Vec<u8>, String> {
+    Err(String::from("downloading ROMs is only supported by the native build"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagnostics() {
+        let config = MachineConfig::default();
+        let check = |rom: &[u8]| check_rom("game.ch8", rom, &config);
+
+        assert_eq!(check(&[0x00, 0xE0, 0x12, 0x00]), Ok(()));
+        assert_eq!(
+            check(&[]),
+            Err(RomError::Empty {
+                source: String::from("game.ch8")
+            })
+        );
+        assert_eq!(
+            check(b"<!DOCTYPE html>\n<html><body>Pong</body></html>"),
+            Err(RomError::NotBinary {
+                source: String::from("game.ch8"),
+                kind: "an HTML page"
+            })
+        );
+        assert_eq!(
+            check(b"Pong by Paul Vervalin\n"),
+            Err(RomError::NotBinary {
+                source: String::from("game.ch8"),
+                kind: "a text file"
+            })
+        );
+
+        let error = check(&[0xFF; 3585]).unwrap_err();
+        assert_eq!(error.to_string(), "game.ch8 is 3585 bytes, but at most 3584 bytes fit in memory");
+    }
+
+    #[test]
+    fn formats() {
+        let listing = "; Clear the screen and loop\n0200: 00E0 0x12 $02  # jump back\n\n// done\n";
+        assert_eq!(parse_hex(listing), Ok(vec![0x00, 0xE0, 0x12, 0x02]));
+        assert_eq!(parse_hex("00E0\n12 0"), Err((2, String::from("\"0\" is not a sequence of hex bytes"))));
+
+        let decode = |content: &[u8], format| decode_rom("game.hex", content.to_vec(), format);
+        assert_eq!(decode(b"00 E0 12 00\n", RomFormat::Auto), Ok(vec![0x00, 0xE0, 0x12, 0x00]));
+        assert_eq!(decode(b"Pong\n", RomFormat::Auto), Ok(b"Pong\n".to_vec()));
+        assert_eq!(decode(b"00 E0", RomFormat::Binary), Ok(b"00 E0".to_vec()));
+        assert_eq!(decode(b": main clear", RomFormat::Octo), Ok(vec![0x00, 0xE0]));
+        assert_eq!(
+            decode(b"00 E0\nPong\n", RomFormat::Hex).unwrap_err().to_string(),
+            "game.hex, line 2: \"Pong\" is not a sequence of hex bytes"
+        );
+    }
+}
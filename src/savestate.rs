@@ -0,0 +1,219 @@
+use crate::chip8::RPL_FLAGS_SIZE;
+use crate::debugger::Snapshot;
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Serialize;
+use serde_json::Value;
+use std::borrow::Cow;
+use std::convert::TryInto;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+pub const SLOTS: usize = 10;
+
+/* Version of the save state format, stored in each file; it must be increased when the snapshot
+ * changes, adding a step to migrate, so that the states saved by older releases keep loading */
+pub const VERSION: u64 = 2;
+
+/* Save states are stored in this directory, relative to the working directory like the log file */
+const STATES_DIR: &str = "chip8rs-states";
+
+/* Save states are gzipped JSON; the older releases wrote plain JSON, without the .gz extension */
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+
+/* Each ROM has its own set of slots, named after its hash */
+pub fn slot_path(rom_hash: &str, slot: usize) -> PathBuf {
+    PathBuf::from(STATES_DIR).join(format!("{}-{}.json.gz", rom_hash, slot))
+}
+
+/* State saved automatically when the emulator is closed */
+pub fn autosave_path(rom_hash: &str) -> PathBuf {
+    PathBuf::from(STATES_DIR).join(format!("{}-auto.json.gz", rom_hash))
+}
+
+/* RPL user flags saved by SUPER-CHIP programs, as raw bytes */
+pub fn rpl_flags_path(rom_hash: &str) -> PathBuf {
+    PathBuf::from(STATES_DIR).join(format!("{}-flags.bin", rom_hash))
+}
+
+/* The hash of the ROM was added after the version; the states without it load with any ROM */
+#[derive(Serialize)]
+struct SaveState<'a> {
+    version: u64,
+    rom: &'a str,
+    state: &'a Snapshot,
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/* The memory is mostly zeros, so the JSON is compressed */
+fn encode(snapshot: &Snapshot, rom_hash: &str) -> io::Result<Vec<u8>> {
+    let json = serde_json::to_vec(&SaveState {
+        version: VERSION,
+        rom: rom_hash,
+        state: snapshot,
+    })?;
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&json)?;
+    encoder.finish()
+}
+
+/* Convert a state from the given version to the next one */
+fn migrate(version: u64, mut state: Value) -> io::Result<Value> {
+    match version {
+        /* Version 1 had no SUPER-CHIP high resolution mode */
+        1 => {
+            if let Value::Object(fields) = &mut state {
+                fields.insert(String::from("hires"), Value::Bool(false));
+            }
+        }
+        _ => return Err(invalid_data(format!("Cannot migrate save state version {}", version))),
+    }
+
+    Ok(state)
+}
+
+/* Fails if the state was saved with another ROM than the one with the given hash, if any */
+fn decode(content: &[u8], rom_hash: Option<&str>) -> io::Result<Snapshot> {
+    let json = if content.starts_with(&GZIP_MAGIC) {
+        let mut json = Vec::new();
+        GzDecoder::new(content).read_to_end(&mut json)?;
+        Cow::Owned(json)
+    } else {
+        Cow::Borrowed(content)
+    };
+
+    /* The states saved before the version was introduced only contain the snapshot: that's version 1 */
+    let (version, mut state) = match serde_json::from_slice(&json)? {
+        Value::Object(mut fields) if fields.contains_key("version") => {
+            if let (Some(expected), Some(rom)) = (rom_hash, fields.get("rom").and_then(Value::as_str)) {
+                if rom != expected {
+                    return Err(invalid_data(format!("Save state taken with another ROM, whose SHA-1 is {}", rom)));
+                }
+            }
+            let version = fields["version"]
+                .as_u64()
+                .ok_or_else(|| invalid_data(String::from("Invalid save state version")))?;
+            let state = fields
+                .remove("state")
+                .ok_or_else(|| invalid_data(String::from("Missing state in the save state")))?;
+            (version, state)
+        }
+        state => (1, state),
+    };
+
+    if version > VERSION {
+        return Err(invalid_data(format!(
+            "Save state version {} is newer than the supported one, {}",
+            version, VERSION
+        )));
+    }
+
+    for from in version..VERSION {
+        state = migrate(from, state)?;
+    }
+
+    Ok(serde_json::from_value(state)?)
+}
+
+/* The state is tied to the ROM with the given hash, see load */
+pub fn save(path: &Path, snapshot: &Snapshot, rom_hash: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    fs::write(path, encode(snapshot, rom_hash)?)
+}
+
+/* Load a state, checking that it was saved with the ROM with the given hash if any; a missing .gz file
+ * is looked for without the extension, where the older releases saved it */
+pub fn load(path: &Path, rom_hash: Option<&str>) -> io::Result<Snapshot> {
+    let content = match fs::read(path) {
+        Err(error) if error.kind() == io::ErrorKind::NotFound && path.extension() == Some("gz".as_ref()) => {
+            fs::read(path.with_extension(""))?
+        }
+        content => content?,
+    };
+
+    decode(&content, rom_hash)
+}
+
+pub fn save_rpl_flags(path: &Path, flags: &[u8; RPL_FLAGS_SIZE]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    fs::write(path, flags)
+}
+
+pub fn load_rpl_flags(path: &Path) -> io::Result<[u8; RPL_FLAGS_SIZE]> {
+    let content = fs::read(path)?;
+    content
+        .try_into()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid RPL flags file"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            memory: vec![0xAB; 16],
+            pc: 0x202,
+            i: 0x300,
+            regs: [1; 16],
+            stack: vec![0x210],
+            delay_timer: 3,
+            sound_timer: 0,
+            display: vec![vec![0, 1], vec![1, 0]],
+            hires: true,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let content = encode(&snapshot(), "abc").unwrap();
+        assert!(content.starts_with(&GZIP_MAGIC));
+        assert_eq!(decode(&content, Some("abc")).unwrap(), snapshot());
+        assert_eq!(decode(&content, None).unwrap(), snapshot());
+
+        let error = decode(&content, Some("def")).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("another ROM"));
+
+        /* A whole memory of zeros takes a few bytes */
+        let mut empty = snapshot();
+        empty.memory = vec![0; 4096];
+        assert!(encode(&empty, "abc").unwrap().len() < 200);
+    }
+
+    #[test]
+    fn uncompressed_states() {
+        let state = serde_json::to_string(&snapshot()).unwrap();
+        let content = format!(r#"{{"version": {}, "state": {}}}"#, VERSION, state);
+        assert_eq!(decode(content.as_bytes(), Some("abc")).unwrap(), snapshot());
+    }
+
+    #[test]
+    fn migrate_unversioned_states() {
+        let content = r#"{"memory": [171], "pc": 514, "i": 768, "regs": [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            "stack": [], "delay_timer": 0, "sound_timer": 0, "display": [[0]]}"#;
+
+        let snapshot = decode(content.as_bytes(), None).unwrap();
+        assert_eq!(snapshot.pc, 0x202);
+        assert!(!snapshot.hires);
+    }
+
+    #[test]
+    fn newer_versions() {
+        let content = format!(r#"{{"version": {}, "state": {{}}}}"#, VERSION + 1);
+        assert_eq!(decode(content.as_bytes(), None).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,241 @@
+use crate::chip8::HookEvent;
+use crate::debugger::{Debugger, Snapshot};
+use crate::keypad::*;
+
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Scope, AST, INT};
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+const KEYPAD_SIZE: usize = 0x10;
+
+/* Machine state seen by the script functions; only available while an event is handled */
+type SharedState = Arc<Mutex<Option<Snapshot>>>;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+fn with_state<T, F: FnOnce(&mut Snapshot) -> ScriptResult<T>>(state: &SharedState, f: F) -> ScriptResult<T> {
+    match state.lock().unwrap().as_mut() {
+        Some(snapshot) => f(snapshot),
+        None => Err("The machine state is only available in the event handlers".into()),
+    }
+}
+
+fn check_index(index: INT, len: usize, what: &str) -> ScriptResult<usize> {
+    if index >= 0 && (index as usize) < len {
+        Ok(index as usize)
+    } else {
+        Err(format!("Invalid {}: {}", what, index).into())
+    }
+}
+
+/* Script attached to the machine events; it can define these functions, all optional:
+ *   on_frame()               called at 60Hz
+ *   on_breakpoint(pc)        called when the execution stops on a breakpoint
+ *   on_key(key, pressed)     called when a key of the keypad is pressed or released
+ * The handlers can use peek/poke for the memory, reg/set_reg for V0-VF, pc/set_pc, index/set_index,
+ * press/release for the keypad and pause/resume for the execution */
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    state: SharedState,
+}
+
+impl Script {
+    pub fn new(
+        source: &str,
+        debugger: &Arc<Mutex<Debugger>>,
+        keypad: &Arc<Mutex<KeyboardKeypad>>,
+    ) -> Result<Script, String> {
+        let state: SharedState = Arc::new(Mutex::new(None));
+        let mut engine = Engine::new();
+
+        let s = state.clone();
+        engine.register_fn("peek", move |address: INT| {
+            with_state(&s, |snapshot| {
+                let address = check_index(address, snapshot.memory.len(), "address")?;
+                Ok(snapshot.memory[address] as INT)
+            })
+        });
+        let s = state.clone();
+        engine.register_fn("poke", move |address: INT, value: INT| {
+            with_state(&s, |snapshot| {
+                let address = check_index(address, snapshot.memory.len(), "address")?;
+                snapshot.memory[address] = value as u8;
+                Ok(())
+            })
+        });
+        let s = state.clone();
+        engine.register_fn("reg", move |reg: INT| {
+            with_state(&s, |snapshot| {
+                let reg = check_index(reg, snapshot.regs.len(), "register")?;
+                Ok(snapshot.regs[reg] as INT)
+            })
+        });
+        let s = state.clone();
+        engine.register_fn("set_reg", move |reg: INT, value: INT| {
+            with_state(&s, |snapshot| {
+                let reg = check_index(reg, snapshot.regs.len(), "register")?;
+                snapshot.regs[reg] = value as u8;
+                Ok(())
+            })
+        });
+        let s = state.clone();
+        engine.register_fn("pc", move || with_state(&s, |snapshot| Ok(snapshot.pc as INT)));
+        let s = state.clone();
+        engine.register_fn("set_pc", move |address: INT| {
+            with_state(&s, |snapshot| {
+                snapshot.pc = check_index(address, snapshot.memory.len(), "address")? as u16;
+                Ok(())
+            })
+        });
+        let s = state.clone();
+        engine.register_fn("index", move || with_state(&s, |snapshot| Ok(snapshot.i as INT)));
+        let s = state.clone();
+        engine.register_fn("set_index", move |address: INT| {
+            with_state(&s, |snapshot| {
+                snapshot.i = check_index(address, snapshot.memory.len(), "address")? as u16;
+                Ok(())
+            })
+        });
+
+        let k = keypad.clone();
+        engine.register_fn("press", move |key: INT| -> ScriptResult<()> {
+            let key = check_index(key, KEYPAD_SIZE, "key")?;
+            k.lock().unwrap().set_is_pressed(key, true);
+            Ok(())
+        });
+        let k = keypad.clone();
+        engine.register_fn("release", move |key: INT| -> ScriptResult<()> {
+            let key = check_index(key, KEYPAD_SIZE, "key")?;
+            k.lock().unwrap().set_is_pressed(key, false);
+            Ok(())
+        });
+
+        let d = debugger.clone();
+        engine.register_fn("pause", move || d.lock().unwrap().set_paused(true));
+        let d = debugger.clone();
+        engine.register_fn("resume", move || d.lock().unwrap().set_paused(false));
+
+        let ast = engine.compile(source).map_err(|error| error.to_string())?;
+
+        /* The top level statements are run once, when the script is loaded; the handlers don't run them
+         * again */
+        let mut scope = Scope::new();
+        engine
+            .run_ast_with_scope(&mut scope, &ast)
+            .map_err(|error| error.to_string())?;
+
+        Ok(Script {
+            engine,
+            ast,
+            scope,
+            state,
+        })
+    }
+
+    pub fn load(
+        path: &Path,
+        debugger: &Arc<Mutex<Debugger>>,
+        keypad: &Arc<Mutex<KeyboardKeypad>>,
+    ) -> Result<Script, String> {
+        let source = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        Script::new(&source, debugger, keypad)
+    }
+
+    fn has_handler(&self, name: &str) -> bool {
+        self.ast.iter_functions().any(|f| f.name == name)
+    }
+
+    /* Run the handler of the event, if defined; errors are printed without stopping the emulator */
+    pub fn handle(&mut self, event: HookEvent, snapshot: &mut Snapshot) {
+        let (name, args): (&str, Vec<Dynamic>) = match event {
+            HookEvent::Frame => ("on_frame", vec![]),
+            HookEvent::Breakpoint(pc) => ("on_breakpoint", vec![(pc as INT).into()]),
+            HookEvent::Key { key, pressed } => ("on_key", vec![(key as INT).into(), pressed.into()]),
+        };
+
+        if !self.has_handler(name) {
+            return;
+        }
+
+        *self.state.lock().unwrap() = Some(snapshot.clone());
+        let options = CallFnOptions::new().eval_ast(false);
+        let result = self
+            .engine
+            .call_fn_with_options::<Dynamic>(options, &mut self.scope, &self.ast, name, args);
+        *snapshot = self.state.lock().unwrap().take().unwrap();
+
+        if let Err(error) = result {
+            eprintln!("Script error in {}: {}", name, error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            memory: vec![0; 0x1000],
+            pc: 0x200,
+            i: 0,
+            regs: [0; 16],
+            stack: Vec::new(),
+            delay_timer: 0,
+            sound_timer: 0,
+            display: vec![vec![0; 64]; 32],
+            hires: false,
+        }
+    }
+
+    fn script(source: &str) -> Script {
+        let debugger = Arc::new(Mutex::new(Debugger::new()));
+        let keypad = Arc::new(Mutex::new(KeyboardKeypad::new(KEYPAD_SIZE)));
+        Script::new(source, &debugger, &keypad).unwrap()
+    }
+
+    #[test]
+    fn handlers_change_the_state() {
+        let mut script = script(
+            r#"
+            fn on_frame() {
+                poke(0x300, peek(0x300) + 1);
+            }
+            fn on_key(key, pressed) {
+                if pressed { set_reg(key, 0xFF); }
+            }
+            "#,
+        );
+        let mut snapshot = snapshot();
+
+        script.handle(HookEvent::Frame, &mut snapshot);
+        script.handle(HookEvent::Frame, &mut snapshot);
+        assert_eq!(snapshot.memory[0x300], 2);
+
+        script.handle(HookEvent::Key { key: 3, pressed: true }, &mut snapshot);
+        assert_eq!(snapshot.regs[3], 0xFF);
+
+        /* Missing handlers are ignored */
+        script.handle(HookEvent::Breakpoint(0x200), &mut snapshot);
+    }
+
+    #[test]
+    fn errors_keep_the_state() {
+        let mut script = script("fn on_frame() { set_reg(0, 1); peek(0x10000); }");
+        let mut snapshot = snapshot();
+
+        script.handle(HookEvent::Frame, &mut snapshot);
+        assert_eq!(snapshot.regs[0], 1);
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let debugger = Arc::new(Mutex::new(Debugger::new()));
+        let keypad = Arc::new(Mutex::new(KeyboardKeypad::new(KEYPAD_SIZE)));
+
+        assert!(Script::new("fn on_frame( {", &debugger, &keypad).is_err());
+    }
+}
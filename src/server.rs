@@ -0,0 +1,162 @@
+use crate::chip8::Command;
+use crate::debugger::{Debugger, Snapshot};
+use crate::keypad::*;
+
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tungstenite::{Message, WebSocket};
+
+/* How often the machine state is sent to each client, ~30 times per second */
+const STATE_PERIOD_MS: u64 = 33;
+
+/* How long to wait for the CHIP-8 thread to answer a state request */
+const SNAPSHOT_TIMEOUT_MS: u64 = 500;
+
+const KEYPAD_SIZE: usize = 0x10;
+
+/* Messages accepted from the clients, as JSON objects with a "type" field, for example
+ * {"type": "key", "key": 10, "pressed": true} */
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ClientMessage {
+    Key { key: usize, pressed: bool },
+    Pause,
+    Resume,
+    Reset,
+}
+
+/* State sent periodically to the clients, with the fields of the snapshot; the memory is left out to
+ * keep the messages small */
+#[derive(Debug, Serialize)]
+struct StateMessage<'a> {
+    paused: bool,
+    #[serde(flatten)]
+    state: &'a Snapshot,
+}
+
+/* Everything a client needs to control the machine */
+#[derive(Clone)]
+struct Machine {
+    debugger: Arc<Mutex<Debugger>>,
+    commands: Sender<Command>,
+    keypad: Arc<Mutex<KeyboardKeypad>>,
+}
+
+impl Machine {
+    fn snapshot(&self) -> Option<Snapshot> {
+        let (sender, receiver) = mpsc::channel();
+        self.commands.send(Command::Save(sender)).ok()?;
+        receiver.recv_timeout(Duration::from_millis(SNAPSHOT_TIMEOUT_MS)).ok()
+    }
+
+    fn handle(&self, message: ClientMessage) {
+        match message {
+            ClientMessage::Key { key, pressed } if key < KEYPAD_SIZE => {
+                self.keypad.lock().unwrap().set_is_pressed(key, pressed);
+            }
+            ClientMessage::Key { .. } => (),
+            ClientMessage::Pause => self.debugger.lock().unwrap().set_paused(true),
+            ClientMessage::Resume => self.debugger.lock().unwrap().set_paused(false),
+            ClientMessage::Reset => {
+                let _ = self.commands.send(Command::Reset);
+            }
+        }
+    }
+}
+
+fn send_state(socket: &mut WebSocket<TcpStream>, machine: &Machine) -> tungstenite::Result<()> {
+    let mut snapshot = match machine.snapshot() {
+        Some(snapshot) => snapshot,
+        None => return Ok(()),
+    };
+    snapshot.memory.clear();
+
+    let state = StateMessage {
+        paused: machine.debugger.lock().unwrap().is_paused(),
+        state: &snapshot,
+    };
+
+    /* Serializing plain data can't fail */
+    socket.send(Message::Text(serde_json::to_string(&state).unwrap()))
+}
+
+/* Each client is served by its own thread, alternating between reading its messages and sending the
+ * state */
+fn handle_client(stream: TcpStream, machine: Machine) -> tungstenite::Result<()> {
+    /* The stream is still blocking, so the handshake can only fail, not be interrupted */
+    let mut socket = match tungstenite::accept(stream) {
+        Ok(socket) => socket,
+        Err(_) => return Ok(()),
+    };
+
+    let period = Duration::from_millis(STATE_PERIOD_MS);
+    socket.get_mut().set_read_timeout(Some(period))?;
+    let mut last_state = Instant::now() - period;
+
+    loop {
+        match socket.read() {
+            Ok(Message::Text(text)) => match serde_json::from_str(&text) {
+                Ok(message) => machine.handle(message),
+                Err(error) => eprintln!("Invalid message from WebSocket client: {}", error),
+            },
+            Ok(Message::Close(_)) => return Ok(()),
+            Ok(_) => (),
+            Err(tungstenite::Error::Io(error))
+                if error.kind() == io::ErrorKind::WouldBlock || error.kind() == io::ErrorKind::TimedOut => {}
+            Err(error) => return Err(error),
+        }
+
+        if last_state.elapsed() >= period {
+            send_state(&mut socket, &machine)?;
+            last_state = Instant::now();
+        }
+    }
+}
+
+/* Listen for WebSocket clients on the given port, on a separate thread */
+pub fn serve(
+    address: SocketAddr,
+    debugger: &Arc<Mutex<Debugger>>,
+    commands: &Sender<Command>,
+    keypad: &Arc<Mutex<KeyboardKeypad>>,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    let machine = Machine {
+        debugger: Arc::clone(debugger),
+        commands: commands.clone(),
+        keypad: Arc::clone(keypad),
+    };
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let machine = machine.clone();
+            thread::spawn(move || {
+                /* Errors only close the connection of the client */
+                let _ = handle_client(stream, machine);
+            });
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_messages() {
+        let key: ClientMessage = serde_json::from_str(r#"{"type": "key", "key": 10, "pressed": true}"#).unwrap();
+        assert_eq!(key, ClientMessage::Key { key: 10, pressed: true });
+
+        let reset: ClientMessage = serde_json::from_str(r#"{"type": "reset"}"#).unwrap();
+        assert_eq!(reset, ClientMessage::Reset);
+
+        assert!(serde_json::from_str::<ClientMessage>(r#"{"type": "jump"}"#).is_err());
+    }
+}
@@ -0,0 +1,210 @@
+#[cfg(feature = "native")]
+use std::sync::mpsc;
+#[cfg(feature = "native")]
+use std::thread;
+
+#[cfg(feature = "native")]
+use rodio::source::Source;
+#[cfg(feature = "native")]
+use rodio::{OutputStream, Sink};
+#[cfg(feature = "native")]
+use std::time::Duration;
+
+use std::f32::consts::PI;
+
+/* Rate of the samples sent to the audio device and written to the recordings */
+pub const SAMPLE_RATE: u32 = 44100;
+
+/* Output used by the sound timer; a new sound replaces the one playing */
+pub trait SoundBackend {
+    /* Play a sine wave with the given frequency in Hz until stop_tone is called */
+    fn start_tone(&mut self, frequency: u32);
+    fn stop_tone(&mut self);
+    /* Loop 1-bit samples, read from the MSB of each byte at the given rate in Hz, until stop_tone is
+     * called */
+    fn play_buffer(&mut self, samples: &[u8], rate: u32);
+}
+
+impl<T: SoundBackend + ?Sized> SoundBackend for Box<T> {
+    fn start_tone(&mut self, frequency: u32) {
+        (**self).start_tone(frequency);
+    }
+
+    fn stop_tone(&mut self) {
+        (**self).stop_tone();
+    }
+
+    fn play_buffer(&mut self, samples: &[u8], rate: u32) {
+        (**self).play_buffer(samples, rate);
+    }
+}
+
+/* Sound played by a backend, until it's replaced or stopped */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sound {
+    /* Sine wave with the frequency in Hz */
+    Tone(u32),
+    /* 1-bit samples, read from the MSB of each byte at the rate in Hz */
+    Buffer(Vec<u8>, u32),
+}
+
+/* Endless samples of a sound at the given rate, between -1.0 and 1.0; both the audio output and the
+ * recordings are fed from here, so that they sound the same */
+#[derive(Debug, Clone)]
+pub struct Waveform {
+    sound: Sound,
+    rate: u32,
+    position: u64,
+}
+
+impl Waveform {
+    pub fn new(sound: Sound, rate: u32) -> Waveform {
+        Waveform { sound, rate, position: 0 }
+    }
+}
+
+impl Iterator for Waveform {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let time = self.position as f64 / self.rate as f64;
+        self.position += 1;
+
+        let sample = match &self.sound {
+            Sound::Tone(frequency) => (2.0 * PI * ((time * *frequency as f64).fract() as f32)).sin(),
+            Sound::Buffer(samples, _) if samples.is_empty() => 0.0,
+            Sound::Buffer(samples, rate) => {
+                let bit = (time * *rate as f64) as usize % (samples.len() * 8);
+                if samples[bit / 8] & (0x80 >> (bit % 8)) != 0 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+        };
+
+        Some(sample)
+    }
+}
+
+#[cfg(feature = "native")]
+impl Source for Waveform {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/* Discard all the sounds, for headless use */
+pub struct NullBackend;
+
+impl SoundBackend for NullBackend {
+    fn start_tone(&mut self, _frequency: u32) {}
+
+    fn stop_tone(&mut self) {}
+
+    fn play_buffer(&mut self, _samples: &[u8], _rate: u32) {}
+}
+
+#[cfg(feature = "native")]
+enum SoundMessage {
+    Play(Sound),
+    Stop,
+}
+
+/* Plays the sounds on a dedicated thread, which owns the audio output stream */
+#[cfg(feature = "native")]
+pub struct RodioBackend {
+    sender: mpsc::Sender<SoundMessage>,
+}
+
+#[cfg(feature = "native")]
+impl RodioBackend {
+    pub fn new() -> RodioBackend {
+        let (sender, receiver) = mpsc::channel::<SoundMessage>();
+
+        thread::spawn(move || {
+            /* Create the stream handle here so that it doesn't go out of scope after playing a sound; if no
+             * audio device is available, the sounds are just discarded */
+            let output = OutputStream::try_default();
+            let mut sink: Option<Sink> = None;
+
+            for message in receiver {
+                let (_, stream_handle) = match &output {
+                    Ok(output) => output,
+                    Err(_) => continue,
+                };
+
+                /* Dropping the sink stops its sound */
+                sink = None;
+
+                let new_sink = match Sink::try_new(stream_handle) {
+                    Ok(new_sink) => new_sink,
+                    Err(_) => continue,
+                };
+                let sound = match message {
+                    SoundMessage::Play(sound) => sound,
+                    SoundMessage::Stop => continue,
+                };
+                new_sink.append(Waveform::new(sound, SAMPLE_RATE));
+                sink = Some(new_sink);
+            }
+
+            drop(sink);
+        });
+
+        RodioBackend { sender }
+    }
+}
+
+#[cfg(feature = "native")]
+impl Default for RodioBackend {
+    fn default() -> Self {
+        RodioBackend::new()
+    }
+}
+
+#[cfg(feature = "native")]
+impl SoundBackend for RodioBackend {
+    fn start_tone(&mut self, frequency: u32) {
+        let _ = self.sender.send(SoundMessage::Play(Sound::Tone(frequency)));
+    }
+
+    fn stop_tone(&mut self) {
+        let _ = self.sender.send(SoundMessage::Stop);
+    }
+
+    fn play_buffer(&mut self, samples: &[u8], rate: u32) {
+        let _ = self
+            .sender
+            .send(SoundMessage::Play(Sound::Buffer(samples.to_vec(), rate)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waveforms() {
+        /* An eighth of the period for each sample */
+        let tone: Vec<f32> = Waveform::new(Sound::Tone(1000), 8000).take(3).collect();
+        assert_eq!(tone[0], 0.0);
+        assert!(tone[1] > 0.0 && tone[2] > tone[1]);
+
+        /* Two samples for each bit, looping */
+        let buffer: Vec<f32> = Waveform::new(Sound::Buffer(vec![0xA0], 2), 4).skip(14).take(6).collect();
+        assert_eq!(buffer, [-1.0, -1.0, 1.0, 1.0, -1.0, -1.0]);
+    }
+}
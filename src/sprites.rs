@@ -0,0 +1,139 @@
+use crate::instruction::Instruction;
+
+/* Sprites placed side by side in a sheet, and the space between them */
+const SHEET_COLUMNS: usize = 8;
+const SHEET_GAP: usize = 2;
+
+/* Graphics found in a ROM, as pixels indexed by row and column like the framebuffer */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sprite {
+    pub address: u16,
+    pub pixels: Vec<Vec<u8>>,
+}
+
+impl Sprite {
+    /* Sprites are 8 pixels wide, except the 16x16 ones drawn by DXY0, using two bytes for each row */
+    fn new(rom: &[u8], start: u16, address: u16, width: usize, height: usize) -> Option<Sprite> {
+        let offset = address.checked_sub(start)? as usize;
+        let bytes = rom.get(offset..offset + height * width / 8)?;
+
+        let pixels = bytes
+            .chunks(width / 8)
+            .map(|row| {
+                row.iter()
+                    .flat_map(|byte| (0..8).map(move |bit| (byte >> (7 - bit)) & 1))
+                    .collect()
+            })
+            .collect();
+
+        Some(Sprite { address, pixels })
+    }
+
+    pub fn width(&self) -> usize {
+        self.pixels.first().map_or(0, |row| row.len())
+    }
+
+    pub fn height(&self) -> usize {
+        self.pixels.len()
+    }
+}
+
+/* Sprites drawn by the program: the ones read by a DXYN after I was set by ANNN, in the order of their
+ * addresses. The instructions are followed in the order they appear in the ROM, so the sprites whose
+ * address is computed at runtime are not found */
+pub fn find(rom: &[u8], start: u16) -> Vec<Sprite> {
+    let mut sprites: Vec<Sprite> = Vec::new();
+    let mut index = None;
+
+    for bytes in rom.chunks_exact(2) {
+        match Instruction::decode(u16::from_be_bytes([bytes[0], bytes[1]])) {
+            Some(Instruction::SetIndex(address)) => index = Some(address),
+            Some(Instruction::Display(_, _, n)) => {
+                let (width, height) = if n == 0 { (16, 16) } else { (8, n as usize) };
+                let sprite = index.and_then(|address| Sprite::new(rom, start, address, width, height));
+
+                if let Some(sprite) = sprite {
+                    /* The same graphics can be drawn with different heights: keep the largest */
+                    match sprites.iter_mut().find(|other| other.address == sprite.address) {
+                        Some(other) if other.height() < sprite.height() => *other = sprite,
+                        Some(_) => (),
+                        None => sprites.push(sprite),
+                    }
+                }
+            }
+            /* Any other instruction changing I makes its value unknown */
+            Some(Instruction::AddToIndex(_))
+            | Some(Instruction::SetIndexToFont(_))
+            | Some(Instruction::SetIndexToBigFont(_))
+            | Some(Instruction::Store(_))
+            | Some(Instruction::Load(_)) => index = None,
+            _ => (),
+        }
+    }
+
+    sprites.sort_by_key(|sprite| sprite.address);
+    sprites
+}
+
+/* Every run of the given number of bytes in the ROM, as 8 pixels wide sprites, to browse graphics which
+ * find misses */
+pub fn split(rom: &[u8], start: u16, height: usize) -> Vec<Sprite> {
+    (0..rom.len() / height)
+        .filter_map(|run| Sprite::new(rom, start, start + (run * height) as u16, 8, height))
+        .collect()
+}
+
+/* All the sprites on a grid, in rows of SHEET_COLUMNS, to be exported with dump::to_pbm */
+pub fn sheet(sprites: &[Sprite]) -> Vec<Vec<u8>> {
+    let cell_width = sprites.iter().map(Sprite::width).max().unwrap_or(0) + SHEET_GAP;
+    let cell_height = sprites.iter().map(Sprite::height).max().unwrap_or(0) + SHEET_GAP;
+    let columns = sprites.len().min(SHEET_COLUMNS);
+    let rows = (sprites.len() + SHEET_COLUMNS - 1) / SHEET_COLUMNS;
+
+    let mut pixels = vec![vec![0; columns * cell_width]; rows * cell_height];
+    for (position, sprite) in sprites.iter().enumerate() {
+        let left = (position % SHEET_COLUMNS) * cell_width;
+        let top = (position / SHEET_COLUMNS) * cell_height;
+
+        for (y, row) in sprite.pixels.iter().enumerate() {
+            pixels[top + y][left..left + row.len()].copy_from_slice(row);
+        }
+    }
+
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* Draw the sprite at 0x208 with two heights, and one whose address is unknown */
+    const ROM: [u8; 12] = [0xA2, 0x08, 0xD0, 0x12, 0xD0, 0x13, 0xF0, 0x29, 0xF0, 0x81, 0x3C, 0xD0];
+
+    #[test]
+    fn drawn_sprites() {
+        let sprites = find(&ROM, 0x200);
+
+        assert_eq!(sprites.len(), 1);
+        assert_eq!(sprites[0].address, 0x208);
+        assert_eq!(
+            sprites[0].pixels,
+            vec![
+                vec![1, 1, 1, 1, 0, 0, 0, 0],
+                vec![1, 0, 0, 0, 0, 0, 0, 1],
+                vec![0, 0, 1, 1, 1, 1, 0, 0],
+            ]
+        );
+    }
+
+    #[test]
+    fn runs_and_sheet() {
+        let sprites = split(&ROM, 0x200, 5);
+        assert_eq!(sprites.iter().map(|sprite| sprite.address).collect::<Vec<_>>(), vec![0x200, 0x205]);
+
+        let pixels = sheet(&sprites);
+        assert_eq!(pixels.len(), 5 + SHEET_GAP);
+        assert_eq!(pixels[0].len(), 2 * (8 + SHEET_GAP));
+        assert_eq!(pixels[0][8 + SHEET_GAP..16 + SHEET_GAP], sprites[1].pixels[0][..]);
+    }
+}
@@ -0,0 +1,236 @@
+use crate::debugger::Snapshot;
+
+use std::fmt;
+
+/* Memory ranges up to this length are printed with their bytes; the longer ones only with their length */
+const MAX_SHOWN_BYTES: usize = 8;
+
+/* Difference between two machine states, see diff */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Difference {
+    /* A register, the stack, a timer or the resolution, with the two values already formatted */
+    Register { name: String, first: String, second: String },
+    /* Run of consecutive addresses holding different bytes */
+    Memory { start: usize, first: Vec<u8>, second: Vec<u8> },
+    /* The memories have different sizes; only the common part is compared */
+    MemorySize { first: usize, second: usize },
+    /* Smallest rectangle around a group of touching pixels which differ */
+    Display { x: usize, y: usize, width: usize, height: usize, pixels: usize },
+    /* The displays have different sizes, so the pixels are not compared */
+    DisplaySize { first: (usize, usize), second: (usize, usize) },
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02X}", byte)).collect::<Vec<_>>().join(" ")
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Difference::Register { name, first, second } => write!(f, "{}: {} != {}", name, first, second),
+            Difference::Memory { start, first, second } => {
+                write!(f, "memory {:03X}-{:03X} ({} bytes)", start, start + first.len() - 1, first.len())?;
+                if first.len() <= MAX_SHOWN_BYTES {
+                    write!(f, ": {} != {}", hex(first), hex(second))?;
+                }
+                Ok(())
+            }
+            Difference::MemorySize { first, second } => write!(f, "memory size: {} != {} bytes", first, second),
+            Difference::Display { x, y, width, height, pixels } => {
+                write!(f, "display {}x{} at ({}, {}): {} pixels", width, height, x, y, pixels)
+            }
+            Difference::DisplaySize { first, second } => {
+                write!(f, "display size: {}x{} != {}x{}", first.0, first.1, second.0, second.1)
+            }
+        }
+    }
+}
+
+fn register<T: PartialEq>(differences: &mut Vec<Difference>, name: &str, first: T, second: T, format: fn(T) -> String) {
+    if first != second {
+        differences.push(Difference::Register {
+            name: String::from(name),
+            first: format(first),
+            second: format(second),
+        });
+    }
+}
+
+fn memory(differences: &mut Vec<Difference>, first: &[u8], second: &[u8]) {
+    if first.len() != second.len() {
+        differences.push(Difference::MemorySize { first: first.len(), second: second.len() });
+    }
+
+    let mut address = 0;
+    let len = first.len().min(second.len());
+    while address < len {
+        if first[address] == second[address] {
+            address += 1;
+            continue;
+        }
+
+        let start = address;
+        while address < len && first[address] != second[address] {
+            address += 1;
+        }
+        differences.push(Difference::Memory {
+            start,
+            first: first[start..address].to_vec(),
+            second: second[start..address].to_vec(),
+        });
+    }
+}
+
+fn dimensions(display: &[Vec<u8>]) -> (usize, usize) {
+    (display.first().map_or(0, |row| row.len()), display.len())
+}
+
+/* Groups of differing pixels touching each other, also diagonally, so that each sprite drawn in a
+ * different place gives one or two regions instead of a list of pixels */
+fn display(differences: &mut Vec<Difference>, first: &[Vec<u8>], second: &[Vec<u8>]) {
+    let (width, height) = dimensions(first);
+    if (width, height) != dimensions(second) {
+        differences.push(Difference::DisplaySize {
+            first: (width, height),
+            second: dimensions(second),
+        });
+        return;
+    }
+
+    let mut pending: Vec<Vec<bool>> = first
+        .iter()
+        .zip(second)
+        .map(|(first_row, second_row)| first_row.iter().zip(second_row).map(|(a, b)| a != b).collect())
+        .collect();
+
+    for y in 0..height {
+        for x in 0..width {
+            if !pending[y][x] {
+                continue;
+            }
+
+            pending[y][x] = false;
+            let (mut left, mut top, mut right, mut bottom) = (x, y, x, y);
+            let mut pixels = 0;
+            let mut queue = vec![(x, y)];
+            while let Some((px, py)) = queue.pop() {
+                pixels += 1;
+                left = left.min(px);
+                top = top.min(py);
+                right = right.max(px);
+                bottom = bottom.max(py);
+
+                let neighbours = (py.saturating_sub(1)..py + 2)
+                    .flat_map(|ny| (px.saturating_sub(1)..px + 2).map(move |nx| (nx, ny)));
+                for (nx, ny) in neighbours {
+                    if nx < width && ny < height && pending[ny][nx] {
+                        pending[ny][nx] = false;
+                        queue.push((nx, ny));
+                    }
+                }
+            }
+
+            differences.push(Difference::Display {
+                x: left,
+                y: top,
+                width: right - left + 1,
+                height: bottom - top + 1,
+                pixels,
+            });
+        }
+    }
+}
+
+/* Everything which differs between two states, e.g. two save states of the same game taken with
+ * different releases or quirks: the registers first, then the memory and the display */
+pub fn diff(first: &Snapshot, second: &Snapshot) -> Vec<Difference> {
+    let mut differences = Vec::new();
+
+    let address = |value: u16| format!("{:03X}", value);
+    let byte = |value: u8| format!("{:02X}", value);
+    register(&mut differences, "PC", first.pc, second.pc, address);
+    register(&mut differences, "I", first.i, second.i, address);
+    for reg in 0..first.regs.len() {
+        register(&mut differences, &format!("V{:X}", reg), first.regs[reg], second.regs[reg], byte);
+    }
+    register(&mut differences, "stack", &first.stack, &second.stack, |stack| format!("{:03X?}", stack));
+    register(&mut differences, "delay timer", first.delay_timer, second.delay_timer, |value| value.to_string());
+    register(&mut differences, "sound timer", first.sound_timer, second.sound_timer, |value| value.to_string());
+    register(&mut differences, "high resolution", first.hires, second.hires, |value| value.to_string());
+
+    memory(&mut differences, &first.memory, &second.memory);
+    display(&mut differences, &first.display, &second.display);
+
+    differences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            memory: vec![0; 16],
+            pc: 0x200,
+            i: 0,
+            regs: [0; 16],
+            stack: Vec::new(),
+            delay_timer: 0,
+            sound_timer: 0,
+            display: vec![vec![0; 8]; 4],
+            hires: false,
+        }
+    }
+
+    #[test]
+    fn differences() {
+        let first = snapshot();
+        let mut second = snapshot();
+        second.regs[0xA] = 0x12;
+        second.stack.push(0x204);
+        second.memory[2..5].copy_from_slice(&[1, 2, 3]);
+        second.memory[10] = 0xFF;
+        /* A diagonal line on the left, a single pixel on the right */
+        second.display[0][0] = 1;
+        second.display[1][1] = 1;
+        second.display[2][1] = 1;
+        second.display[3][7] = 1;
+
+        assert_eq!(diff(&first, &first), vec![]);
+
+        let lines: Vec<String> = diff(&first, &second).iter().map(Difference::to_string).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "VA: 00 != 12",
+                "stack: [] != [204]",
+                "memory 002-004 (3 bytes): 00 00 00 != 01 02 03",
+                "memory 00A-00A (1 bytes): 00 != FF",
+                "display 2x3 at (0, 0): 3 pixels",
+                "display 1x1 at (7, 3): 1 pixels",
+            ]
+        );
+    }
+
+    #[test]
+    fn different_sizes() {
+        let first = snapshot();
+        let mut second = snapshot();
+        second.memory.resize(32, 0);
+        second.display = vec![vec![0; 16]; 8];
+        second.hires = true;
+
+        assert_eq!(
+            diff(&first, &second),
+            vec![
+                Difference::Register {
+                    name: String::from("high resolution"),
+                    first: String::from("false"),
+                    second: String::from("true"),
+                },
+                Difference::MemorySize { first: 16, second: 32 },
+                Difference::DisplaySize { first: (8, 4), second: (16, 8) },
+            ]
+        );
+    }
+}
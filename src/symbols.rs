@@ -0,0 +1,122 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/* Names of the addresses of a program, like the labels of its Octo source, shown by the disassembler,
+ * the tracer and the debugger in place of the raw addresses */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Symbols {
+    labels: BTreeMap<u16, String>,
+}
+
+/* Addresses are hexadecimal with the "0x" prefix, or decimal, as in Octo */
+fn parse_address(token: &str) -> Option<u16> {
+    match token.strip_prefix("0x").or_else(|| token.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => token.parse().ok(),
+    }
+}
+
+impl Symbols {
+    pub fn new() -> Symbols {
+        Symbols::default()
+    }
+
+    /* Parse a symbol file: a label on each line, followed by its address, like "main 0x202"; the name
+     * can start with ":" as in the Octo label definitions, and comments start with "#" */
+    pub fn parse(text: &str) -> Result<Symbols, String> {
+        let mut symbols = Symbols::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let code = line.split('#').next().unwrap_or("").trim();
+            let tokens: Vec<&str> = code.split_whitespace().filter(|&token| token != ":").collect();
+
+            match *tokens.as_slice() {
+                [] => (),
+                [name, address] => {
+                    let name = name.trim_start_matches(':');
+                    let address = parse_address(address)
+                        .ok_or_else(|| format!("line {}: invalid address: {}", index + 1, address))?;
+                    symbols.insert(name, address);
+                }
+                _ => return Err(format!("line {}: expected a label and its address", index + 1)),
+            }
+        }
+
+        Ok(symbols)
+    }
+
+    /* An address keeps the first name it was given */
+    pub fn insert(&mut self, name: &str, address: u16) {
+        self.labels.entry(address).or_insert_with(|| name.to_string());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.labels.get(&address).map(String::as_str)
+    }
+
+    pub fn address(&self, name: &str) -> Option<u16> {
+        self.labels.iter().find(|(_, label)| label.as_str() == name).map(|(&address, _)| address)
+    }
+
+    /* Address relative to the closest label before it, like "main+0x4", to show where an instruction
+     * is inside a subroutine */
+    pub fn locate(&self, address: u16) -> Option<String> {
+        let (&base, name) = self.labels.range(..=address).next_back()?;
+
+        if base == address {
+            Some(name.clone())
+        } else {
+            Some(format!("{}+0x{:X}", name, address - base))
+        }
+    }
+
+    /* Replace the words like "@main" with the address of the label, in hexadecimal, so that the
+     * debugger commands taking an address accept a label too */
+    pub fn expand(&self, line: &str) -> Result<String, String> {
+        let words = line
+            .split_whitespace()
+            .map(|word| match word.strip_prefix('@') {
+                Some(name) => match self.address(name) {
+                    Some(address) => Ok(format!("{:03X}", address)),
+                    None => Err(format!("Unknown label: {}", name)),
+                },
+                None => Ok(word.to_string()),
+            })
+            .collect::<Result<Vec<String>, String>>()?;
+
+        Ok(words.join(" "))
+    }
+}
+
+pub fn load(path: &Path) -> io::Result<Symbols> {
+    Symbols::parse(&fs::read_to_string(path)?).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_lookup() {
+        let symbols = Symbols::parse("# labels\n: main 0x200\ndraw 0x20A # sprite\n:loop 530\nagain 0x200\n").unwrap();
+
+        assert_eq!(symbols.label(0x200), Some("main"));
+        assert_eq!(symbols.address("loop"), Some(0x212));
+        assert_eq!(symbols.address("again"), None);
+        assert_eq!(symbols.locate(0x1FE), None);
+        assert_eq!(symbols.locate(0x20A).as_deref(), Some("draw"));
+        assert_eq!(symbols.locate(0x20E).as_deref(), Some("draw+0x4"));
+
+        assert_eq!(symbols.expand("break @loop").unwrap(), "break 212");
+        assert_eq!(symbols.expand("mem @nowhere 16"), Err(String::from("Unknown label: nowhere")));
+
+        assert!(Symbols::parse("main").is_err());
+        assert!(Symbols::parse("main 0xZZ").is_err());
+    }
+}
@@ -1,106 +1,174 @@
-use std::sync::{Arc, Mutex};
-use std::{thread, time};
-
-use rodio::source::{SineWave, Source};
-use rodio::OutputStream;
+#[cfg(not(feature = "native"))]
+use crate::sound::NullBackend;
+#[cfg(feature = "native")]
+use crate::sound::RodioBackend;
+use crate::sound::SoundBackend;
 
+/* Timers are decremented by one at each tick; the owner is in charge of ticking them at 60Hz */
 pub trait Timer<T> {
-    fn get_timer_value(&mut self) -> T;
+    fn get_timer_value(&self) -> T;
     fn set_timer_value(&mut self, value: T);
-    fn start(&mut self, freq: f32);
+    fn tick(&mut self);
 }
 
 pub struct DelayTimer {
-    timer: Arc<Mutex<u8>>,
+    timer: u8,
 }
 
 impl DelayTimer {
     pub fn new() -> DelayTimer {
-        DelayTimer {
-            timer: Arc::new(Mutex::new(0)),
-        }
+        DelayTimer { timer: 0 }
+    }
+}
+
+impl Default for DelayTimer {
+    fn default() -> Self {
+        DelayTimer::new()
     }
 }
 
 impl Timer<u8> for DelayTimer {
-    fn get_timer_value(&mut self) -> u8 {
-        let timer = self.timer.lock().unwrap();
-        *timer
+    fn get_timer_value(&self) -> u8 {
+        self.timer
     }
 
     fn set_timer_value(&mut self, value: u8) {
-        let mut timer = self.timer.lock().unwrap();
-        *timer = value;
+        self.timer = value;
     }
 
-    fn start(&mut self, freq: f32) {
-        let timer = self.timer.clone();
-
-        thread::spawn(move || {
-            loop {
-                let period = time::Duration::from_secs_f32(1.0 / freq);
-                thread::sleep(period);
-                let mut timer = timer.lock().unwrap();
-                if *timer > 0 {
-                    *timer -= 1;
-                }
-            }
-        });
+    fn tick(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
     }
 }
 
-pub struct SoundTimer {
-    timer: Arc<Mutex<u8>>,
+/* Frequency of the tone played while the sound timer is active */
+const TONE_FREQUENCY: u32 = 440;
+
+pub struct SoundTimer<B: SoundBackend = Box<dyn SoundBackend + Send>> {
+    timer: u8,
+    /* Set while the backend plays the tone */
+    playing: bool,
+    backend: B,
 }
 
 impl SoundTimer {
+    /* Sound timer playing on the default audio device, or silent without the native frontend */
     pub fn new() -> SoundTimer {
+        #[cfg(feature = "native")]
+        let backend: Box<dyn SoundBackend + Send> = Box::new(RodioBackend::new());
+        #[cfg(not(feature = "native"))]
+        let backend: Box<dyn SoundBackend + Send> = Box::new(NullBackend);
+
+        SoundTimer::with_backend(backend)
+    }
+}
+
+impl<B: SoundBackend> SoundTimer<B> {
+    pub fn with_backend(backend: B) -> SoundTimer<B> {
         SoundTimer {
-            timer: Arc::new(Mutex::new(0)),
+            timer: 0,
+            playing: false,
+            backend,
+        }
+    }
+
+    /* Replace the backend, stopping the sound of the previous one */
+    pub fn set_backend(&mut self, backend: B) {
+        if self.playing {
+            self.backend.stop_tone();
+            self.playing = false;
         }
+        self.backend = backend;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.timer > 0
     }
 }
 
-impl Timer<u8> for SoundTimer {
-    fn get_timer_value(&mut self) -> u8 {
-        let timer = self.timer.lock().unwrap();
-        *timer
+impl Default for SoundTimer {
+    fn default() -> Self {
+        SoundTimer::new()
+    }
+}
+
+impl<B: SoundBackend> Timer<u8> for SoundTimer<B> {
+    fn get_timer_value(&self) -> u8 {
+        self.timer
     }
 
     fn set_timer_value(&mut self, value: u8) {
-        let mut timer = self.timer.lock().unwrap();
-        *timer = value;
-    }
-
-    fn start(&mut self, freq: f32) {
-        let timer = self.timer.clone();
-
-        thread::spawn(move || {
-            /* Create the stream handle here so that it doesn't go out of scope after playing a sound */
-            let (_stream, stream_handle) = OutputStream::try_default().unwrap();
-            /* Save the value with which the timer was loaded; play a tune only when is loaded with a higher value */
-            let mut playing_timer = 0;
-
-            loop {
-                let period = time::Duration::from_secs_f32(1.0 / freq);
-                thread::sleep(period);
-                let mut timer = timer.lock().unwrap();
-
-                if *timer > 0 && *timer > playing_timer {
-                    playing_timer = *timer;
-                    let source = SineWave::new(440)
-                        .take_duration(time::Duration::from_millis((playing_timer as u64) * 16))
-                        .amplify(1.0);
-                    stream_handle.play_raw(source).unwrap();
-                }
-
-                if *timer > 0 {
-                    *timer -= 1;
-                } else if *timer == 0 {
-                    playing_timer = 0;
-                }
+        self.timer = value;
+    }
+
+    /* The tone starts at the first tick with the timer set, and stops at the first one after it
+     * reached 0, so that it lasts as many ticks as the timer value */
+    fn tick(&mut self) {
+        if self.timer == 0 {
+            if self.playing {
+                self.backend.stop_tone();
+                self.playing = false;
             }
-        });
+            return;
+        }
+
+        if !self.playing {
+            self.backend.start_tone(TONE_FREQUENCY);
+            self.playing = true;
+        }
+        self.timer -= 1;
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* Records the calls as strings */
+    #[derive(Default)]
+    struct RecordingBackend {
+        calls: Vec<String>,
+    }
+
+    impl SoundBackend for RecordingBackend {
+        fn start_tone(&mut self, frequency: u32) {
+            self.calls.push(format!("start {}", frequency));
+        }
+
+        fn stop_tone(&mut self) {
+            self.calls.push(String::from("stop"));
+        }
+
+        fn play_buffer(&mut self, samples: &[u8], rate: u32) {
+            self.calls.push(format!("buffer {} {}", samples.len(), rate));
+        }
+    }
+
+    #[test]
+    fn tone_lasts_the_timer_value() {
+        let mut timer = SoundTimer::with_backend(RecordingBackend::default());
+        timer.set_timer_value(2);
+
+        timer.tick();
+        timer.tick();
+        assert_eq!(timer.backend.calls, ["start 440"]);
+
+        timer.tick();
+        timer.tick();
+        assert_eq!(timer.backend.calls, ["start 440", "stop"]);
+    }
+
+    #[test]
+    fn reloading_keeps_the_tone() {
+        let mut timer = SoundTimer::with_backend(RecordingBackend::default());
+        timer.set_timer_value(1);
+        timer.tick();
+        timer.set_timer_value(1);
+        timer.tick();
+        timer.tick();
+
+        assert_eq!(timer.backend.calls, ["start 440", "stop"]);
+    }
+}
@@ -0,0 +1,84 @@
+use crate::symbols::Symbols;
+
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/* Names of the columns, aligned with the values of each line */
+pub const HEADER: &str = "PC   OP   V0 V1 V2 V3 V4 V5 V6 V7 V8 V9 VA VB VC VD VE VF I    SP";
+
+/* State of the CPU before executing an instruction, in hex columns like the traces printed by other
+ * emulators, so that two runs can be compared with diff */
+pub fn format_line(pc: u16, opcode: u16, regs: &[u8; 16], i: u16, sp: usize) -> String {
+    let mut line = format!("{:04X} {:04X}", pc, opcode);
+    for reg in regs {
+        line.push_str(&format!(" {:02X}", reg));
+    }
+    line.push_str(&format!(" {:04X} {:X}", i, sp));
+
+    line
+}
+
+/* Writes a line for each executed instruction, after the header */
+pub struct Tracer {
+    writer: Box<dyn Write + Send>,
+    /* With symbols, each line ends with the location of the instruction, see Symbols::locate */
+    symbols: Symbols,
+}
+
+impl Tracer {
+    pub fn new(mut writer: Box<dyn Write + Send>) -> io::Result<Tracer> {
+        writeln!(writer, "{}", HEADER)?;
+        Ok(Tracer {
+            writer,
+            symbols: Symbols::new(),
+        })
+    }
+
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    /* Trace to a file, or to the standard output if the path is "-" */
+    pub fn create(path: &Path) -> io::Result<Tracer> {
+        if path == Path::new("-") {
+            Tracer::new(Box::new(io::stdout()))
+        } else {
+            Tracer::new(Box::new(BufWriter::new(File::create(path)?)))
+        }
+    }
+
+    pub fn record(&mut self, pc: u16, opcode: u16, regs: &[u8; 16], i: u16, sp: usize) -> io::Result<()> {
+        let line = format_line(pc, opcode, regs, i, sp);
+        match self.symbols.locate(pc) {
+            Some(location) => writeln!(self.writer, "{} {}", line, location),
+            None => writeln!(self.writer, "{}", line),
+        }
+    }
+}
+
+impl Drop for Tracer {
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn columns() {
+        let mut regs = [0; 16];
+        regs[0x0] = 0x05;
+        regs[0xF] = 0x01;
+
+        let line = format_line(0x200, 0xD015, &regs, 0x2A0, 2);
+        assert_eq!(
+            line,
+            "0200 D015 05 00 00 00 00 00 00 00 00 00 00 00 00 00 00 01 02A0 2"
+        );
+        assert_eq!(line.len(), HEADER.len() - 1);
+    }
+}
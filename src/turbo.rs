@@ -0,0 +1,85 @@
+use std::time::{Duration, Instant};
+
+/* Presses per second of the turbo keys by default */
+pub const TURBO_RATE: f64 = 10.0;
+
+/* Keypad keys repeating on their own while held, like the turbo buttons of a gamepad; the other keys
+ * are left alone. update must be called regularly, e.g. on every frame, to toggle them */
+#[derive(Debug, Clone)]
+pub struct Turbo {
+    keys: Vec<usize>,
+    /* Time a key stays pressed, and then released */
+    half_period: Duration,
+    /* Keys held by the player, with the time they were pressed */
+    held: Vec<(usize, Instant)>,
+    /* Keys pressed at the last update */
+    pressed: Vec<usize>,
+}
+
+impl Turbo {
+    pub fn new(keys: &[usize], rate: f64) -> Turbo {
+        Turbo {
+            keys: keys.to_vec(),
+            half_period: Duration::from_secs_f64(0.5 / rate),
+            held: Vec::new(),
+            pressed: Vec::new(),
+        }
+    }
+
+    pub fn is_turbo(&self, key: usize) -> bool {
+        self.keys.contains(&key)
+    }
+
+    /* Called when the player presses or releases a turbo key */
+    pub fn set_held(&mut self, key: usize, held: bool, now: Instant) {
+        self.held.retain(|&(held_key, _)| held_key != key);
+        if held {
+            self.held.push((key, now));
+        }
+    }
+
+    /* Keys to press or release since the last update, as (key, pressed); a key is pressed as soon as
+     * it's held */
+    pub fn update(&mut self, now: Instant) -> Vec<(usize, bool)> {
+        let half_period = self.half_period.as_secs_f64();
+        let pressed: Vec<usize> = self
+            .held
+            .iter()
+            .filter(|(_, start)| (now.saturating_duration_since(*start).as_secs_f64() / half_period) as u64 % 2 == 0)
+            .map(|&(key, _)| key)
+            .collect();
+
+        let mut changes: Vec<(usize, bool)> = self
+            .pressed
+            .iter()
+            .filter(|key| !pressed.contains(key))
+            .map(|&key| (key, false))
+            .collect();
+        changes.extend(pressed.iter().filter(|key| !self.pressed.contains(key)).map(|&key| (key, true)));
+        self.pressed = pressed;
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeat() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut turbo = Turbo::new(&[5], 10.0);
+        assert!(turbo.is_turbo(5) && !turbo.is_turbo(6));
+
+        turbo.set_held(5, true, at(0));
+        assert_eq!(turbo.update(at(10)), vec![(5, true)]);
+        assert_eq!(turbo.update(at(40)), vec![]);
+        assert_eq!(turbo.update(at(60)), vec![(5, false)]);
+        assert_eq!(turbo.update(at(110)), vec![(5, true)]);
+
+        turbo.set_held(5, false, at(120));
+        assert_eq!(turbo.update(at(130)), vec![(5, false)]);
+        assert_eq!(turbo.update(at(200)), vec![]);
+    }
+}
@@ -0,0 +1,223 @@
+use crate::bench::INSTRUCTIONS_PER_TICK;
+use crate::chip8::Chip8;
+use crate::debugger::{Edit, Snapshot};
+use crate::instruction::Instruction;
+use crate::platform::MachineConfig;
+use crate::reference::Reference;
+use crate::sound::NullBackend;
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/* Memory differences listed in a report; the others are only counted */
+const MAX_MEMORY_DIFFERENCES: usize = 8;
+
+/* Machine the core is compared with */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Against {
+    /* The plain interpreter in the reference module */
+    Reference,
+    /* The core itself, with another configuration, e.g. different quirks */
+    Config(MachineConfig),
+}
+
+/* First point where the two machines were found in different states */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /* Instructions executed by each machine */
+    pub instructions: u64,
+    /* Address of the last instruction executed by the core */
+    pub pc: u16,
+    pub differences: Vec<String>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Diverged after {} instructions, the last one at {:03X}:",
+            self.instructions, self.pc
+        )?;
+        for difference in &self.differences {
+            writeln!(f, "  {}", difference)?;
+        }
+
+        Ok(())
+    }
+}
+
+/* Differences between the state of the core and the one of the other machine */
+pub fn compare(core: &Snapshot, other: &Snapshot) -> Vec<String> {
+    let mut differences = Vec::new();
+
+    if core.pc != other.pc {
+        differences.push(format!("PC: {:03X} != {:03X}", core.pc, other.pc));
+    }
+    if core.i != other.i {
+        differences.push(format!("I: {:03X} != {:03X}", core.i, other.i));
+    }
+    for reg in 0..core.regs.len() {
+        if core.regs[reg] != other.regs[reg] {
+            differences.push(format!("V{:X}: {:02X} != {:02X}", reg, core.regs[reg], other.regs[reg]));
+        }
+    }
+    if core.stack != other.stack {
+        differences.push(format!("stack: {:03X?} != {:03X?}", core.stack, other.stack));
+    }
+    if core.delay_timer != other.delay_timer {
+        differences.push(format!("delay timer: {} != {}", core.delay_timer, other.delay_timer));
+    }
+    if core.sound_timer != other.sound_timer {
+        differences.push(format!("sound timer: {} != {}", core.sound_timer, other.sound_timer));
+    }
+
+    let memory: Vec<usize> = (0..core.memory.len().min(other.memory.len()))
+        .filter(|&address| core.memory[address] != other.memory[address])
+        .collect();
+    for &address in memory.iter().take(MAX_MEMORY_DIFFERENCES) {
+        differences.push(format!(
+            "memory at {:03X}: {:02X} != {:02X}",
+            address, core.memory[address], other.memory[address]
+        ));
+    }
+    if memory.len() > MAX_MEMORY_DIFFERENCES {
+        differences.push(format!("{} more memory differences", memory.len() - MAX_MEMORY_DIFFERENCES));
+    }
+
+    let pixels = core
+        .display
+        .iter()
+        .zip(&other.display)
+        .map(|(core_row, other_row)| core_row.iter().zip(other_row).filter(|(a, b)| a != b).count())
+        .sum::<usize>();
+    if pixels > 0 {
+        differences.push(format!("display: {} pixels differ", pixels));
+    }
+
+    differences
+}
+
+fn headless_core(config: MachineConfig, rom: &[u8]) -> Result<Chip8, String> {
+    let mut chip = Chip8::builder().config(config).sound_backend(NullBackend).build();
+    chip.load_program(rom).map_err(|error| error.to_string())?;
+    Ok(chip)
+}
+
+enum Other {
+    Reference(Reference),
+    Core(Chip8),
+}
+
+impl Other {
+    fn step(&mut self) -> Result<(), String> {
+        match self {
+            Other::Reference(reference) => reference.step(),
+            Other::Core(chip) => chip.step().map_err(|error| error.to_string()),
+        }
+    }
+
+    fn tick_timers(&mut self) {
+        match self {
+            Other::Reference(reference) => reference.tick_timers(),
+            Other::Core(chip) => chip.tick_timers(),
+        }
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        match self {
+            Other::Reference(reference) => reference.state.clone(),
+            Other::Core(chip) => chip.snapshot(),
+        }
+    }
+
+    /* Random numbers can't be the same, so the ones drawn by the core are copied */
+    fn set_register(&mut self, reg: u8, value: u8) {
+        match self {
+            Other::Reference(reference) => reference.state.regs[reg as usize] = value,
+            Other::Core(chip) => chip.apply_edit(&Edit::Register(reg, value)),
+        }
+    }
+}
+
+/* Run the ROM on the core and on the other machine side by side, comparing their states every given
+ * number of instructions; returns the first divergence, if any, or an error if a machine cannot
+ * continue. The comparison stops early when the core exits */
+pub fn verify(
+    config: MachineConfig,
+    rom: &[u8],
+    against: Against,
+    cycles: u64,
+    every: u64,
+) -> Result<Option<Divergence>, String> {
+    let mut core = headless_core(config, rom)?;
+    let mut other = match against {
+        Against::Reference => Other::Reference(Reference::new(&config, core.snapshot())?),
+        Against::Config(other_config) => Other::Core(headless_core(other_config, rom)?),
+    };
+
+    let last = Arc::new(Mutex::new(None));
+    let recorder = Arc::clone(&last);
+    core.on_instruction(move |pc, instr| *recorder.lock().unwrap() = Some((pc, *instr)));
+
+    for cycle in 0..cycles {
+        if core.has_exited() {
+            break;
+        }
+
+        core.step().map_err(|error| format!("core: {}", error))?;
+        other.step().map_err(|error| format!("other machine: {}", error))?;
+
+        let (pc, instr) = last.lock().unwrap().unwrap();
+        if let Instruction::Random(reg, _) = instr {
+            other.set_register(reg, core.snapshot().regs[reg as usize]);
+        }
+
+        if cycle % INSTRUCTIONS_PER_TICK == 0 {
+            core.tick_timers();
+            other.tick_timers();
+        }
+
+        let instructions = cycle + 1;
+        if instructions % every == 0 || instructions == cycles {
+            let differences = compare(&core.snapshot(), &other.snapshot());
+            if !differences.is_empty() {
+                return Ok(Some(Divergence {
+                    instructions,
+                    pc,
+                    differences,
+                }));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::Platform;
+
+    /* Count V0 up, draw the digit and store its BCD representation, forever */
+    const ROM: [u8; 14] = [
+        0x70, 0x01, 0xF0, 0x29, 0xD1, 0x25, 0xA3, 0x00, 0xF0, 0x33, 0xF2, 0x65, 0x12, 0x00,
+    ];
+
+    #[test]
+    fn core_matches_reference() {
+        let config = Platform::Chip8.config();
+        assert_eq!(verify(config, &ROM, Against::Reference, 5000, 1), Ok(None));
+    }
+
+    #[test]
+    fn quirks_diverge() {
+        let config = Platform::Chip8.config();
+        let mut quirky = config;
+        quirky.quirks.load_store_increment = true;
+
+        let divergence = verify(config, &ROM, Against::Config(quirky), 5000, 1).unwrap().unwrap();
+        assert_eq!(divergence.instructions, 6);
+        assert_eq!(divergence.pc, 0x20A);
+        assert_eq!(divergence.differences, vec![String::from("I: 300 != 303")]);
+    }
+}
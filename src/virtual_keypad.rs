@@ -0,0 +1,53 @@
+use crate::keypad::LAYOUT;
+
+/* Geometry of a 4x4 keypad drawn in a rectangular area of the window */
+pub struct VirtualKeypad {
+    x: f64,
+    y: f64,
+    key_width: f64,
+    key_height: f64,
+}
+
+impl VirtualKeypad {
+    pub fn new(x: f64, y: f64, width: f64, height: f64) -> VirtualKeypad {
+        VirtualKeypad {
+            x,
+            y,
+            key_width: width / 4.0,
+            key_height: height / 4.0,
+        }
+    }
+
+    /* Rectangle of each key as (code, [x, y, width, height]) */
+    pub fn keys(&self) -> Vec<(usize, [f64; 4])> {
+        let mut keys = Vec::new();
+
+        for (row, codes) in LAYOUT.iter().enumerate() {
+            for (column, code) in codes.iter().enumerate() {
+                keys.push((
+                    *code,
+                    [
+                        self.x + (column as f64) * self.key_width,
+                        self.y + (row as f64) * self.key_height,
+                        self.key_width,
+                        self.key_height,
+                    ],
+                ));
+            }
+        }
+
+        keys
+    }
+
+    /* Code of the key under the given window position, if any */
+    pub fn key_at(&self, x: f64, y: f64) -> Option<usize> {
+        if x < self.x || y < self.y {
+            return None;
+        }
+
+        let column = ((x - self.x) / self.key_width) as usize;
+        let row = ((y - self.y) / self.key_height) as usize;
+
+        LAYOUT.get(row).and_then(|codes| codes.get(column)).cloned()
+    }
+}
@@ -0,0 +1,187 @@
+use crate::sound::{Sound, SoundBackend, Waveform, SAMPLE_RATE};
+
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/* Size of the RIFF header and of the fmt chunk, before the samples */
+const HEADER_SIZE: u32 = 44;
+
+/* 16-bit PCM mono WAV file; the sizes in the header are written by finish, once the samples are known */
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    samples: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut writer: W, rate: u32) -> io::Result<WavWriter<W>> {
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(b"WAVEfmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        /* PCM, one channel */
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&rate.to_le_bytes())?;
+        writer.write_all(&(rate * 2).to_le_bytes())?;
+        /* Bytes per frame, bits per sample */
+        writer.write_all(&2u16.to_le_bytes())?;
+        writer.write_all(&16u16.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+
+        Ok(WavWriter { writer, samples: 0 })
+    }
+
+    /* The sample is clamped between -1.0 and 1.0 */
+    pub fn write_sample(&mut self, sample: f32) -> io::Result<()> {
+        let value = (sample.max(-1.0).min(1.0) * i16::MAX as f32) as i16;
+        self.writer.write_all(&value.to_le_bytes())?;
+        self.samples += 1;
+        Ok(())
+    }
+
+    /* Fill in the sizes and return the writer */
+    pub fn finish(mut self) -> io::Result<W> {
+        let data_size = self.samples * 2;
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&(HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(HEADER_SIZE as u64 - 4))?;
+        self.writer.write_all(&data_size.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/* Samples of the sounds played over time: silence until a sound starts, then its waveform until it's
+ * replaced or stopped; the times are measured from the start of the recording */
+pub struct WavRecorder<W: Write + Seek> {
+    writer: WavWriter<W>,
+    waveform: Option<Waveform>,
+    samples: u64,
+}
+
+impl<W: Write + Seek> WavRecorder<W> {
+    pub fn new(writer: W) -> io::Result<WavRecorder<W>> {
+        Ok(WavRecorder {
+            writer: WavWriter::new(writer, SAMPLE_RATE)?,
+            waveform: None,
+            samples: 0,
+        })
+    }
+
+    /* Write the samples of the current sound up to the given time */
+    fn advance(&mut self, elapsed: Duration) -> io::Result<()> {
+        let target = (elapsed.as_nanos() * SAMPLE_RATE as u128 / 1_000_000_000) as u64;
+
+        while self.samples < target {
+            let sample = self.waveform.as_mut().and_then(Iterator::next).unwrap_or(0.0);
+            self.writer.write_sample(sample)?;
+            self.samples += 1;
+        }
+
+        Ok(())
+    }
+
+    /* Switch to a new sound, or to silence with None, at the given time */
+    pub fn play(&mut self, elapsed: Duration, sound: Option<Sound>) -> io::Result<()> {
+        self.advance(elapsed)?;
+        self.waveform = sound.map(|sound| Waveform::new(sound, SAMPLE_RATE));
+        Ok(())
+    }
+
+    pub fn finish(mut self, elapsed: Duration) -> io::Result<W> {
+        self.advance(elapsed)?;
+        self.writer.finish()
+    }
+}
+
+/* Backend playing the sounds on another backend and recording them to a WAV file, which is completed
+ * when the backend is dropped */
+pub struct AudioRecorder {
+    inner: Box<dyn SoundBackend + Send>,
+    recorder: Option<WavRecorder<BufWriter<File>>>,
+    start: Instant,
+}
+
+impl AudioRecorder {
+    pub fn create(path: &Path, inner: Box<dyn SoundBackend + Send>) -> io::Result<AudioRecorder> {
+        Ok(AudioRecorder {
+            inner,
+            recorder: Some(WavRecorder::new(BufWriter::new(File::create(path)?))?),
+            start: Instant::now(),
+        })
+    }
+
+    /* Stop recording after a write error, e.g. when the disk is full, without stopping the sound */
+    fn record(&mut self, sound: Option<Sound>) {
+        let elapsed = self.start.elapsed();
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(error) = recorder.play(elapsed, sound) {
+                eprintln!("Error recording the audio: {}", error);
+                self.recorder = None;
+            }
+        }
+    }
+}
+
+impl SoundBackend for AudioRecorder {
+    fn start_tone(&mut self, frequency: u32) {
+        self.inner.start_tone(frequency);
+        self.record(Some(Sound::Tone(frequency)));
+    }
+
+    fn stop_tone(&mut self) {
+        self.inner.stop_tone();
+        self.record(None);
+    }
+
+    fn play_buffer(&mut self, samples: &[u8], rate: u32) {
+        self.inner.play_buffer(samples, rate);
+        self.record(Some(Sound::Buffer(samples.to_vec(), rate)));
+    }
+}
+
+impl Drop for AudioRecorder {
+    fn drop(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            if let Err(error) = recorder.finish(self.start.elapsed()) {
+                eprintln!("Error recording the audio: {}", error);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn sample(wav: &[u8], index: usize) -> i16 {
+        let offset = HEADER_SIZE as usize + index * 2;
+        i16::from_le_bytes([wav[offset], wav[offset + 1]])
+    }
+
+    #[test]
+    fn recording() {
+        let mut recorder = WavRecorder::new(Cursor::new(Vec::new())).unwrap();
+        /* 10ms of silence, 10ms of a square wave, 10ms of silence */
+        recorder.play(Duration::from_millis(10), Some(Sound::Buffer(vec![0xF0], 100))).unwrap();
+        recorder.play(Duration::from_millis(20), None).unwrap();
+        let wav = recorder.finish(Duration::from_millis(30)).unwrap().into_inner();
+
+        let samples = SAMPLE_RATE as usize * 3 / 100;
+        assert_eq!(wav.len(), HEADER_SIZE as usize + samples * 2);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes([wav[4], wav[5], wav[6], wav[7]]) as usize, wav.len() - 8);
+        assert_eq!(u32::from_le_bytes([wav[24], wav[25], wav[26], wav[27]]), SAMPLE_RATE);
+        assert_eq!(u32::from_le_bytes([wav[40], wav[41], wav[42], wav[43]]) as usize, samples * 2);
+
+        assert_eq!(sample(&wav, samples / 3 - 1), 0);
+        assert_eq!(sample(&wav, samples / 3), i16::MAX);
+        assert_eq!(sample(&wav, samples * 2 / 3 - 1), i16::MAX);
+        assert_eq!(sample(&wav, samples * 2 / 3), 0);
+    }
+}
@@ -0,0 +1,106 @@
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+
+use wasm_bindgen::prelude::*;
+
+use crate::chip8::Chip8;
+use crate::display::*;
+use crate::keypad::*;
+use crate::platform::MachineConfig;
+
+const KEYPAD_SIZE: usize = 0x10;
+
+/* Emulator driven by the browser: the page calls frame at 60Hz, draws the framebuffer on a canvas and
+ * turns the beep on and off with WebAudio */
+#[wasm_bindgen]
+pub struct WebEmulator {
+    chip: Chip8,
+    display: LedsDisplay,
+    draw_commands: Receiver<DrawCommand>,
+    keypad: Arc<Mutex<KeyboardKeypad>>,
+}
+
+#[wasm_bindgen]
+impl WebEmulator {
+    #[wasm_bindgen(constructor)]
+    pub fn new(rom: &[u8]) -> Result<WebEmulator, JsValue> {
+        let (draw_sender, draw_commands) = mpsc::channel();
+        let keypad = Arc::new(Mutex::new(KeyboardKeypad::new(KEYPAD_SIZE)));
+        let config = MachineConfig::default();
+
+        let mut chip = Chip8::builder().config(config).keypad(&keypad).draw_commands(draw_sender).build();
+        chip.load_program(rom).map_err(|error| JsValue::from_str(&error.to_string()))?;
+
+        Ok(WebEmulator {
+            chip,
+            display: LedsDisplay::new(config.display_width, config.display_height, false),
+            draw_commands,
+            keypad,
+        })
+    }
+
+    /* Execute one 60Hz frame: the given number of instructions and a timer tick */
+    pub fn frame(&mut self, instructions: u32) -> Result<(), JsValue> {
+        for _ in 0..instructions {
+            if self.chip.has_exited() {
+                break;
+            }
+            self.chip
+                .step()
+                .map_err(|error| JsValue::from_str(&error.to_string()))?;
+        }
+
+        self.chip.tick_timers();
+
+        while let Ok(command) = self.draw_commands.try_recv() {
+            command.apply(&mut self.display);
+        }
+
+        Ok(())
+    }
+
+    pub fn width(&self) -> usize {
+        self.display.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.display.height()
+    }
+
+    /* One byte per LED, row by row, holding its palette index; 0 if the LED is off */
+    pub fn framebuffer(&self) -> Vec<u8> {
+        let mut framebuffer = Vec::with_capacity(self.width() * self.height());
+
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                framebuffer.push(self.display.pixel(x, y));
+            }
+        }
+
+        framebuffer
+    }
+
+    pub fn key_down(&mut self, code: usize) {
+        self.set_key(code, true);
+    }
+
+    pub fn key_up(&mut self, code: usize) {
+        self.set_key(code, false);
+    }
+
+    /* True once the program stopped itself with 00FD */
+    pub fn exited(&self) -> bool {
+        self.chip.has_exited()
+    }
+
+    pub fn sound_active(&self) -> bool {
+        self.chip.is_sound_playing()
+    }
+
+    fn set_key(&mut self, code: usize, is_pressed: bool) {
+        if code < KEYPAD_SIZE {
+            self.keypad.lock().unwrap().set_is_pressed(code, is_pressed);
+        }
+    }
+}
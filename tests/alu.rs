@@ -0,0 +1,76 @@
+mod common;
+
+use common::*;
+use quickcheck::quickcheck;
+
+/* Load x in V0 and y in V1, then execute the given instruction */
+fn alu(instruction: [u8; 2], x: u8, y: u8) -> (u8, u8) {
+    let snapshot = run(&[0x60, x, 0x61, y, instruction[0], instruction[1]], 3);
+    (snapshot.regs[0x0], snapshot.regs[0xF])
+}
+
+/* Load x in V0, then execute the given instruction using V0 as both operands */
+fn alu_same_register(instruction: [u8; 2], x: u8) -> (u8, u8) {
+    let snapshot = run(&[0x60, x, instruction[0], instruction[1]], 2);
+    (snapshot.regs[0x0], snapshot.regs[0xF])
+}
+
+/* Reference model: (result, VF) */
+fn add(x: u8, y: u8) -> (u8, u8) {
+    let sum = (x as u16) + (y as u16);
+    ((sum & 0xFF) as u8, (sum > 0xFF) as u8)
+}
+
+fn sub(x: u8, y: u8) -> (u8, u8) {
+    let difference = (x as i16) - (y as i16);
+    ((difference & 0xFF) as u8, (difference >= 0) as u8)
+}
+
+quickcheck! {
+    fn add_matches_reference(x: u8, y: u8) -> bool {
+        alu([0x80, 0x14], x, y) == add(x, y)
+    }
+
+    fn add_same_register(x: u8) -> bool {
+        alu_same_register([0x80, 0x04], x) == add(x, x)
+    }
+
+    fn sub_matches_reference(x: u8, y: u8) -> bool {
+        alu([0x80, 0x15], x, y) == sub(x, y)
+    }
+
+    fn sub_same_register(x: u8) -> bool {
+        alu_same_register([0x80, 0x05], x) == (0, 1)
+    }
+
+    fn sub_inv_matches_reference(x: u8, y: u8) -> bool {
+        alu([0x80, 0x17], x, y) == sub(y, x)
+    }
+
+    fn sub_inv_same_register(x: u8) -> bool {
+        alu_same_register([0x80, 0x07], x) == (0, 1)
+    }
+
+    fn right_shift_matches_reference(x: u8) -> bool {
+        alu_same_register([0x80, 0x06], x) == (x >> 1, x & 0x01)
+    }
+
+    fn left_shift_matches_reference(x: u8) -> bool {
+        alu_same_register([0x80, 0x0E], x) == (x << 1, x >> 7)
+    }
+
+    fn binary_conversion_matches_reference(x: u8) -> bool {
+        /* LD V0, x; LD I, 0x300; LD B, V0 */
+        let snapshot = run(&[0x60, x, 0xA3, 0x00, 0xF0, 0x33], 3);
+        snapshot.memory[0x300..0x303] == [x / 100, (x / 10) % 10, x % 10]
+    }
+}
+
+/* The operands are only 8 bits, so the edge cases can be checked exhaustively too */
+#[test]
+fn sub_equal_operands() {
+    for x in 0..=255 {
+        assert_eq!(alu([0x80, 0x15], x, x), (0, 1));
+        assert_eq!(alu([0x80, 0x17], x, x), (0, 1));
+    }
+}
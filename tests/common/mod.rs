@@ -0,0 +1,40 @@
+/* Helpers shared by the integration tests; not every test uses all of them */
+#![allow(dead_code)]
+
+use chip8rs::chip8::Chip8;
+use chip8rs::debugger::Snapshot;
+use chip8rs::display::DrawCommand;
+use chip8rs::platform::MachineConfig;
+use chip8rs::sound::NullBackend;
+
+use std::sync::mpsc::{self, Receiver};
+
+pub const ROM_START: u16 = 0x200;
+
+/* Headless machine with the program loaded at the default address */
+pub fn machine(program: &[u8]) -> Chip8 {
+    machine_with_config(MachineConfig::default(), program)
+}
+
+pub fn machine_with_config(config: MachineConfig, program: &[u8]) -> Chip8 {
+    machine_with_draws(config, program).0
+}
+
+/* Headless machine, with the receiver of the commands it sends to the renderer */
+pub fn machine_with_draws(config: MachineConfig, program: &[u8]) -> (Chip8, Receiver<DrawCommand>) {
+    let (draw_sender, draw_commands) = mpsc::channel();
+    let mut chip = Chip8::builder().config(config).draw_commands(draw_sender).sound_backend(NullBackend).build();
+    chip.load_program(program).unwrap();
+    (chip, draw_commands)
+}
+
+/* Execute the given number of instructions and return the resulting state */
+pub fn run(program: &[u8], steps: usize) -> Snapshot {
+    let mut chip = machine(program);
+
+    for _ in 0..steps {
+        chip.step().unwrap();
+    }
+
+    chip.snapshot()
+}
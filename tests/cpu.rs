@@ -0,0 +1,857 @@
+mod common;
+
+use chip8rs::bus::{Bus, Ram};
+use chip8rs::chip8::{Chip8, Chip8Error, RPL_FLAGS_SIZE};
+use chip8rs::debugger::{annotated_hexdump, memory_map, Access, CodeWindow, Debugger, Edit, StepMode, WatchHit, Watchpoint};
+use chip8rs::display::{Display, DrawCommand, LedsDisplay};
+use chip8rs::keypad::Keypad;
+use chip8rs::platform::{MachineConfig, Platform, Quirks};
+use chip8rs::rng::RecordedRngSource;
+use chip8rs::sound::NullBackend;
+use common::*;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn set_and_add_register() {
+    /* LD V1, 0xFE; ADD V1, 0x03 */
+    let snapshot = run(&[0x61, 0xFE, 0x71, 0x03], 2);
+
+    /* 7XNN wraps around and never changes VF */
+    assert_eq!(snapshot.regs[0x1], 0x01);
+    assert_eq!(snapshot.regs[0xF], 0x00);
+    assert_eq!(snapshot.pc, ROM_START + 4);
+}
+
+#[test]
+fn add_registers_sets_carry() {
+    /* LD V0, 0xF0; LD V1, 0x20; ADD V0, V1 */
+    let snapshot = run(&[0x60, 0xF0, 0x61, 0x20, 0x80, 0x14], 3);
+
+    assert_eq!(snapshot.regs[0x0], 0x10);
+    assert_eq!(snapshot.regs[0xF], 0x01);
+}
+
+#[test]
+fn skip_if_equal() {
+    /* LD V2, 0x42; SE V2, 0x42; LD V3, 0x01; LD V4, 0x01 */
+    let snapshot = run(&[0x62, 0x42, 0x32, 0x42, 0x63, 0x01, 0x64, 0x01], 3);
+
+    assert_eq!(snapshot.regs[0x3], 0x00);
+    assert_eq!(snapshot.regs[0x4], 0x01);
+}
+
+#[test]
+fn call_and_return() {
+    /* CALL 0x206; JP 0x204 (never reached); RET */
+    let program = [0x22, 0x06, 0x12, 0x04, 0x00, 0x00, 0x00, 0xEE];
+
+    let snapshot = run(&program, 1);
+    assert_eq!(snapshot.pc, 0x206);
+    assert_eq!(snapshot.stack, vec![ROM_START + 2]);
+
+    let snapshot = run(&program, 2);
+    assert_eq!(snapshot.pc, ROM_START + 2);
+    assert!(snapshot.stack.is_empty());
+}
+
+#[test]
+fn return_without_call_fails() {
+    let mut chip = machine(&[0x00, 0xEE]);
+
+    assert_eq!(chip.step(), Err(Chip8Error::StackUnderflow { pc: ROM_START }));
+}
+
+#[test]
+fn unknown_instruction_fails() {
+    /* LD V0, 0x01; 0x0000 is not an instruction */
+    let mut chip = machine(&[0x60, 0x01, 0x00, 0x00]);
+
+    chip.step().unwrap();
+    assert_eq!(
+        chip.step(),
+        Err(Chip8Error::UnknownInstruction {
+            pc: ROM_START + 2,
+            opcode: 0x0000
+        })
+    );
+    assert_eq!(chip.pc(), ROM_START + 2);
+}
+
+#[test]
+fn memory_out_of_bounds_fails() {
+    /* LD I, 0xFFF; LD V3, [I] */
+    let mut chip = machine(&[0xAF, 0xFF, 0xF3, 0x65]);
+    chip.step().unwrap();
+    assert_eq!(
+        chip.step(),
+        Err(Chip8Error::MemoryOutOfBounds {
+            pc: ROM_START + 2,
+            address: 0x1000
+        })
+    );
+
+    /* LD I, 0xFFE; LD B, V0 writes up to 0x1000 */
+    let mut chip = machine(&[0xAF, 0xFE, 0xF0, 0x33]);
+    chip.step().unwrap();
+    assert!(matches!(chip.step(), Err(Chip8Error::MemoryOutOfBounds { address: 0x1000, .. })));
+
+    /* LD I, 0xFFC; DRW V0, V0, 5 */
+    let mut chip = machine(&[0xAF, 0xFC, 0xD0, 0x05]);
+    chip.step().unwrap();
+    assert!(matches!(chip.step(), Err(Chip8Error::MemoryOutOfBounds { address: 0x1000, .. })));
+
+    /* JP 0xFFF: the second byte of the instruction is past the end */
+    let mut chip = machine(&[0x1F, 0xFF]);
+    chip.step().unwrap();
+    assert_eq!(chip.step(), Err(Chip8Error::MemoryOutOfBounds { pc: 0xFFF, address: 0x1000 }));
+}
+
+#[test]
+fn binary_conversion() {
+    /* LD V5, 0xFE; LD I, 0x300; LD B, V5 */
+    let snapshot = run(&[0x65, 0xFE, 0xA3, 0x00, 0xF5, 0x33], 3);
+
+    assert_eq!(snapshot.memory[0x300..0x303], [2, 5, 4]);
+}
+
+#[test]
+fn store_and_load_registers() {
+    /* LD V0, 0x11; LD V1, 0x22; LD I, 0x300; LD [I], V1; LD V0, 0x00; LD V1, 0x00; LD V1, [I] */
+    let program = [
+        0x60, 0x11, 0x61, 0x22, 0xA3, 0x00, 0xF1, 0x55, 0x60, 0x00, 0x61, 0x00, 0xF1, 0x65,
+    ];
+
+    let snapshot = run(&program, 4);
+    assert_eq!(snapshot.memory[0x300..0x302], [0x11, 0x22]);
+
+    let snapshot = run(&program, 7);
+    assert_eq!(snapshot.regs[0x0], 0x11);
+    assert_eq!(snapshot.regs[0x1], 0x22);
+}
+
+#[test]
+fn random_numbers_from_the_source() {
+    /* RND V0, 0xF0; RND V1, 0x0F; RND V2, 0xFF */
+    let mut chip = machine(&[0xC0, 0xF0, 0xC1, 0x0F, 0xC2, 0xFF]);
+    chip.set_rng(Box::new(RecordedRngSource::new(vec![0xAB, 0xCD])));
+
+    for _ in 0..3 {
+        chip.step().unwrap();
+    }
+
+    let snapshot = chip.snapshot();
+    assert_eq!(snapshot.regs[0..3], [0xA0, 0x0D, 0xAB]);
+}
+
+#[test]
+fn draw_font_sprite() {
+    /* LD V0, 0x00; LD F, V0; DRW V0, V0, 5 */
+    let snapshot = run(&[0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05], 3);
+
+    /* The "0" glyph: a 4x5 rectangle */
+    let expected = [
+        [1, 1, 1, 1],
+        [1, 0, 0, 1],
+        [1, 0, 0, 1],
+        [1, 0, 0, 1],
+        [1, 1, 1, 1],
+    ];
+    for (y, row) in expected.iter().enumerate() {
+        assert_eq!(snapshot.display[y][0..4], row[..]);
+        assert_eq!(snapshot.display[y][4], 0);
+    }
+    assert_eq!(snapshot.regs[0xF], 0x00);
+}
+
+#[test]
+fn draw_twice_erases_and_collides() {
+    /* LD V0, 0x00; LD F, V0; DRW V0, V0, 5; DRW V0, V0, 5 */
+    let snapshot = run(&[0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0xD0, 0x05], 4);
+
+    assert!(snapshot.display.iter().all(|row| row.iter().all(|&pixel| pixel == 0)));
+    assert_eq!(snapshot.regs[0xF], 0x01);
+}
+
+#[test]
+fn clear_screen() {
+    /* LD V0, 0x00; LD F, V0; DRW V0, V0, 5; CLS */
+    let snapshot = run(&[0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x00, 0xE0], 4);
+
+    assert!(snapshot.display.iter().all(|row| row.iter().all(|&pixel| pixel == 0)));
+}
+
+#[test]
+fn timers() {
+    /* LD V0, 0x05; LD DT, V0; LD ST, V0 */
+    let mut chip = machine(&[0x60, 0x05, 0xF0, 0x15, 0xF0, 0x18]);
+    for _ in 0..3 {
+        chip.step().unwrap();
+    }
+    chip.tick_timers();
+
+    let snapshot = chip.snapshot();
+    assert_eq!(snapshot.delay_timer, 4);
+    assert_eq!(snapshot.sound_timer, 4);
+}
+
+#[test]
+fn restore_snapshot() {
+    /* LD V0, 0x00; LD F, V0; DRW V0, V0, 5; CALL 0x20A; RET */
+    let program = [0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x22, 0x0A, 0x00, 0x00, 0x00, 0xEE];
+    let snapshot = run(&program, 4);
+
+    /* The state survives a round trip through the save state format */
+    let json = serde_json::to_string(&snapshot).unwrap();
+    let mut chip = machine(&[]);
+    chip.restore(&serde_json::from_str(&json).unwrap());
+
+    assert_eq!(chip.snapshot(), snapshot);
+
+    chip.step().unwrap();
+    assert_eq!(chip.snapshot().pc, ROM_START + 8);
+}
+
+#[test]
+fn watchpoints() {
+    /* LD V0, 0x07; LD I, 0x300; LD [I], V0; LD V1, V0 */
+    let mut chip = machine(&[0x60, 0x07, 0xA3, 0x00, 0xF0, 0x55, 0x81, 0x00]);
+    let debugger = Arc::new(Mutex::new(Debugger::new()));
+    debugger.lock().unwrap().add_watchpoint("0x300".parse().unwrap());
+    debugger.lock().unwrap().add_watchpoint("V1".parse().unwrap());
+    chip.set_debugger(&debugger);
+
+    chip.step().unwrap();
+    chip.step().unwrap();
+    assert!(!debugger.lock().unwrap().is_paused());
+
+    chip.step().unwrap();
+    let hit = WatchHit {
+        watchpoint: Watchpoint::Memory(0x300),
+        access: Access::Write,
+        pc: ROM_START + 4,
+    };
+    assert!(debugger.lock().unwrap().is_paused());
+    assert_eq!(debugger.lock().unwrap().watch_hit(), Some(hit));
+
+    debugger.lock().unwrap().set_paused(false);
+    chip.step().unwrap();
+    let hit = debugger.lock().unwrap().watch_hit().unwrap();
+    assert_eq!(hit.watchpoint, Watchpoint::Register(0x1));
+    assert_eq!(hit.access, Access::Write);
+}
+
+#[test]
+fn sound_flag() {
+    /* LD V0, 0x02; LD ST, V0 */
+    let mut chip = machine(&[0x60, 0x02, 0xF0, 0x18]);
+    let playing = Arc::new(AtomicBool::new(false));
+    chip.set_sound_flag(&playing);
+
+    chip.step().unwrap();
+    chip.step().unwrap();
+    assert!(!playing.load(Ordering::Relaxed));
+
+    /* The tone lasts as many ticks as the timer value */
+    let mut ticks = Vec::new();
+    for _ in 0..3 {
+        chip.tick_timers();
+        ticks.push(playing.load(Ordering::Relaxed));
+    }
+    assert_eq!(ticks, [true, true, false]);
+}
+
+#[test]
+fn step_back() {
+    /* CALL 0x206; JP 0x200; LD I, 0x20E; LD V0, 0x2A; LD [I], V0; DRW V1, V1, 1; RET */
+    let program = [0x22, 0x06, 0x12, 0x00, 0x00, 0x00, 0xA2, 0x0E, 0x60, 0x2A, 0xF0, 0x55, 0xD1, 0x11, 0x00, 0xEE];
+    let mut chip = machine(&program);
+    chip.enable_history(4);
+
+    let mut snapshots = vec![chip.snapshot()];
+    for _ in 0..6 {
+        chip.step().unwrap();
+        snapshots.push(chip.snapshot());
+    }
+    assert_eq!(chip.history_len(), 4);
+
+    /* Undo RET, the sprite drawn over the stored byte, the store and the LD V0 */
+    for expected in snapshots[2..6].iter().rev() {
+        assert!(chip.step_back());
+        assert_eq!(&chip.snapshot(), expected);
+    }
+    assert!(!chip.step_back());
+
+    /* The execution continues from there */
+    chip.step().unwrap();
+    assert_eq!(chip.snapshot(), snapshots[3]);
+}
+
+#[test]
+fn double_buffered_display() {
+    /* LD V0, 0x00; LD F, V0; DRW V0, V0, 5; DRW V0, V0, 5; DRW V0, V0, 5 */
+    let program = [0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0xD0, 0x05, 0xD0, 0x05];
+    let (mut chip, draw_commands) = machine_with_draws(MachineConfig::default(), &program);
+    chip.set_double_buffered(true);
+    while draw_commands.try_recv().is_ok() {}
+
+    /* The sprite erased and drawn again is sent once, as a whole frame, at the end of the frame */
+    for _ in 0..5 {
+        chip.step().unwrap();
+    }
+    assert!(draw_commands.try_recv().is_err());
+
+    chip.tick_timers();
+    match draw_commands.try_recv() {
+        Ok(DrawCommand::Frame { pixels }) => assert_eq!(pixels, chip.snapshot().display),
+        other => panic!("expected a frame, got {:?}", other),
+    }
+    assert!(draw_commands.try_recv().is_err());
+
+    /* Nothing is sent when the display didn't change */
+    chip.tick_timers();
+    assert!(draw_commands.try_recv().is_err());
+}
+
+#[test]
+fn memory_heatmap() {
+    /* LD I, 0x300; LD [I], V1; LD V1, [I]; JP 0x202 */
+    let mut chip = machine(&[0xA3, 0x00, 0xF1, 0x55, 0xF1, 0x65, 0x12, 0x02]);
+    let debugger = Arc::new(Mutex::new(Debugger::new()));
+    debugger.lock().unwrap().enable_heatmap(0x1000);
+    chip.set_debugger(&debugger);
+
+    for _ in 0..7 {
+        chip.step().unwrap();
+    }
+
+    let debugger = debugger.lock().unwrap();
+    let heatmap = debugger.heatmap().unwrap();
+    assert_eq!((heatmap.writes(0x300), heatmap.reads(0x300)), (2, 2));
+    assert_eq!((heatmap.writes(0x301), heatmap.reads(0x302)), (2, 0));
+    /* Fetching the instructions is not counted */
+    assert_eq!(heatmap.reads(0x200), 0);
+}
+
+#[test]
+fn step_over_and_out() {
+    /* CALL 0x206; LD V0, 0x01; LD V1, 0x01; LD V2, 0x01; RET */
+    let program = [0x22, 0x06, 0x60, 0x01, 0x00, 0x00, 0x61, 0x01, 0x62, 0x01, 0x00, 0xEE];
+    let mut chip = machine(&program);
+    let debugger = Arc::new(Mutex::new(Debugger::new()));
+    chip.set_debugger(&debugger);
+
+    /* Step until the debugger pauses again, publishing the snapshot like the run loop does */
+    let pause = |chip: &mut Chip8| {
+        while !debugger.lock().unwrap().is_paused() {
+            chip.step().unwrap();
+        }
+        debugger.lock().unwrap().publish(chip.snapshot());
+        chip.snapshot().pc
+    };
+
+    debugger.lock().unwrap().set_paused(true);
+    debugger.lock().unwrap().publish(chip.snapshot());
+
+    /* Nothing to step out of at the top level */
+    assert!(!debugger.lock().unwrap().step(StepMode::Out, 1));
+
+    assert!(debugger.lock().unwrap().step(StepMode::Over, 1));
+    assert_eq!(pause(&mut chip), ROM_START + 2);
+    assert_eq!(chip.snapshot().regs[0x2], 0x01);
+
+    /* Start again from the call */
+    let mut chip = machine(&program);
+    chip.set_debugger(&debugger);
+    debugger.lock().unwrap().publish(chip.snapshot());
+
+    assert!(debugger.lock().unwrap().step(StepMode::Into, 1));
+    assert_eq!(pause(&mut chip), 0x206);
+
+    assert!(debugger.lock().unwrap().step(StepMode::Out, 1));
+    assert_eq!(pause(&mut chip), ROM_START + 2);
+    assert_eq!(chip.snapshot().regs[0x1], 0x01);
+    assert_eq!(chip.snapshot().regs[0x0], 0x00);
+}
+
+#[test]
+fn run_frame() {
+    /* LD V1, 0x10; LD DT, V1; ADD V0, 0x01; JP 0x204 */
+    let mut chip = machine(&[0x61, 0x10, 0xF1, 0x15, 0x70, 0x01, 0x12, 0x04]);
+
+    /* At 700 instructions per second, a frame runs 12 instructions */
+    chip.run_frame().unwrap();
+
+    let snapshot = chip.snapshot();
+    assert_eq!(snapshot.regs[0x0], 5);
+    assert_eq!(snapshot.delay_timer, 0x0F);
+}
+
+#[test]
+fn load_store_increment_quirk() {
+    /* LD V0, 0x11; LD V1, 0x22; LD I, 0x300; LD [I], V1; LD V1, [I] */
+    let program = [0x60, 0x11, 0x61, 0x22, 0xA3, 0x00, 0xF1, 0x55, 0xF1, 0x65];
+
+    let snapshot = run(&program, 4);
+    assert_eq!(snapshot.i, 0x300);
+
+    let mut config = MachineConfig::default();
+    config.quirks.enable("load-store-increment").unwrap();
+    let mut chip = machine_with_config(config, &program);
+    for _ in 0..5 {
+        chip.step().unwrap();
+    }
+
+    /* I is past the stored registers after each instruction, so the load reads after them */
+    let snapshot = chip.snapshot();
+    assert_eq!(snapshot.memory[0x300..0x302], [0x11, 0x22]);
+    assert_eq!(snapshot.i, 0x304);
+    assert_eq!(snapshot.regs[0x0], 0x00);
+}
+
+#[test]
+fn shift_copies_vy_quirk() {
+    /* LD V0, 0x01; LD V1, 0x81; SHL V0, V1 */
+    let program = [0x60, 0x01, 0x61, 0x81, 0x80, 0x1E];
+
+    let snapshot = run(&program, 3);
+    assert_eq!(snapshot.regs[0x0], 0x02);
+    assert_eq!(snapshot.regs[0xF], 0x00);
+
+    let mut config = MachineConfig::default();
+    config.quirks.enable("shift-copies-vy").unwrap();
+    let mut chip = machine_with_config(config, &program);
+    for _ in 0..3 {
+        chip.step().unwrap();
+    }
+
+    let snapshot = chip.snapshot();
+    assert_eq!(snapshot.regs[0x0], 0x02);
+    assert_eq!(snapshot.regs[0x1], 0x81);
+    assert_eq!(snapshot.regs[0xF], 0x01);
+}
+
+#[test]
+fn jump_uses_vx_quirk() {
+    /* LD V0, 0x02; LD V3, 0x04; JP V0, 0x300 */
+    let program = [0x60, 0x02, 0x63, 0x04, 0xB3, 0x00];
+
+    let snapshot = run(&program, 3);
+    assert_eq!(snapshot.pc, 0x302);
+
+    let mut config = MachineConfig::default();
+    config.quirks.enable("jump-uses-vx").unwrap();
+    let mut chip = machine_with_config(config, &program);
+    for _ in 0..3 {
+        chip.step().unwrap();
+    }
+
+    assert_eq!(chip.snapshot().pc, 0x304);
+}
+
+#[test]
+fn vf_reset_quirk() {
+    /* LD VF, 0x05; LD V0, 0x0C; OR V0, VF */
+    let program = [0x6F, 0x05, 0x60, 0x0C, 0x80, 0xF1];
+
+    let snapshot = run(&program, 3);
+    assert_eq!(snapshot.regs[0x0], 0x0D);
+    assert_eq!(snapshot.regs[0xF], 0x05);
+
+    let mut config = MachineConfig::default();
+    config.quirks.enable("vf-reset").unwrap();
+    let mut chip = machine_with_config(config, &program);
+    for _ in 0..3 {
+        chip.step().unwrap();
+    }
+
+    let snapshot = chip.snapshot();
+    assert_eq!(snapshot.regs[0x0], 0x0D);
+    assert_eq!(snapshot.regs[0xF], 0x00);
+}
+
+#[test]
+fn schip_large_sprites() {
+    /* HIGH; LD I, 0x210; LD V0, 0x00; DRW V0, V0, 0; DRW V0, V0, 0; LD V1, 0x38; DRW V0, V1, 0 */
+    let mut program = vec![
+        0x00, 0xFF, 0xA2, 0x10, 0x60, 0x00, 0xD0, 0x00, 0xD0, 0x00, 0x61, 0x38, 0xD0, 0x10, 0x00, 0x00,
+    ];
+    program.extend([0xFF; 32]);
+    let mut chip = machine_with_config(Platform::Schip.config(), &program);
+
+    for _ in 0..4 {
+        chip.step().unwrap();
+    }
+    let snapshot = chip.snapshot();
+    assert_eq!(snapshot.display[15][15], 1);
+    assert_eq!(snapshot.display[16][0], 0);
+    assert_eq!(snapshot.display[0][16], 0);
+    assert_eq!(snapshot.regs[0xF], 0);
+
+    /* In high resolution VF counts the rows with a collision... */
+    chip.step().unwrap();
+    let snapshot = chip.snapshot();
+    assert_eq!(snapshot.display[0][0], 0);
+    assert_eq!(snapshot.regs[0xF], 16);
+
+    /* ...and the rows clipped at the bottom */
+    chip.step().unwrap();
+    chip.step().unwrap();
+    let snapshot = chip.snapshot();
+    assert_eq!(snapshot.display[63][0], 1);
+    assert_eq!(snapshot.regs[0xF], 8);
+}
+
+#[test]
+fn schip_low_resolution() {
+    /* LD V0, 0x00; LD F, V0; DRW V0, V0, 5 */
+    let program = [0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05];
+    let mut chip = machine_with_config(Platform::Schip.config(), &program);
+    for _ in 0..3 {
+        chip.step().unwrap();
+    }
+
+    /* Each pixel of the "0" glyph is drawn with 2x2 LEDs */
+    let snapshot = chip.snapshot();
+    assert_eq!(snapshot.display[0][7], 1);
+    assert_eq!(snapshot.display[1][7], 1);
+    assert_eq!(snapshot.display[0][8], 0);
+    assert_eq!(snapshot.display[2][1], 1);
+    assert_eq!(snapshot.display[2][2], 0);
+    assert_eq!(snapshot.display[3][6], 1);
+    assert_eq!(snapshot.regs[0xF], 0);
+}
+
+#[test]
+fn rpl_flags() {
+    /* LD V0, 0x11; LD V1, 0x22; LD R, V1; LD V0, 0x00; LD V1, 0x00; LD V2, 0x33; LD V2, R */
+    let program = [
+        0x60, 0x11, 0x61, 0x22, 0xF1, 0x75, 0x60, 0x00, 0x61, 0x00, 0x62, 0x33, 0xF2, 0x85,
+    ];
+    let mut chip = machine(&program);
+    chip.set_rpl_flags([0, 0, 0x44, 0, 0, 0, 0, 0]);
+
+    let saved = Arc::new(Mutex::new(None));
+    let s = saved.clone();
+    chip.on_rpl_flags(move |flags: &[u8; RPL_FLAGS_SIZE]| *s.lock().unwrap() = Some(*flags));
+
+    for _ in 0..7 {
+        chip.step().unwrap();
+    }
+
+    assert_eq!(*saved.lock().unwrap(), Some([0x11, 0x22, 0x44, 0, 0, 0, 0, 0]));
+    assert_eq!(chip.snapshot().regs[0..3], [0x11, 0x22, 0x44]);
+}
+
+#[test]
+fn schip_scroll() {
+    /* HIGH; LD I, 0x20E; DRW V0, V0, 1; SCD 3; SCR; SCL; SCL; data */
+    let program = [
+        0x00, 0xFF, 0xA2, 0x0E, 0xD0, 0x01, 0x00, 0xC3, 0x00, 0xFB, 0x00, 0xFC, 0x00, 0xFC, 0x80,
+    ];
+    let mut chip = machine_with_config(Platform::Schip.config(), &program);
+    for _ in 0..4 {
+        chip.step().unwrap();
+    }
+    let snapshot = chip.snapshot();
+    assert_eq!(snapshot.display[0][0], 0);
+    assert_eq!(snapshot.display[3][0], 1);
+
+    chip.step().unwrap();
+    assert_eq!(chip.snapshot().display[3][4], 1);
+
+    /* Pixels scrolled out are lost */
+    chip.step().unwrap();
+    chip.step().unwrap();
+    let snapshot = chip.snapshot();
+    assert!(snapshot.display.iter().all(|row| row.iter().all(|&pixel| pixel == 0)));
+}
+
+#[test]
+fn exit() {
+    /* LD V0, 0x01; EXIT; LD V0, 0x02 */
+    let mut chip = machine(&[0x60, 0x01, 0x00, 0xFD, 0x60, 0x02]);
+    for _ in 0..3 {
+        chip.step().unwrap();
+    }
+
+    assert!(chip.has_exited());
+    assert_eq!(chip.snapshot().pc, ROM_START + 2);
+    assert_eq!(chip.snapshot().regs[0x0], 0x01);
+}
+
+#[test]
+fn font_sets() {
+    /* LD V0, 0x07; LD F, V0; LD V2, [I] */
+    let program = [0x60, 0x07, 0xF0, 0x29, 0xF2, 0x65];
+
+    let snapshot = run(&program, 3);
+    assert_eq!(snapshot.regs[0x0..0x3], [0xF0, 0x10, 0x20]);
+
+    let config = MachineConfig {
+        font: "vip".parse().unwrap(),
+        ..MachineConfig::default()
+    };
+    let mut chip = machine_with_config(config, &program);
+    for _ in 0..3 {
+        chip.step().unwrap();
+    }
+    assert_eq!(chip.snapshot().regs[0x0..0x3], [0xF0, 0x10, 0x10]);
+}
+
+#[test]
+fn big_font() {
+    /* LD V0, 0x08; LD HF, V0; LD V2, [I] */
+    let snapshot = run(&[0x60, 0x08, 0xF0, 0x30, 0xF2, 0x65], 3);
+
+    assert_eq!(snapshot.regs[0x0..0x3], [0x3C, 0x7E, 0xC3]);
+}
+
+#[test]
+fn debugger_edits() {
+    /* LD V0, 0x01; ADD V0, V1 */
+    let mut chip = machine(&[0x60, 0x01, 0x80, 0x14]);
+
+    chip.apply_edit(&Edit::Memory(0x201, vec![0x05]));
+    chip.apply_edit(&Edit::Register(0x1, 0x10));
+    chip.apply_edit(&Edit::Index(0x300));
+    chip.step().unwrap();
+    chip.step().unwrap();
+    assert_eq!(chip.snapshot().regs[0x0], 0x15);
+    assert_eq!(chip.snapshot().i, 0x300);
+
+    chip.apply_edit(&Edit::Pc(0x202));
+    chip.step().unwrap();
+    assert_eq!(chip.snapshot().regs[0x0], 0x25);
+}
+
+#[test]
+fn code_window() {
+    /* LD V0, 0x07; LD I, 0x300; then data */
+    let mut snapshot = run(&[0x60, 0x07, 0xA3, 0x00, 0xFF, 0xFF], 2);
+
+    let code = CodeWindow::from_snapshot(&snapshot);
+    let lines = code.lines();
+    assert_eq!(code.start, ROM_START + 4 - 12);
+    assert_eq!(lines.len(), 13);
+    assert_eq!(lines[code.current_line()], "204: FFFF  DATA");
+    assert_eq!(lines[code.current_line() - 2], "200: 6007  LD V0, 0x07");
+
+    /* The first instructions of the memory, and the last ones */
+    snapshot.pc = 0x002;
+    assert_eq!(CodeWindow::from_snapshot(&snapshot).current_line(), 1);
+    snapshot.pc = 0xFFE;
+    assert_eq!(CodeWindow::from_snapshot(&snapshot).lines().len(), 7);
+}
+
+#[test]
+fn memory_map_dump() {
+    let config = MachineConfig::default();
+    let snapshot = run(&[0x00, 0xE0, 0x12, 0x00], 0);
+    let regions = memory_map(&config, 4);
+
+    /* The last font character, "F", followed by the first big one */
+    let dump = annotated_hexdump(&snapshot.memory, 0x98, 0x10, &regions);
+    assert_eq!(
+        dump,
+        "098: F0 80 F0 F0 80 F0 80 80 3C 7E E7 C3 C3 C3 C3 E7  |........<~......|  font, big font\n"
+    );
+
+    let dump = annotated_hexdump(&snapshot.memory, 0x200, 4, &regions);
+    assert_eq!(dump, format!("200: 00 E0 12 00{:36}  |....            |  ROM\n", ""));
+}
+
+/* Display using only the per pixel methods, to compare the default batch methods with the ones of
+ * LedsDisplay */
+struct PixelDisplay {
+    pixels: Vec<Vec<u8>>,
+}
+
+impl Display for PixelDisplay {
+    fn set_pixel(&mut self, x: usize, y: usize, value: u8) {
+        self.pixels[y][x] = value;
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y][x]
+    }
+
+    fn width(&self) -> usize {
+        self.pixels[0].len()
+    }
+
+    fn height(&self) -> usize {
+        self.pixels.len()
+    }
+}
+
+#[test]
+fn blit_sprite() {
+    let mut leds = LedsDisplay::new(16, 8, false);
+    let mut pixels = PixelDisplay {
+        pixels: vec![vec![0; 16]; 8],
+    };
+    let displays: [&mut dyn Display; 2] = [&mut leds, &mut pixels];
+
+    for display in displays {
+        assert!(!display.blit_sprite(12, 6, &[0xF0, 0x81, 0xFF]));
+        assert_eq!(display.pixel(12, 6), 1);
+        assert_eq!(display.pixel(15, 7), 0);
+
+        /* Only the second row overlaps the first sprite */
+        assert_eq!(display.blit_rows(10, 5, &[0x0000, 0x2000, 0x0000], 16), 1);
+        assert_eq!(display.pixel(12, 6), 0);
+        assert_eq!(display.pixel(13, 6), 1);
+
+        display.set_row(0, &[2; 20]);
+        assert!((0..16).all(|x| display.pixel(x, 0) == 2));
+    }
+}
+
+#[test]
+fn vip_timing() {
+    let config = MachineConfig {
+        vip_timing: true,
+        ..MachineConfig::default()
+    };
+
+    /* ADD V0, 0x01; JP 0x200: 22 cycles per loop, so the frame ends after the 167th addition */
+    let mut chip = machine_with_config(config, &[0x70, 0x01, 0x12, 0x00]);
+    chip.run_frame().unwrap();
+    assert_eq!(chip.snapshot().regs[0x0], 167);
+
+    /* The 6 cycles exceeding the first frame are taken from the second one, which then ends right
+     * after the 167th addition */
+    chip.run_frame().unwrap();
+    assert_eq!(chip.snapshot().regs[0x0], (167 + 167) as u8);
+}
+
+/* Test device mapped at the last address, collecting the bytes written there */
+struct OutputPort {
+    ram: Ram,
+    output: Arc<Mutex<Vec<u8>>>,
+}
+
+const OUTPUT_PORT: u16 = 0xFFF;
+
+impl Bus for OutputPort {
+    fn read(&self, address: u16) -> u8 {
+        self.ram.read(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if address == OUTPUT_PORT {
+            self.output.lock().unwrap().push(value);
+        } else {
+            self.ram.write(address, value);
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.ram.size()
+    }
+}
+
+#[test]
+fn memory_mapped_device() {
+    let output = Arc::new(Mutex::new(Vec::new()));
+
+    /* The program is loaded again in the new memory */
+    let mut chip = machine(&[]);
+    chip.set_bus(Box::new(OutputPort {
+        ram: Ram::new(0x1000),
+        output: Arc::clone(&output),
+    }));
+    /* LD V0, 0x2A; LD I, 0xFFF; LD [I], V0; LD V0, [I] */
+    chip.load_program(&[0x60, 0x2A, 0xAF, 0xFF, 0xF0, 0x55, 0xF0, 0x65]).unwrap();
+    for _ in 0..4 {
+        chip.step().unwrap();
+    }
+
+    assert_eq!(*output.lock().unwrap(), [0x2A]);
+    /* The written byte never reached the RAM */
+    assert_eq!(chip.snapshot().regs[0x0], 0x00);
+}
+
+/* Keypad with every key held down */
+struct HeldKeypad;
+
+impl Keypad for HeldKeypad {
+    fn set_is_pressed(&mut self, _code: usize, _is_pressed: bool) {}
+
+    fn get_is_pressed(&self, _code: usize) -> bool {
+        true
+    }
+}
+
+/* Display sharing its pixels with the test, since the machine owns it */
+struct SharedDisplay {
+    pixels: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl Display for SharedDisplay {
+    fn set_pixel(&mut self, x: usize, y: usize, value: u8) {
+        self.pixels.lock().unwrap()[y][x] = value;
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.pixels.lock().unwrap()[y][x]
+    }
+
+    fn width(&self) -> usize {
+        self.pixels.lock().unwrap()[0].len()
+    }
+
+    fn height(&self) -> usize {
+        self.pixels.lock().unwrap().len()
+    }
+}
+
+#[test]
+fn builder_components() {
+    let pixels = Arc::new(Mutex::new(vec![vec![0; 64]; 32]));
+    let mut quirks = Quirks::default();
+    quirks.enable("jump-uses-vx").unwrap();
+
+    let mut chip = Chip8::builder()
+        .quirks(quirks)
+        .keypad(&Arc::new(Mutex::new(HeldKeypad)))
+        .display(SharedDisplay {
+            pixels: Arc::clone(&pixels),
+        })
+        .sound_backend(NullBackend)
+        .build();
+    /* LD V0, 0x00; SKP V0; LD V1, 0x01; LD F, V0; DRW V0, V0, 5; LD V3, 0x04; JP V3, 0x300 */
+    chip.load_program(&[0x60, 0x00, 0xE0, 0x9E, 0x61, 0x01, 0xF0, 0x29, 0xD0, 0x05, 0x63, 0x04, 0xB3, 0x00])
+        .unwrap();
+    for _ in 0..6 {
+        chip.step().unwrap();
+    }
+
+    let snapshot = chip.snapshot();
+    assert_eq!(snapshot.regs[0x1], 0x00);
+    assert_eq!(snapshot.pc, 0x304);
+    assert_eq!(pixels.lock().unwrap()[0][0..5], [1, 1, 1, 1, 0]);
+}
+
+#[test]
+fn state_accessors() {
+    /* LD V4, 0x03; LD I, 0x300; LD DT, V4; CALL 0x20A; LD V5, 0x01 (not reached); LD [I], V4 */
+    let mut chip = machine(&[0x64, 0x03, 0xA3, 0x00, 0xF4, 0x15, 0x22, 0x0A, 0x65, 0x01, 0xF4, 0x55]);
+    for _ in 0..5 {
+        chip.step().unwrap();
+    }
+
+    assert_eq!(chip.pc(), 0x20C);
+    assert_eq!(chip.index(), 0x300);
+    assert_eq!(chip.registers()[0x4], 0x03);
+    assert_eq!(chip.stack(), [ROM_START + 8]);
+    assert_eq!(chip.delay_timer(), 0x03);
+    assert_eq!(chip.sound_timer(), 0x00);
+    assert_eq!(chip.memory().read(0x304), 0x03);
+    assert!(!chip.display().is_on(0, 0));
+
+    chip.apply_edit(&Edit::Register(0x5, 0x42));
+    assert_eq!(chip.registers()[0x5], 0x42);
+}
@@ -0,0 +1,102 @@
+import init, { WebEmulator } from "./pkg/chip8rs.js";
+
+/* Same layout as the native frontend */
+const KEYMAP = {
+    "1": 0x1, "2": 0x2, "3": 0x3, "4": 0xC,
+    "q": 0x4, "w": 0x5, "e": 0x6, "r": 0xD,
+    "a": 0x7, "s": 0x8, "d": 0x9, "f": 0xE,
+    "z": 0xA, "x": 0x0, "c": 0xB, "v": 0xF,
+};
+
+/* ~700 instructions per second */
+const INSTRUCTIONS_PER_FRAME = 12;
+
+const canvas = document.getElementById("display");
+const context = canvas.getContext("2d");
+
+let emulator = null;
+let running = false;
+let audio = null;
+let oscillator = null;
+
+function draw() {
+    const width = emulator.width();
+    const height = emulator.height();
+    const ledWidth = canvas.width / width;
+    const framebuffer = emulator.framebuffer();
+
+    context.fillStyle = "white";
+    context.fillRect(0, 0, canvas.width, canvas.height);
+    context.fillStyle = "black";
+
+    for (let y = 0; y < height; y++) {
+        for (let x = 0; x < width; x++) {
+            if (framebuffer[y * width + x]) {
+                context.fillRect(x * ledWidth, y * ledWidth, ledWidth, ledWidth);
+            }
+        }
+    }
+}
+
+function beep(active) {
+    if (active && oscillator === null) {
+        audio = audio || new AudioContext();
+        oscillator = audio.createOscillator();
+        oscillator.frequency.value = 440;
+        oscillator.connect(audio.destination);
+        oscillator.start();
+    } else if (!active && oscillator !== null) {
+        oscillator.stop();
+        oscillator = null;
+    }
+}
+
+function frame() {
+    try {
+        emulator.frame(INSTRUCTIONS_PER_FRAME);
+    } catch (error) {
+        console.error("CHIP-8 error: " + error);
+        beep(false);
+        running = false;
+        return;
+    }
+
+    draw();
+    beep(emulator.sound_active());
+
+    if (emulator.exited()) {
+        beep(false);
+        running = false;
+        return;
+    }
+    requestAnimationFrame(frame);
+}
+
+document.addEventListener("keydown", (event) => {
+    if (emulator !== null && event.key in KEYMAP) {
+        emulator.key_down(KEYMAP[event.key]);
+    }
+});
+
+document.addEventListener("keyup", (event) => {
+    if (emulator !== null && event.key in KEYMAP) {
+        emulator.key_up(KEYMAP[event.key]);
+    }
+});
+
+document.getElementById("rom").addEventListener("change", async (event) => {
+    const rom = new Uint8Array(await event.target.files[0].arrayBuffer());
+
+    try {
+        emulator = new WebEmulator(rom);
+    } catch (error) {
+        alert(error);
+        return;
+    }
+    if (!running) {
+        running = true;
+        requestAnimationFrame(frame);
+    }
+});
+
+await init();